@@ -292,6 +292,44 @@ async fn handle_event(index: usize, node: &mut Routing, event: Event) -> bool {
             "Node #{} adults changed - remaining: {:?}, added: {:?}, removed: {:?}",
             index, remaining, added, removed
         ),
+        Event::DkgStarted {
+            candidates,
+            generation,
+        } => info!(
+            "Node #{} DKG started - candidates: {:?}, generation: {}",
+            index, candidates, generation
+        ),
+        Event::DkgFailed {
+            non_participants,
+            elapsed,
+            ..
+        } => info!(
+            "Node #{} DKG failed after {:?} - non participants: {:?}",
+            index, elapsed, non_participants
+        ),
+        Event::DkgAttemptsExhausted {
+            attempts, excluded, ..
+        } => info!(
+            "Node #{} gave up on DKG after {} attempts - excluded: {:?}",
+            index, attempts, excluded
+        ),
+        Event::DkgCompleted { key, elapsed, .. } => info!(
+            "Node #{} DKG completed after {:?} - key: {:?}",
+            index, elapsed, key
+        ),
+        Event::CustomAgreement { payload, .. } => info!(
+            "Node #{} section agreed on custom payload {:?}",
+            index, payload
+        ),
+        Event::ProposalStalled {
+            proposal,
+            non_responding,
+            elapsed,
+        } => info!(
+            "Node #{} gave up on {:?} after {:?} - non responding: {:?}",
+            index, proposal, elapsed, non_responding
+        ),
+        Event::Lagged { missed } => info!("Node #{} missed {} events", index, missed),
     }
 
     true
@@ -0,0 +1,61 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Stitches the message journals of several nodes into the paths the messages took.
+//!
+//! Start the nodes with `Config::message_journal` set, collect their journals and run:
+//!
+//!     cargo run --example stitch_journals -- JOURNAL...
+//!
+//! Every message is printed with its hops, ordered by time. Use `--incomplete` to only print the
+//! messages that weren't delivered, which is where to look when a message went missing.
+
+use anyhow::Result;
+use sn_routing::{stitch_message_journals, Hop};
+use std::{path::PathBuf, time::UNIX_EPOCH};
+use structopt::StructOpt;
+
+/// Stitches message journals into message paths.
+#[derive(Debug, StructOpt)]
+struct Options {
+    /// Journal files written by the nodes.
+    #[structopt(required = true, parse(from_os_str))]
+    journals: Vec<PathBuf>,
+    /// Only print the messages that were never delivered.
+    #[structopt(short, long)]
+    incomplete: bool,
+}
+
+fn main() -> Result<()> {
+    let opts = Options::from_args();
+    let paths = stitch_message_journals(&opts.journals)?;
+
+    for (msg_id, records) in paths {
+        let delivered = records.iter().any(|record| record.hop == Hop::Delivered);
+        if opts.incomplete && delivered {
+            continue;
+        }
+
+        println!(
+            "{:?}{}",
+            msg_id,
+            if delivered { "" } else { " (not delivered)" }
+        );
+
+        for record in records {
+            let time = record
+                .time
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            println!("  {:.6} {} {:?}", time, record.node, record.hop);
+        }
+    }
+
+    Ok(())
+}
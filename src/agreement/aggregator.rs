@@ -0,0 +1,278 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{Signed, SignedShare};
+use crate::ed25519::Digest256;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+use tiny_keccak::{Hasher, Sha3};
+
+// Partial aggregations that didn't receive a new share for this long are evicted.
+pub(crate) const AGGREGATION_EXPIRATION: Duration = Duration::from_secs(120);
+
+// Partial aggregations that didn't receive a new share for this many changes of our section key
+// are evicted, whatever key they are for.
+const MAX_IDLE_GENERATIONS: u64 = 2;
+
+// Aggregates signature shares into full signatures.
+//
+// Payloads that never get enough shares, e.g. proposals that don't reach a supermajority, are
+// evicted once they expire or once they've been idle for a couple of changes of our section key.
+// The key they are signed with is not a reason on its own: shares signed with our previous key
+// are still valid and keep arriving for a while after the key changes.
+pub(crate) struct SignatureAggregator {
+    map: HashMap<(Digest256, bls::PublicKey), State>,
+    expiration: Duration,
+    // Incremented on every change of our section key.
+    generation: u64,
+    // Number of partial aggregations evicted since the last `take_evicted`.
+    evicted: usize,
+}
+
+impl Default for SignatureAggregator {
+    fn default() -> Self {
+        Self::with_expiration(AGGREGATION_EXPIRATION)
+    }
+}
+
+impl SignatureAggregator {
+    pub fn with_expiration(expiration: Duration) -> Self {
+        Self {
+            map: HashMap::new(),
+            expiration,
+            generation: 0,
+            evicted: 0,
+        }
+    }
+
+    // Adds the signature share of `payload`. Returns the full signature once there are enough
+    // shares, `AggregatorError::NotEnoughShares` until then.
+    pub fn add(
+        &mut self,
+        payload: &[u8],
+        signed_share: SignedShare,
+        now: Instant,
+    ) -> Result<Signed, AggregatorError> {
+        self.evict_expired(now);
+
+        let public_key_set = signed_share.public_key_set;
+        if !public_key_set
+            .public_key_share(signed_share.index)
+            .verify(&signed_share.signature_share, payload)
+        {
+            return Err(AggregatorError::InvalidShare);
+        }
+
+        let public_key = public_key_set.public_key();
+        let key = (payload_digest(payload), public_key);
+        let generation = self.generation;
+        let state = self.map.entry(key).or_insert_with(|| State {
+            public_key_set,
+            shares: BTreeMap::new(),
+            modified: now,
+            generation,
+        });
+
+        let _ = state
+            .shares
+            .insert(signed_share.index, signed_share.signature_share);
+        state.modified = now;
+        state.generation = generation;
+
+        if state.shares.len() <= state.public_key_set.threshold() {
+            return Err(AggregatorError::NotEnoughShares);
+        }
+
+        let signature = state
+            .public_key_set
+            .combine_signatures(state.shares.iter())?;
+        let _ = self.map.remove(&key);
+
+        Ok(Signed {
+            public_key,
+            signature,
+        })
+    }
+
+    // To be called when our section key changes. Evicts the partial aggregations that have been
+    // idle for too many generations.
+    pub fn new_generation(&mut self) {
+        self.generation += 1;
+
+        let generation = self.generation;
+        let before = self.map.len();
+        self.map
+            .retain(|_, state| generation - state.generation <= MAX_IDLE_GENERATIONS);
+        self.evicted += before - self.map.len();
+    }
+
+    // Returns the payloads that don't have enough signature shares yet.
+    pub fn pending(&self) -> impl Iterator<Item = PendingAggregation> + '_ {
+        self.map.iter().map(
+            |((payload_digest, section_key), state)| PendingAggregation {
+                payload_digest: *payload_digest,
+                section_key: *section_key,
+                shares: state.shares.keys().copied().collect(),
+            },
+        )
+    }
+
+    // Returns the number of payloads that don't have enough signature shares yet.
+    pub fn pending_len(&self) -> usize {
+        self.map.len()
+    }
+
+    // Returns the number of partial aggregations evicted since the last call.
+    pub fn take_evicted(&mut self) -> usize {
+        std::mem::take(&mut self.evicted)
+    }
+
+    fn evict_expired(&mut self, now: Instant) {
+        let expiration = self.expiration;
+        let before = self.map.len();
+        self.map
+            .retain(|_, state| now.saturating_duration_since(state.modified) < expiration);
+        self.evicted += before - self.map.len();
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum AggregatorError {
+    #[error("not enough signature shares")]
+    NotEnoughShares,
+    #[error("signature share is invalid")]
+    InvalidShare,
+    #[error("failed to combine signature shares: {0}")]
+    Combine(#[from] bls::error::Error),
+}
+
+// Payload an aggregator holds some signature shares of.
+pub(crate) struct PendingAggregation {
+    pub payload_digest: Digest256,
+    // Key the shares are for.
+    pub section_key: bls::PublicKey,
+    // Indices of the signers whose shares we have.
+    pub shares: BTreeSet<usize>,
+}
+
+struct State {
+    public_key_set: bls::PublicKeySet,
+    shares: BTreeMap<usize, bls::SignatureShare>,
+    // When the last share was added.
+    modified: Instant,
+    // Generation when the last share was added.
+    generation: u64,
+}
+
+pub(crate) fn payload_digest(payload: &[u8]) -> Digest256 {
+    let mut hasher = Sha3::v256();
+    let mut digest = Digest256::default();
+    hasher.update(payload);
+    hasher.finalize(&mut digest);
+    digest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn aggregate() {
+        let sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let payload = b"hello";
+        let now = Instant::now();
+        let mut aggregator = SignatureAggregator::default();
+
+        let share_0 = sign_share(&sk_set, 0, payload);
+        assert_matches!(
+            aggregator.add(payload, share_0, now),
+            Err(AggregatorError::NotEnoughShares)
+        );
+
+        let pending: Vec<_> = aggregator.pending().collect();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].section_key, sk_set.public_keys().public_key());
+        assert_eq!(pending[0].shares, vec![0].into_iter().collect());
+
+        // A share of a different payload doesn't count.
+        let invalid_share = sign_share(&sk_set, 1, b"world");
+        assert_matches!(
+            aggregator.add(payload, invalid_share, now),
+            Err(AggregatorError::InvalidShare)
+        );
+
+        let share_1 = sign_share(&sk_set, 1, payload);
+        let signed = aggregator.add(payload, share_1, now).unwrap();
+        assert!(signed.verify(payload));
+        assert_eq!(aggregator.pending().count(), 0);
+        assert_eq!(aggregator.take_evicted(), 0);
+    }
+
+    #[test]
+    fn evict_expired() {
+        let sk_set = bls::SecretKeySet::random(2, &mut rand::thread_rng());
+        let start = Instant::now();
+        let mut aggregator = SignatureAggregator::with_expiration(Duration::from_secs(10));
+
+        let _ = aggregator.add(b"old", sign_share(&sk_set, 0, b"old"), start);
+        let _ = aggregator.add(
+            b"new",
+            sign_share(&sk_set, 0, b"new"),
+            start + Duration::from_secs(5),
+        );
+
+        // A new share keeps the payload alive.
+        let _ = aggregator.add(
+            b"new",
+            sign_share(&sk_set, 1, b"new"),
+            start + Duration::from_secs(12),
+        );
+
+        assert_eq!(aggregator.pending().count(), 1);
+        assert_eq!(aggregator.take_evicted(), 1);
+        assert_eq!(aggregator.take_evicted(), 0);
+    }
+
+    #[test]
+    fn evict_on_new_generation() {
+        let old_sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let other_sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let now = Instant::now();
+        let mut aggregator = SignatureAggregator::default();
+
+        let _ = aggregator.add(b"a", sign_share(&old_sk_set, 0, b"a"), now);
+        let _ = aggregator.add(b"b", sign_share(&other_sk_set, 0, b"b"), now);
+
+        // Shares signed with the previous key still complete after the key changed.
+        aggregator.new_generation();
+        assert_eq!(aggregator.pending().count(), 2);
+        assert!(aggregator
+            .add(b"a", sign_share(&old_sk_set, 1, b"a"), now)
+            .is_ok());
+        assert_eq!(aggregator.take_evicted(), 0);
+
+        // Payloads are only evicted once they've been idle for too long.
+        for _ in 0..MAX_IDLE_GENERATIONS {
+            aggregator.new_generation();
+        }
+        assert_eq!(aggregator.pending().count(), 0);
+        assert_eq!(aggregator.take_evicted(), 1);
+    }
+
+    fn sign_share(sk_set: &bls::SecretKeySet, index: usize, payload: &[u8]) -> SignedShare {
+        SignedShare {
+            public_key_set: sk_set.public_keys(),
+            index,
+            signature_share: sk_set.secret_key_share(index).sign(payload),
+        }
+    }
+}
@@ -6,18 +6,25 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{DkgFailureSignedSetUtils, DkgFailureSignedUtils};
+use super::{DkgFailureSignedSetUtils, DkgFailureSignedUtils, DkgKeyUtils, DkgPrecedence};
 use crate::{
+    clock::{self, Clock, SharedRng},
     ed25519::{self, Keypair},
     error::Result,
+    event::Event,
     messages::RoutingMsgUtils,
     node::Node,
-    routing::command::{self, Command},
+    routing::{
+        command::{self, Command},
+        DkgPolicy,
+    },
     section::{ElderCandidatesUtils, SectionAuthorityProviderUtils, SectionKeyShare},
     supermajority,
 };
 use bls_dkg::key_gen::{message::Message as DkgMessage, KeyGen};
 use itertools::Itertools;
+use rand::Rng;
+use serde::Serialize;
 use sn_messaging::{
     node::{DkgFailureSigned, DkgFailureSignedSet, DkgKey, ElderCandidates, RoutingMsg, Variant},
     DestInfo, DstLocation, SectionAuthorityProvider,
@@ -27,12 +34,10 @@ use std::{
     fmt::Debug,
     iter, mem,
     net::SocketAddr,
-    time::Duration,
+    sync::Arc,
+    time::{Duration, Instant},
 };
-use xor_name::XorName;
-
-// Interval to progress DKG timed phase
-const DKG_PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+use xor_name::{Prefix, XorName};
 
 const BACKLOG_CAPACITY: usize = 100;
 
@@ -50,27 +55,55 @@ const BACKLOG_CAPACITY: usize = 100;
 /// 6. When the observers accumulate the votes, they can proceed with voting for the section update.
 ///
 /// Note: in case of heavy churn, it can happen that more than one DKG session completes
-/// successfully. Some kind of disambiguation strategy needs to be employed in that case, but that
-/// is currently not a responsibility of this module.
+/// successfully. The outcomes are disambiguated by their `DkgPrecedence` once handed over, which
+/// is why they come with the generation of their session.
+///
+/// The key shares of the outcomes are kept here, with `insert_outcome`, until our section agrees
+/// on one of them or decides on another key for their generation. They are dropped, which zeroes
+/// their secrets, together with the sessions of the decided generations.
 pub(crate) struct DkgVoter {
     sessions: HashMap<DkgKey, Session>,
 
+    // Key shares generated by the sessions, not agreed on yet.
+    outcomes: HashMap<DkgKey, (DkgPrecedence, SectionKeyShare)>,
+
     // Due to the asyncronous nature of the network we might sometimes receive a DKG message before
     // we created the corresponding session. To avoid losing those messages, we store them in this
     // backlog and replay them once we create the session.
     backlog: Backlog,
+
+    policy: DkgPolicy,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
 }
 
 impl Default for DkgVoter {
     fn default() -> Self {
+        Self::new(clock::default_clock(), SharedRng::from_entropy())
+    }
+}
+
+impl DkgVoter {
+    pub fn new(clock: Arc<dyn Clock>, rng: SharedRng) -> Self {
         Self {
             sessions: HashMap::default(),
+            outcomes: HashMap::default(),
             backlog: Backlog::new(),
+            policy: DkgPolicy::default(),
+            clock,
+            rng,
         }
     }
-}
 
-impl DkgVoter {
+    pub fn policy(&self) -> &DkgPolicy {
+        &self.policy
+    }
+
+    // Applies the given policy to the sessions started from now on.
+    pub fn set_policy(&mut self, policy: DkgPolicy) {
+        self.policy = policy;
+    }
+
     // Starts a new DKG session.
     pub fn start(
         &mut self,
@@ -94,21 +127,35 @@ impl DkgVoter {
             return vec![];
         };
 
+        let started = DkgCommand::SendEvent(Event::DkgStarted {
+            candidates: elder_candidates.elders.keys().copied().collect(),
+            generation: dkg_key.generation,
+        });
+
         // Special case: only one participant.
         if elder_candidates.elders.len() == 1 {
-            let secret_key_set = bls::SecretKeySet::random(0, &mut rand::thread_rng());
+            let secret_key_set = bls::SecretKeySet::random(0, &mut self.rng);
             let section_auth = SectionAuthorityProvider::from_elder_candidates(
                 elder_candidates,
                 secret_key_set.public_keys(),
             );
-            return vec![DkgCommand::HandleOutcome {
-                section_auth,
-                outcome: SectionKeyShare {
-                    public_key_set: secret_key_set.public_keys(),
-                    index: participant_index,
-                    secret_key_share: secret_key_set.secret_key_share(0),
+            return vec![
+                started,
+                DkgCommand::SendEvent(Event::DkgCompleted {
+                    key: secret_key_set.public_keys().public_key(),
+                    generation: dkg_key.generation,
+                    elapsed: Duration::default(),
+                }),
+                DkgCommand::HandleOutcome {
+                    section_auth,
+                    generation: dkg_key.generation,
+                    outcome: SectionKeyShare {
+                        public_key_set: secret_key_set.public_keys(),
+                        index: participant_index,
+                        secret_key_share: secret_key_set.secret_key_share(0),
+                    },
                 },
-            }];
+            ];
         }
 
         let threshold = supermajority(elder_candidates.elders.len()) - 1;
@@ -122,19 +169,24 @@ impl DkgVoter {
                     key_gen,
                     elder_candidates,
                     participant_index,
+                    heard_from: iter::once(name).collect(),
                     timer_token: 0,
+                    rounds: 0,
+                    received: false,
+                    progress_interval: self.policy.progress_interval,
+                    max_rounds: self.policy.max_rounds,
                     failures: DkgFailureSignedSet::default(),
                     complete: false,
+                    started_at: self.clock.now(),
+                    clock: self.clock.clone(),
+                    rng: self.rng.clone(),
                 };
 
-                let mut commands = vec![];
+                let mut commands = vec![started];
                 commands.extend(session.broadcast(&dkg_key, keypair, message));
-                commands.extend(
-                    self.backlog
-                        .take(&dkg_key)
-                        .into_iter()
-                        .flat_map(|message| session.process_message(&dkg_key, keypair, message)),
-                );
+                for (sender, message) in self.backlog.take(&dkg_key) {
+                    commands.extend(session.receive(&dkg_key, keypair, sender, message));
+                }
 
                 let _ = self.sessions.insert(dkg_key, session);
 
@@ -167,17 +219,18 @@ impl DkgVoter {
         }
     }
 
-    // Handle a received DkgMessage.
+    // Handle a DkgMessage received from `sender`.
     pub fn process_message(
         &mut self,
         keypair: &Keypair,
         dkg_key: &DkgKey,
         message: DkgMessage,
+        sender: XorName,
     ) -> Vec<DkgCommand> {
         if let Some(session) = self.sessions.get_mut(dkg_key) {
-            session.process_message(dkg_key, keypair, message)
+            session.receive(dkg_key, keypair, sender, message)
         } else {
-            self.backlog.push(*dkg_key, message);
+            self.backlog.push(*dkg_key, sender, message);
             vec![]
         }
     }
@@ -187,11 +240,117 @@ impl DkgVoter {
         dkg_key: &DkgKey,
         non_participants: &BTreeSet<XorName>,
         signed: DkgFailureSigned,
-    ) -> Option<DkgCommand> {
+    ) -> Vec<DkgCommand> {
+        if let Some(session) = self.sessions.get_mut(dkg_key) {
+            session.process_failure(dkg_key, non_participants, signed)
+        } else {
+            vec![]
+        }
+    }
+
+    // Returns whether the outcome of a session with the given precedence is superseded by one we
+    // keep.
+    pub fn is_outcome_superseded(&self, precedence: &DkgPrecedence) -> bool {
+        self.outcomes
+            .values()
+            .any(|(outcome_precedence, _)| outcome_precedence > precedence)
+    }
+
+    // Keeps the key share generated by the session for `elder_candidates` and `generation`,
+    // discarding the ones it supersedes. All the outcomes compete with each other, as all the
+    // sessions we participate in are for prefixes matching our name. Returns `false`, without
+    // keeping it, if it is itself superseded.
+    pub fn insert_outcome(
+        &mut self,
+        elder_candidates: &ElderCandidates,
+        generation: u64,
+        key_share: SectionKeyShare,
+    ) -> bool {
+        let precedence = DkgPrecedence::new(elder_candidates, generation);
+        if self.is_outcome_superseded(&precedence) {
+            return false;
+        }
+
+        self.outcomes.retain(|dkg_key, (outcome_precedence, _)| {
+            let superseded = *outcome_precedence < precedence;
+            if superseded {
+                trace!("Discarding superseded DKG outcome of {:?}", dkg_key);
+            }
+            !superseded
+        });
+
+        let dkg_key = DkgKey::new(elder_candidates, generation);
+        let _ = self.outcomes.insert(dkg_key, (precedence, key_share));
+        true
+    }
+
+    // Takes out the key share for `public_key`, once our section agreed on it.
+    pub fn take_outcome(&mut self, public_key: &bls::PublicKey) -> Option<SectionKeyShare> {
+        let dkg_key = *self
+            .outcomes
+            .iter()
+            .find(|(_, (_, key_share))| key_share.public_key_set.public_key() == *public_key)?
+            .0;
+        self.outcomes
+            .remove(&dkg_key)
+            .map(|(_, key_share)| key_share)
+    }
+
+    // Returns the public keys of the outcomes not agreed on yet.
+    pub fn outcome_public_keys(&self) -> impl Iterator<Item = bls::PublicKey> + '_ {
+        self.outcomes
+            .values()
+            .map(|(_, key_share)| key_share.public_key_set.public_key())
+    }
+
+    // Drops the sessions of the generations before `generation`, which are decided, together with
+    // the key shares they generated.
+    pub fn discard_outdated(&mut self, generation: u64) {
         self.sessions
-            .get_mut(dkg_key)?
-            .process_failure(dkg_key, non_participants, signed)
+            .retain(|dkg_key, _| dkg_key.generation >= generation);
+        self.outcomes
+            .retain(|dkg_key, _| dkg_key.generation >= generation);
+    }
+
+    // Returns the state of the sessions, for debugging.
+    pub fn session_snapshots(&self) -> Vec<DkgSessionSnapshot> {
+        let mut snapshots: Vec<_> = self
+            .sessions
+            .iter()
+            .map(|(dkg_key, session)| DkgSessionSnapshot {
+                generation: dkg_key.generation,
+                candidates: session.elder_candidates.elders.keys().copied().collect(),
+                prefix: session.elder_candidates.prefix,
+                participant_index: session.participant_index,
+                complete: session.complete,
+                elapsed: session.elapsed(),
+            })
+            .collect();
+        snapshots.sort_by_key(|snapshot| snapshot.generation);
+        snapshots
     }
+
+    // Returns the number of messages waiting for their session to start.
+    pub fn backlog_len(&self) -> usize {
+        self.backlog.0.len()
+    }
+}
+
+/// A DKG session we take part in. See `DebugSnapshot`.
+#[derive(Clone, Debug, Serialize)]
+pub struct DkgSessionSnapshot {
+    /// Generation of the session.
+    pub generation: u64,
+    /// The candidates generating the key.
+    pub candidates: BTreeSet<XorName>,
+    /// Prefix of the section the key is for.
+    pub prefix: Prefix,
+    /// Our index among the candidates.
+    pub participant_index: usize,
+    /// Whether the session completed, successfully or not.
+    pub complete: bool,
+    /// How long the session has been running.
+    pub elapsed: Duration,
 }
 
 // Data for a DKG participant.
@@ -199,15 +358,40 @@ struct Session {
     elder_candidates: ElderCandidates,
     participant_index: usize,
     key_gen: KeyGen,
+    // Candidates we received DKG messages from, including us.
+    heard_from: BTreeSet<XorName>,
     timer_token: u64,
+    // Number of consecutive rounds (timeouts of the progress timer) during which no DKG message was
+    // received from the other candidates.
+    rounds: usize,
+    // Whether a DKG message was received from the other candidates during the current round.
+    received: bool,
+    progress_interval: Duration,
+    max_rounds: usize,
     failures: DkgFailureSignedSet,
     // Flag to track whether this session has completed (either with success or failure). We don't
     // remove complete sessions because the other participants might still need us to respond to
     // their messages.
     complete: bool,
+    started_at: Instant,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
 }
 
 impl Session {
+    // Handles a DKG message received from another candidate.
+    fn receive(
+        &mut self,
+        dkg_key: &DkgKey,
+        keypair: &Keypair,
+        sender: XorName,
+        message: DkgMessage,
+    ) -> Vec<DkgCommand> {
+        let _ = self.heard_from.insert(sender);
+        self.received = true;
+        self.process_message(dkg_key, keypair, message)
+    }
+
     fn process_message(
         &mut self,
         dkg_key: &DkgKey,
@@ -217,7 +401,7 @@ impl Session {
         trace!("process DKG message {:?}", message);
         let responses = self
             .key_gen
-            .handle_message(&mut rand::thread_rng(), message)
+            .handle_message(&mut self.rng, message)
             .unwrap_or_default();
 
         // Only a valid DkgMessage, which results in some responses, shall reset the ticker.
@@ -273,9 +457,37 @@ impl Session {
             return vec![];
         }
 
+        if mem::take(&mut self.received) {
+            self.rounds = 0;
+        } else {
+            self.rounds += 1;
+        }
+
+        if self.rounds > self.max_rounds {
+            let non_participants: BTreeSet<_> = self
+                .elder_candidates
+                .elders
+                .keys()
+                .filter(|name| !self.heard_from.contains(name))
+                .copied()
+                .collect();
+            trace!(
+                "DKG for {:?} failed: no progress after {} rounds, non participants: {:?}",
+                self.elder_candidates,
+                self.max_rounds,
+                non_participants
+            );
+
+            // Keep the timer running, as the set of non-participants might still change.
+            let reset_timer = self.reset_timer();
+            let mut commands = self.report_failure(dkg_key, non_participants, keypair);
+            commands.push(reset_timer);
+            return commands;
+        }
+
         trace!("DKG for {:?} progressing", self.elder_candidates);
 
-        match self.key_gen.timed_phase_transition(&mut rand::thread_rng()) {
+        match self.key_gen.timed_phase_transition(&mut self.rng) {
             Ok(messages) => {
                 let mut commands: Vec<_> = messages
                     .into_iter()
@@ -366,10 +578,18 @@ impl Session {
             secret_key_share: outcome.secret_key_share,
         };
 
-        vec![DkgCommand::HandleOutcome {
-            section_auth,
-            outcome,
-        }]
+        vec![
+            DkgCommand::SendEvent(Event::DkgCompleted {
+                key: outcome.public_key_set.public_key(),
+                generation: dkg_key.generation,
+                elapsed: self.elapsed(),
+            }),
+            DkgCommand::HandleOutcome {
+                section_auth,
+                generation: dkg_key.generation,
+                outcome,
+            },
+        ]
     }
 
     fn report_failure(
@@ -384,7 +604,7 @@ impl Session {
             return vec![];
         }
 
-        self.check_failure_agreement()
+        self.check_failure_agreement(dkg_key)
             .into_iter()
             .chain(iter::once(DkgCommand::SendFailureObservation {
                 recipients: self.recipients(),
@@ -400,72 +620,82 @@ impl Session {
         dkg_key: &DkgKey,
         non_participants: &BTreeSet<XorName>,
         signed: DkgFailureSigned,
-    ) -> Option<DkgCommand> {
+    ) -> Vec<DkgCommand> {
         if !self
             .elder_candidates
             .elders
             .contains_key(&ed25519::name(&signed.public_key))
         {
-            return None;
+            return vec![];
         }
 
         if !signed.verify(dkg_key, non_participants) {
-            return None;
+            return vec![];
         }
 
         if !self.failures.insert(signed, non_participants) {
-            return None;
+            return vec![];
         }
 
-        self.check_failure_agreement()
+        self.check_failure_agreement(dkg_key)
     }
 
-    fn check_failure_agreement(&mut self) -> Option<DkgCommand> {
+    fn check_failure_agreement(&mut self, dkg_key: &DkgKey) -> Vec<DkgCommand> {
         if self.failures.has_agreement(&self.elder_candidates) {
             self.complete = true;
 
-            Some(DkgCommand::HandleFailureAgreement(mem::take(
-                &mut self.failures,
-            )))
+            let failures = mem::take(&mut self.failures);
+            vec![
+                DkgCommand::SendEvent(Event::DkgFailed {
+                    non_participants: failures.non_participants.clone(),
+                    generation: dkg_key.generation,
+                    elapsed: self.elapsed(),
+                }),
+                DkgCommand::HandleFailureAgreement(failures),
+            ]
         } else {
-            None
+            vec![]
         }
     }
 
+    fn elapsed(&self) -> Duration {
+        self.clock.now().saturating_duration_since(self.started_at)
+    }
+
     fn reset_timer(&mut self) -> DkgCommand {
         self.timer_token = command::next_timer_token();
         DkgCommand::ScheduleTimeout {
-            duration: DKG_PROGRESS_INTERVAL,
+            duration: self.progress_interval,
             token: self.timer_token,
         }
     }
 }
 
-struct Backlog(VecDeque<(DkgKey, DkgMessage)>);
+struct Backlog(VecDeque<(DkgKey, XorName, DkgMessage)>);
 
 impl Backlog {
     fn new() -> Self {
         Self(VecDeque::with_capacity(BACKLOG_CAPACITY))
     }
 
-    fn push(&mut self, dkg_key: DkgKey, message: DkgMessage) {
+    fn push(&mut self, dkg_key: DkgKey, sender: XorName, message: DkgMessage) {
         if self.0.len() == self.0.capacity() {
             let _ = self.0.pop_front();
         }
 
-        self.0.push_back((dkg_key, message))
+        self.0.push_back((dkg_key, sender, message))
     }
 
-    fn take(&mut self, dkg_key: &DkgKey) -> Vec<DkgMessage> {
+    fn take(&mut self, dkg_key: &DkgKey) -> Vec<(XorName, DkgMessage)> {
         let mut output = Vec::new();
         let max = self.0.len();
 
         for _ in 0..max {
-            if let Some((message_dkg_key, message)) = self.0.pop_front() {
+            if let Some((message_dkg_key, sender, message)) = self.0.pop_front() {
                 if &message_dkg_key == dkg_key {
-                    output.push(message)
+                    output.push((sender, message))
                 } else {
-                    self.0.push_back((message_dkg_key, message))
+                    self.0.push_back((message_dkg_key, sender, message))
                 }
             }
         }
@@ -475,7 +705,7 @@ impl Backlog {
 
     fn prune(&mut self, dkg_key: &DkgKey) {
         self.0
-            .retain(|(old_dkg_key, _)| old_dkg_key.generation >= dkg_key.generation)
+            .retain(|(old_dkg_key, _, _)| old_dkg_key.generation >= dkg_key.generation)
     }
 }
 
@@ -492,6 +722,7 @@ pub(crate) enum DkgCommand {
     },
     HandleOutcome {
         section_auth: SectionAuthorityProvider,
+        generation: u64,
         outcome: SectionKeyShare,
     },
     SendFailureObservation {
@@ -501,10 +732,11 @@ pub(crate) enum DkgCommand {
         non_participants: BTreeSet<XorName>,
     },
     HandleFailureAgreement(DkgFailureSignedSet),
+    SendEvent(Event),
 }
 
 impl DkgCommand {
-    fn into_command(self, node: &Node, key: bls::PublicKey) -> Result<Command> {
+    fn into_command(self, node: &Node, key: bls::PublicKey, rng: &SharedRng) -> Result<Command> {
         match self {
             Self::SendMessage {
                 recipients,
@@ -520,7 +752,7 @@ impl DkgCommand {
                     recipients.len(),
                     message,
                     DestInfo {
-                        dest: XorName::random(),
+                        dest: rng.clone().gen(),
                         dest_section_pk: key,
                     },
                 ))
@@ -530,9 +762,11 @@ impl DkgCommand {
             }
             Self::HandleOutcome {
                 section_auth,
+                generation,
                 outcome,
             } => Ok(Command::HandleDkgOutcome {
                 section_auth,
+                generation,
                 outcome,
             }),
             Self::SendFailureObservation {
@@ -554,32 +788,48 @@ impl DkgCommand {
                     recipients.len(),
                     message,
                     DestInfo {
-                        dest: XorName::random(),
+                        dest: rng.clone().gen(),
                         dest_section_pk: key,
                     },
                 ))
             }
             Self::HandleFailureAgreement(signeds) => Ok(Command::HandleDkgFailure(signeds)),
+            Self::SendEvent(event) => Ok(Command::SendEvent(event)),
         }
     }
 }
 
 pub(crate) trait DkgCommands {
-    fn into_commands(self, node: &Node, key: bls::PublicKey) -> Result<Vec<Command>>;
+    fn into_commands(
+        self,
+        node: &Node,
+        key: bls::PublicKey,
+        rng: &SharedRng,
+    ) -> Result<Vec<Command>>;
 }
 
 impl DkgCommands for Vec<DkgCommand> {
-    fn into_commands(self, node: &Node, key: bls::PublicKey) -> Result<Vec<Command>> {
+    fn into_commands(
+        self,
+        node: &Node,
+        key: bls::PublicKey,
+        rng: &SharedRng,
+    ) -> Result<Vec<Command>> {
         self.into_iter()
-            .map(|command| command.into_command(node, key))
+            .map(|command| command.into_command(node, key, rng))
             .collect()
     }
 }
 
 impl DkgCommands for Option<DkgCommand> {
-    fn into_commands(self, node: &Node, key: bls::PublicKey) -> Result<Vec<Command>> {
+    fn into_commands(
+        self,
+        node: &Node,
+        key: bls::PublicKey,
+        rng: &SharedRng,
+    ) -> Result<Vec<Command>> {
         self.into_iter()
-            .map(|command| command.into_command(node, key))
+            .map(|command| command.into_command(node, key, rng))
             .collect()
     }
 }
@@ -588,12 +838,14 @@ impl DkgCommands for Option<DkgCommand> {
 mod tests {
     use super::*;
     use crate::{
-        agreement::DkgKeyUtils, ed25519, node::test_utils::arbitrary_unique_nodes,
-        section::test_utils::gen_addr, ELDER_SIZE, MIN_ADULT_AGE,
+        node::test_utils::arbitrary_unique_nodes,
+        section::test_utils::{gen_addr, gen_node},
+        ELDER_SIZE,
     };
     use assert_matches::assert_matches;
     use proptest::prelude::*;
     use rand::{rngs::SmallRng, SeedableRng};
+    use sn_messaging::node::Peer;
     use std::{collections::HashMap, iter};
     use xor_name::Prefix;
 
@@ -603,15 +855,118 @@ mod tests {
 
         let mut voter = DkgVoter::default();
 
-        let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-            gen_addr(),
-        );
+        let node = gen_node();
         let elder_candidates = ElderCandidates::new(iter::once(node.peer()), Prefix::default());
         let dkg_key = DkgKey::new(&elder_candidates, 0);
 
         let commands = voter.start(&node.keypair, dkg_key, elder_candidates);
-        assert_matches!(&commands[..], &[DkgCommand::HandleOutcome { .. }]);
+        assert_matches!(
+            &commands[..],
+            &[
+                DkgCommand::SendEvent(Event::DkgStarted { .. }),
+                DkgCommand::SendEvent(Event::DkgCompleted { .. }),
+                DkgCommand::HandleOutcome { .. }
+            ]
+        );
+    }
+
+    #[test]
+    fn superseded_outcomes() {
+        let mut voter = DkgVoter::default();
+
+        let mut outcomes: Vec<_> = (0..3)
+            .map(|_| {
+                let elder_candidates = ElderCandidates::new(
+                    (0..3).map(|_| Peer::new(XorName::random(), gen_addr())),
+                    Prefix::default(),
+                );
+                let precedence = DkgPrecedence::new(&elder_candidates, 1);
+                (precedence, elder_candidates, gen_key_share())
+            })
+            .collect();
+        outcomes.sort_by_key(|(precedence, ..)| *precedence);
+
+        let (_, highest_candidates, highest_key_share) = outcomes.pop().expect("no outcome");
+        let (_, middle_candidates, middle_key_share) = outcomes.pop().expect("no outcome");
+        let (_, lowest_candidates, lowest_key_share) = outcomes.pop().expect("no outcome");
+        let middle_key = middle_key_share.public_key_set.public_key();
+        let highest_key = highest_key_share.public_key_set.public_key();
+
+        assert!(voter.insert_outcome(&middle_candidates, 1, middle_key_share));
+        // Superseded by the kept one.
+        assert!(!voter.insert_outcome(&lowest_candidates, 1, lowest_key_share));
+        // Supersedes the kept one.
+        assert!(voter.insert_outcome(&highest_candidates, 1, highest_key_share));
+
+        assert_eq!(
+            voter.outcome_public_keys().collect::<Vec<_>>(),
+            vec![highest_key]
+        );
+        assert!(voter.take_outcome(&middle_key).is_none());
+
+        voter.discard_outdated(2);
+        assert_eq!(voter.outcome_public_keys().count(), 0);
+    }
+
+    #[test]
+    fn report_silent_participants_after_max_rounds() {
+        let mut voter = DkgVoter::default();
+        voter.set_policy(DkgPolicy {
+            max_rounds: 0,
+            ..DkgPolicy::default()
+        });
+
+        let nodes: Vec<_> = (0..2).map(|_| gen_node()).collect();
+        let elder_candidates =
+            ElderCandidates::new(nodes.iter().map(Node::peer), Prefix::default());
+        let dkg_key = DkgKey::new(&elder_candidates, 0);
+
+        let _ = voter.start(&nodes[0].keypair, dkg_key, elder_candidates);
+        let token = voter.sessions[&dkg_key].timer_token;
+
+        let commands = voter.handle_timeout(&nodes[0].keypair, token);
+        assert!(commands.iter().any(|command| matches!(
+            command,
+            DkgCommand::SendFailureObservation { non_participants, .. }
+                if non_participants == &iter::once(nodes[1].name()).collect()
+        )));
+        assert!(commands
+            .iter()
+            .any(|command| matches!(command, DkgCommand::ScheduleTimeout { .. })));
+    }
+
+    #[test]
+    fn count_only_rounds_without_messages() {
+        let nodes: Vec<_> = (0..2).map(|_| gen_node()).collect();
+        let elder_candidates =
+            ElderCandidates::new(nodes.iter().map(Node::peer), Prefix::default());
+        let dkg_key = DkgKey::new(&elder_candidates, 0);
+
+        let mut voter = DkgVoter::default();
+        let _ = voter.start(&nodes[0].keypair, dkg_key, elder_candidates.clone());
+
+        let mut other_voter = DkgVoter::default();
+        let message = other_voter
+            .start(&nodes[1].keypair, dkg_key, elder_candidates)
+            .into_iter()
+            .find_map(|command| match command {
+                DkgCommand::SendMessage { message, .. } => Some(message),
+                _ => None,
+            })
+            .expect("initial DKG message not sent");
+
+        let timeout = |voter: &mut DkgVoter| {
+            let token = voter.sessions[&dkg_key].timer_token;
+            let _ = voter.handle_timeout(&nodes[0].keypair, token);
+            voter.sessions[&dkg_key].rounds
+        };
+
+        assert_eq!(timeout(&mut voter), 1);
+        assert_eq!(timeout(&mut voter), 2);
+
+        let _ = voter.process_message(&nodes[0].keypair, &dkg_key, message, nodes[1].name());
+        assert_eq!(timeout(&mut voter), 0);
+        assert_eq!(timeout(&mut voter), 1);
     }
 
     proptest! {
@@ -664,12 +1019,13 @@ mod tests {
             // NOTE: this panics if `messages` is empty, but that's OK because it would mean
             // failure anyway.
             let index = rng.gen_range(0, messages.len());
-            let (addr, message) = messages.swap_remove(index);
+            let (addr, sender, message) = messages.swap_remove(index);
 
             let actor = actors.get_mut(&addr).expect("unknown message recipient");
-            let commands = actor
-                .voter
-                .process_message(&actor.node.keypair, &dkg_key, message);
+            let commands =
+                actor
+                    .voter
+                    .process_message(&actor.node.keypair, &dkg_key, message, sender);
 
             for command in commands {
                 messages.extend(actor.handle(command, &dkg_key))
@@ -696,7 +1052,7 @@ mod tests {
             &mut self,
             command: DkgCommand,
             expected_dkg_key: &DkgKey,
-        ) -> Vec<(SocketAddr, DkgMessage)> {
+        ) -> Vec<(SocketAddr, XorName, DkgMessage)> {
             match command {
                 DkgCommand::SendMessage {
                     recipients,
@@ -707,14 +1063,14 @@ mod tests {
                     assert_eq!(dkg_key, *expected_dkg_key);
                     recipients
                         .into_iter()
-                        .map(|addr| (addr.1, message.clone()))
+                        .map(|addr| (addr.1, self.node.name(), message.clone()))
                         .collect()
                 }
                 DkgCommand::HandleOutcome { outcome, .. } => {
                     self.outcome = Some(outcome.public_key_set.public_key());
                     vec![]
                 }
-                DkgCommand::ScheduleTimeout { .. } => vec![],
+                DkgCommand::ScheduleTimeout { .. } | DkgCommand::SendEvent(_) => vec![],
                 DkgCommand::SendFailureObservation { .. }
                 | DkgCommand::HandleFailureAgreement { .. } => {
                     panic!("unexpected command: {:?}", command)
@@ -723,6 +1079,15 @@ mod tests {
         }
     }
 
+    fn gen_key_share() -> SectionKeyShare {
+        let secret_key_set = bls::SecretKeySet::random(0, &mut rand::thread_rng());
+        SectionKeyShare {
+            public_key_set: secret_key_set.public_keys(),
+            index: 0,
+            secret_key_share: secret_key_set.secret_key_share(0),
+        }
+    }
+
     fn arbitrary_elder_nodes() -> impl Strategy<Value = Vec<Node>> {
         arbitrary_unique_nodes(2..=ELDER_SIZE)
     }
@@ -0,0 +1,77 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::DkgKeyUtils;
+use crate::ed25519::Digest256;
+use sn_messaging::node::{DkgKey, ElderCandidates};
+
+// Precedence of the outcome of a DKG session over the outcomes of other sessions that completed as
+// well, which can happen under heavy churn. Outcomes for compatible prefixes compete, as opposed to
+// the two halves of a split. Of the competing outcomes, only the one with the greatest
+// precedence is proposed and kept pending, so that every node picks the same one regardless of the
+// order it learns about them in. Should more than one get agreed anyway, the first one agreed
+// decides the generation and the others are refused. Outcomes are ordered by:
+//
+// 1. generation, as a later session is based on more recent knowledge of the section,
+// 2. length of the prefix, so the outcomes of a split win over an outcome without one (both halves
+//    of a split have to win for the split to happen),
+// 3. hash of the elder candidates, as an arbitrary but deterministic tie breaker.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct DkgPrecedence {
+    generation: u64,
+    prefix_len: usize,
+    candidates_hash: Digest256,
+}
+
+impl DkgPrecedence {
+    pub fn new(elder_candidates: &ElderCandidates, generation: u64) -> Self {
+        Self {
+            generation,
+            prefix_len: elder_candidates.prefix.bit_count(),
+            candidates_hash: DkgKey::new(elder_candidates, generation).hash,
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::section::{test_utils::gen_addr, ElderCandidatesUtils};
+    use sn_messaging::node::Peer;
+    use xor_name::{Prefix, XorName};
+
+    #[test]
+    fn order() {
+        let prefix = Prefix::default();
+        let split_prefix = prefix.pushed(false);
+
+        let candidates_a = gen_elder_candidates(split_prefix);
+        let candidates_b = gen_elder_candidates(prefix);
+        let candidates_c = gen_elder_candidates(prefix);
+
+        // Later generation wins.
+        assert!(DkgPrecedence::new(&candidates_b, 3) > DkgPrecedence::new(&candidates_a, 2));
+        // Split wins in the same generation.
+        assert!(DkgPrecedence::new(&candidates_a, 2) > DkgPrecedence::new(&candidates_b, 2));
+
+        // Otherwise the hash of the candidates decides.
+        assert_ne!(
+            DkgPrecedence::new(&candidates_b, 2),
+            DkgPrecedence::new(&candidates_c, 2)
+        );
+    }
+
+    fn gen_elder_candidates(prefix: Prefix) -> ElderCandidates {
+        let peers = (0..3).map(|_| Peer::new(prefix.substituted_in(XorName::random()), gen_addr()));
+        ElderCandidates::new(peers, prefix)
+    }
+}
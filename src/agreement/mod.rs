@@ -6,21 +6,25 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod aggregator;
 mod dkg;
 mod dkg_msgs_utils;
+mod dkg_precedence;
 mod proposal;
 mod proven;
 #[cfg(test)]
 pub mod test_utils;
 
 pub(crate) use self::{
+    aggregator::{payload_digest, AggregatorError, PendingAggregation, SignatureAggregator},
     dkg::{DkgCommands, DkgVoter},
     dkg_msgs_utils::{DkgFailureSignedSetUtils, DkgFailureSignedUtils, DkgKeyUtils},
-    proposal::{ProposalAggregator, ProposalError, ProposalUtils},
+    dkg_precedence::DkgPrecedence,
+    proposal::{proposal_digest, ProposalAggregator, ProposalError, ProposalUtils},
 };
-pub use proven::ProvenUtils;
+pub use self::{dkg::DkgSessionSnapshot, proven::ProvenUtils};
 use serde::Serialize;
-pub(crate) use sn_messaging::node::{SignatureAggregator, Signed, SignedShare};
+pub(crate) use sn_messaging::node::{Signed, SignedShare};
 
 // Verify the integrity of `message` against `signed`.
 pub(crate) fn verify_signed<T: Serialize>(signed: &Signed, message: &T) -> bool {
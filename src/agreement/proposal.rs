@@ -6,10 +6,13 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{SignatureAggregator, Signed, SignedShare};
-use crate::{error::Result, messages::PlainMessageUtils};
+use super::{
+    payload_digest, AggregatorError, PendingAggregation, SignatureAggregator, Signed, SignedShare,
+};
+use crate::{ed25519::Digest256, error::Result, messages::PlainMessageUtils};
 use serde::{Serialize, Serializer};
 use sn_messaging::node::Proposal;
+use std::time::Instant;
 use thiserror::Error;
 
 pub trait ProposalUtils {
@@ -64,6 +67,13 @@ impl<'a> Serialize for SignableView<'a> {
     }
 }
 
+// Identifies `proposal` by the digest of its signable part, which is what `ProposalAggregator`
+// aggregates the signatures of.
+pub(crate) fn proposal_digest(proposal: &Proposal) -> Option<Digest256> {
+    let bytes = bincode::serialize(&SignableView(proposal)).ok()?;
+    Some(payload_digest(&bytes))
+}
+
 // Aggregator of `Proposal`s.
 #[derive(Default)]
 pub(crate) struct ProposalAggregator(SignatureAggregator);
@@ -73,18 +83,38 @@ impl ProposalAggregator {
         &mut self,
         proposal: Proposal,
         signed_share: SignedShare,
+        now: Instant,
     ) -> Result<(Proposal, Signed), ProposalError> {
         let bytes =
             bincode::serialize(&SignableView(&proposal)).map_err(|_| ProposalError::Invalid)?;
-        let signed = self.0.add(&bytes, signed_share)?;
+        let signed = self.0.add(&bytes, signed_share, now)?;
         Ok((proposal, signed))
     }
+
+    // See `SignatureAggregator::new_generation`.
+    pub fn new_generation(&mut self) {
+        self.0.new_generation()
+    }
+
+    // Returns the proposals that don't have enough signature shares yet.
+    pub fn pending(&self) -> impl Iterator<Item = PendingAggregation> + '_ {
+        self.0.pending()
+    }
+
+    pub fn pending_len(&self) -> usize {
+        self.0.pending_len()
+    }
+
+    // Returns the number of proposals evicted since the last call.
+    pub fn take_evicted(&mut self) -> usize {
+        self.0.take_evicted()
+    }
 }
 
 #[derive(Debug, Error)]
 pub enum ProposalError {
     #[error("failed to aggregate signature shares: {0}")]
-    Aggregation(#[from] sn_messaging::node::Error),
+    Aggregation(#[from] AggregatorError),
     #[error("invalid proposal")]
     Invalid,
 }
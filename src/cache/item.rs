@@ -13,9 +13,9 @@ struct Time {
 }
 
 impl<T> Item<T> {
-    pub fn new(object: T, item_duration: Option<Duration>) -> Self {
+    pub fn new(object: T, item_duration: Option<Duration>, now: Instant) -> Self {
         let time = item_duration.map(|duration| {
-            let start = Instant::now();
+            let start = now;
             Time {
                 start,
                 expiry: start + duration,
@@ -24,15 +24,13 @@ impl<T> Item<T> {
         Item { object, time }
     }
 
-    pub fn expired(&self) -> bool {
-        self.time
-            .map(|time| time.expiry < Instant::now())
-            .unwrap_or(false)
+    pub fn expired(&self, now: Instant) -> bool {
+        self.time.map(|time| time.expiry < now).unwrap_or(false)
     }
 
-    pub fn elapsed(&self) -> u128 {
+    pub fn elapsed(&self, now: Instant) -> u128 {
         self.time
-            .map(|time| Instant::now() - time.start)
+            .map(|time| now.saturating_duration_since(time.start))
             .unwrap_or_default()
             .as_millis()
     }
@@ -41,20 +39,20 @@ impl<T> Item<T> {
 #[cfg(test)]
 mod tests {
     use super::Item;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
 
     const OBJECT: &str = "OBJECT";
 
     #[tokio::test]
     async fn not_expired_when_duration_is_none() {
-        let item = Item::new(OBJECT, None);
-        assert_eq!(item.expired(), false);
+        let item = Item::new(OBJECT, None, Instant::now());
+        assert_eq!(item.expired(Instant::now()), false);
     }
 
     #[tokio::test]
     async fn expired_when_duration_is_zero() {
-        let item = Item::new(OBJECT, Some(Duration::new(0, 0)));
+        let item = Item::new(OBJECT, Some(Duration::new(0, 0)), Instant::now());
         tokio::time::sleep(Duration::new(0, 0)).await;
-        assert_eq!(item.expired(), true);
+        assert_eq!(item.expired(Instant::now()), true);
     }
 }
@@ -9,9 +9,11 @@
 mod item;
 
 use self::item::Item;
+use crate::clock::{self, Clock};
 use itertools::Itertools;
 use std::collections::BTreeMap;
 use std::hash::Hash;
+use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 
@@ -24,6 +26,7 @@ where
     items: RwLock<BTreeMap<T, Item<V>>>,
     item_duration: Option<Duration>,
     capacity: usize,
+    clock: Arc<dyn Clock>,
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -37,6 +40,7 @@ where
             items: RwLock::new(BTreeMap::new()),
             item_duration: None,
             capacity,
+            clock: clock::default_clock(),
         }
     }
 
@@ -46,6 +50,7 @@ where
             items: RwLock::new(BTreeMap::new()),
             item_duration: Some(duration),
             capacity: usize::MAX,
+            clock: clock::default_clock(),
         }
     }
 
@@ -55,9 +60,16 @@ where
             items: RwLock::new(BTreeMap::new()),
             item_duration: Some(duration),
             capacity,
+            clock: clock::default_clock(),
         }
     }
 
+    /// Makes the items expire by the time of `clock` instead of the tokio runtime's.
+    pub fn with_clock(mut self, clock: Arc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     ///
     pub async fn len(&self) -> usize {
         self.items.read().await.len()
@@ -82,11 +94,12 @@ where
         T: Eq + Hash,
         V: Clone,
     {
+        let now = self.clock.now();
         self.items
             .read()
             .await
             .get(key)
-            .filter(|&item| !item.expired())
+            .filter(|&item| !item.expired(now))
             .map(|k| k.object.clone())
     }
 
@@ -101,7 +114,11 @@ where
             .await
             .insert(
                 key,
-                Item::new(value, custom_duration.or(self.item_duration)),
+                Item::new(
+                    value,
+                    custom_duration.or(self.item_duration),
+                    self.clock.now(),
+                ),
             )
             .map(|item| item.object);
         self.remove_expired().await;
@@ -113,11 +130,12 @@ where
     #[allow(unused_assignments)]
     pub async fn remove_expired(&self) {
         let mut expired_keys = Vec::new();
+        let now = self.clock.now();
         {
             let read_items = self.items.read().await;
             expired_keys = read_items
                 .iter()
-                .filter(|(_, item)| item.expired())
+                .filter(|(_, item)| item.expired(now))
                 .map(|(key, _)| *key)
                 .collect();
         }
@@ -134,12 +152,15 @@ where
         if len > self.capacity {
             let excess = len - self.capacity;
             let mut excess_keys = Vec::new();
+            let now = self.clock.now();
             {
                 let read_items = self.items.read().await;
                 let mut items = read_items.iter().collect_vec();
 
                 // reversed sort
-                items.sort_by(|(_, item_a), (_, item_b)| item_b.elapsed().cmp(&item_a.elapsed()));
+                items.sort_by(|(_, item_a), (_, item_b)| {
+                    item_b.elapsed(now).cmp(&item_a.elapsed(now))
+                });
 
                 // take the excess
                 excess_keys = items.iter().take(excess).map(|(key, _)| **key).collect();
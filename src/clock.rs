@@ -0,0 +1,176 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Sources of time and randomness of a node.
+
+use futures::{
+    future::{self, BoxFuture, Either},
+    pin_mut, FutureExt,
+};
+use rand::{CryptoRng, RngCore, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::{
+    fmt::{self, Debug, Formatter},
+    future::Future,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+/// Source of the current time and of timers.
+pub trait Clock: Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+
+    /// Returns a future which completes once `duration` has passed.
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+}
+
+/// The clock of the tokio runtime, used by default.
+///
+/// When the runtime's time is paused (see `tokio::time::pause`), it stands still while any task
+/// can make progress and then jumps straight to the next timer. On a single-threaded runtime this
+/// turns the nodes into a discrete-event simulation, running hours of network life in seconds.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TokioClock;
+
+impl Clock for TokioClock {
+    fn now(&self) -> Instant {
+        time::Instant::now().into_std()
+    }
+
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        time::sleep(duration).boxed()
+    }
+}
+
+pub(crate) fn default_clock() -> Arc<dyn Clock> {
+    Arc::new(TokioClock)
+}
+
+// Runs `future` to completion, unless `duration` passes on `clock` first, in which case `None` is
+// returned.
+pub(crate) async fn timeout<F: Future>(
+    clock: &dyn Clock,
+    duration: Duration,
+    future: F,
+) -> Option<F::Output> {
+    pin_mut!(future);
+    match future::select(future, clock.sleep(duration)).await {
+        Either::Left((output, _)) => Some(output),
+        Either::Right(_) => None,
+    }
+}
+
+/// Cryptographically secure RNG which can be shared by the components of a node.
+///
+/// Seed it with `SharedRng::seeded` to make a simulation reproducible. Give every node its own
+/// RNG (e.g. using `fork`), as draws from a single RNG shared by several nodes depend on the order
+/// their tasks happen to run in.
+#[derive(Clone)]
+pub struct SharedRng(Arc<Mutex<ChaChaRng>>);
+
+impl SharedRng {
+    /// Creates a RNG seeded from the operating system's entropy source.
+    pub fn from_entropy() -> Self {
+        Self::from_rng(ChaChaRng::from_entropy())
+    }
+
+    /// Creates a RNG which always produces the same numbers for the same `seed`.
+    pub fn seeded(seed: u64) -> Self {
+        Self::from_rng(ChaChaRng::seed_from_u64(seed))
+    }
+
+    /// Creates a new RNG seeded from this one.
+    pub fn fork(&self) -> Self {
+        Self::seeded(self.lock().next_u64())
+    }
+
+    fn from_rng(rng: ChaChaRng) -> Self {
+        Self(Arc::new(Mutex::new(rng)))
+    }
+
+    fn lock(&self) -> MutexGuard<ChaChaRng> {
+        self.0.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Default for SharedRng {
+    fn default() -> Self {
+        Self::from_entropy()
+    }
+}
+
+impl RngCore for SharedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.lock().next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.lock().next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.lock().fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.lock().try_fill_bytes(dest)
+    }
+}
+
+impl CryptoRng for SharedRng {}
+
+impl Debug for SharedRng {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "SharedRng")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = SharedRng::seeded(1);
+        let mut b = SharedRng::seeded(1);
+        let a_numbers: Vec<u64> = (0..10).map(|_| a.gen()).collect();
+        let b_numbers: Vec<u64> = (0..10).map(|_| b.gen()).collect();
+        assert_eq!(a_numbers, b_numbers);
+
+        assert_eq!(a.fork().next_u64(), b.fork().next_u64());
+    }
+
+    #[tokio::test]
+    async fn timeout_on_clock() {
+        time::pause();
+        let clock = TokioClock;
+
+        assert_eq!(
+            timeout(&clock, Duration::from_secs(1), future::ready(1)).await,
+            Some(1)
+        );
+        assert_eq!(
+            timeout(&clock, Duration::from_secs(1), future::pending::<()>()).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn paused_tokio_clock_jumps_to_timers() {
+        time::pause();
+        let clock = TokioClock;
+        let start = clock.now();
+
+        clock.sleep(Duration::from_secs(60 * 60)).await;
+
+        assert!(clock.now() - start >= Duration::from_secs(60 * 60));
+    }
+}
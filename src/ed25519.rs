@@ -13,6 +13,7 @@ pub use ed25519_dalek::{
 };
 
 use ed25519_dalek::ExpandedSecretKey;
+use rand::{CryptoRng, RngCore};
 use std::ops::RangeInclusive;
 use xor_name::{XorName, XOR_NAME_LEN};
 
@@ -45,11 +46,13 @@ pub fn gen_name_with_age(age: u8) -> XorName {
 
 /// Construct a `Keypair` whose name is in the interval [start, end] (both endpoints inclusive).
 /// And the last byte equals to the targeted age.
-pub fn gen_keypair(range: &RangeInclusive<XorName>, age: u8) -> Keypair {
-    let mut rng = rand::thread_rng();
-
+pub fn gen_keypair<R: CryptoRng + RngCore>(
+    rng: &mut R,
+    range: &RangeInclusive<XorName>,
+    age: u8,
+) -> Keypair {
     loop {
-        let keypair = Keypair::generate(&mut rng);
+        let keypair = Keypair::generate(rng);
         let new_name = XorName::from(sn_data_types::PublicKey::Ed25519(keypair.public));
         if range.contains(&new_name) && age == new_name[XOR_NAME_LEN - 1] {
             return keypair;
@@ -12,7 +12,7 @@ use crate::{
 };
 use qp2p::Error as Qp2pError;
 use secured_linked_list::error::Error as SecuredLinkedListError;
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 use thiserror::Error;
 use xor_name::XorName;
 
@@ -54,6 +54,10 @@ pub enum Error {
     InvalidMessage,
     #[error("A signature share is invalid.")]
     InvalidSignatureShare,
+    #[error("A decryption share is invalid.")]
+    InvalidDecryptionShare,
+    #[error("The ciphertext is invalid.")]
+    InvalidCiphertext,
     #[error("The secret key share is missing.")]
     MissingSecretKeyShare,
     #[error("Failed to send a message to {0}, {1}")]
@@ -72,12 +76,83 @@ pub enum Error {
     ExtendSignedError(#[from] ExtendSignedChainError),
     #[error("invalid payload")]
     InvalidPayload,
+    /// No longer returned: joining reports `Error::Join(JoinError::JoinsDisallowed)` instead.
+    /// Kept so existing matches on it still compile.
     #[error("Routing is set to not allow taking any new node")]
     TryJoinLater,
+    #[error("Failed to join the network: {0}")]
+    Join(#[from] JoinError),
     #[error("No matching Section")]
     NoMatchingSection,
     #[error("No matching Elder")]
     NoMatchingElder,
+    /// No longer returned: joining reports `Error::Join(JoinError::NodeNotReachable)` instead.
+    /// Kept so existing matches on it still compile.
     #[error("Node cannot join the network since it is not externally reachable: {0}")]
     NodeNotReachable(SocketAddr),
+    #[error("Failed to access the persisted node state: {0}")]
+    StateStore(#[from] std::io::Error),
+    #[error("The persisted node state is invalid")]
+    InvalidPersistedState,
+    #[error("Timed out waiting for the section to agree on our departure")]
+    LeaveTimeout,
+    #[error("Timed out waiting for the section signature")]
+    SectionSignatureTimeout,
+    #[error("Timed out waiting for the decryption shares")]
+    DecryptionTimeout,
+    #[error("The decrypted data was sealed for another context")]
+    DecryptionContextMismatch,
+    #[error("Our elders no longer hold the key shares of section key {0:?}")]
+    SectionKeyTooOld(bls::PublicKey),
+    #[error("Invalid network parameters: {0}")]
+    InvalidNetworkParams(&'static str),
+    #[error("Serving the maximum number of end users already")]
+    TooManyEndUsers,
+    #[error("Unknown end user: {0}")]
+    UnknownEndUser(SocketAddr),
+    #[error("Transport error: {0}")]
+    Transport(#[from] TransportError),
+    #[error("Failed to access the message journal: {0}")]
+    MessageJournal(std::io::Error),
+    #[error("Failed to access the traffic log: {0}")]
+    TrafficLog(std::io::Error),
+    #[error("The traffic log is invalid")]
+    InvalidTrafficLog,
+}
+
+/// The reason joining the network failed.
+#[derive(Debug, Error)]
+pub enum JoinError {
+    /// The section doesn't accept new nodes at the moment. Try joining later.
+    #[error("Routing is set to not allow taking any new node")]
+    JoinsDisallowed,
+    /// The section couldn't reach this node at the given address.
+    #[error("Node cannot join the network since it is not externally reachable: {0}")]
+    NodeNotReachable(SocketAddr),
+    /// The section didn't accept our resource proof.
+    #[error("The resource proof was not accepted")]
+    ResourceProofFailed,
+    /// We kept being redirected between the same sections without getting closer to ours.
+    #[error("Redirected in a loop between sections")]
+    RedirectLoop,
+    /// The section didn't respond to any of our join requests.
+    #[error("No response after {0} join attempts")]
+    RetriesExhausted(usize),
+    /// Joining didn't complete within the join policy timeout.
+    #[error("Joining timed out after {0:?}")]
+    Timeout(Duration),
+}
+
+/// Error reported by a `Transport`.
+#[derive(Debug, Error)]
+pub enum TransportError {
+    /// The transport was closed locally.
+    #[error("Connection closed locally")]
+    Closed,
+    /// The peer couldn't be reached.
+    #[error("Peer unreachable")]
+    Unreachable,
+    /// Other error of the underlying transport.
+    #[error("{0}")]
+    Other(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
@@ -6,22 +6,41 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use crate::{
+    agreement::verify_signed,
+    messages::{tagged, SignableView, CUSTOM_AGREEMENT_TAG},
+};
 use bytes::Bytes;
 use ed25519_dalek::Keypair;
 use hex_fmt::HexFmt;
 pub use qp2p::{RecvStream, SendStream};
-use sn_messaging::{client::ClientMsg, node::Signed, DstLocation, EndUser, SrcLocation};
+use sn_messaging::{
+    client::ClientMsg,
+    node::{Proposal, Signed, Variant},
+    DstLocation, EndUser, SrcLocation,
+};
 use std::{
     collections::BTreeSet,
     fmt::{self, Debug, Formatter},
     net::SocketAddr,
     sync::Arc,
+    time::Duration,
 };
 use xor_name::{Prefix, XorName};
 
+/// Verifies that `signed` is the section signature over the `payload` of a
+/// `Event::CustomAgreement`. Check `signed.public_key` is a key of the section too.
+pub fn verify_custom_agreement(payload: &[u8], signed: &Signed) -> bool {
+    let signable = SignableView {
+        dst: &DstLocation::DirectAndUnrouted,
+        variant: &Variant::UserMessage(tagged(CUSTOM_AGREEMENT_TAG, payload)),
+    };
+    verify_signed(signed, &signable)
+}
+
 /// A flag in EldersChanged event, indicating
 /// whether the node got promoted, demoted or did not change.
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum NodeElderChange {
     /// The node was promoted to Elder.
     Promoted,
@@ -54,6 +73,7 @@ pub struct Elders {
 /// `Request` and `Response` events from section locations are only raised once the majority has
 /// been reached, i.e. enough members of the section have sent the same message.
 #[allow(clippy::large_enum_variant)]
+#[derive(Clone)]
 pub enum Event {
     /// Received a message.
     MessageReceived {
@@ -134,6 +154,112 @@ pub enum Event {
         /// Removed Adults in our section.
         removed: BTreeSet<XorName>,
     },
+    /// A DKG session to generate the key of the next set of elders started, with this node being
+    /// one of the candidates.
+    DkgStarted {
+        /// Names of the elder candidates participating in the session.
+        candidates: BTreeSet<XorName>,
+        /// Generation of the session, i.e. the length of our section chain when it started.
+        generation: u64,
+    },
+    /// A DKG session this node participates in failed. The section retries with different
+    /// candidates if some of them didn't participate, or with the same ones otherwise, as allowed
+    /// by the `DkgPolicy`.
+    DkgFailed {
+        /// Candidates that failed to participate. Empty if the outcome was corrupted instead.
+        non_participants: BTreeSet<XorName>,
+        /// Generation of the session.
+        generation: u64,
+        /// How long the session ran before failing.
+        elapsed: Duration,
+    },
+    /// Our section gave up on generating a new section key after the maximum number of failed DKG
+    /// sessions of the `DkgPolicy`. No more sessions are started until the section key changes or
+    /// the cool-down of the policy elapses. Raised on every elder.
+    DkgAttemptsExhausted {
+        /// Generation of the failed sessions.
+        generation: u64,
+        /// Number of failed sessions.
+        attempts: usize,
+        /// Candidates excluded from the sessions for failing to participate.
+        excluded: BTreeSet<XorName>,
+    },
+    /// A DKG session this node participates in generated a new section key. The key takes effect
+    /// once the section agrees on it, which is notified with `EldersChanged` or `SectionSplit`.
+    DkgCompleted {
+        /// The generated section key.
+        key: bls::PublicKey,
+        /// Generation of the session.
+        generation: u64,
+        /// How long the session took.
+        elapsed: Duration,
+    },
+    /// Our section agreed on an application-defined payload proposed with
+    /// `Routing::propose_custom`. Raised on every elder.
+    CustomAgreement {
+        /// The payload agreed on.
+        payload: Bytes,
+        /// The section signature over the payload. Check it with `verify_custom_agreement`.
+        signed: Signed,
+    },
+    /// The section didn't agree on a proposal of ours within the deadline of the
+    /// `ProposalRetryPolicy`, despite re-sending it to the elders that didn't respond.
+    ProposalStalled {
+        /// The proposal given up on.
+        proposal: Proposal,
+        /// Elders we didn't receive their own share of the proposal from.
+        non_responding: BTreeSet<XorName>,
+        /// How long ago we made the proposal.
+        elapsed: Duration,
+    },
+    /// The subscriber this is delivered to fell behind and the given number of events were
+    /// discarded. Only raised for subscriptions with `OverflowPolicy::Lag`.
+    Lagged {
+        /// Number of discarded events.
+        missed: usize,
+    },
+}
+
+/// Kinds of events, to subscribe to only some of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EventKind {
+    /// `MemberJoined`, `MemberLeft` and `AdultsChanged`.
+    Membership,
+    /// `EldersChanged` and `SectionSplit`.
+    Elders,
+    /// `MessageReceived`, `ClientMsgReceived`, `ClientLost` and `CustomAgreement`.
+    Messages,
+    /// `RelocationStarted` and `Relocated`.
+    Relocation,
+    /// `DkgStarted`, `DkgFailed`, `DkgAttemptsExhausted` and `DkgCompleted`.
+    Dkg,
+    /// `RestartRequired` and `Lagged`.
+    Node,
+    /// `ProposalStalled`.
+    Diagnostics,
+}
+
+impl Event {
+    /// Returns the kind of this event.
+    pub fn kind(&self) -> EventKind {
+        match self {
+            Self::MemberJoined { .. } | Self::MemberLeft { .. } | Self::AdultsChanged { .. } => {
+                EventKind::Membership
+            }
+            Self::EldersChanged { .. } | Self::SectionSplit { .. } => EventKind::Elders,
+            Self::MessageReceived { .. }
+            | Self::ClientMsgReceived { .. }
+            | Self::ClientLost(_)
+            | Self::CustomAgreement { .. } => EventKind::Messages,
+            Self::RelocationStarted { .. } | Self::Relocated { .. } => EventKind::Relocation,
+            Self::DkgStarted { .. }
+            | Self::DkgFailed { .. }
+            | Self::DkgAttemptsExhausted { .. }
+            | Self::DkgCompleted { .. } => EventKind::Dkg,
+            Self::RestartRequired | Self::Lagged { .. } => EventKind::Node,
+            Self::ProposalStalled { .. } => EventKind::Diagnostics,
+        }
+    }
 }
 
 impl Debug for Event {
@@ -210,6 +336,63 @@ impl Debug for Event {
                 .field("added", added)
                 .field("removed", removed)
                 .finish(),
+            Self::DkgStarted {
+                candidates,
+                generation,
+            } => formatter
+                .debug_struct("DkgStarted")
+                .field("candidates", candidates)
+                .field("generation", generation)
+                .finish(),
+            Self::DkgFailed {
+                non_participants,
+                generation,
+                elapsed,
+            } => formatter
+                .debug_struct("DkgFailed")
+                .field("non_participants", non_participants)
+                .field("generation", generation)
+                .field("elapsed", elapsed)
+                .finish(),
+            Self::DkgAttemptsExhausted {
+                generation,
+                attempts,
+                excluded,
+            } => formatter
+                .debug_struct("DkgAttemptsExhausted")
+                .field("generation", generation)
+                .field("attempts", attempts)
+                .field("excluded", excluded)
+                .finish(),
+            Self::DkgCompleted {
+                key,
+                generation,
+                elapsed,
+            } => formatter
+                .debug_struct("DkgCompleted")
+                .field("key", key)
+                .field("generation", generation)
+                .field("elapsed", elapsed)
+                .finish(),
+            Self::CustomAgreement { payload, signed } => formatter
+                .debug_struct("CustomAgreement")
+                .field("payload", &format_args!("{:10}", HexFmt(payload)))
+                .field("signed", signed)
+                .finish(),
+            Self::ProposalStalled {
+                proposal,
+                non_responding,
+                elapsed,
+            } => formatter
+                .debug_struct("ProposalStalled")
+                .field("proposal", proposal)
+                .field("non_responding", non_responding)
+                .field("elapsed", elapsed)
+                .finish(),
+            Self::Lagged { missed } => formatter
+                .debug_struct("Lagged")
+                .field("missed", missed)
+                .finish(),
         }
     }
 }
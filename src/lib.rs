@@ -69,11 +69,26 @@ extern crate tracing;
 // Public API
 // ############################################################################
 pub use self::{
+    agreement::DkgSessionSnapshot,
     cache::Cache,
-    error::{Error, Result},
-    event::{Event, NodeElderChange, SendStream},
+    clock::{Clock, SharedRng, TokioClock},
+    error::{Error, JoinError, Result, TransportError},
+    event::{verify_custom_agreement, Event, EventKind, NodeElderChange, SendStream},
+    network::{KnownSection, NetworkStats, NetworkStatsSample},
+    network_params::NetworkParams,
     peer::PeerUtils,
-    routing::{Config, EventStream, Routing},
+    routing::{
+        replay_traffic_log, seal_for_section, stitch_message_journals, verify_section_signature,
+        BindConfig, BoundTransport, Config, DebugSnapshot, DecryptionPolicy, DecryptionRequest,
+        DkgPolicy, DurationSummary, EndUserInfo, EndUserPolicy, EventStream, Health, Hop,
+        HopRecord, JoinPolicy, JoinProgress, LinkConfig, LocalStateKey, MemberSnapshot,
+        MemoryNetwork, MetricsSnapshot, OverflowPolicy, PendingAggregationSnapshot,
+        ProposalRetryPolicy, QuicTransportBuilder, RelocateStateSnapshot, Replay, ReplayedMessage,
+        Routing, SendOutcomes, StateKeyProvider, Subscription, Transport, TransportBuilder,
+        TransportEvent, TransportEvents, DECRYPTION_ATTEMPTS, DECRYPTION_TIMEOUT,
+        DEFAULT_END_USER_IDLE_TIMEOUT, DEFAULT_SUBSCRIPTION_CAPACITY, LEAVE_TIMEOUT,
+        SECTION_SIGNATURE_ATTEMPTS, SECTION_SIGNATURE_TIMEOUT, STALE_DKG_SESSION_AGE,
+    },
     section::{
         SectionAuthorityProviderUtils, FIRST_SECTION_MAX_AGE, FIRST_SECTION_MIN_AGE, MIN_ADULT_AGE,
         MIN_AGE,
@@ -89,12 +104,14 @@ pub use xor_name::{Prefix, XorName, XOR_NAME_LEN}; // TODO remove pub on API upd
 
 mod agreement;
 mod cache;
+mod clock;
 mod ed25519;
 mod error;
 mod event;
 mod message_filter;
 mod messages;
 mod network;
+mod network_params;
 mod node;
 mod peer;
 mod relocation;
@@ -105,9 +122,11 @@ mod section;
 /// More nodes might be added if requested by the upper layers.
 /// This number also detemines when split happens - if both post-split sections would have at least
 /// this number of nodes.
+/// This is the default of `NetworkParams::recommended_section_size`.
 pub const RECOMMENDED_SECTION_SIZE: usize = 2 * ELDER_SIZE;
 
 /// Number of elders per section.
+/// This is the default of `NetworkParams::elder_size`.
 pub const ELDER_SIZE: usize = 7;
 
 /// SuperMajority of a given group (i.e. > 2/3)
@@ -6,9 +6,12 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::cache::Cache;
+use crate::{
+    cache::Cache,
+    clock::{self, Clock},
+};
 use sn_messaging::{node::RoutingMsg, DstLocation, MessageId};
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 use xor_name::XorName;
 
 const INCOMING_EXPIRY_DURATION: Duration = Duration::from_secs(20 * 60);
@@ -40,16 +43,18 @@ pub(crate) struct MessageFilter {
 }
 
 impl MessageFilter {
-    pub fn new() -> Self {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
         Self {
             incoming: Cache::with_expiry_duration_and_capacity(
                 INCOMING_EXPIRY_DURATION,
                 MAX_ENTRIES,
-            ),
+            )
+            .with_clock(clock.clone()),
             outgoing: Cache::with_expiry_duration_and_capacity(
                 OUTGOING_EXPIRY_DURATION,
                 MAX_ENTRIES,
-            ),
+            )
+            .with_clock(clock),
         }
     }
 
@@ -95,6 +100,6 @@ impl MessageFilter {
 
 impl Default for MessageFilter {
     fn default() -> Self {
-        Self::new()
+        Self::new(clock::default_clock())
     }
 }
@@ -0,0 +1,79 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+// Domain separation of the bytes our section signs on behalf of others, and of our messages that
+// `Variant`, which is defined by sn_messaging, has no variant for. Those messages travel as
+// `Variant::UserMessage`s whose content starts with one of these tags. Content starting with
+// `RESERVED_PREFIX` is never sent on behalf of the user, so neither the messages nor the
+// signatures can be confused with the user's or with each other.
+//
+// The tags carry the version of the protocol. It is bumped whenever the format behind a tag
+// changes incompatibly. Messages with content under `RESERVED_PREFIX` but of another version are
+// dropped rather than surfaced as user messages.
+
+// Prefix of all the tags.
+pub(crate) const RESERVED_PREFIX: &[u8] = b"sn_routing/";
+// Prefix of the tags of the protocol version we speak.
+pub(crate) const PROTOCOL_PREFIX: &[u8] = b"sn_routing/v1/";
+// Messages between the nodes of a section, see `InternalMsg`.
+pub(crate) const INTERNAL_MSG_TAG: &[u8] = b"sn_routing/v1/internal-msg";
+// Data our section signs on behalf of a member.
+pub(crate) const USER_SIGNATURE_TAG: &[u8] = b"sn_routing/v1/user-sig";
+// Data sealed for our section to decrypt, see `seal_for_section`.
+pub(crate) const SEALED_DATA_TAG: &[u8] = b"sn_routing/v1/sealed";
+// Payloads our section agrees on, see `Routing::propose_custom`.
+pub(crate) const CUSTOM_AGREEMENT_TAG: &[u8] = b"sn_routing/v1/custom-agreement";
+
+// Returns `bytes` prefixed with `tag`.
+pub(crate) fn tagged(tag: &[u8], bytes: &[u8]) -> Vec<u8> {
+    let mut tagged = Vec::with_capacity(tag.len() + bytes.len());
+    tagged.extend_from_slice(tag);
+    tagged.extend_from_slice(bytes);
+    tagged
+}
+
+// Returns `bytes` without `tag`, if they start with it.
+pub(crate) fn strip_tag<'a>(tag: &[u8], bytes: &'a [u8]) -> Option<&'a [u8]> {
+    if bytes.starts_with(tag) {
+        Some(&bytes[tag.len()..])
+    } else {
+        None
+    }
+}
+
+// Returns whether `content` starts with a tag reserved for us, which the user can't send.
+pub(crate) fn is_reserved(content: &[u8]) -> bool {
+    content.starts_with(RESERVED_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags() {
+        for tag in &[
+            INTERNAL_MSG_TAG,
+            USER_SIGNATURE_TAG,
+            SEALED_DATA_TAG,
+            CUSTOM_AGREEMENT_TAG,
+        ] {
+            assert!(tag.starts_with(PROTOCOL_PREFIX));
+
+            let bytes = tagged(tag, b"payload");
+            assert!(is_reserved(&bytes));
+            assert_eq!(strip_tag(tag, &bytes), Some(&b"payload"[..]));
+        }
+
+        assert_eq!(
+            strip_tag(INTERNAL_MSG_TAG, &tagged(USER_SIGNATURE_TAG, b"payload")),
+            None
+        );
+        assert!(!is_reserved(b"payload"));
+    }
+}
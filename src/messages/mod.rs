@@ -6,9 +6,15 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+mod domain;
 mod plain_message;
 mod src_authority;
 
+pub(crate) use self::domain::{
+    is_reserved, strip_tag, tagged, CUSTOM_AGREEMENT_TAG, INTERNAL_MSG_TAG, PROTOCOL_PREFIX,
+    SEALED_DATA_TAG, USER_SIGNATURE_TAG,
+};
+
 pub use self::{plain_message::PlainMessageUtils, src_authority::SrcAuthorityUtils};
 use crate::{
     agreement::ProvenUtils,
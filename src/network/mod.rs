@@ -10,7 +10,8 @@
 // mod prefix_map;
 mod stats;
 
-use self::stats::NetworkStats;
+pub(crate) use self::stats::NetworkStatsHistory;
+pub use self::stats::{KnownSection, NetworkStats, NetworkStatsSample};
 use crate::{
     agreement::{verify_signed, ProvenUtils, Signed},
     peer::PeerUtils,
@@ -78,8 +79,9 @@ pub trait NetworkUtils {
     /// excluding self section.
     fn section_by_name(&self, name: &XorName) -> Result<SectionAuthorityProvider>;
 
-    /// Returns network statistics.
-    fn network_stats(&self, our: &SectionAuthorityProvider) -> NetworkStats;
+    /// Returns network statistics, given our section and its number of members. The history is
+    /// left empty.
+    fn network_stats(&self, our: &SectionAuthorityProvider, our_members: usize) -> NetworkStats;
 
     fn network_elder_counts(&self, our: &SectionAuthorityProvider) -> (u64, u64, bool);
 }
@@ -210,14 +212,47 @@ impl NetworkUtils for Network {
             .map(|value| value.section_auth.value.clone())
     }
 
-    /// Returns network statistics.
-    fn network_stats(&self, our: &SectionAuthorityProvider) -> NetworkStats {
-        let (known_elders, total_elders, total_elders_exact) = self.network_elder_counts(our);
+    /// Returns network statistics, given our section and its number of members. The history is
+    /// left empty.
+    fn network_stats(&self, our: &SectionAuthorityProvider, our_members: usize) -> NetworkStats {
+        let (known_elders, estimated_elders, exact) = self.network_elder_counts(our);
+
+        let known_sections: Vec<_> = iter::once(our)
+            .chain(self.all().filter(|info| info.prefix != our.prefix))
+            .map(|info| KnownSection {
+                prefix: info.prefix,
+                depth: info.prefix.bit_count(),
+                elders: info.elder_count(),
+            })
+            .collect();
+
+        // Fraction of the network the known sections are responsible for. Can exceed 1 while
+        // we still know sections that have since split.
+        let confidence: f64 = known_sections
+            .iter()
+            .map(|section| 1.0 / (section.depth as f64).exp2())
+            .sum();
+        let confidence = if exact { 1.0 } else { confidence.min(1.0) };
+
+        let estimated_sections = if exact {
+            known_sections.len() as u64
+        } else {
+            (known_sections.len() as f64 / confidence).ceil() as u64
+        };
+
+        // Names are uniformly distributed, so every section holds about as many nodes per part of
+        // the network it is responsible for as ours does.
+        let estimated_nodes = (our_members as f64 * (our.prefix.bit_count() as f64).exp2()) as u64;
 
         NetworkStats {
+            known_sections,
             known_elders,
-            total_elders,
-            total_elders_exact,
+            estimated_sections,
+            estimated_elders,
+            estimated_nodes,
+            exact,
+            confidence,
+            history: vec![],
         }
     }
 
@@ -293,6 +328,44 @@ mod tests {
         assert_eq!(map.closest(&n11).map(|i| &i.prefix), Some(&p10));
     }
 
+    #[test]
+    fn network_stats() {
+        let sk = bls::SecretKey::random();
+        let chain = SecuredLinkedList::new(sk.public_key());
+
+        let p00: Prefix = "00".parse().unwrap();
+        let p01: Prefix = "01".parse().unwrap();
+        let p1: Prefix = "1".parse().unwrap();
+
+        let (our, _, _) = section::test_utils::gen_section_authority_provider(p00, 5);
+
+        // We know about (00) and (01) only, which is half of the network.
+        let mut map = Network::new();
+        let _ = map.update_section(gen_proven_section_auth(&sk, p01), None, &chain);
+
+        let stats = map.network_stats(&our, 8);
+        assert!(!stats.exact);
+        assert_eq!(stats.known_sections.len(), 2);
+        assert!(stats
+            .known_sections
+            .iter()
+            .all(|section| section.depth == 2));
+        assert_eq!(stats.known_elders, 10);
+        assert_eq!(stats.estimated_elders, 20);
+        assert_eq!(stats.estimated_sections, 4);
+        assert_eq!(stats.estimated_nodes, 32);
+        assert!((stats.confidence - 0.5).abs() < f64::EPSILON);
+
+        // Knowing (1) as well covers the whole network.
+        let _ = map.update_section(gen_proven_section_auth(&sk, p1), None, &chain);
+
+        let stats = map.network_stats(&our, 8);
+        assert!(stats.exact);
+        assert_eq!(stats.known_elders, 15);
+        assert_eq!(stats.estimated_sections, 3);
+        assert!((stats.confidence - 1.0).abs() < f64::EPSILON);
+    }
+
     fn gen_proven_section_auth(
         sk: &bls::SecretKey,
         prefix: Prefix,
@@ -6,21 +6,199 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+use xor_name::Prefix;
+
+// Maximum number of samples kept in the history of the network statistics.
+const MAX_HISTORY_LEN: usize = 100;
+
+/// Statistics about the network, as far as this node knows it. See `Routing::network_stats`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct NetworkStats {
-    pub(super) known_elders: u64,
-    pub(super) total_elders: u64,
-    pub(super) total_elders_exact: bool,
+    /// The sections we know about, including ours.
+    pub known_sections: Vec<KnownSection>,
+    /// Number of elders of the known sections.
+    pub known_elders: u64,
+    /// Estimated number of sections in the network.
+    pub estimated_sections: u64,
+    /// Estimated number of elders in the network.
+    pub estimated_elders: u64,
+    /// Estimated number of nodes in the network, extrapolated from the number of members of our
+    /// section and the part of the network it is responsible for.
+    pub estimated_nodes: u64,
+    /// Whether we know all the sections, making `estimated_sections` and `estimated_elders`
+    /// exact.
+    pub exact: bool,
+    /// Fraction of the network covered by the known sections, between 0 and 1. The closer to 1,
+    /// the more reliable the estimates are.
+    pub confidence: f64,
+    /// How the estimates changed over time, oldest first. Only changes are recorded.
+    pub history: Vec<NetworkStatsSample>,
 }
 
 impl NetworkStats {
-    pub fn print(&self) {
-        if self.total_elders_exact {
-            info!("*** Exact total network elders: {} ***", self.known_elders)
+    pub(crate) fn print(&self) {
+        if self.exact {
+            info!(
+                "*** Exact total network elders: {}, sections: {}, estimated nodes: {} ***",
+                self.known_elders, self.estimated_sections, self.estimated_nodes
+            )
         } else {
             info!(
-                "*** Known network elders: {}, Estimated total network elders: {} ***",
-                self.known_elders, self.total_elders
+                "*** Known network elders: {}, Estimated total network elders: {}, sections: {}, \
+                 nodes: {} (confidence: {:.2}) ***",
+                self.known_elders,
+                self.estimated_elders,
+                self.estimated_sections,
+                self.estimated_nodes,
+                self.confidence
             )
         }
     }
 }
+
+/// A section known to this node.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct KnownSection {
+    /// Prefix of the section.
+    pub prefix: Prefix,
+    /// Length of the prefix. Each section at depth `d` is responsible for `1 / 2^d` of the
+    /// network.
+    pub depth: usize,
+    /// Number of elders of the section.
+    pub elders: usize,
+}
+
+/// The estimates of `NetworkStats` at some point in the past.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct NetworkStatsSample {
+    /// How long ago the estimates changed to these values.
+    pub age: Duration,
+    /// Number of sections we knew about.
+    pub known_sections: u64,
+    /// Estimated number of sections in the network.
+    pub estimated_sections: u64,
+    /// Estimated number of elders in the network.
+    pub estimated_elders: u64,
+    /// Estimated number of nodes in the network.
+    pub estimated_nodes: u64,
+    /// Fraction of the network covered by the known sections.
+    pub confidence: f64,
+}
+
+// Bounded history of the changes of the network statistics.
+#[derive(Clone, Default)]
+pub(crate) struct NetworkStatsHistory {
+    samples: VecDeque<(Instant, NetworkStatsSample)>,
+}
+
+impl NetworkStatsHistory {
+    // Records the estimates of `stats`, taken at `now`. Returns whether they changed since the
+    // last time.
+    pub fn record(&mut self, stats: &NetworkStats, now: Instant) -> bool {
+        let sample = NetworkStatsSample {
+            age: Duration::default(),
+            known_sections: stats.known_sections.len() as u64,
+            estimated_sections: stats.estimated_sections,
+            estimated_elders: stats.estimated_elders,
+            estimated_nodes: stats.estimated_nodes,
+            confidence: stats.confidence,
+        };
+
+        if self.samples.back().map(|(_, last)| last) == Some(&sample) {
+            return false;
+        }
+
+        if self.samples.len() >= MAX_HISTORY_LEN {
+            let _ = self.samples.pop_front();
+        }
+        self.samples.push_back((now, sample));
+
+        true
+    }
+
+    // Returns the recorded samples with their ages as of `now`, oldest first.
+    pub fn samples(&self, now: Instant) -> Vec<NetworkStatsSample> {
+        self.samples
+            .iter()
+            .map(|(recorded_at, sample)| NetworkStatsSample {
+                age: now.saturating_duration_since(*recorded_at),
+                ..*sample
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_records_changes_only() {
+        let mut history = NetworkStatsHistory::default();
+        let start = Instant::now();
+        let mut stats = stats(10);
+
+        assert!(history.record(&stats, start));
+        assert!(!history.record(&stats, start + Duration::from_secs(1)));
+
+        stats.estimated_nodes = 20;
+        assert!(history.record(&stats, start + Duration::from_secs(2)));
+
+        let samples = history.samples(start + Duration::from_secs(5));
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].estimated_nodes, 10);
+        assert_eq!(samples[0].age, Duration::from_secs(5));
+        assert_eq!(samples[1].estimated_nodes, 20);
+        assert_eq!(samples[1].age, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn history_is_bounded() {
+        let mut history = NetworkStatsHistory::default();
+        let now = Instant::now();
+
+        for nodes in 0..2 * MAX_HISTORY_LEN as u64 {
+            let _ = history.record(&stats(nodes), now);
+        }
+
+        let samples = history.samples(now);
+        assert_eq!(samples.len(), MAX_HISTORY_LEN);
+        assert_eq!(
+            samples.last().map(|sample| sample.estimated_nodes),
+            Some(2 * MAX_HISTORY_LEN as u64 - 1)
+        );
+    }
+
+    #[test]
+    fn serialize_with_history() -> serde_json::Result<()> {
+        let mut history = NetworkStatsHistory::default();
+        let now = Instant::now();
+        let mut stats = stats(10);
+        let _ = history.record(&stats, now);
+        stats.history = history.samples(now);
+
+        let value = serde_json::to_value(&stats)?;
+        assert_eq!(value["estimated_nodes"], 10);
+        assert_eq!(value["history"][0]["estimated_nodes"], 10);
+
+        Ok(())
+    }
+
+    fn stats(estimated_nodes: u64) -> NetworkStats {
+        NetworkStats {
+            known_sections: vec![],
+            known_elders: 5,
+            estimated_sections: 1,
+            estimated_elders: 5,
+            estimated_nodes,
+            exact: false,
+            confidence: 0.5,
+            history: vec![],
+        }
+    }
+}
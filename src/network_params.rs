@@ -0,0 +1,126 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    error::{Error, Result},
+    ELDER_SIZE, FIRST_SECTION_MAX_AGE, FIRST_SECTION_MIN_AGE, MIN_ADULT_AGE, MIN_AGE,
+    RECOMMENDED_SECTION_SIZE,
+};
+use serde::{Deserialize, Serialize};
+
+const RESOURCE_PROOF_DIFFICULTY: u8 = 2;
+const KEY_CACHE_SIZE: u8 = 5;
+
+/// Parameters of the network which all its nodes must agree on.
+///
+/// They are chosen by the genesis node, which signs them with the genesis key. The elders of the
+/// section a node asks to join send it the signed parameters, and the node adopts them in place of
+/// its own once it has checked them against the genesis key. The defaults match the
+/// `ELDER_SIZE`, `RECOMMENDED_SECTION_SIZE` and age constants.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkParams {
+    /// Number of elders per section.
+    pub elder_size: usize,
+    /// Recommended section size. Sections keep adding nodes until they reach this size and only
+    /// split when both post-split sections would have at least this number of nodes.
+    pub recommended_section_size: usize,
+    /// The age new nodes join with, once the network is past its first section.
+    pub min_adult_age: u8,
+    /// Lower bound of the age range nodes joining the first section start at.
+    pub first_section_min_age: u8,
+    /// Higher bound of the age range nodes joining the first section start at.
+    pub first_section_max_age: u8,
+    /// Difficulty of the resource proof joining nodes have to solve.
+    pub resource_proof_difficulty: u8,
+    /// Number of section key shares an elder keeps around.
+    pub key_cache_size: u8,
+}
+
+impl NetworkParams {
+    /// Checks the parameters are consistent with each other.
+    pub fn validate(&self) -> Result<()> {
+        if self.elder_size == 0 {
+            return Err(Error::InvalidNetworkParams("elder_size must be positive"));
+        }
+
+        if self.recommended_section_size < self.elder_size {
+            return Err(Error::InvalidNetworkParams(
+                "recommended_section_size must not be smaller than elder_size",
+            ));
+        }
+
+        if self.min_adult_age <= MIN_AGE {
+            return Err(Error::InvalidNetworkParams(
+                "min_adult_age must be greater than MIN_AGE",
+            ));
+        }
+
+        if self.first_section_min_age < self.min_adult_age
+            || self.first_section_max_age < self.first_section_min_age
+        {
+            return Err(Error::InvalidNetworkParams(
+                "first section age range must be above min_adult_age and not empty",
+            ));
+        }
+
+        if self.key_cache_size == 0 {
+            return Err(Error::InvalidNetworkParams(
+                "key_cache_size must be positive",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for NetworkParams {
+    fn default() -> Self {
+        Self {
+            elder_size: ELDER_SIZE,
+            recommended_section_size: RECOMMENDED_SECTION_SIZE,
+            min_adult_age: MIN_ADULT_AGE,
+            first_section_min_age: FIRST_SECTION_MIN_AGE,
+            first_section_max_age: FIRST_SECTION_MAX_AGE,
+            resource_proof_difficulty: RESOURCE_PROOF_DIFFICULTY,
+            key_cache_size: KEY_CACHE_SIZE,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_is_valid() {
+        assert!(NetworkParams::default().validate().is_ok());
+    }
+
+    #[test]
+    fn small_network_is_valid() {
+        let params = NetworkParams {
+            elder_size: 3,
+            recommended_section_size: 6,
+            ..Default::default()
+        };
+        assert!(params.validate().is_ok());
+    }
+
+    #[test]
+    fn section_smaller_than_elders_is_invalid() {
+        let params = NetworkParams {
+            elder_size: 7,
+            recommended_section_size: 5,
+            ..Default::default()
+        };
+        assert!(matches!(
+            params.validate(),
+            Err(Error::InvalidNetworkParams(_))
+        ));
+    }
+}
@@ -6,32 +6,39 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{comm::ConnectionEvent, Comm};
+use super::{
+    bootstrap_cache::BootstrapCache, comm::ConnectionEvent, internal_msg::InternalMsg, Comm,
+};
 use crate::{
+    agreement::ProvenUtils,
+    clock::{self, Clock, SharedRng},
     ed25519::{self},
-    error::{Error, Result},
+    error::{Error, JoinError, Result},
     messages::{RoutingMsgUtils, VerifyStatus},
     node::Node,
     peer::PeerUtils,
     relocation::{RelocatePayloadUtils, SignedRelocateDetailsUtils},
     routing::comm::SendStatus,
     section::{SectionAuthorityProviderUtils, SectionUtils},
-    FIRST_SECTION_MAX_AGE, FIRST_SECTION_MIN_AGE,
+    NetworkParams,
 };
 use futures::future;
-use rand::seq::IteratorRandom;
+use rand::{seq::IteratorRandom, Rng};
 use resource_proof::ResourceProof;
 use sn_data_types::PublicKey;
 use sn_messaging::{
     node::{
-        JoinRejectionReason, JoinRequest, JoinResponse, RelocatePayload, ResourceProofResponse,
-        RoutingMsg, Section, SignedRelocateDetails, Variant,
+        JoinRejectionReason, JoinRequest, JoinResponse, Proven, RelocatePayload,
+        ResourceProofResponse, RoutingMsg, Section, SignedRelocateDetails, Variant,
     },
     DestInfo, DstLocation, MessageType, WireMsg,
 };
 use std::{
+    cmp,
     collections::{HashSet, VecDeque},
     net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 use tokio::sync::mpsc;
 use tracing::Instrument;
@@ -39,23 +46,115 @@ use xor_name::{Prefix, XorName};
 
 const BACKLOG_CAPACITY: usize = 100;
 
+/// Limits on how long and how hard a node tries to join the network.
+#[derive(Clone, Debug)]
+pub struct JoinPolicy {
+    /// Maximum time the whole join may take, including connecting to the bootstrap contacts.
+    pub timeout: Duration,
+    /// Maximum number of times an unanswered join request is sent again.
+    pub max_retries: usize,
+    /// How long to wait for a response before sending the join request again. Doubles after each
+    /// unanswered attempt, up to `max_retry_backoff`.
+    pub retry_backoff: Duration,
+    /// Upper bound of the wait between two attempts.
+    pub max_retry_backoff: Duration,
+    /// Maximum number of times we follow a redirect to another section.
+    pub max_redirects: usize,
+}
+
+impl Default for JoinPolicy {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(300),
+            max_retries: 10,
+            retry_backoff: Duration::from_secs(10),
+            max_retry_backoff: Duration::from_secs(60),
+            max_redirects: 16,
+        }
+    }
+}
+
+/// Updates on the progress of joining the network.
+#[derive(Clone, Debug)]
+pub enum JoinProgress {
+    /// A join request was sent to the given peers.
+    RequestSent {
+        /// Key of the section we are trying to join, as far as we know.
+        section_key: bls::PublicKey,
+        /// Addresses the request was sent to.
+        recipients: Vec<SocketAddr>,
+        /// How many times the request was sent without getting a response, starting at zero.
+        attempt: usize,
+    },
+    /// The section asked us to try again with its up to date info.
+    Retrying {
+        /// Prefix of the section to join.
+        prefix: Prefix,
+    },
+    /// We were redirected to a section closer to our name.
+    Redirected {
+        /// Prefix of the section we were redirected to.
+        prefix: Prefix,
+    },
+    /// We are solving the resource proof the section challenged us with.
+    SolvingResourceProof {
+        /// Difficulty of the proof.
+        difficulty: u8,
+    },
+    /// No response came within `backoff`, so the request is going to be sent again.
+    NoResponse {
+        /// The attempt that got no response.
+        attempt: usize,
+        /// How long we waited for it.
+        backoff: Duration,
+    },
+    /// We were approved to join the section with the given prefix.
+    Approved {
+        /// Prefix of the section we joined.
+        prefix: Prefix,
+    },
+}
+
 /// Bootstrap into the network as new node.
 ///
-/// NOTE: It's not guaranteed this function ever returns. This can happen due to messages being
-/// lost in transit or other reasons. It's the responsibility of the caller to handle this case,
-/// for example by using a timeout.
+/// If `cache` knows a section close to our name, the join request goes to its elders first and
+/// only to `bootstrap_addr` if they don't respond.
+///
+/// Gives up once `join_policy` is exhausted.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn initial(
     node: Node,
+    network_params: NetworkParams,
+    join_policy: JoinPolicy,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
+    progress_tx: Option<mpsc::Sender<JoinProgress>>,
+    cache: Option<BootstrapCache>,
     comm: &Comm,
     incoming_conns: &mut mpsc::Receiver<ConnectionEvent>,
     bootstrap_addr: SocketAddr,
-) -> Result<(Node, Section, Vec<(RoutingMsg, SocketAddr, DestInfo)>)> {
+) -> Result<(
+    Node,
+    Section,
+    Proven<NetworkParams>,
+    Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+)> {
     let (send_tx, send_rx) = mpsc::channel(1);
     let recv_rx = MessageReceiver::Raw(incoming_conns);
 
     let span = trace_span!("bootstrap", name = %node.name());
 
-    let state = State::new(node, send_tx, recv_rx);
+    let mut state = State::new(
+        node,
+        network_params,
+        join_policy,
+        clock,
+        rng,
+        send_tx,
+        recv_rx,
+    );
+    state.progress_tx = progress_tx;
+    state.cache = cache;
 
     future::join(
         state.run(vec![bootstrap_addr], None, None),
@@ -68,21 +167,39 @@ pub(crate) async fn initial(
 
 /// Re-bootstrap as a relocated node.
 ///
-/// NOTE: It's not guaranteed this function ever returns. This can happen due to messages being
-/// lost in transit or other reasons. It's the responsibility of the caller to handle this case,
-/// for example by using a timeout.
+/// Gives up once `join_policy` is exhausted.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn relocate(
     node: Node,
+    network_params: NetworkParams,
+    proven_params: Option<Proven<NetworkParams>>,
+    join_policy: JoinPolicy,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
     comm: &Comm,
     recv_rx: mpsc::Receiver<(MessageType, SocketAddr)>,
     bootstrap_addrs: Vec<SocketAddr>,
     genesis_key: bls::PublicKey,
     relocate_details: SignedRelocateDetails,
-) -> Result<(Node, Section, Vec<(RoutingMsg, SocketAddr, DestInfo)>)> {
+) -> Result<(
+    Node,
+    Section,
+    Proven<NetworkParams>,
+    Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+)> {
     let (send_tx, send_rx) = mpsc::channel(1);
     let recv_rx = MessageReceiver::Deserialized(recv_rx);
 
-    let state = State::new(node, send_tx, recv_rx);
+    let mut state = State::new(
+        node,
+        network_params,
+        join_policy,
+        clock,
+        rng,
+        send_tx,
+        recv_rx,
+    );
+    state.proven_params = proven_params;
 
     future::join(
         state.run(bootstrap_addrs, Some(genesis_key), Some(relocate_details)),
@@ -98,30 +215,75 @@ struct State<'a> {
     // Receiver for incoming messages.
     recv_rx: MessageReceiver<'a>,
     node: Node,
+    network_params: NetworkParams,
+    // The network parameters the elders sent us, signed by the key they claim to be the genesis
+    // key. Checked against the genesis key of the approval before we accept it.
+    proven_params: Option<Proven<NetworkParams>>,
+    // Approval received before the network parameters it has to come with.
+    pending_approval: Option<(JoinResponse, SocketAddr, DestInfo)>,
+    // Prefix of the section we are asking to join, once we know it.
+    prefix: Option<Prefix>,
+    join_policy: JoinPolicy,
+    // Instant by which the join has to complete.
+    deadline: Instant,
+    // Sender for join progress updates, if anyone is interested in them.
+    progress_tx: Option<mpsc::Sender<JoinProgress>>,
+    // The last join request we sent and its recipients, to be sent again if unanswered.
+    last_request: Option<(JoinRequest, Vec<(XorName, SocketAddr)>)>,
+    // Sections known from a previous run, to try before the bootstrap contacts.
+    cache: Option<BootstrapCache>,
+    // Section key and recipients to fall back to if the cached section doesn't respond.
+    fallback: Option<(bls::PublicKey, Vec<(XorName, SocketAddr)>)>,
     // Backlog for unknown messages
     backlog: VecDeque<(RoutingMsg, SocketAddr, DestInfo)>,
+    // Source of the time of the deadlines.
+    clock: Arc<dyn Clock>,
+    // Source of the new names and the keys we pick.
+    rng: SharedRng,
 }
 
 impl<'a> State<'a> {
     fn new(
         node: Node,
+        network_params: NetworkParams,
+        join_policy: JoinPolicy,
+        clock: Arc<dyn Clock>,
+        rng: SharedRng,
         send_tx: mpsc::Sender<(MessageType, Vec<(XorName, SocketAddr)>)>,
         recv_rx: MessageReceiver<'a>,
     ) -> Self {
+        let deadline = clock.now() + join_policy.timeout;
         Self {
             send_tx,
             recv_rx,
             node,
+            network_params,
+            proven_params: None,
+            pending_approval: None,
+            prefix: None,
+            join_policy,
+            deadline,
+            progress_tx: None,
+            last_request: None,
+            cache: None,
+            fallback: None,
             backlog: VecDeque::with_capacity(BACKLOG_CAPACITY),
+            clock,
+            rng,
         }
     }
 
     async fn run(
-        self,
+        mut self,
         bootstrap_addrs: Vec<SocketAddr>,
         genesis_key: Option<bls::PublicKey>,
         relocate_details: Option<SignedRelocateDetails>,
-    ) -> Result<(Node, Section, Vec<(RoutingMsg, SocketAddr, DestInfo)>)> {
+    ) -> Result<(
+        Node,
+        Section,
+        Proven<NetworkParams>,
+        Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+    )> {
         let (dest_pk, dest_xorname) = match relocate_details {
             Some(ref details) => (
                 details.relocate_details()?.destination_key,
@@ -129,17 +291,43 @@ impl<'a> State<'a> {
             ),
             None => {
                 // Use our XorName as we do not know their name or section key yet.
-                (bls::SecretKey::random().public_key(), self.node.name())
+                let dest_sk: bls::SecretKey = self.rng.gen();
+                (dest_sk.public_key(), self.node.name())
             }
         };
 
-        let elders = bootstrap_addrs
+        let elders: Vec<_> = bootstrap_addrs
             .iter()
             .map(|addr| (dest_xorname, *addr))
             .collect();
 
-        self.join(dest_pk, elders, genesis_key, relocate_details)
-            .await
+        let closest_cached = self.cache.take().and_then(|cache| {
+            cache
+                .verified_sections(&dest_xorname)
+                .first()
+                .map(|section_auth| {
+                    (
+                        section_auth.section_key(),
+                        section_auth
+                            .peers()
+                            .map(|peer| (*peer.name(), *peer.addr()))
+                            .collect::<Vec<_>>(),
+                    )
+                })
+        });
+
+        match closest_cached {
+            Some((section_key, cached_elders)) if relocate_details.is_none() => {
+                info!("Trying the closest cached section first");
+                self.fallback = Some((dest_pk, elders));
+                self.join(section_key, cached_elders, genesis_key, relocate_details)
+                    .await
+            }
+            _ => {
+                self.join(dest_pk, elders, genesis_key, relocate_details)
+                    .await
+            }
+        }
     }
 
     // Change our name to fit the destination section and apply the new age.
@@ -157,7 +345,7 @@ impl<'a> State<'a> {
         );
 
         let age = relocate_details.relocate_details()?.age;
-        let new_keypair = ed25519::gen_keypair(&name_prefix.range_inclusive(), age);
+        let new_keypair = ed25519::gen_keypair(&mut self.rng, &name_prefix.range_inclusive(), age);
         let new_name = XorName::from(PublicKey::from(new_keypair.public));
         let relocate_payload =
             RelocatePayload::new(relocate_details, &new_name, &self.node.keypair);
@@ -180,7 +368,12 @@ impl<'a> State<'a> {
         mut recipients: Vec<(XorName, SocketAddr)>,
         genesis_key: Option<bls::PublicKey>,
         relocate_details: Option<SignedRelocateDetails>,
-    ) -> Result<(Node, Section, Vec<(RoutingMsg, SocketAddr, DestInfo)>)> {
+    ) -> Result<(
+        Node,
+        Section,
+        Proven<NetworkParams>,
+        Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+    )> {
         let join_request = JoinRequest {
             section_key,
             relocate_payload: None,
@@ -194,12 +387,83 @@ impl<'a> State<'a> {
             .await?;
 
         let mut relocate_payload = None;
+        let mut attempt = 0;
+        let mut backoff = self.join_policy.retry_backoff;
+        let mut redirects = 0;
+        // Whether we were redirected only to peers we already contacted since the last request.
+        let mut redirected_back = false;
+        // Whether we are waiting for the response to our resource proof.
+        let mut proof_sent = false;
+
         loop {
             used_recipient.extend(recipients.iter().map(|(_, addr)| addr));
 
-            let (response, sender, dest_info) = self
-                .receive_join_response(genesis_key.as_ref(), relocate_payload.as_ref())
-                .await?;
+            let now = self.clock.now();
+            if now >= self.deadline {
+                error!("Joining timed out");
+                return Err(JoinError::Timeout(self.join_policy.timeout).into());
+            }
+
+            let attempt_deadline = cmp::min(now + backoff, self.deadline);
+            let clock = self.clock.clone();
+            let response = clock::timeout(
+                &*clock,
+                attempt_deadline - now,
+                self.receive_join_response(genesis_key.as_ref(), relocate_payload.as_ref()),
+            )
+            .await;
+
+            let (response, sender, dest_info) = match response {
+                Some(response) => {
+                    // The section we contacted is alive, no need to fall back anymore.
+                    self.fallback = None;
+                    response?
+                }
+                None => {
+                    if self.clock.now() >= self.deadline {
+                        error!("Joining timed out");
+                        return Err(JoinError::Timeout(self.join_policy.timeout).into());
+                    }
+
+                    if let Some((fallback_key, fallback_recipients)) = self.fallback.take() {
+                        info!("No response from the cached section, trying the bootstrap contacts");
+                        section_key = fallback_key;
+                        recipients = fallback_recipients;
+                        let join_request = JoinRequest {
+                            section_key,
+                            relocate_payload: None,
+                            resource_proof_response: None,
+                        };
+                        self.send_join_requests(join_request, &recipients, section_key)
+                            .await?;
+                        attempt = 0;
+                        backoff = self.join_policy.retry_backoff;
+                        continue;
+                    }
+
+                    if redirected_back {
+                        error!("Joining redirected back to peers we already contacted");
+                        return Err(JoinError::RedirectLoop.into());
+                    }
+
+                    self.report_progress(JoinProgress::NoResponse { attempt, backoff });
+
+                    attempt += 1;
+                    if attempt > self.join_policy.max_retries {
+                        error!("No response to {} join attempts", attempt);
+                        return Err(if proof_sent {
+                            JoinError::ResourceProofFailed
+                        } else {
+                            JoinError::RetriesExhausted(attempt)
+                        }
+                        .into());
+                    }
+
+                    backoff = cmp::min(backoff * 2, self.join_policy.max_retry_backoff);
+                    self.resend_join_request(attempt).await?;
+                    continue;
+                }
+            };
 
             match response {
                 JoinResponse::Rejected(JoinRejectionReason::NodeNotReachable(addr)) => {
@@ -207,11 +471,17 @@ impl<'a> State<'a> {
                         "Node cannot join the network since it is not externally reachable: {}",
                         addr
                     );
-                    return Err(Error::NodeNotReachable(addr));
+                    return Err(JoinError::NodeNotReachable(addr).into());
                 }
                 JoinResponse::Rejected(JoinRejectionReason::JoinsDisallowed) => {
                     error!("Network is set to not taking any new joining node, try join later.");
-                    return Err(Error::TryJoinLater);
+                    return Err(JoinError::JoinsDisallowed.into());
+                }
+                JoinResponse::Approval { genesis_key, .. }
+                    if self.proven_params_of(&genesis_key).is_none() =>
+                {
+                    trace!("Waiting for the network parameters to come with the approval");
+                    self.pending_approval = Some((response, sender, dest_info));
                 }
                 JoinResponse::Approval {
                     section_auth,
@@ -219,9 +489,18 @@ impl<'a> State<'a> {
                     section_chain,
                     ..
                 } => {
+                    let network_params = self
+                        .proven_params_of(&genesis_key)
+                        .ok_or(Error::InvalidState)?;
+
+                    self.report_progress(JoinProgress::Approved {
+                        prefix: section_auth.value.prefix,
+                    });
+
                     return Ok((
                         self.node,
                         Section::new(genesis_key, section_chain, section_auth)?,
+                        network_params,
                         self.backlog.into_iter().collect(),
                     ));
                 }
@@ -237,22 +516,14 @@ impl<'a> State<'a> {
                         .collect();
 
                     let prefix = section_auth.prefix;
+                    self.prefix = Some(prefix);
 
-                    // For the first section, using age random among 6 to 100 to avoid
-                    // relocating too many nodes at the same time.
-                    if prefix.is_empty() && self.node.age() < FIRST_SECTION_MIN_AGE {
-                        let age: u8 = (FIRST_SECTION_MIN_AGE..FIRST_SECTION_MAX_AGE)
-                            .choose(&mut rand::thread_rng())
-                            .unwrap_or(FIRST_SECTION_MAX_AGE);
-
-                        let new_keypair =
-                            ed25519::gen_keypair(&Prefix::default().range_inclusive(), age);
-                        let new_name = ed25519::name(&new_keypair.public);
-
-                        info!("Setting Node name to {}", new_name);
-                        self.node = Node::new(new_keypair, self.node.addr);
+                    if relocate_details.is_none() {
+                        let _ = self.fit_age(&prefix);
                     }
 
+                    self.report_progress(JoinProgress::Retrying { prefix });
+
                     // if we are relocating, and we didn't generate
                     // the relocation payload yet, we do it now
                     if relocate_payload.is_none() {
@@ -277,6 +548,9 @@ impl<'a> State<'a> {
                         recipients = new_recipients;
                         self.send_join_requests(join_request, &recipients, section_key)
                             .await?;
+                        attempt = 0;
+                        backoff = self.join_policy.retry_backoff;
+                        proof_sent = false;
                     } else {
                         warn!(
                             "Newer Join response not for our prefix {:?} from {:?}",
@@ -299,6 +573,7 @@ impl<'a> State<'a> {
 
                     if new_recipients.is_empty() {
                         debug!("Joining redirected to the same set of peers we already contacted - ignoring response");
+                        redirected_back = true;
                         continue;
                     } else {
                         info!(
@@ -321,9 +596,21 @@ impl<'a> State<'a> {
                             resource_proof_response: None,
                         };
 
+                        redirects += 1;
+                        if redirects > self.join_policy.max_redirects {
+                            error!("Joining redirected more than {} times", redirects - 1);
+                            return Err(JoinError::RedirectLoop.into());
+                        }
+
+                        self.report_progress(JoinProgress::Redirected { prefix });
+
                         recipients = new_recipients;
                         self.send_join_requests(join_request, &recipients, section_key)
                             .await?;
+                        attempt = 0;
+                        backoff = self.join_policy.retry_backoff;
+                        redirected_back = false;
+                        proof_sent = false;
                     } else {
                         warn!(
                             "Newer Join response not for our prefix {:?} from {:?}",
@@ -337,6 +624,8 @@ impl<'a> State<'a> {
                     nonce,
                     nonce_signature,
                 } => {
+                    self.report_progress(JoinProgress::SolvingResourceProof { difficulty });
+
                     let rp = ResourceProof::new(data_size, difficulty);
                     let data = rp.create_proof_data(&nonce);
                     let mut prover = rp.create_prover(data.clone());
@@ -355,6 +644,9 @@ impl<'a> State<'a> {
                     let recipients = &[(dest_info.dest, sender)];
                     self.send_join_requests(join_request, recipients, section_key)
                         .await?;
+                    attempt = 0;
+                    backoff = self.join_policy.retry_backoff;
+                    proof_sent = true;
                 }
             }
         }
@@ -365,9 +657,38 @@ impl<'a> State<'a> {
         join_request: JoinRequest,
         recipients: &[(XorName, SocketAddr)],
         section_key: bls::PublicKey,
+    ) -> Result<()> {
+        self.send_join_requests_attempt(join_request, recipients, section_key, 0)
+            .await
+    }
+
+    // Sends the last join request again, to the same recipients.
+    async fn resend_join_request(&mut self, attempt: usize) -> Result<()> {
+        if let Some((join_request, recipients)) = self.last_request.take() {
+            let section_key = join_request.section_key;
+            self.send_join_requests_attempt(join_request, &recipients, section_key, attempt)
+                .await
+        } else {
+            Ok(())
+        }
+    }
+
+    async fn send_join_requests_attempt(
+        &mut self,
+        join_request: JoinRequest,
+        recipients: &[(XorName, SocketAddr)],
+        section_key: bls::PublicKey,
+        attempt: usize,
     ) -> Result<()> {
         info!("Sending {:?} to {:?}", join_request, recipients);
 
+        self.report_progress(JoinProgress::RequestSent {
+            section_key,
+            recipients: recipients.iter().map(|(_, addr)| *addr).collect(),
+            attempt,
+        });
+        self.last_request = Some((join_request.clone(), recipients.to_vec()));
+
         let variant = Variant::JoinRequest(Box::new(join_request));
         let message = RoutingMsg::single_src(
             &self.node,
@@ -413,6 +734,23 @@ impl<'a> State<'a> {
                     if let Variant::JoinResponse(resp) = &msg.variant {
                         let join_response = resp.clone();
                         (msg, dest_info, *join_response)
+                    } else if let Some(params) = self.network_params_of(&msg, sender) {
+                        if self.adopt_network_params(params, expected_genesis_key)
+                            && relocate_payload.is_none()
+                        {
+                            if let Some(prefix) = self.prefix {
+                                if self.fit_age(&prefix) {
+                                    // Ask again under our new name.
+                                    self.resend_join_request(0).await?;
+                                }
+                            }
+                        }
+
+                        if let Some(approval) = self.pending_approval.take() {
+                            return Ok(approval);
+                        }
+
+                        continue;
                     } else {
                         self.backlog_message(msg, sender, dest_info);
                         continue;
@@ -501,6 +839,113 @@ impl<'a> State<'a> {
         Err(Error::InvalidState)
     }
 
+    // Returns the network parameters the elders of the section we are joining sent us with
+    // `message`, if it is such a message from one of the peers we sent our last join request to.
+    fn network_params_of(
+        &self,
+        message: &RoutingMsg,
+        sender: SocketAddr,
+    ) -> Option<Proven<NetworkParams>> {
+        let content = match &message.variant {
+            Variant::UserMessage(content) if InternalMsg::is_internal(content) => content,
+            _ => return None,
+        };
+
+        let params = match InternalMsg::from_content(content) {
+            Ok(InternalMsg::NetworkParams(params)) => params,
+            _ => return None,
+        };
+
+        let (_, recipients) = self.last_request.as_ref()?;
+        if !recipients.iter().any(|(_, addr)| *addr == sender) {
+            trace!(
+                "Ignoring network parameters from {} - not contacted",
+                sender
+            );
+            return None;
+        }
+
+        if !self.verify_message(message, None) {
+            return None;
+        }
+
+        Some(params)
+    }
+
+    // Adopts the network parameters the elders sent us, provided they are signed by the genesis
+    // key, as far as we know it yet. Returns whether they were adopted.
+    fn adopt_network_params(
+        &mut self,
+        params: Proven<NetworkParams>,
+        genesis_key: Option<&bls::PublicKey>,
+    ) -> bool {
+        if !params.self_verify() {
+            error!("Invalid signature of the network parameters: {:?}", params);
+            return false;
+        }
+
+        if let Some(genesis_key) = genesis_key {
+            if params.signed.public_key != *genesis_key {
+                trace!("Network parameters not signed by the genesis key");
+                return false;
+            }
+        }
+
+        if let Err(error) = params.value.validate() {
+            error!("Ignoring network parameters {:?}: {}", params.value, error);
+            return false;
+        }
+
+        if params.value != self.network_params {
+            info!(
+                "Adopting the network parameters of the section we are joining: {:?}",
+                params.value
+            );
+            self.network_params = params.value;
+        }
+
+        self.proven_params = Some(params);
+        true
+    }
+
+    // Returns the network parameters we hold if they are signed by `genesis_key`.
+    fn proven_params_of(&self, genesis_key: &bls::PublicKey) -> Option<Proven<NetworkParams>> {
+        self.proven_params
+            .as_ref()
+            .filter(|params| params.signed.public_key == *genesis_key && params.self_verify())
+            .cloned()
+    }
+
+    // Picks a new name if our age isn't the one a new node joining the section with `prefix` has
+    // to have under the network parameters. Returns whether we did.
+    fn fit_age(&mut self, prefix: &Prefix) -> bool {
+        let params = &self.network_params;
+        let age = if prefix.is_empty() {
+            // For the first section, using age random among 6 to 100 to avoid
+            // relocating too many nodes at the same time.
+            if (params.first_section_min_age..=params.first_section_max_age)
+                .contains(&self.node.age())
+            {
+                return false;
+            }
+
+            (params.first_section_min_age..params.first_section_max_age)
+                .choose(&mut self.rng)
+                .unwrap_or(params.first_section_max_age)
+        } else if self.node.age() == params.min_adult_age {
+            return false;
+        } else {
+            params.min_adult_age
+        };
+
+        let new_keypair = ed25519::gen_keypair(&mut self.rng, &prefix.range_inclusive(), age);
+        let new_name = ed25519::name(&new_keypair.public);
+
+        info!("Setting Node name to {}", new_name);
+        self.node = Node::new(new_keypair, self.node.addr);
+        true
+    }
+
     fn verify_message(&self, message: &RoutingMsg, trusted_key: Option<&bls::PublicKey>) -> bool {
         match message.verify(trusted_key) {
             Ok(VerifyStatus::Full) => true,
@@ -517,6 +962,13 @@ impl<'a> State<'a> {
         }
     }
 
+    fn report_progress(&self, progress: JoinProgress) {
+        if let Some(progress_tx) = &self.progress_tx {
+            // Don't hold up joining because of a slow observer.
+            let _ = progress_tx.try_send(progress);
+        }
+    }
+
     fn backlog_message(&mut self, message: RoutingMsg, sender: SocketAddr, dest_info: DestInfo) {
         while self.backlog.len() >= BACKLOG_CAPACITY {
             let _ = self.backlog.pop_front();
@@ -585,7 +1037,7 @@ mod tests {
         messages::RoutingMsgUtils,
         section::test_utils::*,
         section::{MemberInfoUtils, SectionAuthorityProviderUtils},
-        ELDER_SIZE, MIN_ADULT_AGE, MIN_AGE,
+        ELDER_SIZE, MIN_AGE,
     };
     use anyhow::{anyhow, Error, Result};
     use assert_matches::assert_matches;
@@ -594,14 +1046,17 @@ mod tests {
         pin_mut,
     };
     use secured_linked_list::SecuredLinkedList;
-    use sn_messaging::{node::MemberInfo, SectionAuthorityProvider};
+    use sn_messaging::{
+        node::{MemberInfo, Peer},
+        SectionAuthorityProvider,
+    };
     use std::collections::BTreeMap;
     use tokio::task;
 
     #[tokio::test]
     async fn join_as_adult() -> Result<()> {
         let (send_tx, mut send_rx) = mpsc::channel(1);
-        let (recv_tx, recv_rx) = mpsc::channel(1);
+        let (recv_tx, recv_rx) = mpsc::channel(2);
         let recv_rx = MessageReceiver::Deserialized(recv_rx);
 
         let (section_auth, mut nodes, sk_set) =
@@ -616,11 +1071,23 @@ mod tests {
         // Otherwise during the bootstrap process, node will change its id and age.
         let node_age = MIN_AGE + 2;
         let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), node_age),
+            ed25519::gen_keypair(
+                &mut rand::thread_rng(),
+                &Prefix::default().range_inclusive(),
+                node_age,
+            ),
             gen_addr(),
         );
         let peer = node.peer();
-        let state = State::new(node, send_tx, recv_rx);
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
 
         // Create the bootstrap task, but don't run it yet.
         let bootstrap = async move {
@@ -699,36 +1166,179 @@ mod tests {
                 *peer.name(),
             )?;
 
+            // The approval is only accepted together with the network parameters.
+            send_response(
+                &recv_tx,
+                InternalMsg::NetworkParams(proven(sk, NetworkParams::default())?).to_variant()?,
+                &bootstrap_node,
+                section_auth.value.section_key(),
+                *peer.name(),
+            )?;
+
             Ok(())
         };
 
         // Drive both tasks to completion concurrently (but on the same thread).
-        let ((node, section, _backlog), _) = future::try_join(bootstrap, others).await?;
+        let ((node, section, network_params, _backlog), _) =
+            future::try_join(bootstrap, others).await?;
 
         assert_eq!(*section.authority_provider(), section_auth);
         assert_eq!(*section.chain().last_key(), pk);
         assert_eq!(node.age(), node_age);
+        assert_eq!(network_params.value, NetworkParams::default());
+        assert_eq!(network_params.signed.public_key, pk);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn join_receive_redirect_response() -> Result<()> {
+    async fn join_adopts_network_params() -> Result<()> {
         let (send_tx, mut send_rx) = mpsc::channel(1);
         let (recv_tx, recv_rx) = mpsc::channel(1);
         let recv_rx = MessageReceiver::Deserialized(recv_rx);
 
         let (section_auth, mut nodes, sk_set) =
-            gen_section_authority_provider(Prefix::default(), ELDER_SIZE);
+            gen_section_authority_provider(Prefix::default(), 3);
         let bootstrap_node = nodes.remove(0);
-        let pk_set = sk_set.public_keys();
+        let bootstrap_addr = bootstrap_node.addr;
+
+        let sk = sk_set.secret_key();
+        let pk = sk.public_key();
+
+        // The network was started with parameters other than ours.
+        let network_params = NetworkParams {
+            elder_size: 3,
+            recommended_section_size: 6,
+            first_section_min_age: MIN_AGE + 10,
+            first_section_max_age: MIN_AGE + 20,
+            ..Default::default()
+        };
 
         let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
+            ed25519::gen_keypair(
+                &mut rand::thread_rng(),
+                &Prefix::default().range_inclusive(),
+                MIN_AGE + 2,
+            ),
             gen_addr(),
         );
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
+
+        let bootstrap = async move {
+            state
+                .run(vec![bootstrap_addr], None, None)
+                .await
+                .map_err(Error::from)
+        };
+
+        let others = async {
+            let (message, _) = send_rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("JoinRequest was not received"))?;
+            let name = assert_matches!(message, MessageType::Routing { msg, .. } =>
+                msg.src.name());
+
+            send_response(
+                &recv_tx,
+                Variant::JoinResponse(Box::new(JoinResponse::Retry(section_auth.clone()))),
+                &bootstrap_node,
+                section_auth.section_key(),
+                name,
+            )?;
+
+            // Our age doesn't fit the first section under the default parameters, so we don't
+            // pass the elders' age check until we adopt theirs.
+            let (message, _) = send_rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("JoinRequest was not received"))?;
+            let name = assert_matches!(message, MessageType::Routing { msg, .. } =>
+                msg.src.name());
+
+            send_response(
+                &recv_tx,
+                InternalMsg::NetworkParams(proven(sk, network_params)?).to_variant()?,
+                &bootstrap_node,
+                pk,
+                name,
+            )?;
+
+            // We ask again under a new name, with the age the parameters require.
+            let (message, _) = send_rx
+                .recv()
+                .await
+                .ok_or_else(|| anyhow!("JoinRequest was not received"))?;
+            let peer = assert_matches!(message, MessageType::Routing { msg, .. } => {
+                assert_matches!(msg.variant, Variant::JoinRequest(_));
+                Peer::new(msg.src.name(), gen_addr())
+            });
+            assert!(
+                (network_params.first_section_min_age..=network_params.first_section_max_age)
+                    .contains(&peer.age())
+            );
+
+            let section_auth = proven(sk, section_auth.clone())?;
+            let member_info = proven(sk, MemberInfo::joined(peer))?;
+            send_response(
+                &recv_tx,
+                Variant::JoinResponse(Box::new(JoinResponse::Approval {
+                    genesis_key: pk,
+                    section_auth: section_auth.clone(),
+                    member_info,
+                    section_chain: SecuredLinkedList::new(pk),
+                })),
+                &bootstrap_node,
+                pk,
+                *peer.name(),
+            )?;
+
+            Ok(())
+        };
+
+        let ((node, section, adopted, _backlog), _) = future::try_join(bootstrap, others).await?;
+
+        assert_eq!(section.authority_provider().elder_count(), 3);
+        assert_eq!(adopted.value, network_params);
+        assert_eq!(adopted.signed.public_key, pk);
+        assert!(
+            (network_params.first_section_min_age..=network_params.first_section_max_age)
+                .contains(&node.age())
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn join_receive_redirect_response() -> Result<()> {
+        let (send_tx, mut send_rx) = mpsc::channel(1);
+        let (recv_tx, recv_rx) = mpsc::channel(1);
+        let recv_rx = MessageReceiver::Deserialized(recv_rx);
+
+        let (section_auth, mut nodes, sk_set) =
+            gen_section_authority_provider(Prefix::default(), ELDER_SIZE);
+        let bootstrap_node = nodes.remove(0);
+        let pk_set = sk_set.public_keys();
+
+        let node = gen_node();
         let name = node.name();
-        let state = State::new(node, send_tx, recv_rx);
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
 
         let bootstrap_task = state.run(vec![bootstrap_node.addr], None, None);
         let test_task = async move {
@@ -815,12 +1425,17 @@ mod tests {
         let bootstrap_node = nodes.remove(0);
         let pk_set = sk_set.public_keys();
 
-        let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-            gen_addr(),
-        );
+        let node = gen_node();
         let node_name = node.name();
-        let state = State::new(node, send_tx, recv_rx);
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
 
         let bootstrap_task = state.run(vec![bootstrap_node.addr], None, None);
         let test_task = async {
@@ -892,13 +1507,18 @@ mod tests {
             gen_section_authority_provider(Prefix::default(), ELDER_SIZE);
         let bootstrap_node = nodes.remove(0);
 
-        let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-            gen_addr(),
-        );
+        let node = gen_node();
 
         let node_name = node.name();
-        let state = State::new(node, send_tx, recv_rx);
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
 
         let bootstrap_task = state.run(vec![bootstrap_node.addr], None, None);
         let test_task = async {
@@ -925,7 +1545,7 @@ mod tests {
 
         let (join_result, test_result) = future::join(bootstrap_task, test_task).await;
 
-        if let Err(RoutingError::TryJoinLater) = join_result {
+        if let Err(RoutingError::Join(JoinError::JoinsDisallowed)) = join_result {
         } else {
             return Err(anyhow!("Not getting an execpted network rejection."));
         }
@@ -934,20 +1554,75 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn join_invalid_retry_prefix_response() -> Result<()> {
+    async fn join_without_response_gives_up() -> Result<()> {
         let (send_tx, mut send_rx) = mpsc::channel(1);
-        let (recv_tx, recv_rx) = mpsc::channel(1);
+        let (_recv_tx, recv_rx) = mpsc::channel(1);
         let recv_rx = MessageReceiver::Deserialized(recv_rx);
+        let (progress_tx, mut progress_rx) = mpsc::channel(10);
 
-        let bootstrap_node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-            gen_addr(),
+        let bootstrap_node = gen_node();
+        let node = gen_node();
+
+        let join_policy = JoinPolicy {
+            max_retries: 2,
+            retry_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut state = State::new(
+            node,
+            NetworkParams::default(),
+            join_policy,
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
         );
+        state.progress_tx = Some(progress_tx);
 
-        let node = Node::new(
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-            gen_addr(),
+        let bootstrap_task = state.run(vec![bootstrap_node.addr], None, None);
+        let test_task = async {
+            // The initial request plus two retries.
+            for _ in 0..3 {
+                let (message, _) = send_rx
+                    .recv()
+                    .await
+                    .ok_or_else(|| anyhow!("JoinRequest was not received"))?;
+                assert_matches!(message, MessageType::Routing { msg, .. } =>
+                                assert_matches!(msg.variant, Variant::JoinRequest{..}));
+            }
+
+            Ok(())
+        };
+
+        let (join_result, test_result) = future::join(bootstrap_task, test_task).await;
+        test_result?;
+
+        assert_matches!(
+            join_result,
+            Err(RoutingError::Join(JoinError::RetriesExhausted(3)))
         );
+
+        // The state is dropped by now, closing the progress channel.
+        let mut attempts = vec![];
+        while let Some(progress) = progress_rx.recv().await {
+            if let JoinProgress::RequestSent { attempt, .. } = progress {
+                attempts.push(attempt);
+            }
+        }
+        assert_eq!(attempts, vec![0, 1, 2]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn join_invalid_retry_prefix_response() -> Result<()> {
+        let (send_tx, mut send_rx) = mpsc::channel(1);
+        let (recv_tx, recv_rx) = mpsc::channel(1);
+        let recv_rx = MessageReceiver::Deserialized(recv_rx);
+
+        let bootstrap_node = gen_node();
+
+        let node = gen_node();
         let node_name = node.name();
 
         let (good_prefix, bad_prefix) = {
@@ -961,7 +1636,15 @@ mod tests {
             }
         };
 
-        let state = State::new(node, send_tx, recv_rx);
+        let state = State::new(
+            node,
+            NetworkParams::default(),
+            JoinPolicy::default(),
+            clock::default_clock(),
+            SharedRng::from_entropy(),
+            send_tx,
+            recv_rx,
+        );
 
         let section_key = bls::SecretKey::random().public_key();
         let elders = (0..ELDER_SIZE)
@@ -0,0 +1,150 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    network::OtherSectionUtils,
+    peer::PeerUtils,
+    section::{SectionAuthorityProviderUtils, SectionUtils},
+};
+use secured_linked_list::SecuredLinkedList;
+use serde::{Deserialize, Serialize};
+use sn_messaging::{
+    node::{Network, OtherSection, Section},
+    SectionAuthorityProvider,
+};
+use std::{iter, net::SocketAddr};
+use xor_name::XorName;
+
+/// The sections, and their elders, a node knew about the last time it was running. Used to join
+/// the network again without depending on the hard-coded contacts alone.
+#[derive(Clone, Serialize, Deserialize)]
+pub(crate) struct BootstrapCache {
+    // Chain of the section we were a member of. Proves the cached sections.
+    chain: SecuredLinkedList,
+    // Our section as well as the other sections we knew about.
+    sections: Vec<OtherSection>,
+}
+
+impl BootstrapCache {
+    pub fn new(section: &Section, network: &Network) -> Self {
+        let ours = OtherSection {
+            section_auth: section.proven_authority_provider().clone(),
+            key_signed: None,
+        };
+
+        Self {
+            chain: section.chain().clone(),
+            sections: iter::once(ours)
+                .chain(network.sections.iter().cloned())
+                .collect(),
+        }
+    }
+
+    /// Returns the cached sections which are proven by the cached chain, closest to `name` first.
+    /// A cache whose chain doesn't verify yields nothing.
+    pub fn verified_sections(&self, name: &XorName) -> Vec<&SectionAuthorityProvider> {
+        if !self.chain.self_verify() {
+            warn!("Discarding bootstrap cache with invalid section chain");
+            return vec![];
+        }
+
+        let mut sections: Vec<_> = self
+            .sections
+            .iter()
+            .filter(|section| {
+                let verified = section.verify(&self.chain);
+                if !verified {
+                    trace!(
+                        "Ignoring unproven cached section {:?}",
+                        section.section_auth.value.prefix
+                    );
+                }
+                verified
+            })
+            .map(|section| &section.section_auth.value)
+            .collect();
+        sections.sort_by(|lhs, rhs| lhs.prefix.cmp_distance(&rhs.prefix, name));
+        sections
+    }
+
+    /// Returns the addresses of the elders of all verified cached sections.
+    pub fn contacts(&self, name: &XorName) -> Vec<SocketAddr> {
+        self.verified_sections(name)
+            .into_iter()
+            .flat_map(|section| section.peers())
+            .map(|peer| *peer.addr())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agreement::test_utils::proven, network::NetworkUtils,
+        section::test_utils::gen_section_authority_provider,
+    };
+    use anyhow::Result;
+    use rand::Rng;
+    use xor_name::Prefix;
+
+    #[test]
+    fn closest_section_first() -> Result<()> {
+        let sk = bls::SecretKey::random();
+        let chain = SecuredLinkedList::new(sk.public_key());
+
+        let p0: Prefix = "0".parse()?;
+        let p10: Prefix = "10".parse()?;
+        let p11: Prefix = "11".parse()?;
+
+        let (our_auth, _, _) = gen_section_authority_provider(p0, 3);
+        let section = Section::new(sk.public_key(), chain.clone(), proven(&sk, our_auth)?)?;
+
+        let mut network = Network::new();
+        for prefix in &[p10, p11] {
+            let (section_auth, _, _) = gen_section_authority_provider(*prefix, 3);
+            assert!(network.update_section(proven(&sk, section_auth)?, None, &chain));
+        }
+
+        let cache = BootstrapCache::new(&section, &network);
+        let name = p11.substituted_in(rand::thread_rng().gen());
+
+        let prefixes: Vec<_> = cache
+            .verified_sections(&name)
+            .into_iter()
+            .map(|section| section.prefix)
+            .collect();
+        assert_eq!(prefixes, vec![p11, p10, p0]);
+        assert_eq!(cache.contacts(&name).len(), 9);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unproven_section_is_ignored() -> Result<()> {
+        let sk = bls::SecretKey::random();
+        let chain = SecuredLinkedList::new(sk.public_key());
+
+        let (our_auth, _, _) = gen_section_authority_provider(Prefix::default(), 3);
+        let section = Section::new(sk.public_key(), chain, proven(&sk, our_auth)?)?;
+
+        // Signed by a key that is not part of our chain.
+        let other_sk = bls::SecretKey::random();
+        let (other_auth, _, _) = gen_section_authority_provider("1".parse()?, 3);
+
+        let mut cache = BootstrapCache::new(&section, &Network::new());
+        cache.sections.push(OtherSection {
+            section_auth: proven(&other_sk, other_auth)?,
+            key_signed: None,
+        });
+
+        assert_eq!(cache.verified_sections(&XorName::random()).len(), 1);
+
+        Ok(())
+    }
+}
@@ -6,103 +6,83 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::error::{Error, Result};
+use super::{
+    metrics::Metrics,
+    transport::{
+        BindConfig, BoundTransport, Transport, TransportBuilder, TransportEvent, TransportEvents,
+    },
+};
+use crate::error::{Error, Result, TransportError};
 use crate::XorName;
 use bytes::Bytes;
 use futures::stream::{FuturesUnordered, StreamExt};
 use hex_fmt::HexFmt;
-use qp2p::{Endpoint, QuicP2p};
 use sn_messaging::MessageType;
 use std::{
     fmt::{self, Debug, Formatter},
     net::SocketAddr,
-    sync::RwLock,
+    sync::{Arc, RwLock},
 };
 use tokio::{sync::mpsc, task};
 
 // Communication component of the node to interact with other nodes.
 pub(crate) struct Comm {
-    _quic_p2p: QuicP2p,
-    endpoint: Endpoint,
+    transport: Box<dyn Transport>,
     // Sender for connection events. Kept here so we can clone it and pass it to the incoming
     // messages handler every time we establish new connection. It's kept in an `Option` so we can
     // take it out and drop it on `terminate` which together with all the incoming message handlers
     // terminating closes the corresponding receiver.
     event_tx: RwLock<Option<mpsc::Sender<ConnectionEvent>>>,
+    // Metrics of the node. Created here as `Comm` outlives the other components across
+    // relocations.
+    metrics: Arc<Metrics>,
 }
 
 impl Comm {
+    // Creates `Comm` on top of the transport created by `builder`.
     pub async fn new(
-        transport_config: qp2p::Config,
+        builder: &dyn TransportBuilder,
+        config: BindConfig,
         event_tx: mpsc::Sender<ConnectionEvent>,
     ) -> Result<Self> {
-        let quic_p2p = QuicP2p::with_config(Some(transport_config), &[], true)
-            .map_err(|err| Error::InvalidConfig { err })?;
-
-        // Don't bootstrap, just create an endpoint to listen to
-        // the incoming messages from other nodes.
-        // This also returns the a channel where we can listen for
-        // disconnection events.
-        let (endpoint, _incoming_connections, incoming_messages, disconnections) = quic_p2p
-            .new_endpoint()
-            .await
-            .map_err(|err| Error::CannotConnectEndpoint { err })?;
-
-        let _ = task::spawn(handle_incoming_messages(
-            incoming_messages,
-            event_tx.clone(),
-        ));
-
-        let _ = task::spawn(handle_disconnection_events(
-            disconnections,
-            event_tx.clone(),
-        ));
-
-        Ok(Self {
-            _quic_p2p: quic_p2p,
-            endpoint,
-            event_tx: RwLock::new(Some(event_tx)),
-        })
+        let bound = builder.bind(config).await?;
+        Ok(Self::with_transport(bound, event_tx))
     }
 
+    // Like `new`, but also connects to one of `contacts`. Returns the address of that contact
+    // too.
     pub async fn bootstrap(
-        transport_config: qp2p::Config,
+        builder: &dyn TransportBuilder,
+        config: BindConfig,
+        contacts: Vec<SocketAddr>,
         event_tx: mpsc::Sender<ConnectionEvent>,
     ) -> Result<(Self, SocketAddr)> {
-        let quic_p2p = QuicP2p::with_config(Some(transport_config), &[], true)
-            .map_err(|err| Error::InvalidConfig { err })?;
-
-        // Bootstrap to the network returning the connection to a node.
-        // We can use the returned channels to listen for incoming messages and disconnection events
-        let (endpoint, _incoming_connections, incoming_messages, disconnections, bootstrap_addr) =
-            quic_p2p
-                .bootstrap()
-                .await
-                .map_err(|err| Error::CannotConnectEndpoint { err })?;
+        let (bound, bootstrap_addr) = builder.bootstrap(config, contacts).await?;
+        Ok((Self::with_transport(bound, event_tx), bootstrap_addr))
+    }
 
-        let _ = task::spawn(handle_incoming_messages(
-            incoming_messages,
-            event_tx.clone(),
-        ));
+    fn with_transport(
+        (transport, events): BoundTransport,
+        event_tx: mpsc::Sender<ConnectionEvent>,
+    ) -> Self {
+        let metrics = Arc::new(Metrics::default());
 
-        let _ = task::spawn(handle_disconnection_events(
-            disconnections,
+        let _ = task::spawn(handle_transport_events(
+            events,
             event_tx.clone(),
+            metrics.clone(),
         ));
 
-        Ok((
-            Self {
-                _quic_p2p: quic_p2p,
-                endpoint,
-                event_tx: RwLock::new(Some(event_tx)),
-            },
-            bootstrap_addr,
-        ))
+        Self {
+            transport,
+            event_tx: RwLock::new(Some(event_tx)),
+            metrics,
+        }
     }
 
     // Close all existing connections and stop accepting new ones.
     pub fn terminate(&self) {
-        self.endpoint.close();
+        self.transport.close();
         let _ = self
             .event_tx
             .write()
@@ -110,8 +90,17 @@ impl Comm {
             .take();
     }
 
+    // Closes the connection to the given peer, if any.
+    pub async fn disconnect(&self, addr: &SocketAddr) {
+        self.transport.disconnect(addr).await
+    }
+
     pub fn our_connection_info(&self) -> SocketAddr {
-        self.endpoint.socket_addr()
+        self.transport.local_addr()
+    }
+
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
     }
 
     /// Sends a message on an existing connection. If no such connection exists, returns an error.
@@ -123,39 +112,29 @@ impl Comm {
         msg.update_dest_info(None, Some(recipient.0));
 
         let bytes = msg.serialize()?;
-        self.endpoint
-            .send_message(bytes, &recipient.1)
+        let len = bytes.len();
+        self.transport
+            .send(&recipient.1, bytes)
             .await
             .map_err(|err| {
                 error!("Sending to {:?} failed with {}", recipient, err);
                 Error::FailedSend(recipient.1, recipient.0)
             })?;
 
+        self.metrics.record_message_sent(&msg);
+        self.metrics.record_bytes_sent(len);
+
         Ok(())
     }
 
     /// Tests whether the peer is reachable.
     pub async fn is_reachable(&self, peer: &SocketAddr) -> Result<(), Error> {
-        let qp2p_config = qp2p::Config {
-            local_ip: Some(self.endpoint.local_addr().ip()),
-            local_port: Some(0),
-            forward_port: false,
-            ..Default::default()
-        };
-
-        let qp2p = QuicP2p::with_config(Some(qp2p_config), &[], false)
-            .map_err(|err| Error::InvalidConfig { err })?;
-        let (connectivity_endpoint, _, _, _) = qp2p
-            .new_endpoint()
-            .await
-            .map_err(|err| Error::CannotConnectEndpoint { err })?;
-
-        connectivity_endpoint
+        self.transport
             .is_reachable(peer)
             .await
             .map_err(|err| {
                 info!("Peer {} is NOT externally reachable: {}", peer, err);
-                Error::AddressNotReachable { err }
+                Error::Transport(err)
             })
             .map(|()| {
                 info!("Peer {} is externally reachable.", peer);
@@ -217,12 +196,10 @@ impl Comm {
                 .send_to(&recipient.1, msg_bytes)
                 .await
                 .map_err(|err| match err {
-                    qp2p::Error::Connection(qp2p::ConnectionError::LocallyClosed) => {
-                        Error::ConnectionClosed
-                    }
+                    TransportError::Closed => Error::ConnectionClosed,
                     _ => {
                         trace!("during sending, received error {:?}", err);
-                        Error::AddressNotReachable { err }
+                        Error::Transport(err)
                     }
                 });
 
@@ -265,40 +242,43 @@ impl Comm {
             failed_recipients
         );
 
-        if successes == delivery_group_size {
+        if successes > 0 {
+            self.metrics.record_message_sent(&msg);
+        }
+        self.metrics.record_bytes_sent(msg_bytes.len() * successes);
+
+        let status = if successes == delivery_group_size {
             if failed_recipients.is_empty() {
-                Ok(SendStatus::AllRecipients)
+                SendStatus::AllRecipients
             } else {
-                Ok(SendStatus::MinDeliveryGroupSizeReached(failed_recipients))
+                SendStatus::MinDeliveryGroupSizeReached(failed_recipients)
             }
         } else {
-            Ok(SendStatus::MinDeliveryGroupSizeFailed(failed_recipients))
-        }
+            SendStatus::MinDeliveryGroupSizeFailed(failed_recipients)
+        };
+        self.metrics.record_send_status(&status);
+
+        Ok(status)
     }
 
     // Low-level send
-    async fn send_to(&self, recipient: &SocketAddr, msg: Bytes) -> Result<(), qp2p::Error> {
-        trace!("Low level send for msg over qp2p");
+    async fn send_to(&self, recipient: &SocketAddr, msg: Bytes) -> Result<(), TransportError> {
+        trace!("Low level send for msg over the transport");
         // This will attempt to use a cached connection
-        if self
-            .endpoint
-            .send_message(msg.clone(), recipient)
-            .await
-            .is_ok()
-        {
+        if self.transport.send(recipient, msg.clone()).await.is_ok() {
             return Ok(());
         }
 
         // If the sending of a message failed the connection would no longer
         // exist in the pool. So we connect again and then send the message.
-        self.endpoint.connect_to(recipient).await?;
-        self.endpoint.send_message(msg, recipient).await
+        self.transport.connect(recipient).await?;
+        self.transport.send(recipient, msg).await
     }
 }
 
 impl Drop for Comm {
     fn drop(&mut self) {
-        self.endpoint.close()
+        self.transport.close()
     }
 }
 
@@ -316,23 +296,20 @@ impl Debug for ConnectionEvent {
     }
 }
 
-async fn handle_disconnection_events(
-    mut disconnections: qp2p::DisconnectionEvents,
-    event_tx: mpsc::Sender<ConnectionEvent>,
-) {
-    while let Some(peer_addr) = disconnections.next().await {
-        let _ = event_tx
-            .send(ConnectionEvent::Disconnected(peer_addr))
-            .await;
-    }
-}
-
-async fn handle_incoming_messages(
-    mut incoming_msgs: qp2p::IncomingMessages,
+async fn handle_transport_events(
+    mut events: TransportEvents,
     event_tx: mpsc::Sender<ConnectionEvent>,
+    metrics: Arc<Metrics>,
 ) {
-    while let Some((src, msg)) = incoming_msgs.next().await {
-        let _ = event_tx.send(ConnectionEvent::Received((src, msg))).await;
+    while let Some(event) = events.next().await {
+        let event = match event {
+            TransportEvent::Received { src, msg } => {
+                metrics.record_bytes_received(msg.len());
+                ConnectionEvent::Received((src, msg))
+            }
+            TransportEvent::Disconnected(addr) => ConnectionEvent::Disconnected(addr),
+        };
+        let _ = event_tx.send(event).await;
     }
 }
 
@@ -344,13 +321,35 @@ pub enum SendStatus {
     MinDeliveryGroupSizeFailed(Vec<SocketAddr>),
 }
 
+impl SendStatus {
+    // Returns the recipients `Comm::send` delivered the message to, given the `recipients` and
+    // `delivery_group_size` it was called with. It tries the next recipient for every failed one.
+    pub fn delivered_to<'a>(
+        &'a self,
+        recipients: &'a [(XorName, SocketAddr)],
+        delivery_group_size: usize,
+    ) -> impl Iterator<Item = &'a (XorName, SocketAddr)> {
+        let failed: &[SocketAddr] = match self {
+            Self::AllRecipients => &[],
+            Self::MinDeliveryGroupSizeReached(failed)
+            | Self::MinDeliveryGroupSizeFailed(failed) => failed,
+        };
+        let tried = (delivery_group_size + failed.len()).min(recipients.len());
+
+        recipients[..tried]
+            .iter()
+            .filter(move |(_, addr)| !failed.contains(addr))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::routing::transport::QuicTransportBuilder;
     use anyhow::Result;
     use assert_matches::assert_matches;
     use futures::future;
-    use qp2p::Config;
+    use qp2p::{Config, QuicP2p};
     use sn_data_types::PublicKey;
     use sn_messaging::{section_info::SectionInfoMsg, DestInfo, WireMsg};
     use std::{net::Ipv4Addr, slice, time::Duration};
@@ -361,7 +360,12 @@ mod tests {
     #[tokio::test]
     async fn successful_send() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
-        let comm = Comm::new(transport_config(), tx).await?;
+        let comm = Comm::new(
+            &QuicTransportBuilder::new(transport_config()),
+            BindConfig::default(),
+            tx,
+        )
+        .await?;
 
         let mut peer0 = Peer::new().await?;
         let mut peer1 = Peer::new().await?;
@@ -393,7 +397,12 @@ mod tests {
     #[tokio::test]
     async fn successful_send_to_subset() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
-        let comm = Comm::new(transport_config(), tx).await?;
+        let comm = Comm::new(
+            &QuicTransportBuilder::new(transport_config()),
+            BindConfig::default(),
+            tx,
+        )
+        .await?;
 
         let mut peer0 = Peer::new().await?;
         let mut peer1 = Peer::new().await?;
@@ -426,11 +435,12 @@ mod tests {
     async fn failed_send() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
         let comm = Comm::new(
-            Config {
+            &QuicTransportBuilder::new(Config {
                 // This makes this test faster.
                 idle_timeout_msec: Some(1),
                 ..transport_config()
-            },
+            }),
+            BindConfig::default(),
             tx,
         )
         .await?;
@@ -448,6 +458,8 @@ mod tests {
             &status,
             &SendStatus::MinDeliveryGroupSizeFailed(_) => vec![invalid_addr]
         );
+        // A message that reached no one doesn't count as sent.
+        assert!(comm.metrics().snapshot().messages_sent.is_empty());
 
         Ok(())
     }
@@ -456,10 +468,11 @@ mod tests {
     async fn successful_send_after_failed_attempts() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
         let comm = Comm::new(
-            Config {
+            &QuicTransportBuilder::new(Config {
                 idle_timeout_msec: Some(1),
                 ..transport_config()
-            },
+            }),
+            BindConfig::default(),
             tx,
         )
         .await?;
@@ -468,13 +481,13 @@ mod tests {
         let name = XorName::random();
 
         let mut message = new_section_info_message();
-        let _ = comm
-            .send(
-                &[(name, invalid_addr), (peer._name, peer.addr)],
-                1,
-                message.clone(),
-            )
-            .await?;
+        let recipients = [(name, invalid_addr), (peer._name, peer.addr)];
+        let status = comm.send(&recipients, 1, message.clone()).await?;
+
+        assert_eq!(
+            status.delivered_to(&recipients, 1).collect::<Vec<_>>(),
+            vec![&(peer._name, peer.addr)]
+        );
 
         // Using first name of the recipients to represent section_name.
         if let Some(bytes) = peer.rx.recv().await {
@@ -488,10 +501,11 @@ mod tests {
     async fn partially_successful_send() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
         let comm = Comm::new(
-            Config {
+            &QuicTransportBuilder::new(Config {
                 idle_timeout_msec: Some(1),
                 ..transport_config()
-            },
+            }),
+            BindConfig::default(),
             tx,
         )
         .await?;
@@ -500,14 +514,13 @@ mod tests {
         let name = XorName::random();
 
         let mut message = new_section_info_message();
-        let status = comm
-            .send(
-                &[(name, invalid_addr), (peer._name, peer.addr)],
-                2,
-                message.clone(),
-            )
-            .await?;
+        let recipients = [(name, invalid_addr), (peer._name, peer.addr)];
+        let status = comm.send(&recipients, 2, message.clone()).await?;
 
+        assert_eq!(
+            status.delivered_to(&recipients, 2).collect::<Vec<_>>(),
+            vec![&(peer._name, peer.addr)]
+        );
         assert_matches!(
             status,
             SendStatus::MinDeliveryGroupSizeFailed(_) => vec![invalid_addr]
@@ -524,7 +537,12 @@ mod tests {
     #[tokio::test]
     async fn send_after_reconnect() -> Result<()> {
         let (tx, _rx) = mpsc::channel(1);
-        let send_comm = Comm::new(transport_config(), tx).await?;
+        let send_comm = Comm::new(
+            &QuicTransportBuilder::new(transport_config()),
+            BindConfig::default(),
+            tx,
+        )
+        .await?;
 
         let recv_transport = QuicP2p::with_config(Some(transport_config()), &[], false)?;
         let (recv_endpoint, _, mut incoming_msgs, _) = recv_transport.new_endpoint().await?;
@@ -584,11 +602,21 @@ mod tests {
     #[tokio::test]
     async fn incoming_connection_lost() -> Result<()> {
         let (tx, mut rx0) = mpsc::channel(1);
-        let comm0 = Comm::new(transport_config(), tx).await?;
+        let comm0 = Comm::new(
+            &QuicTransportBuilder::new(transport_config()),
+            BindConfig::default(),
+            tx,
+        )
+        .await?;
         let addr0 = comm0.our_connection_info();
 
         let (tx, _rx) = mpsc::channel(1);
-        let comm1 = Comm::new(transport_config(), tx).await?;
+        let comm1 = Comm::new(
+            &QuicTransportBuilder::new(transport_config()),
+            BindConfig::default(),
+            tx,
+        )
+        .await?;
         let addr1 = comm1.our_connection_info();
 
         // Send a message to establish the connection
@@ -6,7 +6,7 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::{routing::Peer, section::SectionKeyShare, XorName};
+use crate::{event::Event, routing::Peer, section::SectionKeyShare, XorName};
 use bytes::Bytes;
 use hex_fmt::HexFmt;
 use sn_messaging::{
@@ -51,6 +51,8 @@ pub(crate) enum Command {
     /// the proposed new elders).
     HandleDkgOutcome {
         section_auth: SectionAuthorityProvider,
+        /// Generation of the DKG session.
+        generation: u64,
         outcome: SectionKeyShare,
     },
     /// Handle a DKG failure that was observed by a majority of the DKG participants.
@@ -91,11 +93,25 @@ pub(crate) enum Command {
     },
     /// Proposes a peer as offline
     ProposeOffline(XorName),
+    /// Proposes an application-defined payload for our section to agree on.
+    ProposeCustom(Bytes),
+    /// Keep re-sending our `proposal`, signed with `key`, to `recipients` until our section agrees
+    /// on it. `message` carries our share of the proposal.
+    TrackProposal {
+        proposal: Proposal,
+        key: bls::PublicKey,
+        message: RoutingMsg,
+        recipients: Vec<Peer>,
+    },
+    /// Handle `elder` sending us its own share of our `proposal`.
+    HandleProposalResponse { proposal: Proposal, elder: XorName },
     /// Send a signal to all Elders to
     /// test the connectivity to a specific node
     StartConnectivityTest(XorName),
     /// Test Connectivity
     TestConnectivity(XorName),
+    /// Raise an event to the user.
+    SendEvent(Event),
 }
 
 impl Command {
@@ -158,10 +174,12 @@ impl Debug for Command {
                 .finish(),
             Self::HandleDkgOutcome {
                 section_auth,
+                generation,
                 outcome,
             } => f
                 .debug_struct("HandleDkgOutcome")
                 .field("section_auth", section_auth)
+                .field("generation", generation)
                 .field("outcome", &outcome.public_key_set.public_key())
                 .finish(),
             Self::HandleDkgFailure(signeds) => {
@@ -215,10 +233,31 @@ impl Debug for Command {
                 .field("previous_name", previous_name)
                 .finish(),
             Self::ProposeOffline(name) => f.debug_tuple("ProposeOffline").field(name).finish(),
+            Self::ProposeCustom(payload) => f
+                .debug_tuple("ProposeCustom")
+                .field(&format_args!("{:10}", HexFmt(payload)))
+                .finish(),
+            Self::TrackProposal {
+                proposal,
+                key,
+                recipients,
+                ..
+            } => f
+                .debug_struct("TrackProposal")
+                .field("proposal", proposal)
+                .field("key", key)
+                .field("recipients", recipients)
+                .finish(),
+            Self::HandleProposalResponse { proposal, elder } => f
+                .debug_struct("HandleProposalResponse")
+                .field("proposal", proposal)
+                .field("elder", elder)
+                .finish(),
             Self::TestConnectivity(name) => f.debug_tuple("TestConnectivity").field(name).finish(),
             Self::StartConnectivityTest(name) => {
                 f.debug_tuple("StartConnectivityTest").field(name).finish()
             }
+            Self::SendEvent(event) => f.debug_tuple("SendEvent").field(event).finish(),
         }
     }
 }
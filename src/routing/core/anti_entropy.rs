@@ -207,7 +207,11 @@ mod tests {
             section_pk: bls::PublicKey,
         ) -> Result<RoutingMsg> {
             let sender = Node::new(
-                ed25519::gen_keypair(&src_section.range_inclusive(), MIN_ADULT_AGE),
+                ed25519::gen_keypair(
+                    &mut rand::thread_rng(),
+                    &src_section.range_inclusive(),
+                    MIN_ADULT_AGE,
+                ),
                 gen_addr(),
             );
 
@@ -8,44 +8,235 @@
 
 use super::{delivery_group, Core};
 use crate::{
+    agreement::{DkgVoter, Signed},
+    clock::{Clock, SharedRng},
     error::Result,
-    messages::RoutingMsgUtils,
-    network::NetworkUtils,
+    message_filter::MessageFilter,
+    messages::{is_reserved, tagged, RoutingMsgUtils, CUSTOM_AGREEMENT_TAG},
+    network::{NetworkStatsHistory, NetworkUtils},
     node::Node,
     peer::PeerUtils,
-    routing::{command::Command, enduser_registry::SocketId},
-    section::{MemberInfoUtils, SectionAuthorityProviderUtils, SectionUtils},
-    Error, Event,
+    routing::{
+        command::{self, Command},
+        decryption::DecryptionPolicy,
+        dkg_restarts::DkgPolicy,
+        enduser_registry::{EndUserInfo, EndUserPolicy, EndUserRegistry, SocketId},
+        internal_msg::InternalMsg,
+        message_journal::{Hop, MessageJournal},
+        metrics::Metrics,
+        proposal_tracker::{ProposalRetryPolicy, ProposalTracker},
+        section_signature::SectionSigned,
+        state_store::NodeState,
+    },
+    section::{
+        self, MemberInfoUtils, SectionAuthorityProviderUtils, SectionKeysProvider, SectionUtils,
+    },
+    Error, Event, NetworkParams,
 };
 use bytes::Bytes;
 use secured_linked_list::SecuredLinkedList;
 use sn_messaging::{
-    node::{MemberInfo, Network, Peer, Proposal, RoutingMsg, Section, Variant},
+    node::{MemberInfo, Network, Peer, Proposal, Proven, RoutingMsg, Section, Variant},
     section_info::Error as TargetSectionError,
-    DestInfo, EndUser, Itinerary, SectionAuthorityProvider, SrcLocation,
+    DestInfo, DstLocation, EndUser, Itinerary, SectionAuthorityProvider, SrcLocation,
+};
+use std::{net::SocketAddr, sync::Arc};
+use tokio::sync::{
+    mpsc::{self, error::TrySendError},
+    oneshot,
 };
-use std::net::SocketAddr;
-use tokio::sync::mpsc;
 use xor_name::{Prefix, XorName};
 
 impl Core {
     // Creates `Core` for the first node in the network
-    pub fn first_node(node: Node, event_tx: mpsc::Sender<Event>) -> Result<Self> {
-        let (section, section_key_share) = Section::first_node(node.peer())?;
-        Ok(Self::new(node, section, Some(section_key_share), event_tx))
+    pub fn first_node(
+        node: Node,
+        network_params: NetworkParams,
+        event_tx: mpsc::Sender<Event>,
+        rng: &mut SharedRng,
+    ) -> Result<Self> {
+        let (section, section_key_share) = Section::first_node(node.peer(), rng)?;
+        let network_params_proof = section::create_first_signed(
+            &section_key_share.public_key_set,
+            &section_key_share.secret_key_share,
+            &network_params,
+        )?;
+
+        let mut core = Self::new(
+            node,
+            section,
+            Some(section_key_share),
+            network_params,
+            event_tx,
+        );
+        core.network_params_proof = Some(network_params_proof);
+        Ok(core)
+    }
+
+    // Creates `Core` from the state persisted by a previous run of this node.
+    pub fn resume(node: Node, state: NodeState, event_tx: mpsc::Sender<Event>) -> Self {
+        let section = state.section.clone();
+        let network = state.network.clone();
+        let joins_allowed = state.joins_allowed;
+        let network_params = state.network_params;
+
+        let mut core = Self::new(node, section, None, network_params, event_tx);
+        core.network_params_proof = state.network_params_proof.clone();
+        core.network = network;
+        core.joins_allowed = joins_allowed;
+        core.section_keys_provider =
+            SectionKeysProvider::restore(network_params.key_cache_size, state.key_shares());
+        core
+    }
+
+    // Returns the part of our state that needs to survive a restart.
+    pub fn node_state(&self) -> NodeState {
+        NodeState::new(
+            &self.node,
+            self.section.clone(),
+            self.network.clone(),
+            self.section_keys_provider.key_shares(),
+            self.joins_allowed,
+            self.network_params,
+            self.network_params_proof.clone(),
+        )
+    }
+
+    pub fn network_params(&self) -> &NetworkParams {
+        &self.network_params
+    }
+
+    // Returns our network parameters proven by the genesis key, unless we don't know the proof.
+    pub fn proven_network_params(&self) -> Option<Proven<NetworkParams>> {
+        self.network_params_proof.clone().map(|signed| Proven {
+            value: self.network_params,
+            signed,
+        })
+    }
+
+    // Sets the signature of the genesis key over our network parameters.
+    pub fn set_network_params_proof(&mut self, proof: Signed) {
+        self.network_params_proof = Some(proof);
+    }
+
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    pub fn rng(&self) -> &SharedRng {
+        &self.rng
+    }
+
+    pub fn set_message_journal(&mut self, message_journal: Option<Arc<MessageJournal>>) {
+        self.message_journal = message_journal;
+    }
+
+    pub fn network_stats_history(&self) -> &NetworkStatsHistory {
+        &self.network_stats_history
+    }
+
+    // Continues the history of the network statistics recorded by a previous incarnation of this
+    // node, e.g. before relocation.
+    pub fn set_network_stats_history(&mut self, history: NetworkStatsHistory) {
+        self.network_stats_history = history;
     }
 
-    pub fn get_enduser_by_addr(&self, sender: &SocketAddr) -> Option<&EndUser> {
-        self.end_users.get_enduser_by_addr(sender)
+    // Makes this node record its metrics into `metrics`.
+    pub fn set_metrics(&mut self, metrics: Arc<Metrics>) {
+        self.metrics = metrics;
     }
 
-    pub fn get_socket_addr(&self, id: SocketId) -> Option<&SocketAddr> {
+    // Makes this node take the time from `clock` and the randomness from `rng`. Resets the DKG
+    // sessions and the message filter, so call this before handling any message.
+    pub fn set_clock_and_rng(&mut self, clock: Arc<dyn Clock>, rng: SharedRng) {
+        let dkg_policy = *self.dkg_voter.policy();
+        self.dkg_voter = DkgVoter::new(clock.clone(), rng.clone());
+        self.dkg_voter.set_policy(dkg_policy);
+        self.msg_filter = MessageFilter::new(clock.clone());
+        self.clock = clock;
+        self.rng = rng;
+    }
+
+    pub fn get_socket_addr(&self, id: SocketId) -> Option<SocketAddr> {
         self.end_users.get_socket_addr(id)
     }
 
-    pub fn try_add(&mut self, sender: SocketAddr) -> Result<EndUser> {
+    pub fn try_add(&self, sender: SocketAddr) -> Result<EndUser> {
         let section_prefix = self.section.prefix();
-        self.end_users.try_add(sender, section_prefix)
+        self.end_users
+            .try_add(sender, section_prefix, self.clock.now())
+    }
+
+    pub fn remove_enduser(&self, addr: &SocketAddr) -> Option<EndUser> {
+        self.end_users.remove(addr)
+    }
+
+    pub fn end_users(&self) -> Vec<EndUserInfo> {
+        self.end_users.list(self.clock.now())
+    }
+
+    pub fn end_user_info(&self, addr: &SocketAddr) -> Option<EndUserInfo> {
+        self.end_users.info(addr, self.clock.now())
+    }
+
+    pub fn end_user_policy(&self) -> &EndUserPolicy {
+        self.end_users.policy()
+    }
+
+    // Applies the given policy. Forgets the current end users, so call this before serving any.
+    pub fn set_end_user_policy(&mut self, policy: EndUserPolicy) {
+        self.end_users = EndUserRegistry::new(policy);
+    }
+
+    pub fn proposal_retry_policy(&self) -> &ProposalRetryPolicy {
+        self.proposal_tracker.policy()
+    }
+
+    // Applies the given policy. Forgets the proposals tracked so far.
+    pub fn set_proposal_retry_policy(&mut self, policy: ProposalRetryPolicy) {
+        self.proposal_tracker = ProposalTracker::new(policy);
+    }
+
+    pub fn decryption_policy(&self) -> Option<&Arc<dyn DecryptionPolicy>> {
+        self.decryption_policy.as_ref()
+    }
+
+    pub fn set_decryption_policy(&mut self, policy: Option<Arc<dyn DecryptionPolicy>>) {
+        self.decryption_policy = policy;
+    }
+
+    pub fn dkg_policy(&self) -> &DkgPolicy {
+        self.dkg_voter.policy()
+    }
+
+    // Applies the given policy to the DKG sessions started from now on.
+    pub fn set_dkg_policy(&mut self, policy: DkgPolicy) {
+        self.dkg_voter.set_policy(policy);
+    }
+
+    // Schedules the next check for idle end users, if the policy evicts them.
+    pub fn schedule_end_user_eviction(&mut self) -> Option<Command> {
+        // Check twice per timeout so idle end users don't linger for much longer than it.
+        let duration = self.end_users.policy().idle_timeout? / 2;
+        self.end_user_eviction_token = command::next_timer_token();
+
+        Some(Command::ScheduleTimeout {
+            duration,
+            token: self.end_user_eviction_token,
+        })
+    }
+
+    pub fn evict_idle_end_users(&mut self) -> Vec<Command> {
+        let evicted = self.end_users.evict_idle(self.clock.now());
+        if !evicted.is_empty() {
+            debug!("Evicted {} idle end users", evicted.len());
+        }
+
+        evicted
+            .into_iter()
+            .map(|addr| Command::SendEvent(Event::ClientLost(addr)))
+            .chain(self.schedule_end_user_eviction())
+            .collect()
     }
 
     pub fn node(&self) -> &Node {
@@ -69,6 +260,11 @@ impl Core {
         self.section.is_elder(&self.node.name())
     }
 
+    // Whether our section agreed on us leaving it, see `propose_leave`.
+    pub fn has_left(&self) -> bool {
+        self.left
+    }
+
     /// Tries to sign with the secret corresponding to the provided BLS public key
     pub fn sign_with_section_key_share(
         &self,
@@ -121,8 +317,22 @@ impl Core {
     }
 
     pub async fn send_event(&self, event: Event) {
+        self.metrics.record_event(&event);
+
         // Note: cloning the sender to avoid mutable access. Should have negligible cost.
-        if self.event_tx.clone().send(event).await.is_err() {
+        let result = match self.event_tx.try_send(event) {
+            Ok(()) => {
+                self.metrics.record_event_sent(false);
+                Ok(())
+            }
+            Err(TrySendError::Full(event)) => {
+                self.metrics.record_event_sent(true);
+                self.event_tx.clone().send(event).await.map_err(|_| ())
+            }
+            Err(TrySendError::Closed(_)) => Err(()),
+        };
+
+        if result.is_err() {
             error!("Event receiver has been closed");
         }
     }
@@ -138,6 +348,7 @@ impl Core {
             &self.node.name(),
             &self.section,
             &self.network,
+            &self.network_params,
         )?;
 
         let target_name = msg.dst.name().ok_or(Error::CannotRoute)?;
@@ -160,6 +371,14 @@ impl Core {
             return Ok(None);
         }
 
+        self.trace_hop(
+            msg.id,
+            Hop::Relayed {
+                targets: targets.iter().map(|(name, _)| *name).collect(),
+                delivery_group_size: dg_size,
+            },
+        );
+
         trace!(
             "relay {:?} to first {:?} of {:?} (Section PK: {:?})",
             msg,
@@ -173,7 +392,7 @@ impl Core {
             dg_size,
             msg.clone(),
             DestInfo {
-                dest: XorName::random(),
+                dest: self.random_name(),
                 dest_section_pk: dest_pk,
             },
         );
@@ -183,7 +402,11 @@ impl Core {
 
     #[allow(unused)]
     pub fn check_key_status(&self, bls_pk: &bls::PublicKey) -> Result<(), TargetSectionError> {
-        let elders_candidates = self.section.promote_and_demote_elders(&self.node.name());
+        let elders_candidates = self.section.promote_and_demote_elders(
+            &self.network_params,
+            &self.node.name(),
+            self.dkg_restarts.excluded(),
+        );
         // Whenever there is a elders candidate, it is considered as having ongoing DKG.
         if !elders_candidates.is_empty() {
             trace!("Non empty elder candidates {:?}", elders_candidates);
@@ -229,6 +452,10 @@ impl Core {
         if matches!(itinerary.src, SrcLocation::EndUser(_)) {
             return Err(Error::InvalidSrcLocation);
         }
+        if is_reserved(&content) {
+            error!("Not sending user message: the content starts with a reserved tag");
+            return Err(Error::InvalidPayload);
+        }
         let dst_name = if let Some(name) = itinerary.dst_name() {
             name
         } else {
@@ -295,6 +522,98 @@ impl Core {
         Ok(commands)
     }
 
+    // Custom proposals are carried by `AccumulateAtSrc` proposals of a `UserMessage` with a
+    // `DirectAndUnrouted` destination, whose content is tagged so the section signature over it
+    // can't pass for the signature of a user message.
+    pub fn propose_custom(&self, payload: Bytes) -> Result<Vec<Command>> {
+        let proposal = self.create_aggregate_at_src_proposal(
+            DstLocation::DirectAndUnrouted,
+            Variant::UserMessage(tagged(CUSTOM_AGREEMENT_TAG, &payload)),
+            None,
+        )?;
+        self.propose(proposal)
+    }
+
+    // Asks our elders for their signature shares over `data`. Returns the commands to send the
+    // request and the receiver of the signature once enough shares have been aggregated.
+    pub fn request_section_signature(
+        &mut self,
+        data: Bytes,
+    ) -> Result<(Vec<Command>, oneshot::Receiver<SectionSigned>)> {
+        let (_, rx) = self.section_signature_requests.insert(data.clone());
+
+        let request = InternalMsg::SignatureRequest {
+            data,
+            last_known_key: *self.section.chain().last_key(),
+        };
+
+        Ok((self.send_internal_request(request)?, rx))
+    }
+
+    // Forgets the request for the section signature over `data` if nobody waits for it anymore.
+    pub fn forget_section_signature_request(&mut self, data: &[u8]) {
+        self.section_signature_requests.remove_abandoned(data)
+    }
+
+    // Asks our elders for their decryption shares of `ciphertext`, encrypted to `section_key`,
+    // for `context`. Returns the commands to send the request and the receiver of the plaintext
+    // once enough shares have been combined.
+    pub fn request_decryption(
+        &mut self,
+        ciphertext: bls::Ciphertext,
+        section_key: bls::PublicKey,
+        context: Bytes,
+    ) -> Result<(Vec<Command>, oneshot::Receiver<Bytes>)> {
+        if !ciphertext.verify() {
+            return Err(Error::InvalidCiphertext);
+        }
+
+        // Our elders only keep the key shares of the latest `key_cache_size` keys.
+        let age = self
+            .section
+            .chain()
+            .get_proof_chain_to_current(&section_key)?
+            .main_branch_len()
+            - 1;
+        if age >= self.network_params.key_cache_size as usize {
+            return Err(Error::SectionKeyTooOld(section_key));
+        }
+
+        let (_, rx) = self
+            .decryption_requests
+            .insert(ciphertext.clone(), section_key)?;
+        let request = InternalMsg::DecryptionRequest {
+            ciphertext,
+            section_key,
+            context,
+        };
+
+        Ok((self.send_internal_request(request)?, rx))
+    }
+
+    // Forgets the request for the decryption of `ciphertext` if nobody waits for it anymore.
+    pub fn forget_decryption_request(&mut self, ciphertext: &bls::Ciphertext) {
+        self.decryption_requests.remove_abandoned(ciphertext)
+    }
+
+    // Sends `request` to each of our elders.
+    pub(crate) fn send_internal_request(&self, request: InternalMsg) -> Result<Vec<Command>> {
+        let variant = request.to_variant()?;
+        let mut commands = vec![];
+
+        for elder in self.section.authority_provider().peers() {
+            let message = RoutingMsg::single_src(
+                &self.node,
+                DstLocation::Node(*elder.name()),
+                variant.clone(),
+                self.section.authority_provider().section_key(),
+            )?;
+            commands.extend(self.send_or_handle(message, &[elder]));
+        }
+
+        Ok(commands)
+    }
+
     pub async fn make_online_proposal(
         &self,
         peer: Peer,
@@ -8,16 +8,18 @@
 
 use super::Core;
 use crate::{
-    agreement::{DkgCommands, DkgFailureSignedSetUtils},
+    agreement::{DkgCommands, DkgFailureSignedSetUtils, ProvenUtils},
     error::Result,
     peer::PeerUtils,
-    routing::command::Command,
+    routing::{command::Command, internal_msg::InternalMsg},
     section::{MemberInfoUtils, SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils},
-    Error,
+    Error, Event,
 };
 use bls_dkg::key_gen::message::Message as DkgMessage;
+use secured_linked_list::SecuredLinkedList;
 use sn_messaging::node::{
-    DkgFailureSigned, DkgFailureSignedSet, DkgKey, ElderCandidates, Proposal,
+    DkgFailureSigned, DkgFailureSignedSet, DkgKey, ElderCandidates, MemberInfo, PeerState,
+    Proposal, Proven,
 };
 use std::{collections::BTreeSet, iter, net::SocketAddr, slice};
 use xor_name::XorName;
@@ -31,7 +33,7 @@ impl Core {
         trace!("Received DkgStart for {:?}", elder_candidates);
         self.dkg_voter
             .start(&self.node.keypair, dkg_key, elder_candidates)
-            .into_commands(&self.node, *self.section_chain().last_key())
+            .into_commands(&self.node, *self.section_chain().last_key(), &self.rng)
     }
 
     pub(crate) fn handle_dkg_message(
@@ -43,8 +45,8 @@ impl Core {
         trace!("handle DKG message {:?} from {}", message, sender);
 
         self.dkg_voter
-            .process_message(&self.node.keypair, &dkg_key, message)
-            .into_commands(&self.node, *self.section_chain().last_key())
+            .process_message(&self.node.keypair, &dkg_key, message, sender)
+            .into_commands(&self.node, *self.section_chain().last_key(), &self.rng)
     }
 
     pub(crate) fn handle_dkg_failure_observation(
@@ -55,11 +57,11 @@ impl Core {
     ) -> Result<Vec<Command>> {
         self.dkg_voter
             .process_failure(&dkg_key, non_participants, signed)
-            .into_commands(&self.node, *self.section_chain().last_key())
+            .into_commands(&self.node, *self.section_chain().last_key(), &self.rng)
     }
 
     pub(crate) fn handle_dkg_failure_agreement(
-        &self,
+        &mut self,
         sender: &XorName,
         signeds: &DkgFailureSignedSet,
     ) -> Result<Vec<Command>> {
@@ -73,7 +75,11 @@ impl Core {
         let generation = self.section.chain().main_branch_len() as u64;
         let elder_candidates = self
             .section
-            .promote_and_demote_elders(&self.node.name())
+            .promote_and_demote_elders(
+                &self.network_params,
+                &self.node.name(),
+                self.dkg_restarts.excluded(),
+            )
             .into_iter()
             .find(|elder_candidates| signeds.verify(elder_candidates, generation));
         let elder_candidates = if let Some(elder_candidates) = elder_candidates {
@@ -83,15 +89,61 @@ impl Core {
             return Ok(vec![]);
         };
 
-        if signeds.non_participants.is_empty() {
+        let policy = *self.dkg_voter.policy();
+        let dkg_key = DkgKey::new(&elder_candidates, generation);
+        let is_new = self
+            .dkg_restarts
+            .record_failure(dkg_key, &signeds.non_participants);
+        let attempts = self.dkg_restarts.attempts();
+
+        let mut commands = vec![];
+
+        if attempts >= policy.max_attempts {
+            if is_new && attempts == policy.max_attempts {
+                warn!(
+                    "Giving up on DKG generation({}) after {} attempts",
+                    generation, attempts
+                );
+                commands.push(Command::SendEvent(Event::DkgAttemptsExhausted {
+                    generation,
+                    attempts,
+                    excluded: self.dkg_restarts.excluded().clone(),
+                }));
+                commands.push(self.dkg_restarts.exhaust(&policy));
+            }
+        } else if signeds.non_participants.is_empty() {
             // The DKG failure is a corrupted one due to lagging.
             trace!(
                 "Received DKG failure agreement - restarting: {:?}",
                 elder_candidates
             );
 
-            self.send_dkg_start_to(elder_candidates, slice::from_ref(sender))
-        } else {
+            return self.send_dkg_start_to(elder_candidates, slice::from_ref(sender));
+        } else if is_new {
+            // Restart without the non-participants, substituting the next best candidates.
+            let prefix = elder_candidates.prefix;
+            let new_elder_candidates = self
+                .section
+                .promote_and_demote_elders(
+                    &self.network_params,
+                    &self.node.name(),
+                    self.dkg_restarts.excluded(),
+                )
+                .into_iter()
+                .find(|elder_candidates| elder_candidates.prefix == prefix);
+
+            if let Some(new_elder_candidates) = new_elder_candidates {
+                trace!(
+                    "Restarting DKG generation({}) without {:?} (attempt {})",
+                    generation,
+                    signeds.non_participants,
+                    attempts + 1
+                );
+                commands.push(self.dkg_restarts.schedule(new_elder_candidates, &policy));
+            }
+        }
+
+        if !signeds.non_participants.is_empty() {
             // The DKG failure is regarding non_participants, i.e. potential unresponsive node.
             trace!(
                 "Received DKG failure agreement of non_participants {:?} , DKG generation({}) {:?}",
@@ -99,21 +151,52 @@ impl Core {
                 generation,
                 elder_candidates
             );
-            self.cast_offline_proposals(&signeds.non_participants)
+            commands.extend(self.cast_offline_proposals(&signeds.non_participants)?);
         }
+
+        Ok(commands)
     }
 
-    pub fn handle_connection_lost(&self, addr: SocketAddr) -> Result<Vec<Command>> {
+    // Restarts the failed DKG session once its backoff elapsed, unless the elder candidates changed
+    // or the attempts got exhausted in the meantime.
+    pub(crate) fn handle_dkg_restart_timeout(
+        &self,
+        elder_candidates: ElderCandidates,
+    ) -> Result<Vec<Command>> {
+        if self.dkg_restarts.is_exhausted() {
+            trace!("Not restarting DKG, the attempts are exhausted");
+            return Ok(vec![]);
+        }
+
+        let still_expected = self
+            .section
+            .promote_and_demote_elders(
+                &self.network_params,
+                &self.node.name(),
+                self.dkg_restarts.excluded(),
+            )
+            .contains(&elder_candidates);
+
+        if still_expected {
+            self.send_dkg_start(elder_candidates)
+        } else {
+            trace!(
+                "Not restarting DKG with outdated candidates {:?}",
+                elder_candidates
+            );
+            Ok(vec![])
+        }
+    }
+
+    pub fn handle_connection_lost(&mut self, addr: SocketAddr) -> Result<Vec<Command>> {
         if let Some(peer) = self.section.find_joined_member_by_addr(&addr) {
             debug!(
                 "Possible connection loss detected with known peer {:?}",
                 peer
             )
-        } else if let Some(end_user) = self.get_enduser_by_addr(&addr) {
-            debug!(
-                "Possible connection loss detected with known client {:?}",
-                end_user
-            )
+        } else if let Some(end_user) = self.remove_enduser(&addr) {
+            debug!("Lost connection to client {:?}, removing it", end_user);
+            return Ok(vec![Command::SendEvent(Event::ClientLost(addr))]);
         } else {
             debug!("Possible connection loss detected with addr: {:?}", addr);
         }
@@ -139,6 +222,57 @@ impl Core {
         Ok(commands)
     }
 
+    // Asks our elders, including us if we are one, to agree on us leaving the section.
+    pub fn propose_leave(&self) -> Result<Vec<Command>> {
+        self.send_internal_request(InternalMsg::Leaving {
+            last_known_key: *self.section.chain().last_key(),
+        })
+    }
+
+    // Proposes the member `name`, which announced it is leaving, offline. It is told once our
+    // section agrees.
+    pub(crate) fn handle_leaving(
+        &mut self,
+        name: XorName,
+        last_known_key: bls::PublicKey,
+    ) -> Result<Vec<Command>> {
+        if !self.is_elder() || !self.section.members().is_joined(&name) {
+            trace!("Ignoring leaving announcement of {}", name);
+            return Ok(vec![]);
+        }
+
+        info!("{} is leaving the section", name);
+        let _ = self.leaving.insert(name, last_known_key);
+        self.propose_offline(name)
+    }
+
+    // Records that our section agreed on us leaving, if `member_info` proves so.
+    pub(crate) fn handle_left(
+        &mut self,
+        src_name: XorName,
+        member_info: Proven<MemberInfo>,
+        proof_chain: SecuredLinkedList,
+    ) {
+        if !self.section.authority_provider().contains_elder(&src_name) {
+            debug!(
+                "Ignoring departure confirmation from non-elder {}",
+                src_name
+            );
+            return;
+        }
+
+        let valid = member_info.value.peer.name() == &self.node.name()
+            && matches!(member_info.value.state, PeerState::Left)
+            && self.section.chain().has_key(proof_chain.root_key())
+            && proof_chain.self_verify()
+            && member_info.verify(&proof_chain);
+        if valid {
+            self.left = true;
+        } else {
+            error!("Invalid departure confirmation from {}", src_name);
+        }
+    }
+
     pub fn propose_offline(&self, name: XorName) -> Result<Vec<Command>> {
         self.cast_offline_proposals(&iter::once(name).collect())
     }
@@ -13,7 +13,7 @@ use crate::{
     network::NetworkUtils,
     peer::PeerUtils,
     section::{SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils},
-    supermajority, ELDER_SIZE,
+    supermajority, NetworkParams,
 };
 use itertools::Itertools;
 use sn_messaging::{
@@ -42,6 +42,7 @@ pub(crate) fn delivery_targets(
     our_name: &XorName,
     section: &Section,
     network: &Network,
+    network_params: &NetworkParams,
 ) -> Result<(Vec<Peer>, usize)> {
     if !section.is_elder(our_name) {
         // We are not Elder - return all the elders of our section, so the message can be properly
@@ -53,10 +54,10 @@ pub(crate) fn delivery_targets(
 
     let (best_section, dg_size) = match dst {
         DstLocation::Section(target_name) => {
-            section_candidates(target_name, our_name, section, network)?
+            section_candidates(target_name, our_name, section, network, network_params)?
         }
         DstLocation::EndUser(user) => {
-            section_candidates(&user.xorname, our_name, section, network)?
+            section_candidates(&user.xorname, our_name, section, network, network_params)?
         }
         DstLocation::Node(target_name) => {
             if target_name == our_name {
@@ -66,7 +67,7 @@ pub(crate) fn delivery_targets(
                 return Ok((vec![node], 1));
             }
 
-            candidates(target_name, our_name, section, network)?
+            candidates(target_name, our_name, section, network, network_params)?
         }
         DstLocation::DirectAndUnrouted => return Err(Error::CannotRoute),
     };
@@ -79,6 +80,7 @@ fn section_candidates(
     our_name: &XorName,
     section: &Section,
     network: &Network,
+    network_params: &NetworkParams,
 ) -> Result<(Vec<Peer>, usize)> {
     // Find closest section to `target_name` out of the ones we know (including our own)
     let info = iter::once(section.authority_provider())
@@ -96,7 +98,7 @@ fn section_candidates(
         return Ok((chosen_section, dg_size));
     }
 
-    candidates(target_name, our_name, section, network)
+    candidates(target_name, our_name, section, network, network_params)
 }
 
 // Obtain the delivery group candidates for this target
@@ -105,6 +107,7 @@ fn candidates(
     our_name: &XorName,
     section: &Section,
     network: &Network,
+    network_params: &NetworkParams,
 ) -> Result<(Vec<Peer>, usize)> {
     // All sections we know (including our own), sorted by distance to `target_name`.
     let sections = iter::once(section.authority_provider())
@@ -113,7 +116,8 @@ fn candidates(
         .map(|info| (&info.prefix, info.elder_count(), info.peers()));
 
     // gives at least 1 honest target among recipients.
-    let min_dg_size = 1 + ELDER_SIZE - supermajority(ELDER_SIZE);
+    let elder_size = network_params.elder_size;
+    let min_dg_size = 1 + elder_size - supermajority(elder_size);
     let mut dg_size = min_dg_size;
     let mut candidates = Vec::new();
     for (idx, (prefix, len, connected)) in sections.enumerate() {
@@ -172,6 +176,7 @@ mod tests {
             test_utils::{gen_addr, gen_section_authority_provider},
             MemberInfoUtils, SectionAuthorityProviderUtils, MIN_ADULT_AGE,
         },
+        ELDER_SIZE,
     };
     use anyhow::{Context, Result};
     use rand::seq::IteratorRandom;
@@ -192,7 +197,13 @@ mod tests {
             .context("too few elders")?;
 
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send only to the dst node.
         assert_eq!(dg_size, 1);
@@ -213,7 +224,13 @@ mod tests {
         assert!(section.update_member(member_info));
 
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send only to the dst node.
         assert_eq!(dg_size, 1);
@@ -228,7 +245,13 @@ mod tests {
 
         let dst_name = section.prefix().substituted_in(rand::random());
         let dst = DstLocation::Section(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all our elders except us.
         let expected_recipients = section
@@ -256,7 +279,13 @@ mod tests {
 
         let dst_name = choose_elder_name(section_auth1)?;
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send only to the dst node.
         assert_eq!(dg_size, 1);
@@ -275,7 +304,13 @@ mod tests {
 
         let dst_name = section_auth1.prefix.substituted_in(rand::random());
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders in the dst section
         let expected_recipients = section_auth1
@@ -301,7 +336,13 @@ mod tests {
             .pushed(false)
             .substituted_in(rand::random());
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders in the dst section
         let expected_recipients = elders_info1
@@ -325,7 +366,13 @@ mod tests {
 
         let dst_name = section_auth1.prefix.substituted_in(rand::random());
         let dst = DstLocation::Section(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders in the final dst section
         let expected_recipients = section_auth1
@@ -351,7 +398,13 @@ mod tests {
             .pushed(false)
             .substituted_in(rand::random());
         let dst = DstLocation::Section(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to a subset of elders in the intermediary dst section
         let min_dg_size =
@@ -373,7 +426,13 @@ mod tests {
 
         let dst_name = choose_elder_name(section.authority_provider())?;
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders
         assert_eq!(dg_size, section.authority_provider().elder_count());
@@ -388,7 +447,13 @@ mod tests {
 
         let dst_name = section.prefix().substituted_in(rand::random());
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders
         assert_eq!(dg_size, section.authority_provider().elder_count());
@@ -403,7 +468,13 @@ mod tests {
 
         let dst_name = section.prefix().substituted_in(rand::random());
         let dst = DstLocation::Section(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders
         assert_eq!(dg_size, section.authority_provider().elder_count());
@@ -420,7 +491,13 @@ mod tests {
             .pushed(true)
             .substituted_in(rand::random());
         let dst = DstLocation::Node(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders
         assert_eq!(dg_size, section.authority_provider().elder_count());
@@ -437,7 +514,13 @@ mod tests {
             .pushed(true)
             .substituted_in(rand::random());
         let dst = DstLocation::Section(dst_name);
-        let (recipients, dg_size) = delivery_targets(&dst, &our_name, &section, &network)?;
+        let (recipients, dg_size) = delivery_targets(
+            &dst,
+            &our_name,
+            &section,
+            &network,
+            &NetworkParams::default(),
+        )?;
 
         // Send to all elders
         assert_eq!(dg_size, section.authority_provider().elder_count());
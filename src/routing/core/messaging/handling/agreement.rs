@@ -9,17 +9,18 @@
 use std::cmp;
 
 use crate::{
-    agreement::ProvenUtils,
+    agreement::{DkgPrecedence, ProvenUtils},
     error::Result,
-    messages::RoutingMsgUtils,
+    messages::{strip_tag, RoutingMsgUtils, CUSTOM_AGREEMENT_TAG},
     network::NetworkUtils,
     peer::PeerUtils,
-    routing::command::Command,
+    routing::{command::Command, internal_msg::InternalMsg},
     section::{
         ElderCandidatesUtils, SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils,
     },
     Error, Event, MIN_AGE,
 };
+use bytes::Bytes;
 use secured_linked_list::SecuredLinkedList;
 use sn_messaging::{
     node::{MemberInfo, PeerState, PlainMessage, Proposal, Proven, RoutingMsg, Signed, Variant},
@@ -37,6 +38,9 @@ impl Core {
         signed: Signed,
     ) -> Result<Vec<Command>> {
         debug!("handle agreement on {:?}", proposal);
+        self.metrics.record_agreement(&proposal, self.clock.now());
+        self.last_agreement_at = Some(self.clock.now());
+        self.proposal_tracker.complete(&proposal);
 
         match proposal {
             Proposal::Online {
@@ -56,6 +60,11 @@ impl Core {
             Proposal::OurElders(section_auth) => {
                 self.handle_our_elders_agreement(section_auth, signed).await
             }
+            Proposal::AccumulateAtSrc { message, .. }
+                if matches!(message.dst, DstLocation::DirectAndUnrouted) =>
+            {
+                self.handle_custom_agreement(*message, signed)
+            }
             Proposal::AccumulateAtSrc { message, .. } => {
                 let dest_name = if let Some(name) = message.dst.name() {
                     name
@@ -84,6 +93,30 @@ impl Core {
         }
     }
 
+    fn handle_custom_agreement(
+        &self,
+        message: PlainMessage,
+        signed: Signed,
+    ) -> Result<Vec<Command>> {
+        let payload = match &message.variant {
+            Variant::UserMessage(content) => strip_tag(CUSTOM_AGREEMENT_TAG, content),
+            _ => None,
+        };
+
+        if let Some(payload) = payload {
+            Ok(vec![Command::SendEvent(Event::CustomAgreement {
+                payload: Bytes::copy_from_slice(payload),
+                signed,
+            })])
+        } else {
+            error!(
+                "Not handling AccumulateAtSrc {:?}: Not a custom agreement",
+                message
+            );
+            Err(Error::InvalidMessage)
+        }
+    }
+
     async fn handle_online_agreement(
         &mut self,
         new_info: MemberInfo,
@@ -110,7 +143,7 @@ impl Core {
             if new_age > MIN_AGE {
                 // TODO: consider handling the relocation inside the bootstrap phase, to avoid
                 // having to send this `NodeApproval`.
-                commands.push(self.send_node_approval(old_info.clone())?);
+                commands.extend(self.send_node_approval(old_info.clone())?);
                 commands.extend(self.relocate_rejoining_peer(&old_info.value.peer, new_age)?);
 
                 return Ok(commands);
@@ -145,9 +178,9 @@ impl Core {
         }
 
         commands.extend(result);
-        commands.push(self.send_node_approval(new_info)?);
+        commands.extend(self.send_node_approval(new_info)?);
 
-        self.print_network_stats();
+        self.update_network_stats();
 
         Ok(commands)
     }
@@ -163,10 +196,11 @@ impl Core {
         let age = peer.age();
         let signature = signed.signature.clone();
 
-        if !self.section.update_member(Proven {
+        let member_info = Proven {
             value: member_info,
             signed,
-        }) {
+        };
+        if !self.section.update_member(member_info.clone()) {
             info!("ignore Offline: {:?}", peer);
             return Ok(commands);
         }
@@ -182,6 +216,28 @@ impl Core {
 
         commands.extend(result);
 
+        // Let a peer leaving the section gracefully know its departure has been agreed on, as it
+        // waits for this before shutting down.
+        if peer.name() == &self.node.name() {
+            self.left = true;
+        } else if let Some(last_known_key) = self.leaving.remove(peer.name()) {
+            // Fall back to the whole chain if the peer's key is not in ours.
+            let proof_chain = self
+                .section
+                .chain()
+                .get_proof_chain_to_current(&last_known_key)
+                .unwrap_or_else(|_| self.section.chain().clone());
+            let left = InternalMsg::Left {
+                member_info,
+                proof_chain,
+            };
+            commands.push(self.send_direct_message(
+                (*peer.name(), *peer.addr()),
+                left.to_variant()?,
+                *self.section.chain().last_key(),
+            )?);
+        }
+
         self.send_event(Event::MemberLeft {
             name: *peer.name(),
             age,
@@ -205,7 +261,11 @@ impl Core {
         if equal_or_extension {
             // Our section of sub-section
 
-            let infos = self.section.promote_and_demote_elders(&self.node.name());
+            let infos = self.section.promote_and_demote_elders(
+                &self.network_params,
+                &self.node.name(),
+                self.dkg_restarts.excluded(),
+            );
             if !infos.contains(&section_auth.value.elder_candidates()) {
                 // SectionInfo out of date, ignore.
                 return Ok(commands);
@@ -235,7 +295,7 @@ impl Core {
                     len,
                     sync_message,
                     DestInfo {
-                        dest: XorName::random(),
+                        dest: self.random_name(),
                         dest_section_pk: signed.public_key,
                     },
                 ));
@@ -263,9 +323,34 @@ impl Core {
         section_auth: Proven<SectionAuthorityProvider>,
         key_signed: Signed,
     ) -> Result<Vec<Command>> {
-        let updates = self
-            .split_barrier
-            .process(self.section.prefix(), section_auth, key_signed);
+        // The DKG session that generated the new key started when the key that signed it was our
+        // last one.
+        let generation = if let Ok(chain) = self
+            .section
+            .chain()
+            .get_proof_chain_to_current(&key_signed.public_key)
+        {
+            (self.section.chain().main_branch_len() - chain.main_branch_len() + 1) as u64
+        } else {
+            trace!(
+                "Ignoring OurElders agreement signed with a key not in our main branch: {:?}",
+                key_signed.public_key
+            );
+            return Ok(vec![]);
+        };
+        let precedence = DkgPrecedence::new(&section_auth.value.elder_candidates(), generation);
+
+        if self.is_superseded_by_our_elders(&section_auth.value, precedence) {
+            trace!(
+                "Ignoring OurElders agreement superseded by our current elders: {:?}",
+                section_auth.value
+            );
+            return Ok(vec![]);
+        }
+
+        let updates =
+            self.split_barrier
+                .process(self.section.prefix(), section_auth, key_signed, precedence);
         if updates.is_empty() {
             return Ok(vec![]);
         }
@@ -287,6 +372,31 @@ impl Core {
         self.update_state(snapshot).await
     }
 
+    // Returns whether the DKG outcome `section_auth` with the given precedence competes with the
+    // one of our current elders. Once a generation is decided, which is when the first of its
+    // outcomes is agreed and inserted into our section chain, its competing outcomes are refused:
+    // inserting them would fork the chain and let the chain's fork choice, rather than
+    // `DkgPrecedence`, pick our section key.
+    fn is_superseded_by_our_elders(
+        &self,
+        section_auth: &SectionAuthorityProvider,
+        precedence: DkgPrecedence,
+    ) -> bool {
+        let our_section_auth = self.section.authority_provider();
+        if section_auth.section_key() == our_section_auth.section_key()
+            || !section_auth
+                .prefix()
+                .is_compatible(&our_section_auth.prefix())
+        {
+            // Not competing: either the same outcome or the other half of a split.
+            return false;
+        }
+
+        // Generation of the DKG session that generated our current key.
+        let our_generation = self.section.chain().main_branch_len() as u64 - 1;
+        precedence.generation() <= our_generation
+    }
+
     fn handle_accumulate_at_src_agreement(
         &self,
         message: PlainMessage,
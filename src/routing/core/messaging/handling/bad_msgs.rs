@@ -10,7 +10,7 @@ use super::Core;
 use crate::{
     messages::{RoutingMsgUtils, SrcAuthorityUtils},
     peer::PeerUtils,
-    routing::command::Command,
+    routing::{command::Command, message_journal::Hop},
     section::{SectionAuthorityProviderUtils, SectionUtils},
     Error, Result,
 };
@@ -30,6 +30,8 @@ impl Core {
         msg: RoutingMsg,
         received_dest_info: DestInfo,
     ) -> Result<Command> {
+        self.trace_hop(msg.id, Hop::BouncedUntrusted { sender });
+
         let src_name = msg.src.name();
         let bounce_dst_key = self.section_key_by_name(&src_name);
         let dest_info = DestInfo {
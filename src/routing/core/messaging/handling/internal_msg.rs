@@ -0,0 +1,277 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::Core;
+use crate::{
+    agreement::{payload_digest, SignedShare},
+    ed25519::Digest256,
+    error::{Error, Result},
+    messages::RoutingMsgUtils,
+    routing::{
+        command::Command,
+        decryption::{ciphertext_digest, DecryptionRequest},
+        internal_msg::InternalMsg,
+        section_signature,
+    },
+    section::{SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils},
+};
+use bytes::Bytes;
+use secured_linked_list::SecuredLinkedList;
+use sn_messaging::{
+    node::{Peer, RoutingMsg},
+    DstLocation,
+};
+use std::net::SocketAddr;
+use xor_name::XorName;
+
+// Internal messages, mostly the services our elders provide to the members of our section with
+// their key shares.
+impl Core {
+    pub(crate) fn handle_internal_msg(
+        &mut self,
+        sender: Option<SocketAddr>,
+        src_name: XorName,
+        content: &[u8],
+    ) -> Result<Vec<Command>> {
+        match InternalMsg::from_content(content)? {
+            InternalMsg::SignatureRequest {
+                data,
+                last_known_key,
+            } => {
+                let sender = sender.ok_or(Error::InvalidSrcLocation)?;
+                self.handle_section_signature_request(
+                    Peer::new(src_name, sender),
+                    data,
+                    last_known_key,
+                )
+            }
+            InternalMsg::SignatureResponse {
+                digest,
+                signed_share,
+                proof_chain,
+            } => {
+                self.handle_section_signature_response(
+                    src_name,
+                    digest,
+                    signed_share,
+                    proof_chain,
+                )?;
+                Ok(vec![])
+            }
+            InternalMsg::DecryptionRequest {
+                ciphertext,
+                section_key,
+                context,
+            } => {
+                let sender = sender.ok_or(Error::InvalidSrcLocation)?;
+                self.handle_decryption_request(
+                    Peer::new(src_name, sender),
+                    ciphertext,
+                    section_key,
+                    context,
+                )
+            }
+            InternalMsg::DecryptionResponse {
+                digest,
+                public_key_set,
+                index,
+                decryption_share,
+            } => {
+                if !self.section.authority_provider().contains_elder(&src_name) {
+                    debug!("Ignoring decryption share from non-elder {}", src_name);
+                    return Ok(vec![]);
+                }
+
+                self.decryption_requests.handle_response(
+                    &digest,
+                    public_key_set,
+                    index,
+                    decryption_share,
+                )?;
+                Ok(vec![])
+            }
+            InternalMsg::Leaving { last_known_key } => {
+                self.handle_leaving(src_name, last_known_key)
+            }
+            InternalMsg::Left {
+                member_info,
+                proof_chain,
+            } => {
+                self.handle_left(src_name, member_info, proof_chain);
+                Ok(vec![])
+            }
+            InternalMsg::NetworkParams(_) => {
+                // Only of interest to a node while it is joining.
+                trace!("Ignoring network parameters from {}", src_name);
+                Ok(vec![])
+            }
+        }
+    }
+
+    fn handle_section_signature_request(
+        &self,
+        requester: Peer,
+        data: Bytes,
+        last_known_key: bls::PublicKey,
+    ) -> Result<Vec<Command>> {
+        if !self.is_authorised_requester(&requester) {
+            return Ok(vec![]);
+        }
+
+        let section_key = *self.section.chain().last_key();
+        let key_share = self
+            .section_keys_provider
+            .key_shares()
+            .find(|key_share| key_share.public_key_set.public_key() == section_key)
+            .ok_or(Error::MissingSecretKeyShare)?;
+
+        let signed_share = SignedShare {
+            public_key_set: key_share.public_key_set.clone(),
+            index: key_share.index,
+            signature_share: self
+                .section_keys_provider
+                .sign_with(&section_signature::signable_bytes(&data), &section_key)?,
+        };
+
+        // Fall back to the whole chain if the requester's key is not in ours, e.g. because it
+        // comes from a fork.
+        let proof_chain = self
+            .section
+            .chain()
+            .get_proof_chain_to_current(&last_known_key)
+            .unwrap_or_else(|_| self.section.chain().clone());
+
+        let response = InternalMsg::SignatureResponse {
+            digest: payload_digest(&data),
+            signed_share,
+            proof_chain,
+        };
+
+        self.send_internal_response(response, requester)
+    }
+
+    fn handle_section_signature_response(
+        &mut self,
+        src_name: XorName,
+        digest: Digest256,
+        signed_share: SignedShare,
+        proof_chain: SecuredLinkedList,
+    ) -> Result<()> {
+        if !self.section.authority_provider().contains_elder(&src_name) {
+            debug!(
+                "Ignoring section signature share from non-elder {}",
+                src_name
+            );
+            return Ok(());
+        }
+
+        if !self.section.chain().has_key(proof_chain.root_key()) || !proof_chain.self_verify() {
+            error!("Section signature share with an untrusted proof chain");
+            return Err(Error::InvalidSignatureShare);
+        }
+
+        self.section_signature_requests.handle_response(
+            &digest,
+            signed_share,
+            proof_chain,
+            self.clock.now(),
+        )
+    }
+
+    fn handle_decryption_request(
+        &self,
+        requester: Peer,
+        ciphertext: bls::Ciphertext,
+        section_key: bls::PublicKey,
+        context: Bytes,
+    ) -> Result<Vec<Command>> {
+        if !self.is_authorised_requester(&requester) {
+            return Ok(vec![]);
+        }
+
+        let request = DecryptionRequest {
+            requester: *requester.name(),
+            section_key: &section_key,
+            ciphertext: &ciphertext,
+            context: &context,
+        };
+        if !self
+            .decryption_policy
+            .as_ref()
+            .map_or(false, |policy| policy.authorise(&request))
+        {
+            debug!(
+                "Decryption request from {} not authorised by our policy",
+                requester.name()
+            );
+            return Ok(vec![]);
+        }
+
+        // Only keys of our section, not the pending ones of an unfinished DKG. The requester
+        // checks the key isn't older than those we keep, so we only miss it if we became an
+        // elder after it was replaced.
+        let key_share = if let Some(key_share) = self
+            .section_keys_provider
+            .key_shares()
+            .find(|key_share| key_share.public_key_set.public_key() == section_key)
+        {
+            key_share
+        } else {
+            debug!(
+                "Not serving decryption request: no key share of {:?}",
+                section_key
+            );
+            return Ok(vec![]);
+        };
+
+        let response = InternalMsg::DecryptionResponse {
+            digest: ciphertext_digest(&ciphertext)?,
+            public_key_set: key_share.public_key_set.clone(),
+            index: key_share.index,
+            decryption_share: self
+                .section_keys_provider
+                .decrypt_share_with(&ciphertext, &section_key)?,
+        };
+
+        self.send_internal_response(response, requester)
+    }
+
+    // Only our elders serve requests, and only those of our members, as anyone holding the
+    // responses can use the section's signature or read the plaintext.
+    fn is_authorised_requester(&self, requester: &Peer) -> bool {
+        if !self.is_elder() {
+            trace!("Ignoring section service request as we are not an elder");
+            return false;
+        }
+
+        if !self.section.members().is_joined(requester.name()) {
+            debug!(
+                "Ignoring section service request from non-member {}",
+                requester.name()
+            );
+            return false;
+        }
+
+        true
+    }
+
+    fn send_internal_response(
+        &self,
+        response: InternalMsg,
+        requester: Peer,
+    ) -> Result<Vec<Command>> {
+        let message = RoutingMsg::single_src(
+            &self.node,
+            DstLocation::Node(*requester.name()),
+            response.to_variant()?,
+            self.section.authority_provider().section_key(),
+        )?;
+
+        Ok(self.send_or_handle(message, &[requester]))
+    }
+}
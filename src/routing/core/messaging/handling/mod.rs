@@ -9,26 +9,26 @@
 mod agreement;
 mod bad_msgs;
 mod decisions;
+mod internal_msg;
 mod relocation;
 mod resource_proof;
 
 use super::super::Core;
 use crate::{
-    agreement::{DkgCommands, ProposalError, SignedShare},
+    agreement::{AggregatorError, DkgCommands, DkgPrecedence, ProposalError, SignedShare},
     error::{Error, Result},
     event::Event,
-    messages::{MessageStatus, RoutingMsgUtils, SrcAuthorityUtils, VerifyStatus},
+    messages::{
+        is_reserved, MessageStatus, RoutingMsgUtils, SrcAuthorityUtils, VerifyStatus,
+        PROTOCOL_PREFIX,
+    },
     network::NetworkUtils,
     peer::PeerUtils,
     relocation::{RelocatePayloadUtils, RelocateState, SignedRelocateDetailsUtils},
-    routing::command::Command,
-    section::{
-        SectionAuthorityProviderUtils, SectionKeyShare, SectionPeersUtils, SectionUtils,
-        FIRST_SECTION_MAX_AGE, FIRST_SECTION_MIN_AGE, MIN_ADULT_AGE,
-    },
+    routing::{command::Command, internal_msg::InternalMsg, message_journal::Hop},
+    section::{SectionAuthorityProviderUtils, SectionKeyShare, SectionPeersUtils, SectionUtils},
 };
 use bytes::Bytes;
-use sn_messaging::node::Error as AggregatorError;
 use sn_messaging::{
     client::ClientMsg,
     node::{
@@ -49,6 +49,8 @@ impl Core {
         msg: RoutingMsg,
         dest_info: DestInfo,
     ) -> Result<Vec<Command>> {
+        self.trace_hop(msg.id, Hop::Received { sender });
+
         let mut commands = vec![];
 
         // Check if the message is for us.
@@ -66,7 +68,17 @@ impl Core {
             return Ok(commands);
         }
 
-        match self.decide_message_status(&msg)? {
+        let status = self.decide_message_status(&msg)?;
+        if matches!(status, MessageStatus::Useful | MessageStatus::Untrusted)
+            && matches!(msg.src, SrcAuthority::Node { .. })
+        {
+            // The sender addressed the message with what it believes our section key to be. Coming
+            // from one of our elders, this tells whether we are behind them, also on adults which
+            // don't see the proposals of the elders.
+            self.record_elder_key(msg.src.name(), dest_info.dest_section_pk);
+        }
+
+        match status {
             MessageStatus::Useful => {
                 trace!("Useful message from {:?}: {:?}", sender, msg);
                 let (entropy_commands, shall_be_handled) =
@@ -166,9 +178,29 @@ impl Core {
     }
 
     pub(crate) fn handle_timeout(&mut self, token: u64) -> Result<Vec<Command>> {
+        if token == self.end_user_eviction_token {
+            return Ok(self.evict_idle_end_users());
+        }
+
+        if let Some(timeout) = self
+            .proposal_tracker
+            .handle_timeout(token, self.clock.now())
+        {
+            return Ok(self.handle_proposal_timeout(timeout));
+        }
+
+        if let Some(elder_candidates) = self.dkg_restarts.handle_timeout(token) {
+            return self.handle_dkg_restart_timeout(elder_candidates);
+        }
+
+        if self.dkg_restarts.handle_cooldown_timeout(token) {
+            debug!("Starting DKG over after the cool-down");
+            return self.promote_and_demote_elders();
+        }
+
         self.dkg_voter
             .handle_timeout(&self.node.keypair, token)
-            .into_commands(&self.node, *self.section_chain().last_key())
+            .into_commands(&self.node, *self.section_chain().last_key(), &self.rng)
     }
 
     // Insert the proposal into the proposal aggregator and handle it if aggregated.
@@ -177,11 +209,14 @@ impl Core {
         proposal: Proposal,
         signed_share: SignedShare,
     ) -> Result<Vec<Command>> {
-        match self.proposal_aggregator.add(proposal, signed_share) {
+        let result = self
+            .proposal_aggregator
+            .add(proposal, signed_share, self.clock.now());
+        self.record_aggregations();
+
+        match result {
             Ok((proposal, signed)) => Ok(vec![Command::HandleAgreement { proposal, signed }]),
-            Err(ProposalError::Aggregation(sn_messaging::node::Error::NotEnoughShares)) => {
-                Ok(vec![])
-            }
+            Err(ProposalError::Aggregation(AggregatorError::NotEnoughShares)) => Ok(vec![]),
             Err(error) => {
                 error!("Failed to add proposal: {}", error);
                 Err(Error::InvalidSignatureShare)
@@ -192,18 +227,31 @@ impl Core {
     pub(crate) fn handle_dkg_outcome(
         &mut self,
         section_auth: SectionAuthorityProvider,
+        generation: u64,
         key_share: SectionKeyShare,
     ) -> Result<Vec<Command>> {
+        let public_key = key_share.public_key_set.public_key();
+        let elder_candidates = section_auth.elder_candidates();
+        let precedence = DkgPrecedence::new(&elder_candidates, generation);
+
+        if self.dkg_voter.is_outcome_superseded(&precedence) {
+            trace!(
+                "Ignoring DKG outcome superseded by a pending one: {:?}",
+                public_key
+            );
+            return Ok(vec![]);
+        }
+
         let proposal = Proposal::SectionInfo(section_auth);
         let recipients: Vec<_> = self.section.authority_provider().peers().collect();
         let result = self.send_proposal_with(&recipients, proposal, &key_share);
 
-        let public_key = key_share.public_key_set.public_key();
-
-        self.section_keys_provider.insert_dkg_outcome(key_share);
-
         if self.section.chain().has_key(&public_key) {
-            self.section_keys_provider.finalise_dkg(&public_key)
+            self.section_keys_provider.insert(key_share);
+        } else {
+            let _ = self
+                .dkg_voter
+                .insert_outcome(&elder_candidates, generation, key_share);
         }
 
         result
@@ -230,12 +278,15 @@ impl Core {
 
         let signed_bytes =
             bincode::serialize(&msg.signable_view()).map_err(|_| Error::InvalidMessage)?;
-        match self
-            .message_aggregator
-            .add(&signed_bytes, signed_share.clone())
-        {
+        let result =
+            self.message_aggregator
+                .add(&signed_bytes, signed_share.clone(), self.clock.now());
+        self.record_aggregations();
+
+        match result {
             Ok(signed) => {
                 trace!("Successfully accumulated signatures for message: {:?}", msg);
+                self.trace_hop(msg.id, Hop::Aggregated);
                 Ok(Some(msg.into_dst_accumulated(signed)?))
             }
             Err(AggregatorError::NotEnoughShares) => Ok(None),
@@ -296,6 +347,25 @@ impl Core {
                 let sender = sender.ok_or(Error::InvalidSrcLocation)?;
                 self.handle_join_request(msg.src.peer(sender)?, *join_request.clone())
             }
+            Variant::UserMessage(content) if InternalMsg::is_internal(content) => {
+                if msg.src.is_section() {
+                    return Err(Error::InvalidSrcLocation);
+                }
+                self.handle_internal_msg(sender, src_name, content)
+            }
+            Variant::UserMessage(content) if is_reserved(content) => {
+                // Users can't send such content, so it's ours, but of a protocol version or kind
+                // we don't know. Don't pass it off as a user message.
+                if content.starts_with(PROTOCOL_PREFIX) {
+                    debug!("Dropping message from {} with an unknown tag", src_name);
+                } else {
+                    warn!(
+                        "Dropping message from {} of another protocol version",
+                        src_name
+                    );
+                }
+                Ok(vec![])
+            }
             Variant::UserMessage(content) => {
                 let bytes = Bytes::from(content.clone());
                 self.handle_user_message(msg, bytes).await
@@ -344,6 +414,13 @@ impl Core {
                 signed_share,
             } => {
                 let mut commands = vec![];
+                self.record_elder_key(src_name, signed_share.public_key_set.public_key());
+                if self.proposal_tracker.is_awaiting(content, &src_name) {
+                    commands.push(Command::HandleProposalResponse {
+                        proposal: content.clone(),
+                        elder: src_name,
+                    });
+                }
                 let result = self.handle_proposal(content.clone(), signed_share.clone());
 
                 if let Some(addr) = sender {
@@ -448,8 +525,9 @@ impl Core {
             socket_id,
         }) = msg.dst
         {
-            if let Some(socket_addr) = self.get_socket_addr(socket_id).copied() {
+            if let Some(socket_addr) = self.get_socket_addr(socket_id) {
                 trace!("sending user message {:?} to client {:?}", msg, socket_addr);
+                self.trace_hop(msg.id, Hop::Delivered);
                 return Ok(vec![Command::SendMessage {
                     recipients: vec![(xor_name, socket_addr)],
                     delivery_group_size: 1,
@@ -470,6 +548,7 @@ impl Core {
             }
         }
 
+        self.trace_hop(msg.id, Hop::Delivered);
         self.send_event(Event::MessageReceived {
             content,
             src: msg.src.src_location(),
@@ -551,11 +630,13 @@ impl Core {
                 self.section.authority_provider().clone(),
             )));
             trace!("Sending {:?} to {}", variant, peer);
-            return Ok(vec![self.send_direct_message(
+            let mut commands = vec![self.send_direct_message(
                 (*peer.name(), *peer.addr()),
                 variant,
                 *self.section.chain().last_key(),
-            )?]);
+            )?];
+            commands.extend(self.send_network_params(&peer)?);
+            return Ok(commands);
         }
 
         if self.section.members().is_joined(peer.name()) {
@@ -619,7 +700,7 @@ impl Core {
                 )?]);
             } else {
                 // Start as Adult as long as passed resource signeding.
-                (MIN_ADULT_AGE, None, None)
+                (self.network_params.min_adult_age, None, None)
             };
 
         // Age differentiate only applies to the new node.
@@ -628,24 +709,28 @@ impl Core {
             // relocated at the same time. After the first section got split, later on nodes shall
             // only start with age of MIN_ADULT_AGE
             if self.section.prefix().is_empty() {
-                if peer.age() < FIRST_SECTION_MIN_AGE || peer.age() > FIRST_SECTION_MAX_AGE {
+                if peer.age() < self.network_params.first_section_min_age
+                    || peer.age() > self.network_params.first_section_max_age
+                {
                     debug!(
                         "Ignoring JoinRequest from {} - first-section node having wrong age {:?}",
                         peer,
                         peer.age(),
                     );
-                    return Ok(vec![]);
+                    return Ok(self.send_network_params(&peer)?.into_iter().collect());
                 } else {
                     age = peer.age();
                 }
-            } else if peer.age() != MIN_ADULT_AGE {
-                // After section split, new node has to join with age of MIN_ADULT_AGE.
+            } else if peer.age() != self.network_params.min_adult_age {
+                // After section split, new node has to join with age of `min_adult_age`. A node
+                // with a different age most likely uses different network parameters, so let it
+                // know ours.
                 debug!(
                     "Ignoring JoinRequest from {} - non-first-section node having wrong age {:?}",
                     peer,
                     peer.age(),
                 );
-                return Ok(vec![]);
+                return Ok(self.send_network_params(&peer)?.into_iter().collect());
             }
         }
 
@@ -669,7 +754,9 @@ impl Core {
                     return Ok(vec![]);
                 }
             } else {
-                return Ok(vec![self.send_resource_proof_challenge(&peer)?]);
+                let mut commands = vec![self.send_resource_proof_challenge(&peer)?];
+                commands.extend(self.send_network_params(&peer)?);
+                return Ok(commands);
             }
         }
 
@@ -680,12 +767,38 @@ impl Core {
         }])
     }
 
+    // Sends our network parameters, proven by the genesis key, to the joining node so it can
+    // verify and adopt them. Nothing to send if we don't know the proof.
+    pub(crate) fn send_network_params(&self, peer: &Peer) -> Result<Option<Command>> {
+        let params = if let Some(params) = self.proven_network_params() {
+            params
+        } else {
+            warn!("Not sending network parameters to {} - no proof", peer);
+            return Ok(None);
+        };
+
+        Ok(Some(self.send_direct_message(
+            (*peer.name(), *peer.addr()),
+            InternalMsg::NetworkParams(params).to_variant()?,
+            *self.section.chain().last_key(),
+        )?))
+    }
+
     // Generate a new section info based on the current set of members and if it differs from the
-    // current elders, trigger a DKG.
+    // current elders, trigger a DKG. Nothing is triggered while the DKG attempts are exhausted.
     pub(crate) fn promote_and_demote_elders(&mut self) -> Result<Vec<Command>> {
+        if self.dkg_restarts.is_exhausted() {
+            trace!("Not starting DKG, the attempts are exhausted");
+            return Ok(vec![]);
+        }
+
         let mut commands = vec![];
 
-        for info in self.section.promote_and_demote_elders(&self.node.name()) {
+        for info in self.section.promote_and_demote_elders(
+            &self.network_params,
+            &self.node.name(),
+            self.dkg_restarts.excluded(),
+        ) {
             commands.extend(self.send_dkg_start(info)?);
         }
 
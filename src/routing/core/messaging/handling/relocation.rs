@@ -15,7 +15,7 @@ use crate::{
     },
     routing::command::Command,
     section::{MemberInfoUtils, SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils},
-    Event, ELDER_SIZE,
+    Event,
 };
 use sn_messaging::node::{
     Peer, Proposal, RelocateDetails, RelocatePromise, RoutingMsg, SignedRelocateDetails,
@@ -33,7 +33,7 @@ impl Core {
         let mut commands = vec![];
 
         // Do not carry out relocation when there is not enough elder nodes.
-        if self.section.authority_provider().elder_count() < ELDER_SIZE {
+        if self.section.authority_provider().elder_count() < self.network_params.elder_size {
             return Ok(commands);
         }
 
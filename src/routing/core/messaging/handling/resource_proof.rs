@@ -10,14 +10,12 @@ use super::Core;
 use crate::{
     ed25519,
     peer::PeerUtils,
-    routing::{
-        command::Command,
-        core::{RESOURCE_PROOF_DATA_SIZE, RESOURCE_PROOF_DIFFICULTY},
-    },
+    routing::{command::Command, core::RESOURCE_PROOF_DATA_SIZE},
     section::SectionUtils,
     Error, Result,
 };
 use ed25519_dalek::Verifier;
+use rand::Rng;
 use sn_messaging::node::{JoinResponse, Peer, ResourceProofResponse, Variant};
 use xor_name::XorName;
 
@@ -49,12 +47,12 @@ impl Core {
     }
 
     pub(crate) fn send_resource_proof_challenge(&self, peer: &Peer) -> Result<Command> {
-        let nonce: [u8; 32] = rand::random();
+        let nonce: [u8; 32] = self.rng.clone().gen();
         let serialized =
             bincode::serialize(&(peer.name(), &nonce)).map_err(|_| Error::InvalidMessage)?;
         let response = Variant::JoinResponse(Box::new(JoinResponse::ResourceChallenge {
             data_size: RESOURCE_PROOF_DATA_SIZE,
-            difficulty: RESOURCE_PROOF_DIFFICULTY,
+            difficulty: self.network_params.resource_proof_difficulty,
             nonce,
             nonce_signature: ed25519::sign(&serialized, &self.node.keypair),
         }));
@@ -13,8 +13,10 @@ use super::Core;
 use crate::{
     agreement::{ProposalUtils, SignedShare},
     error::Result,
+    event::Event,
     messages::RoutingMsgUtils,
-    routing::command::Command,
+    peer::PeerUtils,
+    routing::{command::Command, proposal_tracker::ProposalTimeout},
     section::{SectionAuthorityProviderUtils, SectionKeyShare, SectionUtils},
 };
 use sn_messaging::{
@@ -63,9 +65,11 @@ impl Core {
             &key_share.secret_key_share,
         )?;
 
+        self.metrics.record_proposal(&proposal, self.clock.now());
+
         // Broadcast the proposal to the rest of the section elders.
         let variant = Variant::Propose {
-            content: proposal,
+            content: proposal.clone(),
             signed_share,
         };
         let message = RoutingMsg::single_src(
@@ -75,7 +79,73 @@ impl Core {
             self.section.authority_provider().section_key(),
         )?;
 
-        Ok(self.send_or_handle(message, recipients))
+        let mut commands = self.send_or_handle(message.clone(), recipients);
+
+        // Keep re-sending the proposal to the other elders until they agree on it.
+        let others: Vec<_> = recipients
+            .iter()
+            .filter(|peer| peer.name() != &self.node.name())
+            .copied()
+            .collect();
+        if !others.is_empty() {
+            commands.push(Command::TrackProposal {
+                proposal,
+                key: key_share.public_key_set.public_key(),
+                message,
+                recipients: others,
+            });
+        }
+
+        Ok(commands)
+    }
+
+    // Starts tracking our proposal and returns the command to schedule its first re-send, if it's
+    // not tracked already.
+    pub(crate) fn track_proposal(
+        &mut self,
+        proposal: &Proposal,
+        key: bls::PublicKey,
+        message: RoutingMsg,
+        recipients: Vec<Peer>,
+    ) -> Option<Command> {
+        self.proposal_tracker
+            .track(proposal, key, message, recipients, self.clock.now())
+    }
+
+    pub(crate) fn handle_proposal_response(&mut self, proposal: &Proposal, elder: XorName) {
+        self.proposal_tracker.record_response(proposal, elder)
+    }
+
+    // Re-sends our proposal to the elders that didn't respond to it yet or gives up on it if it
+    // stalled.
+    pub(crate) fn handle_proposal_timeout(&self, timeout: ProposalTimeout) -> Vec<Command> {
+        match timeout {
+            ProposalTimeout::Retry {
+                message,
+                recipients,
+                next,
+            } => {
+                trace!("Re-sending proposal to {:?}", recipients);
+                let mut commands = self.send_or_handle(message, &recipients);
+                commands.push(next);
+                commands
+            }
+            ProposalTimeout::Stalled {
+                proposal,
+                non_responding,
+                elapsed,
+            } => {
+                warn!(
+                    "Giving up on {:?} after {:?}, no response from {:?}",
+                    proposal, elapsed, non_responding
+                );
+                vec![Command::SendEvent(Event::ProposalStalled {
+                    proposal,
+                    non_responding,
+                    elapsed,
+                })]
+            }
+        }
     }
 
     // ------------------------------------------------------------------------------------------------------------
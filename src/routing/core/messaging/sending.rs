@@ -30,16 +30,21 @@ use xor_name::XorName;
 
 // RoutingMsg sending
 impl Core {
-    // Send NodeApproval to a joining node which makes them a section member
-    pub(crate) fn send_node_approval(&self, member_info: Proven<MemberInfo>) -> Result<Command> {
+    // Send NodeApproval to a joining node which makes them a section member, together with the
+    // network parameters it needs to verify before accepting it.
+    pub(crate) fn send_node_approval(
+        &self,
+        member_info: Proven<MemberInfo>,
+    ) -> Result<Vec<Command>> {
         info!(
             "Our section with {:?} has approved peer {:?}.",
             self.section.prefix(),
             member_info.value.peer
         );
 
-        let addr = *member_info.value.peer.addr();
-        let name = *member_info.value.peer.name();
+        let peer = member_info.value.peer;
+        let addr = *peer.addr();
+        let name = *peer.name();
 
         let variant = Variant::JoinResponse(Box::new(JoinResponse::Approval {
             genesis_key: *self.section.genesis_key(),
@@ -55,14 +60,16 @@ impl Core {
             self.section.authority_provider().section_key(),
         )?;
 
-        Ok(Command::send_message_to_node(
+        let mut commands = vec![Command::send_message_to_node(
             (name, addr),
             message,
             DestInfo {
                 dest: name,
                 dest_section_pk: *self.section.chain().last_key(),
             },
-        ))
+        )];
+        commands.extend(self.send_network_params(&peer)?);
+        Ok(commands)
     }
 
     pub(crate) fn send_sync(&mut self, section: Section, network: Network) -> Result<Vec<Command>> {
@@ -76,7 +83,7 @@ impl Core {
                 self.section.authority_provider().section_key(),
             )?;
             let dest_info = DestInfo {
-                dest: XorName::random(),
+                dest: self.random_name(),
                 dest_section_pk: *self.section.chain().last_key(),
             };
             Ok(Command::send_message_to_nodes(
@@ -127,7 +134,7 @@ impl Core {
                 recipients.len(),
                 message,
                 DestInfo {
-                    dest: XorName::random(),
+                    dest: self.random_name(),
                     dest_section_pk: *self.section_chain().last_key(),
                 },
             ))
@@ -311,7 +318,7 @@ impl Core {
                 count,
                 message.clone(),
                 DestInfo {
-                    dest: XorName::random(), // will be updated when sending
+                    dest: self.random_name(), // will be updated when sending
                     dest_section_pk,
                 },
             ));
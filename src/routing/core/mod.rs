@@ -12,33 +12,53 @@ mod connectivity;
 mod delivery_group;
 mod messaging;
 
-use super::{command::Command, enduser_registry::EndUserRegistry, split_barrier::SplitBarrier};
+use super::{
+    command::Command,
+    debug_snapshot::{DebugSnapshot, MemberSnapshot, RelocateStateSnapshot},
+    decryption::{DecryptionPolicy, DecryptionRequests},
+    dkg_restarts::DkgRestarts,
+    enduser_registry::{EndUserPolicy, EndUserRegistry},
+    health::{Health, STALE_DKG_SESSION_AGE},
+    message_journal::{Hop, MessageJournal},
+    metrics::Metrics,
+    proposal_tracker::{ProposalRetryPolicy, ProposalTracker},
+    section_signature::SectionSignatureRequests,
+    split_barrier::SplitBarrier,
+};
 use crate::{
-    agreement::{DkgVoter, ProposalAggregator},
+    agreement::{DkgVoter, ProposalAggregator, SignatureAggregator, Signed},
+    clock::{self, Clock, SharedRng},
     error::Result,
     event::{Elders, Event, NodeElderChange},
     message_filter::MessageFilter,
     messages::RoutingMsgUtils,
-    network::NetworkUtils,
+    network::{NetworkStats, NetworkStatsHistory, NetworkUtils},
     node::Node,
+    peer::PeerUtils,
     relocation::RelocateState,
-    section::{SectionAuthorityProviderUtils, SectionKeyShare, SectionKeysProvider, SectionUtils},
+    section::{
+        SectionAuthorityProviderUtils, SectionKeyShare, SectionKeysProvider, SectionPeersUtils,
+        SectionUtils,
+    },
+    NetworkParams,
 };
 use itertools::Itertools;
+use rand::Rng;
 use resource_proof::ResourceProof;
 use secured_linked_list::SecuredLinkedList;
-use sn_messaging::node::SignatureAggregator;
 use sn_messaging::{
     node::{Network, Proposal, Proven, RoutingMsg, Section, Variant},
     DestInfo, DstLocation, MessageId, SectionAuthorityProvider,
 };
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+    time::Instant,
+};
 use tokio::sync::mpsc;
 use xor_name::{Prefix, XorName};
 
 pub const RESOURCE_PROOF_DATA_SIZE: usize = 64;
-pub const RESOURCE_PROOF_DIFFICULTY: u8 = 2;
-const KEY_CACHE_SIZE: u8 = 5;
 
 // State + logic of a routing node.
 pub(crate) struct Core {
@@ -48,15 +68,43 @@ pub(crate) struct Core {
     section_keys_provider: SectionKeysProvider,
     message_aggregator: SignatureAggregator,
     proposal_aggregator: ProposalAggregator,
+    // Our proposals not agreed on yet.
+    proposal_tracker: ProposalTracker,
     split_barrier: SplitBarrier,
+    // Our requests for section signatures over arbitrary data.
+    section_signature_requests: SectionSignatureRequests,
+    // Our requests for decryption of ciphertexts encrypted to our section key.
+    decryption_requests: DecryptionRequests,
+    // Decides which decryption requests we serve as an elder. None without a policy.
+    decryption_policy: Option<Arc<dyn DecryptionPolicy>>,
     // Voter for Dkg
     dkg_voter: DkgVoter,
+    // Failed DKG sessions of the current generation and the restart waiting for its backoff.
+    dkg_restarts: DkgRestarts,
     relocate_state: Option<RelocateState>,
     msg_filter: MessageFilter,
     pub(super) event_tx: mpsc::Sender<Event>,
     joins_allowed: bool,
     resource_proof: ResourceProof,
     end_users: EndUserRegistry,
+    // Token of the timer that triggers eviction of idle end users.
+    end_user_eviction_token: u64,
+    network_params: NetworkParams,
+    // Signature of the genesis key over `network_params`, which lets joining nodes verify them.
+    network_params_proof: Option<Signed>,
+    network_stats_history: NetworkStatsHistory,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
+    metrics: Arc<Metrics>,
+    message_journal: Option<Arc<MessageJournal>>,
+    // Latest section keys our elders signed their proposals with.
+    elder_keys: BTreeMap<XorName, bls::PublicKey>,
+    // When our section last agreed on a proposal.
+    last_agreement_at: Option<Instant>,
+    // Members that announced they are leaving, with the latest key of their section chain.
+    leaving: BTreeMap<XorName, bls::PublicKey>,
+    // Whether our section agreed on us leaving it.
+    left: bool,
 }
 
 impl Core {
@@ -65,9 +113,11 @@ impl Core {
         node: Node,
         section: Section,
         section_key_share: Option<SectionKeyShare>,
+        network_params: NetworkParams,
         event_tx: mpsc::Sender<Event>,
     ) -> Self {
-        let section_keys_provider = SectionKeysProvider::new(KEY_CACHE_SIZE, section_key_share);
+        let section_keys_provider =
+            SectionKeysProvider::new(network_params.key_cache_size, section_key_share);
 
         Self {
             node,
@@ -75,15 +125,35 @@ impl Core {
             network: Network::new(),
             section_keys_provider,
             proposal_aggregator: ProposalAggregator::default(),
+            proposal_tracker: ProposalTracker::new(ProposalRetryPolicy::default()),
             split_barrier: SplitBarrier::new(),
+            section_signature_requests: SectionSignatureRequests::default(),
+            decryption_requests: DecryptionRequests::default(),
+            decryption_policy: None,
             message_aggregator: SignatureAggregator::default(),
             dkg_voter: DkgVoter::default(),
+            dkg_restarts: DkgRestarts::default(),
             relocate_state: None,
-            msg_filter: MessageFilter::new(),
+            msg_filter: MessageFilter::default(),
             event_tx,
             joins_allowed: true,
-            resource_proof: ResourceProof::new(RESOURCE_PROOF_DATA_SIZE, RESOURCE_PROOF_DIFFICULTY),
-            end_users: EndUserRegistry::new(),
+            resource_proof: ResourceProof::new(
+                RESOURCE_PROOF_DATA_SIZE,
+                network_params.resource_proof_difficulty,
+            ),
+            end_users: EndUserRegistry::new(EndUserPolicy::default()),
+            end_user_eviction_token: 0,
+            network_params,
+            network_params_proof: None,
+            network_stats_history: NetworkStatsHistory::default(),
+            clock: clock::default_clock(),
+            rng: SharedRng::from_entropy(),
+            metrics: Arc::new(Metrics::default()),
+            message_journal: None,
+            elder_keys: BTreeMap::new(),
+            last_agreement_at: None,
+            leaving: BTreeMap::new(),
+            left: false,
         }
     }
 
@@ -91,8 +161,34 @@ impl Core {
     // Miscellaneous
     ////////////////////////////////////////////////////////////////////////////
 
+    // Returns a random name from the RNG of the node. Used as the destination of messages sent
+    // directly to nodes, which `Comm::send` replaces with the name of the first recipient.
+    pub(crate) fn random_name(&self) -> XorName {
+        self.rng.clone().gen()
+    }
+
+    // Records a hop of the message with the given id in the message journal, if enabled.
+    pub(crate) fn trace_hop(&self, msg_id: MessageId, hop: Hop) {
+        if let Some(journal) = &self.message_journal {
+            journal.record(self.node.name(), msg_id, hop)
+        }
+    }
+
+    // Records the number of partial aggregations in the metrics.
+    pub(crate) fn record_aggregations(&mut self) {
+        let evicted =
+            self.proposal_aggregator.take_evicted() + self.message_aggregator.take_evicted();
+        self.metrics.record_aggregations(
+            self.proposal_aggregator.pending_len(),
+            self.message_aggregator.pending_len(),
+            evicted,
+        );
+    }
+
     pub async fn add_to_filter(&mut self, msg_id: &MessageId) -> bool {
-        self.msg_filter.add_to_filter(msg_id).await
+        let new = self.msg_filter.add_to_filter(msg_id).await;
+        self.metrics.record_message_filter(!new);
+        new
     }
 
     async fn check_for_entropy(
@@ -137,6 +233,7 @@ impl Core {
             .update_section(section_auth, None, &section_chain)
         {
             info!("Neighbour section knowledge updated: {:?}", prefix);
+            self.update_network_stats();
         } else {
             warn!("Neighbour section update failed");
         }
@@ -146,8 +243,12 @@ impl Core {
         let mut commands = vec![];
         let new = self.state_snapshot();
 
-        self.section_keys_provider
-            .finalise_dkg(self.section.chain().last_key());
+        if let Some(key_share) = self.dkg_voter.take_outcome(self.section.chain().last_key()) {
+            self.section_keys_provider.insert(key_share);
+        }
+        // The generations before the current length of our chain are decided.
+        self.dkg_voter
+            .discard_outdated(self.section.chain().main_branch_len() as u64);
 
         if new.prefix != old.prefix {
             info!("Split");
@@ -156,6 +257,12 @@ impl Core {
         if new.last_key != old.last_key {
             self.msg_filter.reset().await;
 
+            self.proposal_aggregator.new_generation();
+            self.message_aggregator.new_generation();
+            self.record_aggregations();
+            let outdated_proposals = self.proposal_tracker.take_outdated(&old.last_key);
+            self.dkg_restarts.new_generation();
+
             if new.is_elder {
                 info!(
                     "Section updated: prefix: ({:b}), key: {:?}, elders: {}",
@@ -169,9 +276,14 @@ impl Core {
                     // Whenever there is an elders change, casting a round of joins_allowed
                     // proposals to sync.
                     commands.extend(self.propose(Proposal::JoinsAllowed(self.joins_allowed))?);
+
+                    // Our proposals that weren't agreed on under the old key still need agreeing on.
+                    for proposal in outdated_proposals {
+                        commands.extend(self.propose(proposal)?);
+                    }
                 }
 
-                self.print_network_stats();
+                self.update_network_stats();
 
                 // Sending SectionKnowledge to other sections for new SAP.
                 let section_auth = self.section.proven_authority_provider();
@@ -193,7 +305,7 @@ impl Core {
                         .collect();
                     let len = targets.len();
                     let dest_info = DestInfo {
-                        dest: XorName::random(),
+                        dest: self.random_name(),
                         dest_section_pk: sap.section_key(),
                     };
                     trace!("Sending updated SectionInfo to all known sections");
@@ -224,7 +336,8 @@ impl Core {
             } else if old.is_elder && !new.is_elder {
                 info!("Demoted");
                 self.network = Network::new();
-                self.section_keys_provider = SectionKeysProvider::new(KEY_CACHE_SIZE, None);
+                self.section_keys_provider =
+                    SectionKeysProvider::new(self.network_params.key_cache_size, None);
                 NodeElderChange::Demoted
             } else {
                 NodeElderChange::None
@@ -288,10 +401,119 @@ impl Core {
         }
     }
 
-    pub(crate) fn print_network_stats(&self) {
-        self.network
-            .network_stats(self.section.authority_provider())
-            .print()
+    pub(crate) fn network_stats(&self) -> NetworkStats {
+        let mut stats = self.current_network_stats();
+        stats.history = self.network_stats_history.samples(self.clock.now());
+        stats
+    }
+
+    // Records the current network statistics in their history, printing them if they changed.
+    pub(crate) fn update_network_stats(&mut self) {
+        let stats = self.current_network_stats();
+        if self.network_stats_history.record(&stats, self.clock.now()) {
+            stats.print()
+        }
+    }
+
+    fn current_network_stats(&self) -> NetworkStats {
+        self.network.network_stats(
+            self.section.authority_provider(),
+            self.section.members().joined().count(),
+        )
+    }
+
+    // Records the section key one of our elders signed a proposal with or addressed a message to
+    // us with.
+    pub(crate) fn record_elder_key(&mut self, elder: XorName, key: bls::PublicKey) {
+        let elders = self.section.authority_provider().names();
+        self.elder_keys.retain(|name, _| elders.contains(name));
+
+        if elders.contains(&elder) {
+            let _ = self.elder_keys.insert(elder, key);
+        }
+    }
+
+    // Returns the health of this node, except the reachability of the other elders which the
+    // caller has to find out.
+    pub(crate) fn health(&self) -> Health {
+        let now = self.clock.now();
+        let elders = self.section.authority_provider().names();
+        let chain = self.section.chain();
+
+        let mut behind_elders = false;
+        let mut lagging_elders = vec![];
+        for (name, key) in &self.elder_keys {
+            if !elders.contains(name) || key == chain.last_key() {
+                continue;
+            }
+
+            if chain.has_key(key) {
+                lagging_elders.push(*name);
+            } else {
+                behind_elders = true;
+            }
+        }
+
+        Health {
+            is_elder: self.is_elder(),
+            elders: elders.len(),
+            reachable_elders: if self.is_elder() { 1 } else { 0 },
+            section_key: *chain.last_key(),
+            behind_elders,
+            lagging_elders,
+            since_last_agreement: self
+                .last_agreement_at
+                .map(|time| now.saturating_duration_since(time)),
+            stale_dkg_sessions: self
+                .dkg_voter
+                .session_snapshots()
+                .into_iter()
+                .filter(|session| !session.complete && session.elapsed > STALE_DKG_SESSION_AGE)
+                .collect(),
+            event_channel_saturated: self.event_tx.capacity() == 0,
+        }
+    }
+
+    pub(crate) fn debug_snapshot(&self) -> DebugSnapshot {
+        let relocate_state = self.relocate_state.as_ref().map(|state| match state {
+            RelocateState::Delayed(msg) => RelocateStateSnapshot::Delayed { promise_id: msg.id },
+            RelocateState::InProgress(_) => RelocateStateSnapshot::InProgress,
+        });
+
+        DebugSnapshot {
+            name: self.node.name(),
+            age: self.node.age(),
+            is_elder: self.is_elder(),
+            prefix: *self.section.prefix(),
+            section_key: *self.section.chain().last_key(),
+            section_authority: self.section.authority_provider().clone(),
+            members: self
+                .section
+                .members()
+                .all()
+                .map(|info| MemberSnapshot {
+                    name: *info.peer.name(),
+                    addr: *info.peer.addr(),
+                    age: info.peer.age(),
+                    state: info.state,
+                })
+                .collect(),
+            key_shares: self
+                .section_keys_provider
+                .key_shares()
+                .map(|share| share.public_key_set.public_key())
+                .collect(),
+            pending_key_shares: self.dkg_voter.outcome_public_keys().collect(),
+            dkg_sessions: self.dkg_voter.session_snapshots(),
+            dkg_backlog: self.dkg_voter.backlog_len(),
+            pending_proposals: self.proposal_aggregator.pending().map(Into::into).collect(),
+            pending_messages: self.message_aggregator.pending().map(Into::into).collect(),
+            split_barrier: self.split_barrier.pending().cloned().collect(),
+            relocate_state,
+            joins_allowed: self.joins_allowed,
+            end_users: self.end_users.count(),
+            network: self.network.all().cloned().collect(),
+        }
     }
 }
 
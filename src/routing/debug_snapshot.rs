@@ -0,0 +1,104 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::agreement::{DkgSessionSnapshot, PendingAggregation};
+use hex_fmt::HexFmt;
+use serde::Serialize;
+use sn_messaging::{node::PeerState, MessageId, SectionAuthorityProvider};
+use std::{collections::BTreeSet, net::SocketAddr};
+use xor_name::{Prefix, XorName};
+
+/// Dump of the internal state of a node, as returned by `Routing::debug_snapshot`.
+///
+/// Meant to be serialized (e.g. to JSON) and attached to bug reports. It contains no secret keys,
+/// but it does reveal the addresses of the peers. The layout is not stable across versions.
+#[derive(Clone, Debug, Serialize)]
+pub struct DebugSnapshot {
+    /// Our name.
+    pub name: XorName,
+    /// Our age.
+    pub age: u8,
+    /// Whether we are an elder.
+    pub is_elder: bool,
+    /// Prefix of our section.
+    pub prefix: Prefix,
+    /// The latest key of our section chain.
+    pub section_key: bls::PublicKey,
+    /// Our section as its elders know it.
+    pub section_authority: SectionAuthorityProvider,
+    /// The members of our section, including those that left or were relocated.
+    pub members: Vec<MemberSnapshot>,
+    /// Public keys of our section key shares, oldest first.
+    pub key_shares: Vec<bls::PublicKey>,
+    /// Public keys of the key shares generated by DKG but not in use yet.
+    pub pending_key_shares: Vec<bls::PublicKey>,
+    /// The DKG sessions we take part in.
+    pub dkg_sessions: Vec<DkgSessionSnapshot>,
+    /// Number of DKG messages received before their session started.
+    pub dkg_backlog: usize,
+    /// Proposals we don't have enough signature shares of yet.
+    pub pending_proposals: Vec<PendingAggregationSnapshot>,
+    /// Messages we don't have enough signature shares of yet.
+    pub pending_messages: Vec<PendingAggregationSnapshot>,
+    /// Sections waiting in the split barrier for the agreement on their sibling.
+    pub split_barrier: Vec<SectionAuthorityProvider>,
+    /// Our relocation, if any.
+    pub relocate_state: Option<RelocateStateSnapshot>,
+    /// Whether we allow new nodes to join.
+    pub joins_allowed: bool,
+    /// Number of end users we serve.
+    pub end_users: usize,
+    /// The other sections we know about.
+    pub network: Vec<SectionAuthorityProvider>,
+}
+
+/// A member of our section. See `DebugSnapshot`.
+#[derive(Clone, Debug, Serialize)]
+pub struct MemberSnapshot {
+    /// Name of the member.
+    pub name: XorName,
+    /// Address of the member.
+    pub addr: SocketAddr,
+    /// Age of the member.
+    pub age: u8,
+    /// Whether the member joined, left or was relocated.
+    pub state: PeerState,
+}
+
+/// Payload an aggregator holds signature shares of. See `DebugSnapshot`.
+#[derive(Clone, Debug, Serialize)]
+pub struct PendingAggregationSnapshot {
+    /// Hex-encoded SHA3-256 hash of the payload.
+    pub payload_hash: String,
+    /// The section key the shares are for.
+    pub section_key: bls::PublicKey,
+    /// Indices of the elders whose shares we have.
+    pub shares: BTreeSet<usize>,
+}
+
+impl From<PendingAggregation> for PendingAggregationSnapshot {
+    fn from(pending: PendingAggregation) -> Self {
+        Self {
+            payload_hash: format!("{}", HexFmt(&pending.payload_digest)),
+            section_key: pending.section_key,
+            shares: pending.shares,
+        }
+    }
+}
+
+/// State of our relocation. See `DebugSnapshot`.
+#[derive(Clone, Debug, Serialize)]
+pub enum RelocateStateSnapshot {
+    /// We are going to relocate once we are demoted from elder.
+    Delayed {
+        /// Id of the `RelocatePromise` message we'll exchange for the actual relocation.
+        promise_id: MessageId,
+    },
+    /// We are joining the destination section.
+    InProgress,
+}
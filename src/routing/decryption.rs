@@ -0,0 +1,264 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    agreement::payload_digest,
+    ed25519::Digest256,
+    error::{Error, Result},
+    messages::{strip_tag, tagged, SEALED_DATA_TAG},
+};
+use bytes::Bytes;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Debug,
+};
+use tokio::sync::oneshot;
+use xor_name::XorName;
+
+/// Decides which requests of the members of our section to decrypt a ciphertext encrypted to our
+/// section key this node serves while it is an elder. A node without one serves none, as anyone
+/// holding the decryption shares of a supermajority of the elders can read the plaintext.
+pub trait DecryptionPolicy: Debug + Send + Sync {
+    /// Returns whether to release our decryption share of `request.ciphertext` to
+    /// `request.requester`.
+    fn authorise(&self, request: &DecryptionRequest) -> bool;
+}
+
+/// Request of a member of our section for the decryption of a ciphertext, as passed to
+/// `DecryptionPolicy::authorise`.
+#[derive(Debug)]
+pub struct DecryptionRequest<'a> {
+    /// Name of the member asking for the decryption.
+    pub requester: XorName,
+    /// Our section key the ciphertext is encrypted to.
+    pub section_key: &'a bls::PublicKey,
+    /// The ciphertext to decrypt.
+    pub ciphertext: &'a bls::Ciphertext,
+    /// What the requester asks for the decryption for, e.g. the auction a sealed bid was placed
+    /// in. `Routing::decrypt` only returns data sealed for the same context with
+    /// `seal_for_section`.
+    pub context: &'a [u8],
+}
+
+/// Encrypts `data` to our section key `section_key`, bound to `context`, for our section to
+/// decrypt with `Routing::decrypt` once its `DecryptionPolicy` allows it.
+pub fn seal_for_section(
+    section_key: &bls::PublicKey,
+    context: &[u8],
+    data: &[u8],
+) -> Result<bls::Ciphertext> {
+    let bytes = bincode::serialize(&(context, data)).map_err(|_| Error::InvalidPayload)?;
+    Ok(section_key.encrypt(tagged(SEALED_DATA_TAG, &bytes)))
+}
+
+// Returns the data sealed in `plaintext` by `seal_for_section`, if it was sealed for `context`.
+pub(crate) fn open_sealed(plaintext: &[u8], context: &[u8]) -> Result<Bytes> {
+    let bytes = strip_tag(SEALED_DATA_TAG, plaintext).ok_or(Error::InvalidCiphertext)?;
+    let (sealed_context, data): (Vec<u8>, Vec<u8>) =
+        bincode::deserialize(bytes).map_err(|_| Error::InvalidCiphertext)?;
+
+    if sealed_context != context {
+        return Err(Error::DecryptionContextMismatch);
+    }
+
+    Ok(Bytes::from(data))
+}
+
+// Our requests for decryption of ciphertexts encrypted to a key of our section that haven't been
+// fulfilled yet.
+#[derive(Default)]
+pub(crate) struct DecryptionRequests {
+    pending: HashMap<Digest256, PendingDecryption>,
+}
+
+struct PendingDecryption {
+    ciphertext: bls::Ciphertext,
+    // Key the ciphertext is encrypted to.
+    section_key: bls::PublicKey,
+    // Key set of the shares received so far. Pinned by the first valid share.
+    public_key_set: Option<bls::PublicKeySet>,
+    shares: BTreeMap<usize, bls::DecryptionShare>,
+    // Callers waiting for the plaintext.
+    waiters: Vec<oneshot::Sender<Bytes>>,
+}
+
+impl DecryptionRequests {
+    // Registers a request for the decryption of `ciphertext`. Returns the receiver of the
+    // plaintext and the digest to ask the elders with. Repeated requests for the same ciphertext
+    // share the decryption shares received so far.
+    pub fn insert(
+        &mut self,
+        ciphertext: bls::Ciphertext,
+        section_key: bls::PublicKey,
+    ) -> Result<(Digest256, oneshot::Receiver<Bytes>)> {
+        let digest = ciphertext_digest(&ciphertext)?;
+        let (tx, rx) = oneshot::channel();
+
+        let request = self
+            .pending
+            .entry(digest)
+            .or_insert_with(|| PendingDecryption {
+                ciphertext,
+                section_key,
+                public_key_set: None,
+                shares: BTreeMap::new(),
+                waiters: vec![],
+            });
+        request.waiters.retain(|waiter| !waiter.is_closed());
+        request.waiters.push(tx);
+
+        Ok((digest, rx))
+    }
+
+    // Forgets the request for `ciphertext` unless somebody is still waiting for it.
+    pub fn remove_abandoned(&mut self, ciphertext: &bls::Ciphertext) {
+        let digest = if let Ok(digest) = ciphertext_digest(ciphertext) {
+            digest
+        } else {
+            return;
+        };
+
+        if let Some(request) = self.pending.get_mut(&digest) {
+            request.waiters.retain(|waiter| !waiter.is_closed());
+            if request.waiters.is_empty() {
+                let _ = self.pending.remove(&digest);
+            }
+        }
+    }
+
+    // Adds the decryption share of an elder. Once there are enough shares, the plaintext is passed
+    // to the callers waiting for it.
+    pub fn handle_response(
+        &mut self,
+        digest: &Digest256,
+        public_key_set: bls::PublicKeySet,
+        index: usize,
+        decryption_share: bls::DecryptionShare,
+    ) -> Result<()> {
+        let request = if let Some(request) = self.pending.get_mut(digest) {
+            request
+        } else {
+            trace!("Ignoring decryption share of a request we don't wait for");
+            return Ok(());
+        };
+
+        if public_key_set.public_key() != request.section_key
+            || request
+                .public_key_set
+                .as_ref()
+                .map_or(false, |pinned| *pinned != public_key_set)
+        {
+            error!("Decryption share with a key set of a different key");
+            return Err(Error::InvalidDecryptionShare);
+        }
+
+        if !public_key_set
+            .public_key_share(index)
+            .verify_decryption_share(&decryption_share, &request.ciphertext)
+        {
+            error!("Invalid decryption share from elder #{}", index);
+            return Err(Error::InvalidDecryptionShare);
+        }
+
+        let _ = request.shares.insert(index, decryption_share);
+        if request.shares.len() <= public_key_set.threshold() {
+            request.public_key_set = Some(public_key_set);
+            return Ok(());
+        }
+
+        let plaintext = public_key_set
+            .decrypt(request.shares.iter(), &request.ciphertext)
+            .map_err(|error| {
+                error!("Failed to combine decryption shares: {}", error);
+                Error::InvalidDecryptionShare
+            })?;
+
+        if let Some(request) = self.pending.remove(digest) {
+            let plaintext = Bytes::from(plaintext);
+            for waiter in request.waiters {
+                let _ = waiter.send(plaintext.clone());
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub(crate) fn ciphertext_digest(ciphertext: &bls::Ciphertext) -> Result<Digest256> {
+    let bytes = bincode::serialize(ciphertext).map_err(|_| Error::InvalidMessage)?;
+    Ok(payload_digest(&bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn decrypt_with_shares() -> Result<()> {
+        let sk_set = bls::SecretKeySet::random(2, &mut rand::thread_rng());
+        let pk_set = sk_set.public_keys();
+        let ciphertext = seal_for_section(&pk_set.public_key(), b"auction", b"sealed bid")?;
+
+        let mut requests = DecryptionRequests::default();
+        let (digest, mut rx) = requests.insert(ciphertext.clone(), pk_set.public_key())?;
+
+        for index in 0..=sk_set.threshold() {
+            assert_matches!(rx.try_recv(), Err(_));
+            let share = sk_set
+                .secret_key_share(index)
+                .decrypt_share(&ciphertext)
+                .expect("invalid ciphertext");
+            requests.handle_response(&digest, pk_set.clone(), index, share)?;
+        }
+
+        let plaintext = rx.try_recv()?;
+        assert_eq!(open_sealed(&plaintext, b"auction")?, &b"sealed bid"[..]);
+        assert_matches!(
+            open_sealed(&plaintext, b"escrow"),
+            Err(Error::DecryptionContextMismatch)
+        );
+        assert!(requests.pending.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_invalid_shares() -> Result<()> {
+        let sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let other_sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let pk_set = sk_set.public_keys();
+        let ciphertext = pk_set.public_key().encrypt(b"sealed bid");
+
+        let mut requests = DecryptionRequests::default();
+        let (digest, _rx) = requests.insert(ciphertext.clone(), pk_set.public_key())?;
+
+        // Share of a key set of another key.
+        let share = other_sk_set
+            .secret_key_share(0)
+            .decrypt_share(&ciphertext)
+            .expect("invalid ciphertext");
+        assert_matches!(
+            requests.handle_response(&digest, other_sk_set.public_keys(), 0, share),
+            Err(Error::InvalidDecryptionShare)
+        );
+
+        // Share attributed to the wrong elder.
+        let share = sk_set
+            .secret_key_share(0)
+            .decrypt_share(&ciphertext)
+            .expect("invalid ciphertext");
+        assert_matches!(
+            requests.handle_response(&digest, pk_set, 1, share),
+            Err(Error::InvalidDecryptionShare)
+        );
+
+        Ok(())
+    }
+}
@@ -6,12 +6,27 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{bootstrap, Comm, Command, Core};
+use super::{
+    bootstrap::{self, JoinPolicy},
+    bootstrap_cache::BootstrapCache,
+    message_journal::{Hop, MessageJournal},
+    state_store::StateStore,
+    traffic_log::{TrafficInput, TrafficRecorder},
+    Comm, Command, Core,
+};
 use crate::{
-    error::Result, event::Event, messages::RoutingMsgUtils, peer::PeerUtils,
-    routing::comm::SendStatus, section::SectionPeersUtils, section::SectionUtils, Error, XorName,
+    clock::{self, Clock, SharedRng},
+    error::Result,
+    event::Event,
+    messages::RoutingMsgUtils,
+    peer::PeerUtils,
+    routing::comm::SendStatus,
+    section::SectionPeersUtils,
+    section::SectionUtils,
+    Error, XorName,
 };
 use itertools::Itertools;
+use rand::RngCore;
 use sn_data_types::PublicKey;
 use sn_messaging::{
     node::{
@@ -19,36 +34,140 @@ use sn_messaging::{
     },
     DstLocation, MessageType,
 };
-use std::{net::SocketAddr, sync::Arc, time::Duration};
-use tokio::{
-    sync::{mpsc, watch, RwLock},
-    time,
+use std::{
+    net::SocketAddr,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
 };
+use tokio::sync::{mpsc, watch, RwLock};
 use tracing::Instrument;
 
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
 // `Command` Dispatcher.
 pub(crate) struct Dispatcher {
     pub(super) core: RwLock<Core>,
     pub(super) comm: Comm,
+    state_store: Option<StateStore>,
+    message_journal: Option<Arc<MessageJournal>>,
+    traffic_recorder: Option<TrafficRecorder>,
+    // Limits on rejoining the network after relocation.
+    join_policy: JoinPolicy,
+    // Number of messages currently being sent.
+    pending_sends: AtomicUsize,
+    // Drives the timers scheduled by `Core`.
+    clock: Arc<dyn Clock>,
 
     cancel_timer_tx: watch::Sender<bool>,
     cancel_timer_rx: watch::Receiver<bool>,
 }
 
 impl Dispatcher {
-    pub fn new(state: Core, comm: Comm) -> Self {
+    pub fn new(mut state: Core, comm: Comm) -> Self {
         let (cancel_timer_tx, cancel_timer_rx) = watch::channel(false);
 
         // Take out the initial value.
 
+        let clock = state.clock().clone();
+        state.set_metrics(comm.metrics().clone());
+
         Self {
             core: RwLock::new(state),
             comm,
+            state_store: None,
+            message_journal: None,
+            traffic_recorder: None,
+            join_policy: JoinPolicy::default(),
+            pending_sends: AtomicUsize::new(0),
+            clock,
             cancel_timer_tx,
             cancel_timer_rx,
         }
     }
 
+    // Persist the node state to the given store whenever it changes.
+    pub fn with_state_store(mut self, state_store: StateStore) -> Self {
+        self.state_store = Some(state_store);
+        self
+    }
+
+    // Record the hops of the messages passing this node to the given journal.
+    pub fn with_message_journal(mut self, message_journal: MessageJournal) -> Self {
+        let message_journal = Arc::new(message_journal);
+        self.core
+            .get_mut()
+            .set_message_journal(Some(message_journal.clone()));
+        self.message_journal = Some(message_journal);
+        self
+    }
+
+    // Record the inputs of the node to the traffic log at `path`, starting with its current state
+    // encrypted under `state_key`. The RNG of the node is reseeded with a seed recorded in the log,
+    // so its randomness can be replayed too.
+    pub fn with_traffic_log(mut self, path: &Path, state_key: &bls::PublicKey) -> Result<Self> {
+        let core = self.core.get_mut();
+        let rng_seed = core.rng().clone().next_u64();
+        core.set_clock_and_rng(self.clock.clone(), SharedRng::seeded(rng_seed));
+
+        let state = core.node_state();
+        self.traffic_recorder = Some(TrafficRecorder::create(
+            path,
+            &state,
+            state_key,
+            rng_seed,
+            self.clock.clone(),
+        )?);
+        Ok(self)
+    }
+
+    // Apply the given policy when rejoining the network after relocation.
+    pub fn with_join_policy(mut self, join_policy: JoinPolicy) -> Self {
+        self.join_policy = join_policy;
+        self
+    }
+
+    // Records the input in the traffic log, if enabled.
+    pub fn record_traffic(&self, input: TrafficInput) {
+        if let Some(recorder) = &self.traffic_recorder {
+            recorder.record(input)
+        }
+    }
+
+    // Completes once the inputs recorded so far are written to the traffic log, if enabled.
+    #[cfg(test)]
+    pub async fn flush_traffic_log(&self) {
+        if let Some(recorder) = &self.traffic_recorder {
+            recorder.flush().await
+        }
+    }
+
+    /// Writes the current node state to the state store, if any.
+    pub async fn persist_state(&self) {
+        if let Some(state_store) = &self.state_store {
+            let state = self.core.read().await.node_state();
+            if let Err(error) = state_store.store(&state) {
+                error!(
+                    "Failed to persist node state to {}: {}",
+                    state_store.dir().display(),
+                    error
+                );
+            }
+
+            let cache = BootstrapCache::new(&state.section, &state.network);
+            if let Err(error) = state_store.store_bootstrap_cache(&cache) {
+                error!(
+                    "Failed to persist bootstrap cache to {}: {}",
+                    state_store.dir().display(),
+                    error
+                );
+            }
+        }
+    }
+
     /// Send provided Event to the user which shall receive it through the EventStream
     pub async fn send_event(&self, event: Event) {
         self.core.read().await.send_event(event).await
@@ -93,6 +212,27 @@ impl Dispatcher {
         .await
     }
 
+    // The clock driving the timers of the node.
+    pub fn clock(&self) -> &Arc<dyn Clock> {
+        &self.clock
+    }
+
+    /// Waits until all the messages currently being sent are sent, or the timeout expires.
+    pub async fn flush(&self, timeout: Duration) {
+        let flush = async {
+            while self.pending_sends.load(Ordering::SeqCst) > 0 {
+                self.clock.sleep(FLUSH_POLL_INTERVAL).await
+            }
+        };
+
+        if clock::timeout(&*self.clock, timeout, flush).await.is_none() {
+            warn!(
+                "Timed out flushing {} pending sends",
+                self.pending_sends.load(Ordering::SeqCst)
+            );
+        }
+    }
+
     // Terminate this routing instance - cancel all scheduled timers including any future ones,
     // close all network connections and stop accepting new connections.
     pub fn terminate(&self) {
@@ -129,11 +269,20 @@ impl Dispatcher {
                         }
                     }
                 }
-                self.core
+                let persist = matches!(
+                    message.variant,
+                    Variant::Sync { .. } | Variant::SectionKnowledge { .. }
+                );
+                let commands = self
+                    .core
                     .write()
                     .await
                     .handle_message(sender, message, dest_info)
-                    .await
+                    .await?;
+                if persist {
+                    self.persist_state().await;
+                }
+                Ok(commands)
             }
             Command::HandleSectionInfoMsg {
                 sender,
@@ -147,24 +296,36 @@ impl Dispatcher {
                 .await),
             Command::HandleTimeout(token) => self.core.write().await.handle_timeout(token),
             Command::HandleAgreement { proposal, signed } => {
-                self.core
+                let commands = self
+                    .core
                     .write()
                     .await
                     .handle_agreement(proposal, signed)
-                    .await
+                    .await?;
+                self.persist_state().await;
+                Ok(commands)
             }
             Command::HandleConnectionLost(addr) => {
-                self.core.read().await.handle_connection_lost(addr)
+                self.core.write().await.handle_connection_lost(addr)
             }
             Command::HandlePeerLost(addr) => self.core.read().await.handle_peer_lost(&addr),
             Command::HandleDkgOutcome {
                 section_auth,
+                generation,
                 outcome,
-            } => self
-                .core
-                .write()
-                .await
-                .handle_dkg_outcome(section_auth, outcome),
+            } => {
+                let commands = self.core.write().await.handle_dkg_outcome(
+                    section_auth,
+                    generation,
+                    outcome,
+                )?;
+                self.persist_state().await;
+                Ok(commands)
+            }
+            Command::SendEvent(event) => {
+                self.send_event(event).await;
+                Ok(vec![])
+            }
             Command::HandleDkgFailure(signeds) => self
                 .core
                 .write()
@@ -176,8 +337,12 @@ impl Dispatcher {
                 delivery_group_size,
                 message,
             } => {
-                self.send_message(&recipients, delivery_group_size, message)
-                    .await
+                let _ = self.pending_sends.fetch_add(1, Ordering::SeqCst);
+                let result = self
+                    .send_message(&recipients, delivery_group_size, message)
+                    .await;
+                let _ = self.pending_sends.fetch_sub(1, Ordering::SeqCst);
+                result
             }
             Command::SendUserMessage {
                 itinerary,
@@ -190,11 +355,14 @@ impl Dispatcher {
                     .send_user_message(itinerary, content)
                     .await
             }
-            Command::ScheduleTimeout { duration, token } => Ok(self
-                .handle_schedule_timeout(duration, token)
-                .await
-                .into_iter()
-                .collect()),
+            Command::ScheduleTimeout { duration, token } => {
+                self.record_traffic(TrafficInput::TimerScheduled(token));
+                Ok(self
+                    .handle_schedule_timeout(duration, token)
+                    .await
+                    .into_iter()
+                    .collect())
+            }
             Command::Relocate {
                 bootstrap_addrs,
                 details,
@@ -220,6 +388,26 @@ impl Dispatcher {
                     .await
             }
             Command::ProposeOffline(name) => self.core.read().await.propose_offline(name),
+            Command::ProposeCustom(payload) => self.core.read().await.propose_custom(payload),
+            Command::TrackProposal {
+                proposal,
+                key,
+                message,
+                recipients,
+            } => Ok(self
+                .core
+                .write()
+                .await
+                .track_proposal(&proposal, key, message, recipients)
+                .into_iter()
+                .collect()),
+            Command::HandleProposalResponse { proposal, elder } => {
+                self.core
+                    .write()
+                    .await
+                    .handle_proposal_response(&proposal, elder);
+                Ok(vec![])
+            }
             Command::StartConnectivityTest(name) => {
                 let msg = {
                     let core = self.core.read().await;
@@ -278,13 +466,48 @@ impl Dispatcher {
     ) -> Result<Vec<Command>> {
         let cmds = match message {
             MessageType::Node { .. } | MessageType::Routing { .. } => {
+                let msg_id = if let MessageType::Routing { msg, .. } = &message {
+                    Some(msg.id)
+                } else {
+                    None
+                };
                 let status = self
                     .comm
                     .send(recipients, delivery_group_size, message)
                     .await?;
+
+                if let Some(msg_id) = msg_id {
+                    let delivered_to: Vec<_> = status
+                        .delivered_to(recipients, delivery_group_size)
+                        .map(|(name, _)| *name)
+                        .collect();
+                    if !delivered_to.is_empty() {
+                        self.core.read().await.trace_hop(
+                            msg_id,
+                            Hop::Sent {
+                                recipients: delivered_to,
+                            },
+                        );
+                    }
+                }
+
                 match status {
-                    SendStatus::MinDeliveryGroupSizeReached(failed_recipients)
-                    | SendStatus::MinDeliveryGroupSizeFailed(failed_recipients) => {
+                    SendStatus::MinDeliveryGroupSizeFailed(failed_recipients) => {
+                        if let Some(msg_id) = msg_id {
+                            self.core.read().await.trace_hop(
+                                msg_id,
+                                Hop::SendFailed {
+                                    recipients: failed_recipients.clone(),
+                                },
+                            );
+                        }
+
+                        Ok(failed_recipients
+                            .into_iter()
+                            .map(Command::HandlePeerLost)
+                            .collect())
+                    }
+                    SendStatus::MinDeliveryGroupSizeReached(failed_recipients) => {
                         Ok(failed_recipients
                             .into_iter()
                             .map(Command::HandlePeerLost)
@@ -307,6 +530,7 @@ impl Dispatcher {
                             recipient,
                             message
                         );
+                        let _ = self.core.read().await.remove_enduser(&recipient.1);
                         self.send_event(Event::ClientLost(recipient.1)).await;
                     }
                 }
@@ -335,7 +559,10 @@ impl Dispatcher {
         }
 
         tokio::select! {
-            _ = time::sleep(duration) => Some(Command::HandleTimeout(token)),
+            _ = self.clock.sleep(duration) => {
+                self.record_traffic(TrafficInput::Timeout(token));
+                Some(Command::HandleTimeout(token))
+            }
             _ = cancel_rx.changed() => None,
         }
     }
@@ -346,14 +573,25 @@ impl Dispatcher {
         details: SignedRelocateDetails,
         message_rx: mpsc::Receiver<(MessageType, SocketAddr)>,
     ) -> Result<Vec<Command>> {
-        let (genesis_key, node) = {
+        let (genesis_key, node, network_params, proven_params, rng) = {
             let state = self.core.read().await;
-            (*state.section().genesis_key(), state.node().clone())
+            (
+                *state.section().genesis_key(),
+                state.node().clone(),
+                *state.network_params(),
+                state.proven_network_params(),
+                state.rng().clone(),
+            )
         };
         let previous_name = node.name();
 
-        let (node, section, backlog) = bootstrap::relocate(
+        let (node, section, network_params, backlog) = bootstrap::relocate(
             node,
+            network_params,
+            proven_params,
+            self.join_policy.clone(),
+            self.clock.clone(),
+            rng.clone(),
             &self.comm,
             message_rx,
             bootstrap_addrs,
@@ -362,17 +600,38 @@ impl Dispatcher {
         )
         .await?;
 
-        let mut state = self.core.write().await;
-        let event_tx = state.event_tx.clone();
-        let new_keypair = node.keypair.clone();
-        *state = Core::new(node, section, None, event_tx);
+        let eviction = {
+            let mut state = self.core.write().await;
+            let event_tx = state.event_tx.clone();
+            let end_user_policy = *state.end_user_policy();
+            let proposal_retry_policy = *state.proposal_retry_policy();
+            let dkg_policy = *state.dkg_policy();
+            let decryption_policy = state.decryption_policy().cloned();
+            let network_stats_history = state.network_stats_history().clone();
+            let new_keypair = node.keypair.clone();
+            *state = Core::new(node, section, None, network_params.value, event_tx);
+            state.set_network_params_proof(network_params.signed);
+            state.set_end_user_policy(end_user_policy);
+            state.set_proposal_retry_policy(proposal_retry_policy);
+            state.set_dkg_policy(dkg_policy);
+            state.set_decryption_policy(decryption_policy);
+            state.set_clock_and_rng(self.clock.clone(), rng);
+            state.set_metrics(self.comm.metrics().clone());
+            state.set_message_journal(self.message_journal.clone());
+            state.set_network_stats_history(network_stats_history);
 
-        state
-            .send_event(Event::Relocated {
-                previous_name,
-                new_keypair,
-            })
-            .await;
+            state
+                .send_event(Event::Relocated {
+                    previous_name,
+                    new_keypair,
+                })
+                .await;
+
+            state.schedule_end_user_eviction()
+        };
+
+        self.persist_state().await;
+        self.comm.metrics().record_bootstrap_backlog(backlog.len());
 
         let commands = backlog
             .into_iter()
@@ -381,6 +640,7 @@ impl Dispatcher {
                 sender: Some(sender),
                 dest_info,
             })
+            .chain(eviction)
             .collect();
         Ok(commands)
     }
@@ -0,0 +1,234 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::command::{self, Command};
+use sn_messaging::node::{DkgKey, ElderCandidates};
+use std::{
+    collections::{BTreeSet, HashSet},
+    time::Duration,
+};
+use xor_name::XorName;
+
+/// How DKG sessions progress and how the elders restart the ones that fail.
+///
+/// When a DKG session fails because some candidates didn't participate, the elders start a new
+/// one without them, substituting the next best candidates, after waiting for the backoff. The
+/// backoff doubles with every failed attempt for the same section key. Once `max_attempts`
+/// sessions have failed, `Event::DkgAttemptsExhausted` is raised and no more are started until the
+/// section key changes or `exhausted_cooldown` elapses. After the cool-down the elders start over,
+/// giving the excluded candidates another chance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DkgPolicy {
+    /// Time a DKG session waits for progress before moving on to its next phase.
+    pub progress_interval: Duration,
+    /// Number of times a DKG session moves on without progress before giving up. The candidates
+    /// no DKG message was received from are then reported as non-participants.
+    pub max_rounds: usize,
+    /// Time to wait before restarting a failed DKG session without its non-participants.
+    pub restart_backoff: Duration,
+    /// Number of failed DKG sessions after which no more are started until the cool-down elapses.
+    pub max_attempts: usize,
+    /// Time to wait after the DKG attempts are exhausted before starting over.
+    pub exhausted_cooldown: Duration,
+}
+
+impl Default for DkgPolicy {
+    fn default() -> Self {
+        Self {
+            progress_interval: Duration::from_secs(30),
+            max_rounds: 10,
+            restart_backoff: Duration::from_secs(5),
+            max_attempts: 5,
+            exhausted_cooldown: Duration::from_secs(10 * 60),
+        }
+    }
+}
+
+// Keeps track of the DKG sessions that failed since our section key last changed, of the restart
+// waiting for its backoff and of the cool-down once the attempts are exhausted.
+#[derive(Default)]
+pub(crate) struct DkgRestarts {
+    failed: HashSet<DkgKey>,
+    // Candidates that failed to participate, excluded from the elder candidates.
+    excluded: BTreeSet<XorName>,
+    // Token of the timer of the pending restart and the candidates to restart with.
+    pending: Option<(u64, ElderCandidates)>,
+    // Token of the timer of the cool-down, while the attempts are exhausted.
+    cooldown: Option<u64>,
+}
+
+impl DkgRestarts {
+    pub fn excluded(&self) -> &BTreeSet<XorName> {
+        &self.excluded
+    }
+
+    // Number of failed sessions.
+    pub fn attempts(&self) -> usize {
+        self.failed.len()
+    }
+
+    // Records the failure of the session with `dkg_key` because of `non_participants`, which are
+    // excluded from then on. Returns whether this failure wasn't recorded before.
+    pub fn record_failure(
+        &mut self,
+        dkg_key: DkgKey,
+        non_participants: &BTreeSet<XorName>,
+    ) -> bool {
+        self.excluded.extend(non_participants);
+        self.failed.insert(dkg_key)
+    }
+
+    // Whether the attempts are exhausted, in which case no DKG session is to be started.
+    pub fn is_exhausted(&self) -> bool {
+        self.cooldown.is_some()
+    }
+
+    // Stops starting DKG sessions until the cool-down elapses. Drops any pending restart.
+    pub fn exhaust(&mut self, policy: &DkgPolicy) -> Command {
+        let token = command::next_timer_token();
+        self.pending = None;
+        self.cooldown = Some(token);
+
+        Command::ScheduleTimeout {
+            duration: policy.exhausted_cooldown,
+            token,
+        }
+    }
+
+    // Schedules the restart with `elder_candidates`, replacing any pending one. The backoff
+    // doubles with every attempt.
+    pub fn schedule(&mut self, elder_candidates: ElderCandidates, policy: &DkgPolicy) -> Command {
+        let token = command::next_timer_token();
+        self.pending = Some((token, elder_candidates));
+
+        let exponent = self.attempts().saturating_sub(1).min(16) as u32;
+        Command::ScheduleTimeout {
+            duration: policy.restart_backoff * 2u32.pow(exponent),
+            token,
+        }
+    }
+
+    // Returns the candidates to restart with if `token` is the timer of the pending restart.
+    pub fn handle_timeout(&mut self, token: u64) -> Option<ElderCandidates> {
+        match &self.pending {
+            Some((pending_token, _)) if *pending_token == token => self
+                .pending
+                .take()
+                .map(|(_, elder_candidates)| elder_candidates),
+            _ => None,
+        }
+    }
+
+    // Returns whether `token` is the timer of the cool-down, in which case everything is
+    // forgotten so DKG can start over.
+    pub fn handle_cooldown_timeout(&mut self, token: u64) -> bool {
+        if self.cooldown == Some(token) {
+            *self = Self::default();
+            true
+        } else {
+            false
+        }
+    }
+
+    // To be called when our section key changes. Forgets everything, as the next DKG will be for a
+    // new generation.
+    pub fn new_generation(&mut self) {
+        *self = Self::default();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agreement::DkgKeyUtils,
+        section::{test_utils::gen_addr, ElderCandidatesUtils},
+    };
+    use assert_matches::assert_matches;
+    use sn_messaging::node::Peer;
+    use std::iter;
+    use xor_name::Prefix;
+
+    #[test]
+    fn backoff_and_exclusions() {
+        let policy = DkgPolicy {
+            restart_backoff: Duration::from_secs(2),
+            ..DkgPolicy::default()
+        };
+        let mut restarts = DkgRestarts::default();
+        let non_participant = XorName::random();
+
+        for expected_backoff in vec![2, 4, 8] {
+            let elder_candidates = gen_elder_candidates();
+            let dkg_key = DkgKey::new(&elder_candidates, 0);
+
+            assert!(restarts.record_failure(dkg_key, &iter::once(non_participant).collect()));
+            // Every participant reports the failure, but it only counts once.
+            assert!(!restarts.record_failure(dkg_key, &BTreeSet::new()));
+
+            let token = assert_matches!(
+                restarts.schedule(elder_candidates.clone(), &policy),
+                Command::ScheduleTimeout { duration, token } => {
+                    assert_eq!(duration, Duration::from_secs(expected_backoff));
+                    token
+                }
+            );
+            assert!(restarts.handle_timeout(token + 1).is_none());
+            assert_eq!(restarts.handle_timeout(token), Some(elder_candidates));
+            assert!(restarts.handle_timeout(token).is_none());
+        }
+
+        assert_eq!(restarts.attempts(), 3);
+        assert_eq!(restarts.excluded(), &iter::once(non_participant).collect());
+
+        restarts.new_generation();
+        assert_eq!(restarts.attempts(), 0);
+        assert!(restarts.excluded().is_empty());
+    }
+
+    #[test]
+    fn cooldown_after_exhausted() {
+        let policy = DkgPolicy::default();
+        let mut restarts = DkgRestarts::default();
+        let elder_candidates = gen_elder_candidates();
+        let dkg_key = DkgKey::new(&elder_candidates, 0);
+
+        assert!(restarts.record_failure(dkg_key, &iter::once(XorName::random()).collect()));
+        let restart_token = assert_matches!(
+            restarts.schedule(elder_candidates, &policy),
+            Command::ScheduleTimeout { token, .. } => token
+        );
+        assert!(!restarts.is_exhausted());
+
+        let token = assert_matches!(
+            restarts.exhaust(&policy),
+            Command::ScheduleTimeout { duration, token } => {
+                assert_eq!(duration, policy.exhausted_cooldown);
+                token
+            }
+        );
+        assert!(restarts.is_exhausted());
+        // The pending restart is dropped.
+        assert!(restarts.handle_timeout(restart_token).is_none());
+
+        assert!(!restarts.handle_cooldown_timeout(token + 1));
+        assert!(restarts.is_exhausted());
+
+        assert!(restarts.handle_cooldown_timeout(token));
+        assert!(!restarts.is_exhausted());
+        assert_eq!(restarts.attempts(), 0);
+        assert!(restarts.excluded().is_empty());
+    }
+
+    fn gen_elder_candidates() -> ElderCandidates {
+        ElderCandidates::new(
+            iter::once(Peer::new(XorName::random(), gen_addr())),
+            Prefix::default(),
+        )
+    }
+}
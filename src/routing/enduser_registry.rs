@@ -11,32 +11,146 @@ use sn_messaging::EndUser;
 use std::{
     collections::{btree_map::Entry, BTreeMap},
     net::SocketAddr,
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
 };
 use xor_name::{Prefix, XorName};
 
+/// Default time after which end users that sent no messages are evicted.
+pub const DEFAULT_END_USER_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+// Minimum time between two logs of end users rejected for exceeding `max_end_users`.
+const REJECTION_LOG_INTERVAL: Duration = Duration::from_secs(60);
+
 pub type SocketId = XorName;
+
+/// Limits on the end users (clients) an elder serves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EndUserPolicy {
+    /// Maximum number of end users served at the same time. Messages from further end users are
+    /// dropped until some of the current ones go away. `None` means unlimited.
+    pub max_end_users: Option<usize>,
+    /// End users that send no messages for this long are evicted. `None` disables eviction.
+    pub idle_timeout: Option<Duration>,
+}
+
+impl Default for EndUserPolicy {
+    fn default() -> Self {
+        Self {
+            max_end_users: None,
+            idle_timeout: Some(DEFAULT_END_USER_IDLE_TIMEOUT),
+        }
+    }
+}
+
+/// Information about an end user session.
+#[derive(Clone, Copy, Debug)]
+pub struct EndUserInfo {
+    /// The end user.
+    pub end_user: EndUser,
+    /// Address the end user connects from.
+    pub addr: SocketAddr,
+    /// How long ago the end user sent its first message.
+    pub connected_for: Duration,
+    /// How long ago the end user sent its last message.
+    pub idle_for: Duration,
+    /// Number of messages received from the end user.
+    pub messages_received: u64,
+}
+
+struct Session {
+    end_user: EndUser,
+    connected_at: Instant,
+    last_seen: Instant,
+    messages_received: u64,
+}
+
+impl Session {
+    fn info(&self, addr: SocketAddr, now: Instant) -> EndUserInfo {
+        EndUserInfo {
+            end_user: self.end_user,
+            addr,
+            connected_for: now.saturating_duration_since(self.connected_at),
+            idle_for: now.saturating_duration_since(self.last_seen),
+            messages_received: self.messages_received,
+        }
+    }
+}
+
+// The sessions are behind their own lock, so registering the messages of end users doesn't need
+// exclusive access to the rest of the node.
 pub(crate) struct EndUserRegistry {
-    clients: BTreeMap<SocketAddr, EndUser>,
+    sessions: Mutex<Sessions>,
+    policy: EndUserPolicy,
+}
+
+#[derive(Default)]
+struct Sessions {
+    clients: BTreeMap<SocketAddr, Session>,
     socket_id_mapping: BTreeMap<SocketId, SocketAddr>,
+    // Messages dropped for exceeding `max_end_users` since we last logged them, and when that was.
+    rejected: u64,
+    rejection_logged_at: Option<Instant>,
 }
 
 impl EndUserRegistry {
-    pub fn new() -> Self {
+    pub fn new(policy: EndUserPolicy) -> Self {
         Self {
-            clients: BTreeMap::default(),
-            socket_id_mapping: BTreeMap::default(),
+            sessions: Mutex::default(),
+            policy,
         }
     }
 
-    pub fn get_enduser_by_addr(&self, socketaddr: &SocketAddr) -> Option<&EndUser> {
-        self.clients.get(socketaddr)
+    pub fn policy(&self) -> &EndUserPolicy {
+        &self.policy
     }
 
-    pub fn get_socket_addr(&self, socket_id: SocketId) -> Option<&SocketAddr> {
-        self.socket_id_mapping.get(&socket_id)
+    pub fn get_socket_addr(&self, socket_id: SocketId) -> Option<SocketAddr> {
+        self.lock().socket_id_mapping.get(&socket_id).copied()
     }
 
-    pub fn try_add(&mut self, sender: SocketAddr, section_prefix: &Prefix) -> Result<EndUser> {
+    pub fn list(&self, now: Instant) -> Vec<EndUserInfo> {
+        self.lock()
+            .clients
+            .iter()
+            .map(|(addr, session)| session.info(*addr, now))
+            .collect()
+    }
+
+    pub fn count(&self) -> usize {
+        self.lock().clients.len()
+    }
+
+    pub fn info(&self, addr: &SocketAddr, now: Instant) -> Option<EndUserInfo> {
+        self.lock()
+            .clients
+            .get(addr)
+            .map(|session| session.info(*addr, now))
+    }
+
+    // Records a message from `sender`, registering it as a new end user if we haven't heard of it
+    // yet.
+    pub fn try_add(
+        &self,
+        sender: SocketAddr,
+        section_prefix: &Prefix,
+        now: Instant,
+    ) -> Result<EndUser> {
+        let mut sessions = self.lock();
+
+        if let Some(session) = sessions.clients.get_mut(&sender) {
+            session.last_seen = now;
+            session.messages_received += 1;
+            return Ok(session.end_user);
+        }
+
+        if let Some(max_end_users) = self.policy.max_end_users {
+            if sessions.clients.len() >= max_end_users {
+                sessions.log_rejection(now);
+                return Err(Error::TooManyEndUsers);
+            }
+        }
+
         // create a unique socket id from client socket addr
         let socket_id = XorName::from_content(&[
             &bincode::serialize(&sender).map_err(|_| Error::FailedSignature)?
@@ -56,9 +170,18 @@ impl EndUserRegistry {
             socket_id,
         };
 
-        match self.socket_id_mapping.entry(socket_id) {
+        let sessions = &mut *sessions;
+        match sessions.socket_id_mapping.entry(socket_id) {
             Entry::Vacant(entry) => {
-                let _ = self.clients.insert(sender, end_user);
+                let _ = sessions.clients.insert(
+                    sender,
+                    Session {
+                        end_user,
+                        connected_at: now,
+                        last_seen: now,
+                        messages_received: 1,
+                    },
+                );
                 let _ = entry.insert(sender);
             }
             Entry::Occupied(_) => (),
@@ -66,4 +189,136 @@ impl EndUserRegistry {
 
         Ok(end_user)
     }
+
+    pub fn remove(&self, addr: &SocketAddr) -> Option<EndUser> {
+        self.lock().remove(addr)
+    }
+
+    // Removes the end users idle for longer than the idle timeout and returns their addresses.
+    pub fn evict_idle(&self, now: Instant) -> Vec<SocketAddr> {
+        let idle_timeout = if let Some(idle_timeout) = self.policy.idle_timeout {
+            idle_timeout
+        } else {
+            return vec![];
+        };
+
+        let mut sessions = self.lock();
+        let idle: Vec<_> = sessions
+            .clients
+            .iter()
+            .filter(|(_, session)| now.saturating_duration_since(session.last_seen) >= idle_timeout)
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        for addr in &idle {
+            let _ = sessions.remove(addr);
+        }
+
+        idle
+    }
+
+    fn lock(&self) -> MutexGuard<Sessions> {
+        self.sessions.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+impl Sessions {
+    fn remove(&mut self, addr: &SocketAddr) -> Option<EndUser> {
+        let session = self.clients.remove(addr)?;
+        let _ = self.socket_id_mapping.remove(&session.end_user.socket_id);
+        Some(session.end_user)
+    }
+
+    // Logs the messages dropped for exceeding `max_end_users` at most once per
+    // `REJECTION_LOG_INTERVAL`, as over the limit that can be every client message.
+    fn log_rejection(&mut self, now: Instant) {
+        self.rejected += 1;
+
+        if let Some(logged_at) = self.rejection_logged_at {
+            if now.saturating_duration_since(logged_at) < REJECTION_LOG_INTERVAL {
+                return;
+            }
+        }
+
+        warn!(
+            "Serving the maximum number of end users ({}), dropped {} messages from further ones",
+            self.clients.len(),
+            self.rejected
+        );
+        self.rejected = 0;
+        self.rejection_logged_at = Some(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::section::test_utils::gen_addr;
+    use anyhow::Result;
+
+    #[test]
+    fn add_and_remove() -> Result<()> {
+        let registry = EndUserRegistry::new(EndUserPolicy::default());
+        let addr = gen_addr();
+        let now = Instant::now();
+
+        let end_user = registry.try_add(addr, &Prefix::default(), now)?;
+        assert_eq!(registry.try_add(addr, &Prefix::default(), now)?, end_user);
+        assert_eq!(registry.get_socket_addr(end_user.socket_id), Some(addr));
+        assert_eq!(registry.count(), 1);
+        assert_eq!(
+            registry.info(&addr, now).map(|info| info.messages_received),
+            Some(2)
+        );
+
+        assert_eq!(registry.remove(&addr), Some(end_user));
+        assert!(registry.info(&addr, now).is_none());
+        assert_eq!(registry.get_socket_addr(end_user.socket_id), None);
+        assert!(registry.list(now).is_empty());
+        assert_eq!(registry.count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn evict_idle() -> Result<()> {
+        let idle_timeout = Duration::from_secs(60);
+        let registry = EndUserRegistry::new(EndUserPolicy {
+            idle_timeout: Some(idle_timeout),
+            ..Default::default()
+        });
+
+        let start = Instant::now();
+        let idle_addr = gen_addr();
+        let active_addr = gen_addr();
+        let _ = registry.try_add(idle_addr, &Prefix::default(), start)?;
+        let _ = registry.try_add(active_addr, &Prefix::default(), start)?;
+        let _ = registry.try_add(active_addr, &Prefix::default(), start + idle_timeout / 2)?;
+
+        assert_eq!(registry.evict_idle(start + idle_timeout), vec![idle_addr]);
+        assert!(registry.info(&active_addr, start).is_some());
+
+        Ok(())
+    }
+
+    #[test]
+    fn max_end_users() -> Result<()> {
+        let registry = EndUserRegistry::new(EndUserPolicy {
+            max_end_users: Some(1),
+            ..Default::default()
+        });
+        let now = Instant::now();
+
+        let first = gen_addr();
+        let _ = registry.try_add(first, &Prefix::default(), now)?;
+        assert!(matches!(
+            registry.try_add(gen_addr(), &Prefix::default(), now),
+            Err(Error::TooManyEndUsers)
+        ));
+
+        // Known end users are still served.
+        assert!(registry.try_add(first, &Prefix::default(), now).is_ok());
+
+        Ok(())
+    }
 }
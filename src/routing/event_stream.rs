@@ -6,22 +6,102 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use crate::event::Event;
-use std::fmt::{self, Debug, Formatter};
-use tokio::sync::mpsc;
+use crate::event::{Event, EventKind};
+use futures::{future, Stream};
+use std::{
+    collections::VecDeque,
+    fmt::{self, Debug, Formatter},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    task::{Context, Poll, Waker},
+};
+use tokio::{
+    sync::{mpsc, Notify},
+    task,
+};
+
+/// Default capacity of a subscription.
+pub const DEFAULT_SUBSCRIPTION_CAPACITY: usize = 20;
+
+/// What happens when an event is raised while the queue of a subscriber is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Wait until the subscriber makes room. This holds up the delivery of events to all other
+    /// subscribers and eventually the node itself, so use it only for subscribers which keep up.
+    Block,
+    /// Discard the oldest queued event to make room for the new one.
+    DropOldest,
+    /// Discard the new event. The subscriber receives `Event::Lagged` with the number of
+    /// discarded events once it catches up.
+    Lag,
+}
+
+/// Options of a subscription to the events of a node.
+#[derive(Clone, Debug)]
+pub struct Subscription {
+    /// The kinds of events to receive, or `None` for all of them. `Event::Lagged` is always
+    /// received.
+    pub kinds: Option<Vec<EventKind>>,
+    /// Maximum number of events queued for the subscriber.
+    pub capacity: usize,
+    /// What to do when the queue is full. `OverflowPolicy::Lag` by default, so a slow subscriber
+    /// never holds up the node.
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for Subscription {
+    fn default() -> Self {
+        Self {
+            kinds: None,
+            capacity: DEFAULT_SUBSCRIPTION_CAPACITY,
+            overflow: OverflowPolicy::Lag,
+        }
+    }
+}
+
 /// Stream of routing node events
 pub struct EventStream {
-    events_rx: mpsc::Receiver<Event>,
+    shared: Arc<Shared>,
 }
 
 impl EventStream {
-    pub(crate) fn new(events_rx: mpsc::Receiver<Event>) -> Self {
-        Self { events_rx }
-    }
-
     /// Returns next event
     pub async fn next(&mut self) -> Option<Event> {
-        self.events_rx.recv().await
+        future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Event;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut queue = self.shared.lock();
+
+        if let Some(event) = queue.events.pop_front() {
+            self.shared.space.notify_one();
+            return Poll::Ready(Some(event));
+        }
+
+        if queue.missed > 0 {
+            let missed = queue.missed;
+            queue.missed = 0;
+            return Poll::Ready(Some(Event::Lagged { missed }));
+        }
+
+        if queue.closed {
+            return Poll::Ready(None);
+        }
+
+        queue.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.shared.lock().dropped = true;
+        // Unblock the hub if it waits for us.
+        self.shared.space.notify_one();
     }
 }
 
@@ -30,3 +110,298 @@ impl Debug for EventStream {
         write!(f, "EventStream")
     }
 }
+
+// Distributes the events raised by the node among the subscribers.
+#[derive(Clone)]
+pub(crate) struct EventHub {
+    subscribers: Arc<Mutex<Subscribers>>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    list: Vec<Arc<Shared>>,
+    // Whether the node stopped raising events.
+    closed: bool,
+}
+
+impl EventHub {
+    // Starts distributing the events received from `events_rx`. Returns the hub together with a
+    // subscription to all the events, made before any of them is distributed.
+    pub fn new(events_rx: mpsc::Receiver<Event>) -> (Self, EventStream) {
+        let hub = Self {
+            subscribers: Arc::new(Mutex::new(Subscribers::default())),
+        };
+        let stream = hub.subscribe(Subscription::default());
+        let _ = task::spawn(hub.clone().run(events_rx));
+
+        (hub, stream)
+    }
+
+    pub fn subscribe(&self, subscription: Subscription) -> EventStream {
+        let mut subscribers = lock(&self.subscribers);
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Queue {
+                events: VecDeque::with_capacity(subscription.capacity),
+                missed: 0,
+                waker: None,
+                closed: subscribers.closed,
+                dropped: false,
+            }),
+            space: Notify::new(),
+            subscription,
+        });
+        subscribers.list.push(shared.clone());
+
+        EventStream { shared }
+    }
+
+    async fn run(self, mut events_rx: mpsc::Receiver<Event>) {
+        while let Some(event) = events_rx.recv().await {
+            let subscribers = {
+                let mut subscribers = lock(&self.subscribers);
+                subscribers
+                    .list
+                    .retain(|subscriber| !subscriber.lock().dropped);
+                subscribers.list.clone()
+            };
+
+            for subscriber in subscribers {
+                if subscriber.wants(&event) {
+                    subscriber.push(event.clone()).await;
+                }
+            }
+        }
+
+        let mut subscribers = lock(&self.subscribers);
+        subscribers.closed = true;
+        for subscriber in subscribers.list.drain(..) {
+            subscriber.close();
+        }
+    }
+}
+
+// State shared between the hub and a single subscriber.
+struct Shared {
+    queue: Mutex<Queue>,
+    // Notified when the subscriber takes an event out of a full queue.
+    space: Notify,
+    subscription: Subscription,
+}
+
+struct Queue {
+    events: VecDeque<Event>,
+    // Number of events discarded under `OverflowPolicy::Lag` not yet reported.
+    missed: usize,
+    waker: Option<Waker>,
+    // The hub won't push any more events.
+    closed: bool,
+    // The subscriber is gone.
+    dropped: bool,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<Queue> {
+        lock(&self.queue)
+    }
+
+    fn wants(&self, event: &Event) -> bool {
+        match &self.subscription.kinds {
+            Some(kinds) => kinds.contains(&event.kind()),
+            None => true,
+        }
+    }
+
+    async fn push(&self, event: Event) {
+        let capacity = self.subscription.capacity.max(1);
+
+        loop {
+            {
+                let mut queue = self.lock();
+                if queue.dropped {
+                    return;
+                }
+
+                if queue.events.len() < capacity {
+                    // Report the events missed so far before the newer ones, if there is room.
+                    if queue.missed > 0 {
+                        if queue.events.len() + 1 < capacity {
+                            let missed = queue.missed;
+                            queue.missed = 0;
+                            queue.events.push_back(Event::Lagged { missed });
+                        } else {
+                            queue.missed += 1;
+                            return;
+                        }
+                    }
+
+                    queue.events.push_back(event);
+                    queue.wake();
+                    return;
+                }
+
+                match self.subscription.overflow {
+                    OverflowPolicy::Block => (),
+                    OverflowPolicy::DropOldest => {
+                        let _ = queue.events.pop_front();
+                        queue.events.push_back(event);
+                        queue.wake();
+                        return;
+                    }
+                    OverflowPolicy::Lag => {
+                        queue.missed += 1;
+                        return;
+                    }
+                }
+            }
+
+            self.space.notified().await;
+        }
+    }
+
+    fn close(&self) {
+        let mut queue = self.lock();
+        queue.closed = true;
+        queue.wake();
+    }
+}
+
+impl Queue {
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+// A panic while holding the lock can't leave the queue inconsistent, so ignore poisoning.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Result};
+    use futures::StreamExt;
+    use std::time::Duration;
+    use tokio::time;
+    use xor_name::XorName;
+
+    #[tokio::test]
+    async fn every_subscriber_gets_the_events() -> Result<()> {
+        let (events_tx, events_rx) = mpsc::channel(10);
+        let (hub, mut first) = EventHub::new(events_rx);
+        let mut second = hub.subscribe(Subscription::default());
+
+        let name = XorName::random();
+        events_tx.send(member_left(name)).await?;
+
+        for event in vec![first.next().await, second.next().await] {
+            let event = event.ok_or_else(|| anyhow!("no event"))?;
+            assert!(matches!(event, Event::MemberLeft { name: n, .. } if n == name));
+        }
+
+        drop(events_tx);
+        assert!(first.next().await.is_none());
+        assert!(second.next().await.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn filter_by_kind() -> Result<()> {
+        let (events_tx, events_rx) = mpsc::channel(10);
+        let (hub, _all) = EventHub::new(events_rx);
+        let mut relocation = hub.subscribe(Subscription {
+            kinds: Some(vec![EventKind::Relocation]),
+            overflow: OverflowPolicy::DropOldest,
+            ..Default::default()
+        });
+
+        let previous_name = XorName::random();
+        events_tx.send(member_left(XorName::random())).await?;
+        events_tx
+            .send(Event::RelocationStarted { previous_name })
+            .await?;
+        drop(events_tx);
+
+        let events: Vec<_> = relocation.collect().await;
+        assert_eq!(events.len(), 1);
+        assert!(
+            matches!(events[0], Event::RelocationStarted { previous_name: n } if n == previous_name)
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drop_oldest() -> Result<()> {
+        let (events_tx, events_rx) = mpsc::channel(10);
+        let (hub, all) = EventHub::new(events_rx);
+        drop(all);
+        let stream = hub.subscribe(Subscription {
+            capacity: 2,
+            overflow: OverflowPolicy::DropOldest,
+            ..Default::default()
+        });
+
+        let names: Vec<_> = (0..4).map(|_| XorName::random()).collect();
+        for name in &names {
+            events_tx.send(member_left(*name)).await?;
+        }
+        drop(events_tx);
+
+        let received: Vec<_> = stream
+            .filter_map(|event| async move {
+                match event {
+                    Event::MemberLeft { name, .. } => Some(name),
+                    _ => None,
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(received, names[2..]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn lag_notification() -> Result<()> {
+        time::pause();
+
+        let (events_tx, events_rx) = mpsc::channel(10);
+        let (hub, all) = EventHub::new(events_rx);
+        drop(all);
+        let mut stream = hub.subscribe(Subscription {
+            capacity: 2,
+            overflow: OverflowPolicy::Lag,
+            ..Default::default()
+        });
+
+        for _ in 0..5 {
+            events_tx.send(member_left(XorName::random())).await?;
+        }
+
+        // The paused time only advances once the hub distributed all the events.
+        time::sleep(Duration::from_millis(100)).await;
+
+        assert!(matches!(
+            stream.next().await,
+            Some(Event::MemberLeft { .. })
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Event::MemberLeft { .. })
+        ));
+        assert!(matches!(
+            stream.next().await,
+            Some(Event::Lagged { missed: 3 })
+        ));
+
+        Ok(())
+    }
+
+    fn member_left(name: XorName) -> Event {
+        Event::MemberLeft { name, age: 5 }
+    }
+}
@@ -0,0 +1,98 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{agreement::DkgSessionSnapshot, supermajority};
+use std::time::Duration;
+use xor_name::XorName;
+
+/// DKG sessions that haven't completed within this time are reported as stale by
+/// `Routing::health`.
+pub const STALE_DKG_SESSION_AGE: Duration = Duration::from_secs(2 * 60);
+
+/// Report on the state of a node, as returned by `Routing::health`.
+#[derive(Clone, Debug)]
+pub struct Health {
+    /// Whether we are an elder.
+    pub is_elder: bool,
+    /// Number of the elders of our section.
+    pub elders: usize,
+    /// Number of the elders of our section we can reach, including ourselves if we are one.
+    pub reachable_elders: usize,
+    /// The latest key of our section chain.
+    pub section_key: bls::PublicKey,
+    /// Whether some of our elders last signed a proposal with, or addressed a message to us with,
+    /// a section key we don't know yet, meaning our section chain is lagging behind theirs.
+    pub behind_elders: bool,
+    /// Our elders that last signed a proposal with, or addressed a message to us with, a section
+    /// key older than ours.
+    pub lagging_elders: Vec<XorName>,
+    /// Time since our section last agreed on a proposal, or `None` if it didn't since we joined
+    /// it.
+    pub since_last_agreement: Option<Duration>,
+    /// DKG sessions running for longer than `STALE_DKG_SESSION_AGE` without completing.
+    pub stale_dkg_sessions: Vec<DkgSessionSnapshot>,
+    /// Whether the event channel is full at the time of the report, meaning the user doesn't keep
+    /// up with the events.
+    pub event_channel_saturated: bool,
+}
+
+impl Health {
+    /// Returns whether we can reach a supermajority of our elders, which is needed for the
+    /// section to sign anything on our behalf.
+    pub fn elder_supermajority_reachable(&self) -> bool {
+        self.reachable_elders >= supermajority(self.elders)
+    }
+
+    /// Returns whether the node is alive, i.e. keeps processing its events.
+    pub fn is_live(&self) -> bool {
+        !self.event_channel_saturated
+    }
+
+    /// Returns whether the node is ready to serve client traffic: it is live, can reach its
+    /// elders, is up to date with their section chain and no DKG session is stuck.
+    pub fn is_ready(&self) -> bool {
+        self.is_live()
+            && self.elder_supermajority_reachable()
+            && !self.behind_elders
+            && self.stale_dkg_sessions.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness() {
+        let mut health = Health {
+            is_elder: false,
+            elders: 7,
+            reachable_elders: 5,
+            section_key: bls::SecretKey::random().public_key(),
+            behind_elders: false,
+            lagging_elders: vec![],
+            since_last_agreement: None,
+            stale_dkg_sessions: vec![],
+            event_channel_saturated: false,
+        };
+        assert!(health.is_ready());
+
+        health.reachable_elders = 4;
+        assert!(!health.is_ready());
+        assert!(health.is_live());
+
+        health.reachable_elders = 5;
+        health.behind_elders = true;
+        assert!(!health.is_ready());
+
+        health.behind_elders = false;
+        health.event_channel_saturated = true;
+        assert!(!health.is_live());
+        assert!(!health.is_ready());
+    }
+}
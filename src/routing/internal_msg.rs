@@ -0,0 +1,84 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    agreement::SignedShare,
+    ed25519::Digest256,
+    error::{Error, Result},
+    messages::{strip_tag, tagged, INTERNAL_MSG_TAG},
+    NetworkParams,
+};
+use bytes::Bytes;
+use secured_linked_list::SecuredLinkedList;
+use serde::{Deserialize, Serialize};
+use sn_messaging::node::{MemberInfo, Proven, Variant};
+
+// Messages between the nodes of our section that `Variant` has no variant for. They are sent
+// directly to the recipient as `UserMessage`s tagged with `INTERNAL_MSG_TAG`.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum InternalMsg {
+    // Asks an elder for its signature share over `data`, tagged with `USER_SIGNATURE_TAG`.
+    SignatureRequest {
+        data: Bytes,
+        // The latest key of our section chain, which the proof chain of the response starts at.
+        last_known_key: bls::PublicKey,
+    },
+    // Signature share of an elder over the data of the request with the given digest.
+    SignatureResponse {
+        digest: Digest256,
+        signed_share: SignedShare,
+        // Proves the key of the share is a key of our section.
+        proof_chain: SecuredLinkedList,
+    },
+    // Asks an elder for its decryption share of `ciphertext`, encrypted to `section_key`, for the
+    // application-defined `context` its `DecryptionPolicy` decides on.
+    DecryptionRequest {
+        ciphertext: bls::Ciphertext,
+        section_key: bls::PublicKey,
+        context: Bytes,
+    },
+    // Decryption share of an elder of the ciphertext with the given digest.
+    DecryptionResponse {
+        digest: Digest256,
+        // Key set of the key share the decryption share was produced with.
+        public_key_set: bls::PublicKeySet,
+        index: usize,
+        decryption_share: bls::DecryptionShare,
+    },
+    // The network parameters proven by the genesis key, sent by the elders to a node asking to
+    // join, as neither the `JoinResponse`s nor our `Section` carry them.
+    NetworkParams(Proven<NetworkParams>),
+    // Announces to our elders that the sender leaves our section gracefully.
+    Leaving {
+        // The latest key of the sender's section chain, which the proof chain of `Left` starts at.
+        last_known_key: bls::PublicKey,
+    },
+    // Tells a node that announced it is leaving that our section agreed on it going offline.
+    Left {
+        member_info: Proven<MemberInfo>,
+        // Proves the key `member_info` is signed with is a key of our section.
+        proof_chain: SecuredLinkedList,
+    },
+}
+
+impl InternalMsg {
+    pub fn to_variant(&self) -> Result<Variant> {
+        let bytes = bincode::serialize(self).map_err(|_| Error::InvalidMessage)?;
+        Ok(Variant::UserMessage(tagged(INTERNAL_MSG_TAG, &bytes)))
+    }
+
+    // Returns whether `content` of a `UserMessage` is an internal message.
+    pub fn is_internal(content: &[u8]) -> bool {
+        content.starts_with(INTERNAL_MSG_TAG)
+    }
+
+    pub fn from_content(content: &[u8]) -> Result<Self> {
+        let bytes = strip_tag(INTERNAL_MSG_TAG, content).ok_or(Error::InvalidMessage)?;
+        bincode::deserialize(bytes).map_err(|_| Error::InvalidMessage)
+    }
+}
@@ -0,0 +1,82 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use std::{
+    fs::File,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{mpsc, Mutex},
+    thread,
+};
+#[cfg(test)]
+use tokio::sync::oneshot;
+
+enum Request {
+    Write(Vec<u8>),
+    #[cfg(test)]
+    Flush(oneshot::Sender<()>),
+}
+
+// Appends to a file from a dedicated thread, so the tasks of the node never block on the file
+// system. The writes happen in the order they were requested in. Failures are logged only, as the
+// node doesn't depend on the file.
+pub(crate) struct LogWriter {
+    tx: Mutex<mpsc::Sender<Request>>,
+}
+
+impl LogWriter {
+    pub fn new(path: PathBuf, mut file: File) -> io::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+
+        let _ = thread::Builder::new()
+            .name("sn_routing-log-writer".to_string())
+            .spawn(move || {
+                // Ends once the `LogWriter` is dropped and the pending writes are done.
+                for request in rx {
+                    match request {
+                        Request::Write(bytes) => {
+                            if let Err(error) = file.write_all(&bytes) {
+                                error!("Failed to write to {}: {}", path.display(), error);
+                            }
+                        }
+                        #[cfg(test)]
+                        Request::Flush(done_tx) => {
+                            if let Err(error) = file.flush() {
+                                error!("Failed to flush {}: {}", path.display(), error);
+                            }
+                            let _ = done_tx.send(());
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self { tx: Mutex::new(tx) })
+    }
+
+    // Queues `bytes` to be appended. They are written with a single call, so the writes of
+    // concurrent callers don't interleave.
+    pub fn write(&self, bytes: Vec<u8>) {
+        let _ = self.sender().send(Request::Write(bytes));
+    }
+
+    // Completes once everything queued so far is written.
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        let (done_tx, done_rx) = oneshot::channel();
+        if self.sender().send(Request::Flush(done_tx)).is_ok() {
+            let _ = done_rx.await;
+        }
+    }
+
+    fn sender(&self) -> mpsc::Sender<Request> {
+        self.tx
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+            .clone()
+    }
+}
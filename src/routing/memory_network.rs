@@ -0,0 +1,594 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::transport::{BindConfig, BoundTransport, Transport, TransportBuilder, TransportEvent};
+use crate::{
+    clock::{self, Clock},
+    error::{Error, Result, TransportError},
+};
+use bytes::Bytes;
+use futures::{
+    future::{self, BoxFuture},
+    stream::{self, StreamExt},
+    FutureExt,
+};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaChaRng;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{self, Debug, Formatter},
+    io,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::{Arc, Mutex, MutexGuard, Weak},
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc, Notify},
+    task,
+};
+
+/// Faults injected into the messages sent over a link of a `MemoryNetwork`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkConfig {
+    /// Time it takes a message to arrive.
+    pub latency: Duration,
+    /// Upper bound of a random delay added to `latency` of every message. Messages overtake each
+    /// other when this is larger than the interval they are sent at.
+    pub jitter: Duration,
+    /// Probability (between 0 and 1) that a message is lost.
+    pub loss: f64,
+    /// Probability (between 0 and 1) that a message is delivered twice.
+    pub duplication: f64,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_secs(0),
+            jitter: Duration::from_secs(0),
+            loss: 0.0,
+            duplication: 0.0,
+        }
+    }
+}
+
+/// Network simulated in memory, to run many nodes in a single process without real sockets.
+///
+/// Use it as the `Config::transport` of every node of the simulation. All the randomness of the
+/// injected faults comes from a RNG seeded with the seed passed to `new`, so a scenario can be
+/// replayed by running it again with the same seed. Delayed messages are delivered by the clock
+/// set with `set_clock`, in the order they are due, and in the order they were sent if they are
+/// due at the same time.
+#[derive(Clone)]
+pub struct MemoryNetwork {
+    inner: Arc<Mutex<Inner>>,
+}
+
+struct Inner {
+    rng: ChaChaRng,
+    clock: Arc<dyn Clock>,
+    // Delayed messages by the time they are due and the order they were sent in, with the address
+    // of their recipient.
+    in_flight: BTreeMap<(Instant, u64), (SocketAddr, TransportEvent)>,
+    next_in_flight: u64,
+    // Wakes the task delivering the delayed messages, while it's running.
+    delivery: Option<Arc<Notify>>,
+    endpoints: BTreeMap<SocketAddr, mpsc::UnboundedSender<TransportEvent>>,
+    // Open connections, with the lower address first.
+    connections: BTreeSet<(SocketAddr, SocketAddr)>,
+    default_link: LinkConfig,
+    // Per-link overrides of `default_link`, with the lower address first.
+    links: BTreeMap<(SocketAddr, SocketAddr), LinkConfig>,
+    // Endpoints in different groups can't reach each other. Endpoints not listed are in group 0.
+    groups: BTreeMap<SocketAddr, usize>,
+    next_group: usize,
+    next_port: u16,
+}
+
+impl MemoryNetwork {
+    /// Creates a network whose faults are drawn from a RNG seeded with `seed`.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(Inner {
+                rng: ChaChaRng::seed_from_u64(seed),
+                clock: clock::default_clock(),
+                in_flight: BTreeMap::new(),
+                next_in_flight: 0,
+                delivery: None,
+                endpoints: BTreeMap::new(),
+                connections: BTreeSet::new(),
+                default_link: LinkConfig::default(),
+                links: BTreeMap::new(),
+                groups: BTreeMap::new(),
+                next_group: 1,
+                next_port: 1,
+            })),
+        }
+    }
+
+    /// Sets the clock the delays of the links are measured with. Use the `Config::clock` of the
+    /// nodes. Defaults to `TokioClock`.
+    pub fn set_clock(&self, clock: Arc<dyn Clock>) {
+        self.lock().clock = clock;
+    }
+
+    /// Sets the faults of the links without an explicit configuration.
+    pub fn set_default_link(&self, link: LinkConfig) {
+        self.lock().default_link = link;
+    }
+
+    /// Sets the faults of the link between `a` and `b`, in both directions.
+    pub fn set_link(&self, a: SocketAddr, b: SocketAddr, link: LinkConfig) {
+        let _ = self.lock().links.insert(ordered(a, b), link);
+    }
+
+    /// Cuts the endpoints at `addrs` off the rest of the network. They can still reach each
+    /// other. Open connections across the partition are lost.
+    pub fn partition(&self, addrs: impl IntoIterator<Item = SocketAddr>) {
+        let mut inner = self.lock();
+        let group = inner.next_group;
+        inner.next_group += 1;
+
+        for addr in addrs {
+            let _ = inner.groups.insert(addr, group);
+        }
+
+        let severed: Vec<_> = inner
+            .connections
+            .iter()
+            .filter(|(a, b)| !inner.can_reach(a, b))
+            .copied()
+            .collect();
+        for (a, b) in severed {
+            inner.drop_connection(a, b);
+        }
+    }
+
+    /// Removes all partitions.
+    pub fn heal(&self) {
+        self.lock().groups.clear();
+    }
+
+    /// Returns the addresses of all the endpoints in the network.
+    pub fn addrs(&self) -> Vec<SocketAddr> {
+        self.lock().endpoints.keys().copied().collect()
+    }
+
+    fn bind_endpoint(&self, config: BindConfig) -> Result<BoundTransport> {
+        let mut inner = self.lock();
+
+        let ip = config
+            .local_ip
+            .unwrap_or_else(|| IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let addr = match config.local_port.filter(|port| *port != 0) {
+            Some(port) => SocketAddr::new(ip, port),
+            None => loop {
+                let addr = SocketAddr::new(ip, inner.next_port);
+                inner.next_port = inner.next_port.wrapping_add(1).max(1);
+                if !inner.endpoints.contains_key(&addr) {
+                    break addr;
+                }
+            },
+        };
+
+        if inner.endpoints.contains_key(&addr) {
+            let err = io::Error::new(io::ErrorKind::AddrInUse, addr.to_string());
+            return Err(Error::Transport(TransportError::Other(Box::new(err))));
+        }
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let _ = inner.endpoints.insert(addr, tx);
+
+        let events = stream::unfold(rx, |mut rx| async move {
+            let event = rx.recv().await?;
+            Some((event, rx))
+        })
+        .boxed();
+        let transport = MemoryTransport {
+            addr,
+            network: self.clone(),
+        };
+
+        Ok((Box::new(transport), events))
+    }
+
+    fn lock(&self) -> MutexGuard<Inner> {
+        self.inner.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn connect(&self, src: SocketAddr, dst: SocketAddr) -> Result<(), TransportError> {
+        let mut inner = self.lock();
+        if !inner.endpoints.contains_key(&src) {
+            return Err(TransportError::Closed);
+        }
+        if !inner.endpoints.contains_key(&dst) || !inner.can_reach(&src, &dst) {
+            return Err(TransportError::Unreachable);
+        }
+
+        let _ = inner.connections.insert(ordered(src, dst));
+        Ok(())
+    }
+
+    fn send(&self, src: SocketAddr, dst: SocketAddr, msg: Bytes) -> Result<(), TransportError> {
+        let mut inner = self.lock();
+        if !inner.endpoints.contains_key(&src) {
+            return Err(TransportError::Closed);
+        }
+        if !inner.connections.contains(&ordered(src, dst)) {
+            return Err(TransportError::Unreachable);
+        }
+        let tx = inner
+            .endpoints
+            .get(&dst)
+            .cloned()
+            .ok_or(TransportError::Unreachable)?;
+
+        let link = inner.link(src, dst);
+        if inner.rng.gen_bool(clamp(link.loss)) {
+            trace!("Simulated loss of message from {} to {}", src, dst);
+            return Ok(());
+        }
+
+        let copies = if inner.rng.gen_bool(clamp(link.duplication)) {
+            2
+        } else {
+            1
+        };
+
+        for _ in 0..copies {
+            let jitter = inner.rng.gen_range(0, link.jitter.as_nanos() as u64 + 1);
+            let delay = link.latency + Duration::from_nanos(jitter);
+            let event = TransportEvent::Received {
+                src,
+                msg: msg.clone(),
+            };
+
+            if delay == Duration::from_secs(0) {
+                let _ = tx.send(event);
+            } else {
+                let due = inner.clock.now() + delay;
+                let index = inner.next_in_flight;
+                inner.next_in_flight += 1;
+                let _ = inner.in_flight.insert((due, index), (dst, event));
+
+                match inner.delivery.clone() {
+                    Some(delivery) => delivery.notify_one(),
+                    None => {
+                        let delivery = Arc::new(Notify::new());
+                        inner.delivery = Some(delivery.clone());
+                        let _ = task::spawn(deliver(Arc::downgrade(&self.inner), delivery));
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn is_reachable(&self, src: SocketAddr, dst: SocketAddr) -> bool {
+        let inner = self.lock();
+        inner.endpoints.contains_key(&dst) && inner.can_reach(&src, &dst)
+    }
+
+    fn disconnect(&self, src: SocketAddr, dst: SocketAddr) {
+        let mut inner = self.lock();
+        if inner.connections.remove(&ordered(src, dst)) {
+            inner.notify_disconnected(dst, src);
+        }
+    }
+
+    fn close(&self, addr: SocketAddr) {
+        let mut inner = self.lock();
+        if inner.endpoints.remove(&addr).is_none() {
+            return;
+        }
+
+        let connections: Vec<_> = inner
+            .connections
+            .iter()
+            .filter(|(a, b)| *a == addr || *b == addr)
+            .copied()
+            .collect();
+        for (a, b) in connections {
+            inner.drop_connection(a, b);
+        }
+    }
+}
+
+impl TransportBuilder for MemoryNetwork {
+    fn bind(&self, config: BindConfig) -> BoxFuture<'static, Result<BoundTransport>> {
+        let result = self.bind_endpoint(config);
+        future::ready(result).boxed()
+    }
+}
+
+impl Debug for MemoryNetwork {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "MemoryNetwork")
+    }
+}
+
+impl Inner {
+    fn link(&self, a: SocketAddr, b: SocketAddr) -> LinkConfig {
+        self.links
+            .get(&ordered(a, b))
+            .copied()
+            .unwrap_or(self.default_link)
+    }
+
+    fn can_reach(&self, a: &SocketAddr, b: &SocketAddr) -> bool {
+        self.groups.get(a).unwrap_or(&0) == self.groups.get(b).unwrap_or(&0)
+    }
+
+    fn drop_connection(&mut self, a: SocketAddr, b: SocketAddr) {
+        if self.connections.remove(&(a, b)) {
+            self.notify_disconnected(a, b);
+            self.notify_disconnected(b, a);
+        }
+    }
+
+    // Tells the endpoint at `addr` it lost the connection to `peer`.
+    fn notify_disconnected(&self, addr: SocketAddr, peer: SocketAddr) {
+        if let Some(tx) = self.endpoints.get(&addr) {
+            let _ = tx.send(TransportEvent::Disconnected(peer));
+        }
+    }
+}
+
+struct MemoryTransport {
+    addr: SocketAddr,
+    network: MemoryNetwork,
+}
+
+impl Transport for MemoryTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    fn connect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, Result<(), TransportError>> {
+        future::ready(self.network.connect(self.addr, *peer)).boxed()
+    }
+
+    fn send<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+        msg: Bytes,
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        future::ready(self.network.send(self.addr, *peer, msg)).boxed()
+    }
+
+    fn is_reachable<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        let result = if self.network.is_reachable(self.addr, *peer) {
+            Ok(())
+        } else {
+            Err(TransportError::Unreachable)
+        };
+        future::ready(result).boxed()
+    }
+
+    fn disconnect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, ()> {
+        self.network.disconnect(self.addr, *peer);
+        future::ready(()).boxed()
+    }
+
+    fn close(&self) {
+        self.network.close(self.addr)
+    }
+}
+
+impl Drop for MemoryTransport {
+    fn drop(&mut self) {
+        self.network.close(self.addr)
+    }
+}
+
+// Delivers the delayed messages of the network once they are due. Ends once there are none left
+// or the network is dropped, and is started again by the next delayed message.
+async fn deliver(network: Weak<Mutex<Inner>>, wake: Arc<Notify>) {
+    loop {
+        let (clock, next_due) = {
+            let network = if let Some(network) = network.upgrade() {
+                network
+            } else {
+                return;
+            };
+            let mut inner = network.lock().unwrap_or_else(|err| err.into_inner());
+            let now = inner.clock.now();
+
+            while let Some(key) = inner.in_flight.keys().next().copied() {
+                if key.0 > now {
+                    break;
+                }
+
+                if let Some((dst, event)) = inner.in_flight.remove(&key) {
+                    // Messages to an endpoint closed in the meantime are lost.
+                    if let Some(tx) = inner.endpoints.get(&dst) {
+                        let _ = tx.send(event);
+                    }
+                }
+            }
+
+            if let Some((due, _)) = inner.in_flight.keys().next() {
+                (inner.clock.clone(), *due)
+            } else {
+                inner.delivery = None;
+                return;
+            }
+        };
+
+        // Woken up early when a message due sooner is sent.
+        let delay = next_due.saturating_duration_since(clock.now());
+        let _ = clock::timeout(&*clock, delay, wake.notified()).await;
+    }
+}
+
+fn ordered(a: SocketAddr, b: SocketAddr) -> (SocketAddr, SocketAddr) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+fn clamp(probability: f64) -> f64 {
+    probability.max(0.0).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Result};
+    use assert_matches::assert_matches;
+
+    #[tokio::test]
+    async fn send_and_receive() -> Result<()> {
+        let network = MemoryNetwork::new(0);
+        let (a, _a_events) = bind(&network).await?;
+        let (b, mut b_events) = bind(&network).await?;
+
+        a.connect(&b.local_addr()).await?;
+        a.send(&b.local_addr(), Bytes::from_static(b"hello"))
+            .await?;
+
+        assert_matches!(
+            b_events.next().await,
+            Some(TransportEvent::Received { src, msg }) => {
+                assert_eq!(src, a.local_addr());
+                assert_eq!(msg, Bytes::from_static(b"hello"));
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn same_seed_same_faults() -> Result<()> {
+        let link = LinkConfig {
+            loss: 0.5,
+            duplication: 0.2,
+            ..Default::default()
+        };
+
+        let run = || async {
+            let network = MemoryNetwork::new(42);
+            network.set_default_link(link);
+            let (a, _a_events) = bind(&network).await?;
+            let (b, b_events) = bind(&network).await?;
+
+            a.connect(&b.local_addr()).await?;
+            for i in 0..100u8 {
+                a.send(&b.local_addr(), Bytes::from(vec![i])).await?;
+            }
+            drop(b);
+
+            let received: Vec<_> = b_events
+                .filter_map(|event| async move {
+                    match event {
+                        TransportEvent::Received { msg, .. } => Some(msg[0]),
+                        TransportEvent::Disconnected(_) => None,
+                    }
+                })
+                .collect()
+                .await;
+            Ok::<_, anyhow::Error>(received)
+        };
+
+        let first = run().await?;
+        assert!(!first.is_empty());
+        assert_ne!(first, (0..100).collect::<Vec<_>>());
+        assert_eq!(run().await?, first);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn delays_on_clock() -> Result<()> {
+        tokio::time::pause();
+        let clock = clock::default_clock();
+        let network = MemoryNetwork::new(0);
+        network.set_clock(clock.clone());
+        let (a, _a_events) = bind(&network).await?;
+        let (b, mut b_events) = bind(&network).await?;
+        let (c, _c_events) = bind(&network).await?;
+
+        network.set_link(
+            a.local_addr(),
+            b.local_addr(),
+            LinkConfig {
+                latency: Duration::from_secs(2),
+                ..Default::default()
+            },
+        );
+        network.set_link(
+            c.local_addr(),
+            b.local_addr(),
+            LinkConfig {
+                latency: Duration::from_secs(1),
+                ..Default::default()
+            },
+        );
+
+        let start = clock.now();
+        a.connect(&b.local_addr()).await?;
+        c.connect(&b.local_addr()).await?;
+        a.send(&b.local_addr(), Bytes::from_static(b"slow")).await?;
+        c.send(&b.local_addr(), Bytes::from_static(b"fast")).await?;
+
+        // The message over the faster link arrives first.
+        for (expected_src, expected_msg, expected_delay) in vec![
+            (c.local_addr(), Bytes::from_static(b"fast"), 1),
+            (a.local_addr(), Bytes::from_static(b"slow"), 2),
+        ] {
+            assert_matches!(
+                b_events.next().await,
+                Some(TransportEvent::Received { src, msg }) => {
+                    assert_eq!(src, expected_src);
+                    assert_eq!(msg, expected_msg);
+                }
+            );
+            assert!(clock.now() - start >= Duration::from_secs(expected_delay));
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partition_and_heal() -> Result<()> {
+        let network = MemoryNetwork::new(0);
+        let (a, mut a_events) = bind(&network).await?;
+        let (b, _b_events) = bind(&network).await?;
+
+        a.connect(&b.local_addr()).await?;
+        network.partition(vec![b.local_addr()]);
+
+        assert_matches!(
+            a_events.next().await,
+            Some(TransportEvent::Disconnected(addr)) => assert_eq!(addr, b.local_addr())
+        );
+        assert!(a.is_reachable(&b.local_addr()).await.is_err());
+        assert_matches!(
+            a.connect(&b.local_addr()).await,
+            Err(TransportError::Unreachable)
+        );
+
+        network.heal();
+        a.connect(&b.local_addr()).await?;
+
+        Ok(())
+    }
+
+    async fn bind(network: &MemoryNetwork) -> Result<BoundTransport> {
+        network
+            .bind(BindConfig::default())
+            .await
+            .map_err(|err| anyhow!("{}", err))
+    }
+}
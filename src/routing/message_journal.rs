@@ -0,0 +1,248 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::log_writer::LogWriter;
+use crate::{
+    clock::Clock,
+    error::{Error, Result},
+};
+use serde::{Deserialize, Serialize};
+use sn_messaging::MessageId;
+use std::{
+    collections::BTreeMap,
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+use xor_name::XorName;
+
+/// A step of a message on its way through a node, as recorded in the message journal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "hop", rename_all = "snake_case")]
+pub enum Hop {
+    /// The message was received from `sender`, or from ourselves if `None`.
+    Received {
+        /// Address of the node we received the message from.
+        sender: Option<SocketAddr>,
+    },
+    /// The message was passed on towards its destination.
+    Relayed {
+        /// The nodes the message was relayed to, in the order of preference.
+        targets: Vec<XorName>,
+        /// Number of the targets the message had to reach.
+        delivery_group_size: usize,
+    },
+    /// The message was sent.
+    Sent {
+        /// The recipients the message was sent to.
+        recipients: Vec<XorName>,
+    },
+    /// Sending the message failed for too many of its recipients.
+    SendFailed {
+        /// The recipients the message couldn't be sent to.
+        recipients: Vec<SocketAddr>,
+    },
+    /// Enough signature shares of the message were collected to handle it.
+    Aggregated,
+    /// The message was sent back to its sender because it's signed with a key we don't trust.
+    BouncedUntrusted {
+        /// Address of the node we bounced the message to, if known.
+        sender: Option<SocketAddr>,
+    },
+    /// The message reached its destination and was handed to the user or the end user.
+    Delivered,
+}
+
+/// A line of the message journal.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HopRecord {
+    /// Id of the message.
+    pub msg_id: MessageId,
+    /// Name of the node that recorded the hop.
+    pub node: XorName,
+    /// When the hop happened, by the clock of the recording node (see `Config::clock`), counted
+    /// from the system time when it opened its journal.
+    pub time: SystemTime,
+    /// What happened to the message.
+    #[serde(flatten)]
+    pub hop: Hop,
+}
+
+// Appends the hops of the messages that pass this node to a JSON-lines file.
+pub(crate) struct MessageJournal {
+    writer: LogWriter,
+    clock: Arc<dyn Clock>,
+    // The system time and the time of `clock` when the journal was opened.
+    opened_at: (SystemTime, Instant),
+}
+
+impl MessageJournal {
+    pub fn open(path: impl Into<PathBuf>, clock: Arc<dyn Clock>) -> Result<Self> {
+        let path = path.into();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(Error::MessageJournal)?;
+        let writer = LogWriter::new(path, file).map_err(Error::MessageJournal)?;
+        let opened_at = (SystemTime::now(), clock.now());
+
+        Ok(Self {
+            writer,
+            clock,
+            opened_at,
+        })
+    }
+
+    pub fn record(&self, node: XorName, msg_id: MessageId, hop: Hop) {
+        let (system_time, instant) = self.opened_at;
+        let record = HopRecord {
+            msg_id,
+            node,
+            time: system_time + self.clock.now().saturating_duration_since(instant),
+            hop,
+        };
+
+        let mut line = match serde_json::to_vec(&record) {
+            Ok(line) => line,
+            Err(error) => {
+                error!("Failed to serialize {:?}: {}", record, error);
+                return;
+            }
+        };
+        line.push(b'\n');
+
+        self.writer.write(line);
+    }
+
+    // Completes once everything recorded so far is written.
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        self.writer.flush().await
+    }
+}
+
+/// Reads the message journals written by several nodes (see `Config::message_journal`) and
+/// stitches them into the paths the messages took through the network.
+///
+/// Returns the hops of every message, ordered by time. As the times come from the clocks of
+/// different nodes, the order across nodes is only as accurate as the clocks are in sync. Lines
+/// that can't be parsed, e.g. one cut short by a crash, are skipped.
+pub fn stitch_message_journals<P: AsRef<Path>>(
+    paths: impl IntoIterator<Item = P>,
+) -> Result<BTreeMap<MessageId, Vec<HopRecord>>> {
+    let mut paths_by_msg: BTreeMap<_, Vec<_>> = BTreeMap::new();
+
+    for path in paths {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(Error::MessageJournal)?;
+
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line.map_err(Error::MessageJournal)?;
+            match serde_json::from_str::<HopRecord>(&line) {
+                Ok(record) => paths_by_msg.entry(record.msg_id).or_default().push(record),
+                Err(error) => warn!(
+                    "Skipping line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    error
+                ),
+            }
+        }
+    }
+
+    for records in paths_by_msg.values_mut() {
+        records.sort_by_key(|record| record.time);
+    }
+
+    Ok(paths_by_msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::TokioClock;
+    use anyhow::Result;
+    use std::{env, fs, io::Write, time::Duration};
+    use tokio::time;
+
+    #[tokio::test]
+    async fn stitch() -> Result<()> {
+        time::pause();
+
+        let dir = env::temp_dir().join(format!("sn_routing-{:x}", rand::random::<u64>()));
+        fs::create_dir_all(&dir)?;
+        let path_a = dir.join("a.jsonl");
+        let path_b = dir.join("b.jsonl");
+
+        let node_a = XorName::random();
+        let node_b = XorName::random();
+        let msg_0 = MessageId::new();
+        let msg_1 = MessageId::new();
+
+        let journal_a = MessageJournal::open(&path_a, Arc::new(TokioClock))?;
+        let journal_b = MessageJournal::open(&path_b, Arc::new(TokioClock))?;
+
+        journal_a.record(
+            node_a,
+            msg_0,
+            Hop::Relayed {
+                targets: vec![node_b],
+                delivery_group_size: 1,
+            },
+        );
+        journal_b.record(node_b, msg_0, Hop::Received { sender: None });
+
+        // The times follow the clock of the node.
+        time::advance(Duration::from_secs(60 * 60)).await;
+        journal_b.record(node_b, msg_0, Hop::Delivered);
+        journal_a.record(node_a, msg_1, Hop::Aggregated);
+
+        journal_a.flush().await;
+        journal_b.flush().await;
+
+        // A truncated line is skipped.
+        let mut file = OpenOptions::new().append(true).open(&path_b)?;
+        file.write_all(b"{\"msg_id\":")?;
+
+        let paths = stitch_message_journals(&[&path_a, &path_b])?;
+        fs::remove_dir_all(&dir)?;
+
+        assert_eq!(paths.len(), 2);
+
+        let hops: Vec<_> = paths[&msg_0]
+            .iter()
+            .map(|record| (record.node, record.hop.clone()))
+            .collect();
+        assert_eq!(
+            hops,
+            vec![
+                (
+                    node_a,
+                    Hop::Relayed {
+                        targets: vec![node_b],
+                        delivery_group_size: 1
+                    }
+                ),
+                (node_b, Hop::Received { sender: None }),
+                (node_b, Hop::Delivered),
+            ]
+        );
+        let received_at = paths[&msg_0][1].time;
+        let delivered_at = paths[&msg_0][2].time;
+        assert!(delivered_at.duration_since(received_at)? >= Duration::from_secs(60 * 60));
+
+        assert_eq!(paths[&msg_1].len(), 1);
+        assert_eq!(paths[&msg_1][0].hop, Hop::Aggregated);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,609 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::comm::SendStatus;
+use crate::{agreement::proposal_digest, ed25519::Digest256, event::Event};
+use serde::{Deserialize, Serialize};
+use sn_messaging::{
+    node::{Proposal, Variant},
+    MessageType,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+// Our proposals that aren't agreed on within this time are no longer tracked.
+const MAX_AGREEMENT_LATENCY: Duration = Duration::from_secs(10 * 60);
+
+// Registry of the runtime metrics of a node. Shared by the components that record them.
+#[derive(Default)]
+pub(crate) struct Metrics {
+    messages_sent: Mutex<BTreeMap<&'static str, u64>>,
+    messages_received: Mutex<BTreeMap<&'static str, u64>>,
+    all_recipients: AtomicU64,
+    min_delivery_group_size_reached: AtomicU64,
+    min_delivery_group_size_failed: AtomicU64,
+    bytes_sent: AtomicU64,
+    bytes_received: AtomicU64,
+    // Our proposals not agreed on yet, with the time we made them.
+    pending_proposals: Mutex<HashMap<Digest256, Instant>>,
+    agreement_latency: Mutex<DurationSummary>,
+    dkg_duration: Mutex<DurationSummary>,
+    dkg_failures: AtomicU64,
+    message_filter_checks: AtomicU64,
+    message_filter_hits: AtomicU64,
+    bootstrap_backlog: AtomicU64,
+    events_sent: AtomicU64,
+    events_blocked: AtomicU64,
+    pending_proposal_aggregations: AtomicU64,
+    pending_message_aggregations: AtomicU64,
+    aggregations_evicted: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_message_sent(&self, msg: &MessageType) {
+        *lock(&self.messages_sent)
+            .entry(message_label(msg))
+            .or_default() += 1;
+    }
+
+    pub fn record_message_received(&self, msg: &MessageType) {
+        *lock(&self.messages_received)
+            .entry(message_label(msg))
+            .or_default() += 1;
+    }
+
+    pub fn record_send_status(&self, status: &SendStatus) {
+        let counter = match status {
+            SendStatus::AllRecipients => &self.all_recipients,
+            SendStatus::MinDeliveryGroupSizeReached(_) => &self.min_delivery_group_size_reached,
+            SendStatus::MinDeliveryGroupSizeFailed(_) => &self.min_delivery_group_size_failed,
+        };
+        let _ = counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes_sent(&self, bytes: usize) {
+        let _ = self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_bytes_received(&self, bytes: usize) {
+        let _ = self
+            .bytes_received
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    // Starts measuring the time until `proposal`, which we made at `now`, is agreed on.
+    pub fn record_proposal(&self, proposal: &Proposal, now: Instant) {
+        let digest = if let Some(digest) = proposal_digest(proposal) {
+            digest
+        } else {
+            return;
+        };
+
+        let mut pending = lock(&self.pending_proposals);
+        pending.retain(|_, proposed_at| {
+            now.saturating_duration_since(*proposed_at) < MAX_AGREEMENT_LATENCY
+        });
+        let _ = pending.entry(digest).or_insert(now);
+    }
+
+    // Records the agreement latency of `proposal`, if it's one of ours.
+    pub fn record_agreement(&self, proposal: &Proposal, now: Instant) {
+        let proposed_at = proposal_digest(proposal)
+            .and_then(|digest| lock(&self.pending_proposals).remove(&digest));
+
+        if let Some(proposed_at) = proposed_at {
+            lock(&self.agreement_latency).record(now.saturating_duration_since(proposed_at));
+        }
+    }
+
+    // Records the metrics derived from the events raised by the node.
+    pub fn record_event(&self, event: &Event) {
+        match event {
+            Event::DkgCompleted { elapsed, .. } => lock(&self.dkg_duration).record(*elapsed),
+            Event::DkgFailed { elapsed, .. } => {
+                lock(&self.dkg_duration).record(*elapsed);
+                let _ = self.dkg_failures.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => (),
+        }
+    }
+
+    // Records a lookup in the message filter. `hit` means the message was already known.
+    pub fn record_message_filter(&self, hit: bool) {
+        let _ = self.message_filter_checks.fetch_add(1, Ordering::Relaxed);
+        if hit {
+            let _ = self.message_filter_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn record_bootstrap_backlog(&self, len: usize) {
+        self.bootstrap_backlog.store(len as u64, Ordering::Relaxed);
+    }
+
+    // Records an event sent to the user. `blocked` means the event channel was full so sending
+    // had to wait for the user to catch up.
+    pub fn record_event_sent(&self, blocked: bool) {
+        let _ = self.events_sent.fetch_add(1, Ordering::Relaxed);
+        if blocked {
+            let _ = self.events_blocked.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Records the number of proposals and messages the aggregators hold signature shares of, and
+    // the number of those they evicted since the last call.
+    pub fn record_aggregations(&self, proposals: usize, messages: usize, evicted: usize) {
+        self.pending_proposal_aggregations
+            .store(proposals as u64, Ordering::Relaxed);
+        self.pending_message_aggregations
+            .store(messages as u64, Ordering::Relaxed);
+        let _ = self
+            .aggregations_evicted
+            .fetch_add(evicted as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        fn owned(counters: &BTreeMap<&'static str, u64>) -> BTreeMap<String, u64> {
+            counters
+                .iter()
+                .map(|(label, count)| ((*label).to_string(), *count))
+                .collect()
+        }
+
+        MetricsSnapshot {
+            messages_sent: owned(&lock(&self.messages_sent)),
+            messages_received: owned(&lock(&self.messages_received)),
+            send_outcomes: SendOutcomes {
+                all_recipients: self.all_recipients.load(Ordering::Relaxed),
+                min_delivery_group_size_reached: self
+                    .min_delivery_group_size_reached
+                    .load(Ordering::Relaxed),
+                min_delivery_group_size_failed: self
+                    .min_delivery_group_size_failed
+                    .load(Ordering::Relaxed),
+            },
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            agreement_latency: *lock(&self.agreement_latency),
+            dkg_duration: *lock(&self.dkg_duration),
+            dkg_failures: self.dkg_failures.load(Ordering::Relaxed),
+            message_filter_checks: self.message_filter_checks.load(Ordering::Relaxed),
+            message_filter_hits: self.message_filter_hits.load(Ordering::Relaxed),
+            bootstrap_backlog: self.bootstrap_backlog.load(Ordering::Relaxed),
+            events_sent: self.events_sent.load(Ordering::Relaxed),
+            events_blocked: self.events_blocked.load(Ordering::Relaxed),
+            pending_proposal_aggregations: self
+                .pending_proposal_aggregations
+                .load(Ordering::Relaxed),
+            pending_message_aggregations: self.pending_message_aggregations.load(Ordering::Relaxed),
+            aggregations_evicted: self.aggregations_evicted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Point-in-time copy of the metrics of a node, as returned by `Routing::metrics`.
+///
+/// The counters are totals since the node started.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+    /// Number of messages sent, by message variant. Messages that reached none of their
+    /// recipients are not counted.
+    pub messages_sent: BTreeMap<String, u64>,
+    /// Number of messages received, by message variant. Duplicates are not counted.
+    pub messages_received: BTreeMap<String, u64>,
+    /// Outcomes of sending messages to groups of nodes.
+    pub send_outcomes: SendOutcomes,
+    /// Number of bytes sent, including all copies of messages sent to multiple recipients.
+    pub bytes_sent: u64,
+    /// Number of bytes received, including duplicate messages.
+    pub bytes_received: u64,
+    /// Time from making a proposal to handling the agreement on it. Only our own proposals are
+    /// measured.
+    pub agreement_latency: DurationSummary,
+    /// Duration of the DKG sessions we took part in, successful or not.
+    pub dkg_duration: DurationSummary,
+    /// Number of DKG sessions we took part in that failed.
+    pub dkg_failures: u64,
+    /// Number of incoming messages looked up in the message filter.
+    pub message_filter_checks: u64,
+    /// Number of incoming messages dropped by the message filter as already handled.
+    pub message_filter_hits: u64,
+    /// Number of messages backlogged while the node was joining the network the last time.
+    pub bootstrap_backlog: u64,
+    /// Number of events sent to the user.
+    pub events_sent: u64,
+    /// Number of events that found the event channel full and had to wait for room in it.
+    pub events_blocked: u64,
+    /// Number of proposals we hold signature shares of but can't aggregate yet.
+    pub pending_proposal_aggregations: u64,
+    /// Number of messages we hold signature shares of but can't aggregate yet.
+    pub pending_message_aggregations: u64,
+    /// Number of proposals and messages whose signature shares were discarded before they could
+    /// be aggregated, because they expired or their section key became outdated.
+    pub aggregations_evicted: u64,
+}
+
+impl MetricsSnapshot {
+    /// Returns the fraction of the incoming messages dropped by the message filter, or zero if
+    /// there were none.
+    pub fn message_filter_hit_rate(&self) -> f64 {
+        ratio(self.message_filter_hits, self.message_filter_checks)
+    }
+
+    /// Returns the fraction of the events that found the event channel full, or zero if there
+    /// were none. Values well above zero mean the user doesn't keep up with the events.
+    pub fn event_channel_saturation(&self) -> f64 {
+        ratio(self.events_blocked, self.events_sent)
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut output = String::new();
+
+        render_labelled(
+            &mut output,
+            "messages_sent_total",
+            "Messages sent, by variant.",
+            "variant",
+            &self.messages_sent,
+        );
+        render_labelled(
+            &mut output,
+            "messages_received_total",
+            "Messages received, by variant.",
+            "variant",
+            &self.messages_received,
+        );
+        render_labelled(
+            &mut output,
+            "send_outcomes_total",
+            "Outcomes of sending messages to groups of nodes.",
+            "outcome",
+            &self.send_outcomes.by_label(),
+        );
+        render(
+            &mut output,
+            "bytes_sent_total",
+            "counter",
+            "Bytes sent.",
+            self.bytes_sent,
+        );
+        render(
+            &mut output,
+            "bytes_received_total",
+            "counter",
+            "Bytes received.",
+            self.bytes_received,
+        );
+        render_summary(
+            &mut output,
+            "agreement_latency_seconds",
+            "Time from our proposal to the agreement on it.",
+            &self.agreement_latency,
+        );
+        render_summary(
+            &mut output,
+            "dkg_duration_seconds",
+            "Duration of the DKG sessions.",
+            &self.dkg_duration,
+        );
+        render(
+            &mut output,
+            "dkg_failures_total",
+            "counter",
+            "Failed DKG sessions.",
+            self.dkg_failures,
+        );
+        render(
+            &mut output,
+            "message_filter_checks_total",
+            "counter",
+            "Incoming messages looked up in the message filter.",
+            self.message_filter_checks,
+        );
+        render(
+            &mut output,
+            "message_filter_hits_total",
+            "counter",
+            "Incoming messages dropped as already handled.",
+            self.message_filter_hits,
+        );
+        render(
+            &mut output,
+            "bootstrap_backlog",
+            "gauge",
+            "Messages backlogged during the last join.",
+            self.bootstrap_backlog,
+        );
+        render(
+            &mut output,
+            "events_sent_total",
+            "counter",
+            "Events sent to the user.",
+            self.events_sent,
+        );
+        render(
+            &mut output,
+            "events_blocked_total",
+            "counter",
+            "Events that waited for room in the full event channel.",
+            self.events_blocked,
+        );
+        render(
+            &mut output,
+            "pending_proposal_aggregations",
+            "gauge",
+            "Proposals waiting for more signature shares.",
+            self.pending_proposal_aggregations,
+        );
+        render(
+            &mut output,
+            "pending_message_aggregations",
+            "gauge",
+            "Messages waiting for more signature shares.",
+            self.pending_message_aggregations,
+        );
+        render(
+            &mut output,
+            "aggregations_evicted_total",
+            "counter",
+            "Partial aggregations discarded before completing.",
+            self.aggregations_evicted,
+        );
+
+        output
+    }
+}
+
+/// Number of sends to groups of nodes, by outcome. See `MetricsSnapshot`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SendOutcomes {
+    /// The message was delivered to all the chosen recipients.
+    pub all_recipients: u64,
+    /// The message was delivered to enough recipients, but only after some of them failed.
+    pub min_delivery_group_size_reached: u64,
+    /// The message couldn't be delivered to enough recipients.
+    pub min_delivery_group_size_failed: u64,
+}
+
+impl SendOutcomes {
+    fn by_label(&self) -> BTreeMap<String, u64> {
+        vec![
+            ("all_recipients", self.all_recipients),
+            (
+                "min_delivery_group_size_reached",
+                self.min_delivery_group_size_reached,
+            ),
+            (
+                "min_delivery_group_size_failed",
+                self.min_delivery_group_size_failed,
+            ),
+        ]
+        .into_iter()
+        .map(|(label, count)| (label.to_string(), count))
+        .collect()
+    }
+}
+
+/// Summary of a series of measured durations.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DurationSummary {
+    /// Number of measurements.
+    pub count: u64,
+    /// Sum of all the measurements.
+    pub total: Duration,
+    /// The longest measurement.
+    pub max: Duration,
+}
+
+impl DurationSummary {
+    /// Returns the mean of the measurements, or `None` if there are none.
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_secs_f64(
+                self.total.as_secs_f64() / self.count as f64,
+            ))
+        }
+    }
+
+    fn record(&mut self, duration: Duration) {
+        self.count += 1;
+        self.total += duration;
+        self.max = self.max.max(duration);
+    }
+}
+
+// Name of the variant of `msg`, used to label the message counters.
+fn message_label(msg: &MessageType) -> &'static str {
+    match msg {
+        MessageType::Routing { msg, .. } => variant_name(&msg.variant),
+        MessageType::Node { .. } => "Node",
+        MessageType::Client { .. } => "Client",
+        MessageType::SectionInfo { .. } => "SectionInfo",
+    }
+}
+
+fn variant_name(variant: &Variant) -> &'static str {
+    match variant {
+        Variant::SectionKnowledge { .. } => "SectionKnowledge",
+        Variant::Sync { .. } => "Sync",
+        Variant::Relocate(_) => "Relocate",
+        Variant::RelocatePromise(_) => "RelocatePromise",
+        Variant::StartConnectivityTest(_) => "StartConnectivityTest",
+        Variant::JoinRequest(_) => "JoinRequest",
+        Variant::JoinResponse(_) => "JoinResponse",
+        Variant::UserMessage(_) => "UserMessage",
+        Variant::BouncedUntrustedMessage { .. } => "BouncedUntrustedMessage",
+        Variant::SectionKnowledgeQuery { .. } => "SectionKnowledgeQuery",
+        Variant::DkgStart { .. } => "DkgStart",
+        Variant::DkgMessage { .. } => "DkgMessage",
+        Variant::DkgFailureObservation { .. } => "DkgFailureObservation",
+        Variant::DkgFailureAgreement(_) => "DkgFailureAgreement",
+        Variant::Propose { .. } => "Propose",
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<T> {
+    mutex.lock().unwrap_or_else(|err| err.into_inner())
+}
+
+fn ratio(part: u64, whole: u64) -> f64 {
+    if whole == 0 {
+        0.0
+    } else {
+        part as f64 / whole as f64
+    }
+}
+
+const PROMETHEUS_PREFIX: &str = "sn_routing_";
+
+fn render_header(output: &mut String, name: &str, kind: &str, help: &str) {
+    output.push_str(&format!(
+        "# HELP {prefix}{name} {help}\n# TYPE {prefix}{name} {kind}\n",
+        prefix = PROMETHEUS_PREFIX,
+        name = name,
+        help = help,
+        kind = kind
+    ));
+}
+
+fn render(output: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    render_header(output, name, kind, help);
+    output.push_str(&format!("{}{} {}\n", PROMETHEUS_PREFIX, name, value));
+}
+
+fn render_labelled(
+    output: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &BTreeMap<String, u64>,
+) {
+    render_header(output, name, "counter", help);
+    for (label_value, value) in values {
+        output.push_str(&format!(
+            "{}{}{{{}=\"{}\"}} {}\n",
+            PROMETHEUS_PREFIX, name, label, label_value, value
+        ));
+    }
+}
+
+fn render_summary(output: &mut String, name: &str, help: &str, summary: &DurationSummary) {
+    render_header(output, name, "summary", help);
+    output.push_str(&format!(
+        "{prefix}{name}_sum {sum}\n{prefix}{name}_count {count}\n",
+        prefix = PROMETHEUS_PREFIX,
+        name = name,
+        sum = summary.total.as_secs_f64(),
+        count = summary.count
+    ));
+    render_header(
+        output,
+        &format!("{}_max", name),
+        "gauge",
+        "The longest of the measurements.",
+    );
+    output.push_str(&format!(
+        "{}{}_max {}\n",
+        PROMETHEUS_PREFIX,
+        name,
+        summary.max.as_secs_f64()
+    ));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn agreement_latency() {
+        let metrics = Metrics::default();
+        let proposal = Proposal::JoinsAllowed(true);
+        let start = Instant::now();
+
+        metrics.record_proposal(&proposal, start);
+        metrics.record_agreement(&proposal, start + Duration::from_secs(3));
+
+        // Agreements on proposals we didn't make, or already measured, are ignored.
+        metrics.record_agreement(&proposal, start + Duration::from_secs(5));
+        metrics.record_agreement(
+            &Proposal::JoinsAllowed(false),
+            start + Duration::from_secs(5),
+        );
+
+        let latency = metrics.snapshot().agreement_latency;
+        assert_eq!(latency.count, 1);
+        assert_eq!(latency.total, Duration::from_secs(3));
+        assert_eq!(latency.mean(), Some(Duration::from_secs(3)));
+    }
+
+    #[test]
+    fn stale_proposals_are_forgotten() {
+        let metrics = Metrics::default();
+        let start = Instant::now();
+
+        metrics.record_proposal(&Proposal::JoinsAllowed(true), start);
+        metrics.record_proposal(
+            &Proposal::JoinsAllowed(false),
+            start + MAX_AGREEMENT_LATENCY,
+        );
+        metrics.record_agreement(&Proposal::JoinsAllowed(true), start + MAX_AGREEMENT_LATENCY);
+
+        assert_eq!(metrics.snapshot().agreement_latency.count, 0);
+    }
+
+    #[test]
+    fn rates() {
+        let metrics = Metrics::default();
+        assert_eq!(metrics.snapshot().message_filter_hit_rate(), 0.0);
+
+        metrics.record_message_filter(false);
+        metrics.record_message_filter(false);
+        metrics.record_message_filter(false);
+        metrics.record_message_filter(true);
+        metrics.record_event_sent(false);
+        metrics.record_event_sent(true);
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.message_filter_hit_rate(), 0.25);
+        assert_eq!(snapshot.event_channel_saturation(), 0.5);
+    }
+
+    #[test]
+    fn prometheus() {
+        let metrics = Metrics::default();
+        metrics.record_send_status(&SendStatus::AllRecipients);
+        metrics.record_send_status(&SendStatus::MinDeliveryGroupSizeFailed(vec![]));
+        metrics.record_bytes_sent(100);
+        metrics.record_bootstrap_backlog(3);
+        metrics.record_aggregations(2, 5, 1);
+        metrics.record_aggregations(1, 4, 2);
+
+        let output = metrics.snapshot().to_prometheus();
+        let lines: Vec<_> = output.lines().collect();
+
+        assert!(lines.contains(&"# TYPE sn_routing_bytes_sent_total counter"));
+        assert!(lines.contains(&"sn_routing_bytes_sent_total 100"));
+        assert!(lines.contains(&"sn_routing_send_outcomes_total{outcome=\"all_recipients\"} 1"));
+        assert!(lines.contains(
+            &"sn_routing_send_outcomes_total{outcome=\"min_delivery_group_size_failed\"} 1"
+        ));
+        assert!(lines.contains(&"sn_routing_bootstrap_backlog 3"));
+        assert!(lines.contains(&"sn_routing_pending_proposal_aggregations 1"));
+        assert!(lines.contains(&"sn_routing_pending_message_aggregations 4"));
+        assert!(lines.contains(&"sn_routing_aggregations_evicted_total 3"));
+        assert!(lines.contains(&"sn_routing_dkg_duration_seconds_count 0"));
+    }
+}
@@ -9,50 +9,99 @@
 pub(crate) mod command;
 
 mod bootstrap;
+mod bootstrap_cache;
 mod comm;
 mod core;
+mod debug_snapshot;
+mod decryption;
 mod dispatcher;
+mod dkg_restarts;
 mod enduser_registry;
 mod event_stream;
+mod health;
+mod internal_msg;
+mod log_writer;
+mod memory_network;
+mod message_journal;
+mod metrics;
+mod proposal_tracker;
+mod replay;
+mod section_signature;
 mod split_barrier;
+mod state_store;
 #[cfg(test)]
 pub(crate) mod tests;
-
-pub use self::event_stream::EventStream;
+mod traffic_log;
+mod transport;
+
+pub use self::{
+    bootstrap::{JoinPolicy, JoinProgress},
+    debug_snapshot::{
+        DebugSnapshot, MemberSnapshot, PendingAggregationSnapshot, RelocateStateSnapshot,
+    },
+    decryption::{seal_for_section, DecryptionPolicy, DecryptionRequest},
+    dkg_restarts::DkgPolicy,
+    enduser_registry::{EndUserInfo, EndUserPolicy, DEFAULT_END_USER_IDLE_TIMEOUT},
+    event_stream::{EventStream, OverflowPolicy, Subscription, DEFAULT_SUBSCRIPTION_CAPACITY},
+    health::{Health, STALE_DKG_SESSION_AGE},
+    memory_network::{LinkConfig, MemoryNetwork},
+    message_journal::{stitch_message_journals, Hop, HopRecord},
+    metrics::{DurationSummary, MetricsSnapshot, SendOutcomes},
+    proposal_tracker::ProposalRetryPolicy,
+    replay::{replay_traffic_log, Replay, ReplayedMessage},
+    section_signature::verify_section_signature,
+    state_store::{LocalStateKey, StateKeyProvider},
+    transport::{
+        BindConfig, BoundTransport, QuicTransportBuilder, Transport, TransportBuilder,
+        TransportEvent, TransportEvents,
+    },
+};
 use self::{
+    bootstrap_cache::BootstrapCache,
     comm::{Comm, ConnectionEvent},
     command::Command,
     core::Core,
     dispatcher::Dispatcher,
+    event_stream::EventHub,
+    message_journal::MessageJournal,
+    state_store::StateStore,
+    traffic_log::{traffic_log_dir, TrafficInput},
 };
 use crate::{
+    clock::{self, Clock, SharedRng, TokioClock},
     ed25519,
-    error::Result,
+    error::{JoinError, Result},
     event::{Elders, Event, NodeElderChange},
     messages::RoutingMsgUtils,
-    network::NetworkUtils,
+    network::{NetworkStats, NetworkUtils},
     node::Node,
     peer::PeerUtils,
-    section::{SectionAuthorityProviderUtils, SectionUtils},
-    Error, TransportConfig, MIN_ADULT_AGE,
+    section::{Section, SectionAuthorityProviderUtils, SectionPeersUtils, SectionUtils},
+    Error, NetworkParams, TransportConfig,
 };
 use bytes::Bytes;
 use ed25519_dalek::{Keypair, PublicKey, Signature, Signer, KEYPAIR_LENGTH};
+use futures::future;
 use itertools::Itertools;
 use secured_linked_list::SecuredLinkedList;
 use sn_messaging::{
     client::ClientMsg,
-    node::{Peer, RoutingMsg},
+    node::{Peer, Proven, RoutingMsg, Signed},
     DestInfo, DstLocation, EndUser, Itinerary, MessageType, SectionAuthorityProvider, WireMsg,
 };
 use std::{
     collections::BTreeSet,
     fmt::{self, Debug, Formatter},
     net::SocketAddr,
+    path::PathBuf,
     sync::Arc,
+    time::Duration,
 };
 
-use tokio::{sync::mpsc, task};
+use tokio::{
+    sync::{mpsc, oneshot},
+    task,
+};
 use xor_name::{Prefix, XorName};
 
 /// Routing configuration.
@@ -62,8 +111,53 @@ pub struct Config {
     pub first: bool,
     /// The `Keypair` of the node or `None` for randomly generated one.
     pub keypair: Option<Keypair>,
-    /// Configuration for the underlying network transport.
+    /// Configuration for the underlying network transport. The local address and the bootstrap
+    /// contacts apply to any transport, the rest only to the default QUIC one.
     pub transport_config: TransportConfig,
+    /// Creates the transport the node communicates over. `None` uses QUIC, configured by
+    /// `transport_config`.
+    pub transport: Option<Arc<dyn TransportBuilder>>,
+    /// Directory to persist the node state to, so the node can later be restarted with
+    /// `Routing::resume` without having to rejoin the network. The sections known to the node are
+    /// cached there too and used to join faster on the next start. The node state, which includes
+    /// the secret keys of the node, is stored encrypted under the key provided by `state_key`.
+    /// `None` disables persistence.
+    pub state_dir: Option<PathBuf>,
+    /// Provides the key the node state persisted to `state_dir` is encrypted under. Defaults to
+    /// `LocalStateKey`, which keeps it in `state_dir` and so only protects the state from leaking
+    /// on its own; see `StateKeyProvider` for keeping it elsewhere.
+    pub state_key: Arc<dyn StateKeyProvider>,
+    /// File to record the hops of the messages passing this node to, as JSON lines. Use
+    /// `stitch_message_journals` to put the journals of several nodes together into the paths
+    /// the messages took. `None` disables the journal.
+    pub message_journal: Option<PathBuf>,
+    /// File to record the messages received by this node to, together with its timers and lost
+    /// connections, so its run can be replayed offline with `replay_traffic_log`. The recording
+    /// starts with the state of the node, which includes its secret keys and is encrypted under
+    /// the key `state_key` provides for the directory of the file, and with the seed of the RNG of
+    /// the node. `None` disables recording.
+    pub traffic_log: Option<PathBuf>,
+    /// Parameters of the network. The genesis node chooses them. A joining node uses them only
+    /// until it receives the ones of the network, which it adopts.
+    pub network_params: NetworkParams,
+    /// Limits on how long joining the network, or rejoining it after relocation, may take.
+    pub join_policy: JoinPolicy,
+    /// Receives updates on the progress of joining the network, if set. Updates are dropped
+    /// rather than holding up the join if the channel is full.
+    pub join_progress: Option<mpsc::Sender<JoinProgress>>,
+    /// Limits on the end users served by this node while it is an elder.
+    pub end_user_policy: EndUserPolicy,
+    /// How the proposals of this node are re-sent while it is an elder.
+    pub proposal_retry_policy: ProposalRetryPolicy,
+    /// How the DKG sessions this node participates in progress and are restarted when they fail.
+    pub dkg_policy: DkgPolicy,
+    /// Decides which requests of the members of our section to decrypt a ciphertext sealed for
+    /// our section this node serves while it is an elder. `None` serves none.
+    pub decryption_policy: Option<Arc<dyn DecryptionPolicy>>,
+    /// Source of time for the timers and expiry of the node. Defaults to the tokio runtime's.
+    pub clock: Arc<dyn Clock>,
+    /// Source of randomness of the node, e.g. for its keys. Seed it to reproduce a simulation.
+    pub rng: SharedRng,
 }
 
 impl Default for Config {
@@ -72,10 +166,32 @@ impl Default for Config {
             first: false,
             keypair: None,
             transport_config: TransportConfig::default(),
+            transport: None,
+            state_dir: None,
+            state_key: Arc::new(LocalStateKey),
+            message_journal: None,
+            traffic_log: None,
+            network_params: NetworkParams::default(),
+            join_policy: JoinPolicy::default(),
+            join_progress: None,
+            end_user_policy: EndUserPolicy::default(),
+            proposal_retry_policy: ProposalRetryPolicy::default(),
+            dkg_policy: DkgPolicy::default(),
+            decryption_policy: None,
+            clock: Arc::new(TokioClock),
+            rng: SharedRng::from_entropy(),
         }
     }
 }
 
+impl Config {
+    fn transport_builder(&self) -> Arc<dyn TransportBuilder> {
+        self.transport
+            .clone()
+            .unwrap_or_else(|| Arc::new(QuicTransportBuilder::new(self.transport_config.clone())))
+    }
+}
+
 /// Interface for sending and receiving messages to and from other nodes, in the role of a full
 /// routing node.
 ///
@@ -85,10 +201,28 @@ impl Default for Config {
 /// role, and can be `sn_messaging::SrcLocation::Node` or `sn_messaging::SrcLocation::Section`.
 pub struct Routing {
     dispatcher: Arc<Dispatcher>,
+    event_hub: EventHub,
 }
 
 static EVENT_CHANNEL_SIZE: usize = 20;
 
+/// How long `Routing::leave` waits for the section to agree on the departure and, for an elder,
+/// to hand its duties over to the new elders.
+pub const LEAVE_TIMEOUT: Duration = Duration::from_secs(120);
+const LEAVE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const LEAVE_FLUSH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long `Routing::request_section_signature` waits for the elders to respond before asking
+/// them again.
+pub const SECTION_SIGNATURE_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many times `Routing::request_section_signature` asks the elders before giving up.
+pub const SECTION_SIGNATURE_ATTEMPTS: usize = 3;
+
+/// How long `Routing::decrypt` waits for the elders to respond before asking them again.
+pub const DECRYPTION_TIMEOUT: Duration = Duration::from_secs(30);
+/// How many times `Routing::decrypt` asks the elders before giving up.
+pub const DECRYPTION_ATTEMPTS: usize = 3;
+
 impl Routing {
     ////////////////////////////////////////////////////////////////////////////
     // Public API
@@ -96,28 +230,42 @@ impl Routing {
 
     /// Creates new node using the given config and bootstraps it to the network.
     ///
-    /// NOTE: It's not guaranteed this function ever returns. This can happen due to messages being
-    /// lost in transit during bootstrapping, or other reasons. It's the responsibility of the
-    /// caller to handle this case, for example by using a timeout.
+    /// Gives up with `Error::Join` if the node can't join within the limits of
+    /// `config.join_policy`.
     pub async fn new(config: Config) -> Result<(Self, EventStream)> {
+        config.network_params.validate()?;
+
+        let transport = config.transport_builder();
+        let clock = config.clock;
+        let mut rng = config.rng;
         let keypair = config.keypair.unwrap_or_else(|| {
-            ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE)
+            ed25519::gen_keypair(
+                &mut rng,
+                &Prefix::default().range_inclusive(),
+                config.network_params.min_adult_age,
+            )
         });
         let node_name = ed25519::name(&keypair.public);
 
         let (event_tx, event_rx) = mpsc::channel(EVENT_CHANNEL_SIZE);
+        let (event_hub, event_stream) = EventHub::new(event_rx);
         let (connection_event_tx, mut connection_event_rx) = mpsc::channel(1);
 
-        let (state, comm, backlog) = if config.first {
+        let (mut state, comm, backlog) = if config.first {
             // Genesis node having a fix age of 255.
-            let keypair = ed25519::gen_keypair(&Prefix::default().range_inclusive(), 255);
+            let keypair = ed25519::gen_keypair(&mut rng, &Prefix::default().range_inclusive(), 255);
             let node_name = ed25519::name(&keypair.public);
 
             info!("{} Starting a new network as the genesis node.", node_name);
 
-            let comm = Comm::new(config.transport_config, connection_event_tx).await?;
+            let comm = Comm::new(
+                transport.as_ref(),
+                BindConfig::from(&config.transport_config),
+                connection_event_tx,
+            )
+            .await?;
             let node = Node::new(keypair, comm.our_connection_info());
-            let state = Core::first_node(node, event_tx)?;
+            let state = Core::first_node(node, config.network_params, event_tx, &mut rng)?;
 
             let section = state.section();
 
@@ -139,22 +287,210 @@ impl Routing {
             (state, comm, vec![])
         } else {
             info!("{} Bootstrapping a new node.", node_name);
-            let (comm, bootstrap_addr) =
-                Comm::bootstrap(config.transport_config, connection_event_tx).await?;
-            let node = Node::new(keypair, comm.our_connection_info());
-            let (node, section, backlog) =
-                bootstrap::initial(node, &comm, &mut connection_event_rx, bootstrap_addr).await?;
-            let state = Core::new(node, section, None, event_tx);
+
+            let cache = config.state_dir.as_ref().and_then(|dir| {
+                StateStore::new(dir, &*config.state_key)
+                    .and_then(|store| store.load_bootstrap_cache())
+                    .map_err(|error| debug!("No bootstrap cache loaded: {}", error))
+                    .ok()
+            });
+
+            let (comm, node, section, network_params, backlog) = join(
+                keypair,
+                config.network_params,
+                &config.join_policy,
+                clock.clone(),
+                rng.clone(),
+                config.join_progress,
+                cache,
+                transport.as_ref(),
+                config.transport_config,
+                connection_event_tx,
+                &mut connection_event_rx,
+            )
+            .await?;
+            let mut state = Core::new(node, section, None, network_params.value, event_tx);
+            state.set_network_params_proof(network_params.signed);
 
             (state, comm, backlog)
         };
 
-        let dispatcher = Arc::new(Dispatcher::new(state, comm));
-        let event_stream = EventStream::new(event_rx);
+        state.set_end_user_policy(config.end_user_policy);
+        state.set_proposal_retry_policy(config.proposal_retry_policy);
+        state.set_dkg_policy(config.dkg_policy);
+        state.set_decryption_policy(config.decryption_policy);
+        state.set_clock_and_rng(clock.clone(), rng);
+
+        let state_key = config.state_key;
+        let state_store = config
+            .state_dir
+            .map(|dir| StateStore::new(dir, &*state_key))
+            .transpose()?;
+        let message_journal = config
+            .message_journal
+            .map(|path| MessageJournal::open(path, clock))
+            .transpose()?;
+        let routing = Self::start(
+            state,
+            comm,
+            backlog,
+            state_store,
+            message_journal,
+            traffic_log(config.traffic_log, &*state_key)?,
+            config.join_policy,
+            event_hub,
+            connection_event_rx,
+        )
+        .await?;
         info!("{} Bootstrapped!", node_name);
 
+        Ok((routing, event_stream))
+    }
+
+    /// Restarts a node from the state persisted in `state_dir` by a previous run with
+    /// `Config::state_dir` set.
+    ///
+    /// If the persisted section still lists this node as a member, the node resumes its place in
+    /// it (including its elder key shares, if any) without rejoining. Otherwise it rejoins the
+    /// network with its previous keypair, using the elders it knew about as bootstrap contacts.
+    /// The node tries to listen on its previous address unless `config.transport_config`
+    /// specifies one explicitly. `config.first`, `config.keypair` and `config.network_params` are
+    /// ignored in favour of the persisted state.
+    pub async fn resume(
+        config: Config,
+        state_dir: impl Into<PathBuf>,
+    ) -> Result<(Self, EventStream)> {
+        let state_store = StateStore::new(state_dir, &*config.state_key)?;
+        let persisted = state_store.load()?;
+        let keypair = persisted.keypair()?;
+        let network_params = persisted.network_params;
+        let transport = config.transport_builder();
+        let node_name = ed25519::name(&keypair.public);
+
+        let (event_tx, event_rx) = mpsc::channel(EVENT_CHANNEL_SIZE);
+        let (event_hub, event_stream) = EventHub::new(event_rx);
+        let (connection_event_tx, mut connection_event_rx) = mpsc::channel(1);
+
+        // Other nodes know us by our previous address, so try to keep it.
+        let mut transport_config = config.transport_config;
+        if transport_config.local_ip.is_none() {
+            transport_config.local_ip = Some(persisted.addr.ip());
+        }
+        if transport_config.local_port.is_none() {
+            transport_config.local_port = Some(persisted.addr.port());
+        }
+
+        let (mut state, comm, backlog) = if persisted.section.members().is_joined(&node_name) {
+            info!(
+                "{} Resuming as a member of section {:?}.",
+                node_name,
+                persisted.section.prefix()
+            );
+
+            let comm = Comm::new(
+                transport.as_ref(),
+                BindConfig::from(&transport_config),
+                connection_event_tx,
+            )
+            .await?;
+            let node = Node::new(keypair, comm.our_connection_info());
+            let state = Core::resume(node, persisted, event_tx);
+
+            (state, comm, vec![])
+        } else {
+            info!(
+                "{} No longer a member of the persisted section, rejoining.",
+                node_name
+            );
+
+            let cache = BootstrapCache::new(&persisted.section, &persisted.network);
+
+            let (comm, node, section, network_params, backlog) = join(
+                keypair,
+                network_params,
+                &config.join_policy,
+                config.clock.clone(),
+                config.rng.clone(),
+                config.join_progress,
+                Some(cache),
+                transport.as_ref(),
+                transport_config,
+                connection_event_tx,
+                &mut connection_event_rx,
+            )
+            .await?;
+            let mut state = Core::new(node, section, None, network_params.value, event_tx);
+            state.set_network_params_proof(network_params.signed);
+
+            (state, comm, backlog)
+        };
+
+        state.set_end_user_policy(config.end_user_policy);
+        state.set_proposal_retry_policy(config.proposal_retry_policy);
+        state.set_dkg_policy(config.dkg_policy);
+        state.set_decryption_policy(config.decryption_policy);
+        let clock = config.clock;
+        state.set_clock_and_rng(clock.clone(), config.rng);
+
+        let message_journal = config
+            .message_journal
+            .map(|path| MessageJournal::open(path, clock))
+            .transpose()?;
+        let routing = Self::start(
+            state,
+            comm,
+            backlog,
+            Some(state_store),
+            message_journal,
+            traffic_log(config.traffic_log, &*config.state_key)?,
+            config.join_policy,
+            event_hub,
+            connection_event_rx,
+        )
+        .await?;
+        info!("{} Resumed!", node_name);
+
+        Ok((routing, event_stream))
+    }
+
+    // Creates the dispatcher, processes the bootstrap message backlog and starts listening to
+    // incoming connections.
+    #[allow(clippy::too_many_arguments)]
+    async fn start(
+        state: Core,
+        comm: Comm,
+        backlog: Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+        state_store: Option<StateStore>,
+        message_journal: Option<MessageJournal>,
+        traffic_log: Option<(PathBuf, bls::PublicKey)>,
+        join_policy: JoinPolicy,
+        event_hub: EventHub,
+        connection_event_rx: mpsc::Receiver<ConnectionEvent>,
+    ) -> Result<Self> {
+        let mut dispatcher = Dispatcher::new(state, comm).with_join_policy(join_policy);
+        if let Some(state_store) = state_store {
+            dispatcher = dispatcher.with_state_store(state_store);
+        }
+        if let Some(message_journal) = message_journal {
+            dispatcher = dispatcher.with_message_journal(message_journal);
+        }
+        if let Some((path, state_key)) = traffic_log {
+            dispatcher = dispatcher.with_traffic_log(&path, &state_key)?;
+        }
+        let dispatcher = Arc::new(dispatcher);
+        dispatcher.persist_state().await;
+
         // Process message backlog
+        dispatcher
+            .comm
+            .metrics()
+            .record_bootstrap_backlog(backlog.len());
         for (message, sender, dest_info) in backlog {
+            dispatcher.record_traffic(TrafficInput::BacklogMessage {
+                sender,
+                message: message.clone(),
+                dest_info: dest_info.clone(),
+            });
             dispatcher
                 .clone()
                 .handle_commands(Command::HandleMessage {
@@ -165,15 +501,31 @@ impl Routing {
                 .await?;
         }
 
+        if let Some(command) = dispatcher.core.write().await.schedule_end_user_eviction() {
+            dispatcher.clone().handle_commands(command).await?;
+        }
+
         // Start listening to incoming connections.
         let _ = task::spawn(handle_connection_events(
             dispatcher.clone(),
             connection_event_rx,
         ));
 
-        let routing = Self { dispatcher };
+        Ok(Self {
+            dispatcher,
+            event_hub,
+        })
+    }
 
-        Ok((routing, event_stream))
+    /// Subscribes to the events of this node.
+    ///
+    /// Every subscriber gets its own copy of the events, independent of the `EventStream`
+    /// returned on creation of the node and of the other subscribers. Events raised before
+    /// subscribing are not delivered. Only subscriptions with `OverflowPolicy::Block` hold up
+    /// the node when they fall behind; the others, including the `EventStream` returned on
+    /// creation, discard events instead.
+    pub fn subscribe(&self, subscription: Subscription) -> EventStream {
+        self.event_hub.subscribe(subscription)
     }
 
     /// Sets the JoinsAllowed flag.
@@ -192,12 +544,168 @@ impl Routing {
         self.dispatcher.clone().handle_commands(command).await
     }
 
+    /// Proposes an application-defined `payload` for our section to agree on, e.g. a decision of
+    /// the data layer. Once a supermajority of our elders propose the same payload, every elder
+    /// raises `Event::CustomAgreement` with it and the section signature over it.
+    /// This can be done only by an Elder.
+    pub async fn propose_custom(&self, payload: Bytes) -> Result<()> {
+        if !self.is_elder().await {
+            return Err(Error::InvalidState);
+        }
+        let command = Command::ProposeCustom(payload);
+        self.dispatcher.clone().handle_commands(command).await
+    }
+
+    /// Has our section sign `data`, e.g. a receipt for data the node stored.
+    ///
+    /// Asks our elders for their signature shares and returns the signature they combine into,
+    /// along with the chain proving the signing key belongs to our section. The signature is over
+    /// `data` prefixed with a tag, so check it with `verify_section_signature`. Unlike
+    /// `sign_as_elder`, this can be done by any member of the section. Asks the elders again if
+    /// they don't respond within `SECTION_SIGNATURE_TIMEOUT` and returns
+    /// `Error::SectionSignatureTimeout` after `SECTION_SIGNATURE_ATTEMPTS` attempts.
+    pub async fn request_section_signature(
+        &self,
+        data: Bytes,
+    ) -> Result<(Signed, SecuredLinkedList)> {
+        let response = self
+            .request_from_elders(
+                SECTION_SIGNATURE_TIMEOUT,
+                SECTION_SIGNATURE_ATTEMPTS,
+                |core| core.request_section_signature(data.clone()),
+            )
+            .await?;
+        if let Some(signed) = response {
+            return Ok(signed);
+        }
+
+        self.dispatcher
+            .core
+            .write()
+            .await
+            .forget_section_signature_request(&data);
+
+        Err(Error::SectionSignatureTimeout)
+    }
+
+    /// Has our section decrypt `ciphertext`, sealed for our section key `section_key` and
+    /// `context` with `seal_for_section`, e.g. sealed bids or escrowed data sent to us by clients.
+    ///
+    /// Asks our elders for their decryption shares and returns the sealed data once the shares of
+    /// a supermajority of them are combined. Only members of our section can do this, and the
+    /// elders only respond if their `Config::decryption_policy` authorises the request. Returns
+    /// `Error::DecryptionContextMismatch` if the data was sealed for another context, and
+    /// `Error::SectionKeyTooOld` if our elders no longer hold the key shares of `section_key`,
+    /// as they only keep those of the latest `NetworkParams::key_cache_size` keys. Asks the elders
+    /// again if they don't respond within `DECRYPTION_TIMEOUT` and returns
+    /// `Error::DecryptionTimeout` after `DECRYPTION_ATTEMPTS` attempts.
+    pub async fn decrypt(
+        &self,
+        ciphertext: bls::Ciphertext,
+        section_key: bls::PublicKey,
+        context: Bytes,
+    ) -> Result<Bytes> {
+        let response = self
+            .request_from_elders(DECRYPTION_TIMEOUT, DECRYPTION_ATTEMPTS, |core| {
+                core.request_decryption(ciphertext.clone(), section_key, context.clone())
+            })
+            .await?;
+        if let Some(plaintext) = response {
+            return decryption::open_sealed(&plaintext, &context);
+        }
+
+        self.dispatcher
+            .core
+            .write()
+            .await
+            .forget_decryption_request(&ciphertext);
+
+        Err(Error::DecryptionTimeout)
+    }
+
+    // Sends the request `request` creates to our elders and waits for the response on the receiver
+    // it returns. Asks again if there is none within `timeout`, up to `attempts` times. Returns
+    // `None` if there was no response to any of them.
+    async fn request_from_elders<T>(
+        &self,
+        timeout: Duration,
+        attempts: usize,
+        request: impl Fn(&mut Core) -> Result<(Vec<Command>, oneshot::Receiver<T>)>,
+    ) -> Result<Option<T>> {
+        for attempt in 1..=attempts {
+            let (commands, rx) = request(&mut *self.dispatcher.core.write().await)?;
+            for command in commands {
+                self.dispatcher.clone().handle_commands(command).await?;
+            }
+
+            let clock = self.dispatcher.clock();
+            if let Some(Ok(response)) = clock::timeout(&**clock, timeout, rx).await {
+                return Ok(Some(response));
+            }
+
+            debug!(
+                "No response from our elders after attempt {}/{}",
+                attempt, attempts
+            );
+        }
+
+        Ok(None)
+    }
+
+    /// Gracefully leaves the network.
+    ///
+    /// Asks our section to agree on this node going `Offline`, then waits for the agreement and,
+    /// if this node is an elder, for the new elders to take over. Finally flushes any messages
+    /// still being sent and shuts the node down. Returns `Error::LeaveTimeout` if the section
+    /// doesn't complete this within `LEAVE_TIMEOUT`, in which case the node is shut down anyway.
+    pub async fn leave(self) -> Result<()> {
+        let name = self.name().await;
+        info!("{} Leaving the network.", name);
+
+        let commands = self.dispatcher.core.read().await.propose_leave()?;
+        for command in commands {
+            self.dispatcher.clone().handle_commands(command).await?;
+        }
+
+        let departure = async {
+            loop {
+                {
+                    let core = self.dispatcher.core.read().await;
+                    if core.has_left() && !core.is_elder() {
+                        break;
+                    }
+                }
+                self.dispatcher.clock().sleep(LEAVE_POLL_INTERVAL).await
+            }
+        };
+        let result = clock::timeout(&**self.dispatcher.clock(), LEAVE_TIMEOUT, departure)
+            .await
+            .ok_or(Error::LeaveTimeout);
+
+        self.dispatcher.flush(LEAVE_FLUSH_TIMEOUT).await;
+
+        match &result {
+            Ok(()) => info!("{} Left the network.", name),
+            Err(error) => warn!("{} Leaving the network failed: {}", name, error),
+        }
+
+        result
+    }
+
     /// Signals the Elders of our section to test connectivity to a node.
     pub async fn start_connectivity_test(&self, name: XorName) -> Result<()> {
         let command = Command::StartConnectivityTest(name);
         self.dispatcher.clone().handle_commands(command).await
     }
 
+    /// Returns the parameters of the network this node is part of.
+    ///
+    /// For a node that joined an existing network these are the ones chosen by the genesis node,
+    /// which may differ from `Config::network_params`.
+    pub async fn network_params(&self) -> NetworkParams {
+        *self.dispatcher.core.read().await.network_params()
+    }
+
     /// Returns the current age of this node.
     pub async fn age(&self) -> u8 {
         self.dispatcher.core.read().await.node().age()
@@ -358,6 +866,10 @@ impl Routing {
     /// `additional_proof_chain_key` is a key to be included in the signed chain attached to the
     /// message. This is useful when the message contains some data that is signed with a different
     /// key than the whole message is so that the recipient can verify such key.
+    ///
+    /// Content starting with `sn_routing/` is reserved for the messages routing exchanges between
+    /// nodes, and sending it to another node fails with `Error::InvalidPayload`. Nodes drop such
+    /// content rather than raising `Event::MessageReceived` for it.
     pub async fn send_message(
         &self,
         itinerary: Itinerary,
@@ -366,13 +878,7 @@ impl Routing {
     ) -> Result<()> {
         if let DstLocation::EndUser(EndUser { socket_id, xorname }) = itinerary.dst {
             if self.our_prefix().await.matches(&xorname) {
-                let addr = self
-                    .dispatcher
-                    .core
-                    .read()
-                    .await
-                    .get_socket_addr(socket_id)
-                    .copied();
+                let addr = self.dispatcher.core.read().await.get_socket_addr(socket_id);
 
                 if let Some(socket_addr) = addr {
                     debug!("Sending client msg to {:?}", socket_addr);
@@ -438,6 +944,80 @@ impl Routing {
     pub async fn our_index(&self) -> Result<usize> {
         self.dispatcher.core.read().await.our_index()
     }
+
+    /// Returns statistics about the network as far as this node knows it, including estimates of
+    /// its size and how they changed over time.
+    pub async fn network_stats(&self) -> NetworkStats {
+        self.dispatcher.core.read().await.network_stats()
+    }
+
+    /// Returns a snapshot of the runtime metrics of this node, e.g. to find out why its section
+    /// slows down. Use `MetricsSnapshot::to_prometheus` to expose them to Prometheus.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.dispatcher.comm.metrics().snapshot()
+    }
+
+    /// Returns a report on the state of this node, e.g. to decide whether to route client traffic
+    /// to it. See `Health::is_live` and `Health::is_ready`.
+    ///
+    /// Finding out which elders are reachable requires contacting them, so don't call this too
+    /// often.
+    pub async fn health(&self) -> Health {
+        let (mut health, other_elders) = {
+            let core = self.dispatcher.core.read().await;
+            let our_name = core.node().name();
+            let other_elders: Vec<_> = core
+                .section()
+                .authority_provider()
+                .peers()
+                .filter(|peer| *peer.name() != our_name)
+                .collect();
+            (core.health(), other_elders)
+        };
+
+        health.reachable_elders += future::join_all(
+            other_elders
+                .iter()
+                .map(|peer| self.dispatcher.comm.is_reachable(peer.addr())),
+        )
+        .await
+        .into_iter()
+        .filter(Result::is_ok)
+        .count();
+
+        health
+    }
+
+    /// Returns a dump of the internal state of this node, to attach to bug reports. Serialize it
+    /// with e.g. `serde_json`. Contains no secret keys.
+    pub async fn debug_snapshot(&self) -> DebugSnapshot {
+        self.dispatcher.core.read().await.debug_snapshot()
+    }
+
+    /// Returns the end users (clients) this node currently serves.
+    pub async fn end_users(&self) -> Vec<EndUserInfo> {
+        self.dispatcher.core.read().await.end_users()
+    }
+
+    /// Returns the info about the end user connected from `addr`, if this node serves it.
+    pub async fn end_user(&self, addr: &SocketAddr) -> Option<EndUserInfo> {
+        self.dispatcher.core.read().await.end_user_info(addr)
+    }
+
+    /// Stops serving the end user connected from `addr` and closes the connection to it. Returns
+    /// `Error::UnknownEndUser` if this node doesn't serve such end user.
+    pub async fn disconnect_end_user(&self, addr: SocketAddr) -> Result<()> {
+        let _ = self
+            .dispatcher
+            .core
+            .read()
+            .await
+            .remove_enduser(&addr)
+            .ok_or(Error::UnknownEndUser(addr))?;
+        self.dispatcher.comm.disconnect(&addr).await;
+        self.dispatcher.send_event(Event::ClientLost(addr)).await;
+        Ok(())
+    }
 }
 
 impl Drop for Routing {
@@ -452,6 +1032,78 @@ impl Debug for Routing {
     }
 }
 
+// Connects to one of the bootstrap contacts and joins the section it refers us to, giving up once
+// `join_policy.timeout` elapses. The elders of the sections in `cache` are used as bootstrap
+// contacts as well, and the closest of those sections is asked first.
+#[allow(clippy::too_many_arguments)]
+async fn join(
+    keypair: Keypair,
+    network_params: NetworkParams,
+    join_policy: &JoinPolicy,
+    clock: Arc<dyn Clock>,
+    rng: SharedRng,
+    progress_tx: Option<mpsc::Sender<JoinProgress>>,
+    cache: Option<BootstrapCache>,
+    transport: &dyn TransportBuilder,
+    transport_config: TransportConfig,
+    connection_event_tx: mpsc::Sender<ConnectionEvent>,
+    connection_event_rx: &mut mpsc::Receiver<ConnectionEvent>,
+) -> Result<(
+    Comm,
+    Node,
+    Section,
+    Proven<NetworkParams>,
+    Vec<(RoutingMsg, SocketAddr, DestInfo)>,
+)> {
+    let mut contacts = transport_config.hard_coded_contacts.clone();
+    if let Some(cache) = &cache {
+        let name = ed25519::name(&keypair.public);
+        contacts.extend(cache.contacts(&name));
+    }
+
+    let join = async {
+        let (comm, bootstrap_addr) = Comm::bootstrap(
+            transport,
+            BindConfig::from(&transport_config),
+            contacts.into_iter().collect(),
+            connection_event_tx,
+        )
+        .await?;
+        let node = Node::new(keypair, comm.our_connection_info());
+        let (node, section, network_params, backlog) = bootstrap::initial(
+            node,
+            network_params,
+            join_policy.clone(),
+            clock.clone(),
+            rng,
+            progress_tx,
+            cache,
+            &comm,
+            connection_event_rx,
+            bootstrap_addr,
+        )
+        .await?;
+        Ok((comm, node, section, network_params, backlog))
+    };
+
+    clock::timeout(&*clock, join_policy.timeout, join)
+        .await
+        .ok_or(JoinError::Timeout(join_policy.timeout))?
+}
+
+// Returns the path of the traffic log to record, if any, together with the key its state is
+// encrypted under.
+fn traffic_log(
+    path: Option<PathBuf>,
+    state_key: &dyn StateKeyProvider,
+) -> Result<Option<(PathBuf, bls::PublicKey)>> {
+    path.map(|path| {
+        let state_key = state_key.state_key(traffic_log_dir(&path))?;
+        Ok((path, state_key.public_key()))
+    })
+    .transpose()
+}
+
 // Listen for incoming connection events and handle them.
 async fn handle_connection_events(
     dispatcher: Arc<Dispatcher>,
@@ -461,10 +1113,15 @@ async fn handle_connection_events(
         match event {
             ConnectionEvent::Received((src, bytes)) => {
                 trace!("New message ({} bytes) received from: {}", bytes.len(), src);
+                dispatcher.record_traffic(TrafficInput::Message {
+                    sender: src,
+                    bytes: bytes.to_vec(),
+                });
                 handle_message(dispatcher.clone(), bytes, src).await;
             }
             ConnectionEvent::Disconnected(addr) => {
                 trace!("Lost connection to {:?}", addr);
+                dispatcher.record_traffic(TrafficInput::ConnectionLost(addr));
                 let _ = dispatcher
                     .clone()
                     .handle_commands(Command::HandleConnectionLost(addr))
@@ -475,11 +1132,23 @@ async fn handle_connection_events(
 }
 
 async fn handle_message(dispatcher: Arc<Dispatcher>, bytes: Bytes, sender: SocketAddr) {
+    if let Some(command) = message_command(&dispatcher, bytes, sender).await {
+        let _ = task::spawn(dispatcher.handle_commands(command));
+    }
+}
+
+// Decodes the message received from `sender` into the command that handles it. Messages from end
+// users are passed to the user straight away.
+async fn message_command(
+    dispatcher: &Dispatcher,
+    bytes: Bytes,
+    sender: SocketAddr,
+) -> Option<Command> {
     let wire_msg = match WireMsg::from(bytes) {
         Ok(wire_msg) => wire_msg,
         Err(error) => {
             error!("Failed to deserialize message header: {}", error);
-            return;
+            return None;
         }
     };
     let span = {
@@ -490,7 +1159,7 @@ async fn handle_message(dispatcher: Arc<Dispatcher>, bytes: Bytes, sender: Socke
                 "not handling message - already handled: {:?}",
                 wire_msg.msg_id()
             );
-            return;
+            return None;
         }
 
         trace_span!("handle_message", name = %state.node().name(), %sender)
@@ -505,34 +1174,34 @@ async fn handle_message(dispatcher: Arc<Dispatcher>, bytes: Bytes, sender: Socke
                 wire_msg.msg_id(),
                 error
             );
-            return;
+            return None;
         }
     };
+    dispatcher
+        .comm
+        .metrics()
+        .record_message_received(&message_type);
 
     match message_type {
-        MessageType::SectionInfo { msg, dest_info } => {
-            let command = Command::HandleSectionInfoMsg {
-                sender,
-                message: msg,
-                dest_info,
-            };
-            let _ = task::spawn(dispatcher.handle_commands(command));
-        }
+        MessageType::SectionInfo { msg, dest_info } => Some(Command::HandleSectionInfoMsg {
+            sender,
+            message: msg,
+            dest_info,
+        }),
         MessageType::Routing { msg, dest_info } => {
             if let Err(err) = RoutingMsg::check_signature(&msg) {
                 error!(
                     "Discarding message received ({:?}) due to invalid signature: {:?}",
                     msg.id, err
                 );
-                return;
+                return None;
             }
 
-            let command = Command::HandleMessage {
+            Some(Command::HandleMessage {
                 message: msg,
                 sender: Some(sender),
                 dest_info,
-            };
-            let _ = task::spawn(dispatcher.handle_commands(command));
+            })
         }
         MessageType::Node {
             msg: _,
@@ -540,39 +1209,29 @@ async fn handle_message(dispatcher: Arc<Dispatcher>, bytes: Bytes, sender: Socke
             src_section_pk: _,
         } => unimplemented!(),
         MessageType::Client { msg, .. } => {
-            let end_user = dispatcher
-                .core
-                .read()
-                .await
-                .get_enduser_by_addr(&sender)
-                .copied();
-
-            let end_user = match end_user {
-                Some(end_user) => {
+            // Registers the client if this is the first time we receive a message from it, and
+            // keeps its session alive otherwise.
+            // TODO: remove the enduser registry and simply encrypt socket addr with
+            // this node's keypair and use that as the socket id
+            let end_user = match dispatcher.core.read().await.try_add(sender) {
+                Ok(end_user) => end_user,
+                // Logged by the registry, at a limited rate.
+                Err(Error::TooManyEndUsers) => {
                     debug!(
-                        "Message from client {}, socket id already exists: {:?}",
-                        sender, end_user
+                        "Dropping message from client {}: too many end users",
+                        sender
                     );
-                    end_user
+                    return None;
                 }
-                None => {
-                    // this is the first time we receive a message from this client
-                    debug!("First message from client {}, creating a socket id", sender);
-
-                    // TODO: remove the enduser registry and simply encrypt socket addr with
-                    // this node's keypair and use that as the socket id
-                    match dispatcher.core.write().await.try_add(sender) {
-                        Ok(end_user) => end_user,
-                        Err(err) => {
-                            error!(
-                                "Failed to cache client socket address for message {:?}: {:?}",
-                                msg, err
-                            );
-                            return;
-                        }
-                    }
+                Err(err) => {
+                    error!(
+                        "Failed to cache client socket address for message {:?}: {:?}",
+                        msg, err
+                    );
+                    return None;
                 }
             };
+            debug!("Message from client {}: {:?}", sender, end_user);
 
             let event = Event::ClientMsgReceived {
                 msg: Box::new(msg),
@@ -580,6 +1239,7 @@ async fn handle_message(dispatcher: Arc<Dispatcher>, bytes: Bytes, sender: Socke
             };
 
             dispatcher.send_event(event).await;
+            None
         }
     }
 }
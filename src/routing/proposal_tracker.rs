@@ -0,0 +1,419 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::command::{self, Command};
+use crate::{agreement::proposal_digest, ed25519::Digest256, peer::PeerUtils};
+use sn_messaging::{
+    node::{Peer, Proposal, RoutingMsg},
+    MessageId,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    time::{Duration, Instant},
+};
+use xor_name::XorName;
+
+/// How an elder re-sends its proposals until the section agrees on them.
+///
+/// A proposal is re-sent to the elders we haven't received their own share of it from yet,
+/// waiting twice as long after every attempt. Every attempt carries a message id of its own, so
+/// the elders that got the previous ones don't discard it as a duplicate. Once the deadline passes without an agreement, it is
+/// given up on and `Event::ProposalStalled` is raised.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProposalRetryPolicy {
+    /// Time to wait for the agreement before the first re-send.
+    pub initial_backoff: Duration,
+    /// Upper bound of the time between re-sends.
+    pub max_backoff: Duration,
+    /// Time after which a proposal that wasn't agreed on is given up on.
+    pub deadline: Duration,
+}
+
+impl Default for ProposalRetryPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_secs(5),
+            max_backoff: Duration::from_secs(30),
+            deadline: Duration::from_secs(2 * 60),
+        }
+    }
+}
+
+// Keeps track of our proposals that the section hasn't agreed on yet.
+pub(crate) struct ProposalTracker {
+    policy: ProposalRetryPolicy,
+    outstanding: HashMap<Digest256, Outstanding>,
+}
+
+impl ProposalTracker {
+    pub fn new(policy: ProposalRetryPolicy) -> Self {
+        Self {
+            policy,
+            outstanding: HashMap::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &ProposalRetryPolicy {
+        &self.policy
+    }
+
+    // Starts tracking `proposal`, which we sent to `recipients` in `message` signed with `key`.
+    // Returns the command to schedule the first re-send, unless the proposal is tracked already,
+    // in which case the recipients are merged into the existing ones.
+    pub fn track(
+        &mut self,
+        proposal: &Proposal,
+        key: bls::PublicKey,
+        message: RoutingMsg,
+        recipients: Vec<Peer>,
+        now: Instant,
+    ) -> Option<Command> {
+        let digest = proposal_digest(proposal)?;
+
+        if let Some(existing) = self.outstanding.get_mut(&digest) {
+            for recipient in recipients {
+                if existing
+                    .recipients
+                    .iter()
+                    .all(|peer| peer.name() != recipient.name())
+                {
+                    existing.recipients.push(recipient);
+                }
+            }
+            return None;
+        }
+
+        let token = command::next_timer_token();
+        let _ = self.outstanding.insert(
+            digest,
+            Outstanding {
+                proposal: proposal.clone(),
+                key,
+                message,
+                recipients,
+                responded: BTreeSet::new(),
+                attempts: 0,
+                proposed_at: now,
+                backoff: self.policy.initial_backoff,
+                token,
+            },
+        );
+
+        Some(Command::ScheduleTimeout {
+            duration: self.policy.initial_backoff,
+            token,
+        })
+    }
+
+    // Returns whether we are waiting for `elder` to send us its own share of `proposal`.
+    pub fn is_awaiting(&self, proposal: &Proposal, elder: &XorName) -> bool {
+        proposal_digest(proposal)
+            .and_then(|digest| self.outstanding.get(&digest))
+            .map(|outstanding| {
+                outstanding
+                    .non_responding()
+                    .any(|peer| peer.name() == elder)
+            })
+            .unwrap_or(false)
+    }
+
+    // Records that `elder` sent us its own share of `proposal`, so it doesn't need ours re-sent.
+    pub fn record_response(&mut self, proposal: &Proposal, elder: XorName) {
+        let digest = if let Some(digest) = proposal_digest(proposal) {
+            digest
+        } else {
+            return;
+        };
+
+        if let Some(outstanding) = self.outstanding.get_mut(&digest) {
+            let _ = outstanding.responded.insert(elder);
+        }
+    }
+
+    // Stops tracking `proposal` as it's been agreed on.
+    pub fn complete(&mut self, proposal: &Proposal) {
+        if let Some(digest) = proposal_digest(proposal) {
+            let _ = self.outstanding.remove(&digest);
+        }
+    }
+
+    // Stops tracking the proposals signed with `outdated_key` and returns them. They can no
+    // longer be agreed on as they are, so they need proposing again with the new key.
+    pub fn take_outdated(&mut self, outdated_key: &bls::PublicKey) -> Vec<Proposal> {
+        let outdated: Vec<_> = self
+            .outstanding
+            .iter()
+            .filter(|(_, outstanding)| outstanding.key == *outdated_key)
+            .map(|(digest, _)| *digest)
+            .collect();
+
+        outdated
+            .iter()
+            .filter_map(|digest| self.outstanding.remove(digest))
+            .map(|outstanding| outstanding.proposal)
+            .collect()
+    }
+
+    // Returns what to do about the proposal whose timer with `token` fired, or `None` if `token`
+    // is not one of ours.
+    pub fn handle_timeout(&mut self, token: u64, now: Instant) -> Option<ProposalTimeout> {
+        let outstanding = &mut self.outstanding;
+        let digest = *outstanding
+            .iter()
+            .find(|(_, outstanding)| outstanding.token == token)?
+            .0;
+
+        let elapsed = now.saturating_duration_since(outstanding[&digest].proposed_at);
+        if elapsed >= self.policy.deadline {
+            let stalled = outstanding.remove(&digest)?;
+            let non_responding = stalled.non_responding().map(|peer| *peer.name()).collect();
+
+            return Some(ProposalTimeout::Stalled {
+                proposal: stalled.proposal,
+                non_responding,
+                elapsed,
+            });
+        }
+
+        let entry = outstanding.get_mut(&digest)?;
+        let recipients = entry.non_responding().copied().collect();
+        entry.attempts += 1;
+        entry.backoff = (entry.backoff * 2).min(self.policy.max_backoff);
+        entry.token = command::next_timer_token();
+        let duration = entry.backoff.min(self.policy.deadline - elapsed);
+
+        Some(ProposalTimeout::Retry {
+            message: entry.retry_message(),
+            recipients,
+            next: Command::ScheduleTimeout {
+                duration,
+                token: entry.token,
+            },
+        })
+    }
+}
+
+pub(crate) enum ProposalTimeout {
+    // Re-send `message` to `recipients` and schedule the next attempt with `next`.
+    Retry {
+        message: RoutingMsg,
+        recipients: Vec<Peer>,
+        next: Command,
+    },
+    // The deadline passed, give up.
+    Stalled {
+        proposal: Proposal,
+        non_responding: BTreeSet<XorName>,
+        elapsed: Duration,
+    },
+}
+
+struct Outstanding {
+    proposal: Proposal,
+    // Section key the proposal is signed with.
+    key: bls::PublicKey,
+    // The message carrying our share of the proposal.
+    message: RoutingMsg,
+    // The elders we sent the proposal to, excluding us.
+    recipients: Vec<Peer>,
+    // The elders we received their own share of the proposal from.
+    responded: BTreeSet<XorName>,
+    // Number of re-sends so far.
+    attempts: u32,
+    proposed_at: Instant,
+    // Time waited before the last attempt.
+    backoff: Duration,
+    // Token of the timer of the next attempt.
+    token: u64,
+}
+
+impl Outstanding {
+    fn non_responding(&self) -> impl Iterator<Item = &Peer> {
+        self.recipients
+            .iter()
+            .filter(move |peer| !self.responded.contains(peer.name()))
+    }
+
+    // Returns our message for the current attempt. Its id is derived from the original one and
+    // the attempt, as the recipients and our own outgoing filter would otherwise drop it.
+    fn retry_message(&self) -> RoutingMsg {
+        let mut message = self.message.clone();
+        if let Ok(id) = MessageId::from_content(&(self.message.id, self.attempts)) {
+            message.id = id;
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock,
+        message_filter::MessageFilter,
+        messages::RoutingMsgUtils,
+        section::test_utils::{gen_addr, gen_node},
+    };
+    use anyhow::Result;
+    use assert_matches::assert_matches;
+    use sn_messaging::{node::Variant, DstLocation};
+
+    #[test]
+    fn retry_with_backoff_until_stalled() -> Result<()> {
+        let mut tracker = ProposalTracker::new(ProposalRetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(3),
+            deadline: Duration::from_secs(10),
+        });
+        let peers = gen_peers(3);
+        let proposal = Proposal::JoinsAllowed(false);
+        let key = bls::SecretKey::random().public_key();
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        let mut token = assert_matches!(
+            tracker.track(&proposal, key, gen_message()?, peers.clone(), start),
+            Some(Command::ScheduleTimeout { duration, token }) => {
+                assert_eq!(duration, Duration::from_secs(1));
+                token
+            }
+        );
+
+        // Proposing the same again doesn't schedule another timer.
+        assert!(tracker
+            .track(&proposal, key, gen_message()?, peers.clone(), start)
+            .is_none());
+
+        assert!(tracker.is_awaiting(&proposal, peers[0].name()));
+        tracker.record_response(&proposal, *peers[0].name());
+        assert!(!tracker.is_awaiting(&proposal, peers[0].name()));
+
+        for (secs, expected_recipients, expected_duration) in vec![
+            (1, vec![peers[1], peers[2]], 2),
+            (3, vec![peers[1], peers[2]], 3),
+            (6, vec![peers[1], peers[2]], 3),
+            // The last attempt is cut short by the deadline.
+            (9, vec![peers[2]], 1),
+        ] {
+            token = assert_matches!(
+                tracker.handle_timeout(token, at(secs)),
+                Some(ProposalTimeout::Retry {
+                    recipients,
+                    next: Command::ScheduleTimeout { duration, token },
+                    ..
+                }) => {
+                    assert_eq!(names(&recipients), names(&expected_recipients));
+                    assert_eq!(duration, Duration::from_secs(expected_duration));
+                    token
+                }
+            );
+
+            if secs == 6 {
+                tracker.record_response(&proposal, *peers[1].name());
+            }
+        }
+
+        assert_matches!(
+            tracker.handle_timeout(token, at(10)),
+            Some(ProposalTimeout::Stalled {
+                proposal: Proposal::JoinsAllowed(false),
+                non_responding,
+                elapsed,
+            }) => {
+                assert_eq!(non_responding, names(&peers[2..]).into_iter().collect());
+                assert_eq!(elapsed, Duration::from_secs(10));
+            }
+        );
+        assert!(tracker.handle_timeout(token, at(11)).is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn forget_agreed_and_outdated() -> Result<()> {
+        let mut tracker = ProposalTracker::new(ProposalRetryPolicy::default());
+        let old_key = bls::SecretKey::random().public_key();
+        let new_key = bls::SecretKey::random().public_key();
+        let now = Instant::now();
+
+        let agreed = Proposal::JoinsAllowed(true);
+        let outdated = Proposal::JoinsAllowed(false);
+        let token_agreed = tracker.track(&agreed, new_key, gen_message()?, gen_peers(1), now);
+        let token_outdated = tracker.track(&outdated, old_key, gen_message()?, gen_peers(1), now);
+
+        tracker.complete(&agreed);
+        assert_matches!(
+            tracker.take_outdated(&old_key).as_slice(),
+            [Proposal::JoinsAllowed(false)]
+        );
+
+        for command in vec![token_agreed, token_outdated] {
+            let token =
+                assert_matches!(command, Some(Command::ScheduleTimeout { token, .. }) => token);
+            assert!(tracker.handle_timeout(token, now).is_none());
+        }
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_past_the_duplicate_filter() -> Result<()> {
+        let policy = ProposalRetryPolicy::default();
+        let mut tracker = ProposalTracker::new(policy);
+        let proposal = Proposal::JoinsAllowed(true);
+        let key = bls::SecretKey::random().public_key();
+        let message = gen_message()?;
+        let now = Instant::now();
+
+        let mut token = assert_matches!(
+            tracker.track(&proposal, key, message.clone(), gen_peers(1), now),
+            Some(Command::ScheduleTimeout { token, .. }) => token
+        );
+
+        // The recipient has seen the first copy already, but hasn't responded.
+        let filter = MessageFilter::new(clock::default_clock());
+        assert!(filter.add_to_filter(&message.id).await);
+
+        for _ in 0..2 {
+            let retry = assert_matches!(
+                tracker.handle_timeout(token, now + policy.initial_backoff),
+                Some(ProposalTimeout::Retry {
+                    message,
+                    next: Command::ScheduleTimeout { token: next, .. },
+                    ..
+                }) => {
+                    token = next;
+                    message
+                }
+            );
+            assert!(filter.add_to_filter(&retry.id).await);
+        }
+
+        Ok(())
+    }
+
+    fn gen_peers(count: usize) -> Vec<Peer> {
+        (0..count)
+            .map(|_| Peer::new(XorName::random(), gen_addr()))
+            .collect()
+    }
+
+    fn names(peers: &[Peer]) -> Vec<XorName> {
+        peers.iter().map(|peer| *peer.name()).collect()
+    }
+
+    fn gen_message() -> Result<RoutingMsg> {
+        let node = gen_node();
+        Ok(RoutingMsg::single_src(
+            &node,
+            DstLocation::DirectAndUnrouted,
+            Variant::UserMessage(vec![]),
+            bls::SecretKey::random().public_key(),
+        )?)
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::{
+    comm::Comm,
+    message_command,
+    state_store::StateKeyProvider,
+    traffic_log::{read_traffic_log, traffic_log_dir, TrafficInput},
+    BindConfig, Command, Core, Dispatcher, MemoryNetwork,
+};
+use crate::{
+    clock::{Clock, SharedRng},
+    error::Result,
+    event::Event,
+    node::Node,
+};
+use futures::{
+    future::{self, BoxFuture},
+    FutureExt,
+};
+use sn_messaging::MessageType;
+use std::{
+    collections::{HashMap, VecDeque},
+    net::SocketAddr,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+use xor_name::XorName;
+
+// Large enough for all the events raised while handling a single input.
+const REPLAY_EVENT_CHANNEL_SIZE: usize = 1024;
+
+/// What a node did in response to the inputs replayed by `replay_traffic_log`.
+#[derive(Debug)]
+pub struct Replay {
+    /// The messages the node sent, in the order it sent them.
+    pub sent: Vec<ReplayedMessage>,
+    /// The events the node raised.
+    pub events: Vec<Event>,
+}
+
+/// A message sent by a node during a replay. It doesn't actually leave the node.
+#[derive(Debug)]
+pub struct ReplayedMessage {
+    /// Names and addresses of the recipients.
+    pub recipients: Vec<(XorName, SocketAddr)>,
+    /// The message.
+    pub message: MessageType,
+}
+
+/// Replays the traffic log at `path` (see `Config::traffic_log`) into a fresh node, restored from
+/// the state the log starts with, to reproduce offline what the node did.
+///
+/// `state_key` has to provide the key the log was recorded with, looked up for the directory of
+/// the log. The node takes its time from the log and its randomness from the seed it recorded.
+/// It communicates over an empty in-memory network, so it can't reach any peer, and its timers
+/// fire when the log says they did. The policies of `Config` are not part of the log, so the
+/// node uses the default ones. Replay stops at the first relocation, as the node would continue
+/// with a new state from there.
+pub async fn replay_traffic_log(
+    path: impl AsRef<Path>,
+    state_key: &dyn StateKeyProvider,
+) -> Result<Replay> {
+    let path = path.as_ref();
+    let state_key = state_key.state_key(traffic_log_dir(path))?;
+    let log = read_traffic_log(path, &state_key)?;
+
+    let addr = log.state.addr;
+    let node = Node::new(log.state.keypair()?, addr);
+    let (event_tx, mut event_rx) = mpsc::channel(REPLAY_EVENT_CHANNEL_SIZE);
+    let clock = Arc::new(ReplayClock::default());
+    let mut core = Core::resume(node, log.state, event_tx);
+    core.set_clock_and_rng(clock.clone(), SharedRng::seeded(log.rng_seed));
+
+    let (connection_event_tx, _connection_event_rx) = mpsc::channel(1);
+    let comm = Comm::new(
+        &MemoryNetwork::new(0),
+        BindConfig {
+            local_ip: Some(addr.ip()),
+            local_port: Some(addr.port()),
+        },
+        connection_event_tx,
+    )
+    .await?;
+    let dispatcher = Dispatcher::new(core, comm);
+
+    let mut replay = Replay {
+        sent: vec![],
+        events: vec![],
+    };
+
+    // Timer tokens are unique per process, so the replayed timers get different tokens than the
+    // recorded ones. They are paired in the order they were scheduled.
+    let mut recorded_timers = VecDeque::new();
+    let mut replayed_timers = VecDeque::new();
+    let mut timer_tokens = HashMap::new();
+
+    'replay: for entry in log.entries {
+        clock.set_elapsed(entry.elapsed);
+
+        let command = match entry.input {
+            TrafficInput::Message { sender, bytes } => {
+                message_command(&dispatcher, bytes.into(), sender).await
+            }
+            TrafficInput::BacklogMessage {
+                sender,
+                message,
+                dest_info,
+            } => Some(Command::HandleMessage {
+                sender: Some(sender),
+                message,
+                dest_info,
+            }),
+            TrafficInput::ConnectionLost(addr) => Some(Command::HandleConnectionLost(addr)),
+            TrafficInput::TimerScheduled(token) => {
+                recorded_timers.push_back(token);
+                None
+            }
+            TrafficInput::Timeout(token) => {
+                if let Some(token) = timer_tokens.get(&token) {
+                    Some(Command::HandleTimeout(*token))
+                } else {
+                    warn!(
+                        "Skipping timeout of a timer that wasn't replayed: {}",
+                        token
+                    );
+                    None
+                }
+            }
+        };
+
+        // Commands with effects outside of the node are not executed: sent messages, scheduled
+        // timers and relocations.
+        let mut commands: VecDeque<_> = command.into_iter().collect();
+        while let Some(command) = commands.pop_front() {
+            match command {
+                Command::SendMessage {
+                    recipients,
+                    message,
+                    ..
+                } => replay.sent.push(ReplayedMessage {
+                    recipients,
+                    message,
+                }),
+                Command::ScheduleTimeout { token, .. } => replayed_timers.push_back(token),
+                Command::Relocate { .. } => break 'replay,
+                command => match dispatcher.handle_command(command).await {
+                    Ok(next) => commands.extend(next),
+                    Err(error) => warn!("Error while replaying: {}", error),
+                },
+            }
+        }
+
+        let paired = recorded_timers.len().min(replayed_timers.len());
+        timer_tokens.extend(
+            recorded_timers
+                .drain(..paired)
+                .zip(replayed_timers.drain(..paired)),
+        );
+
+        while let Ok(event) = event_rx.try_recv() {
+            replay.events.push(event);
+        }
+    }
+
+    Ok(replay)
+}
+
+// Clock of a replayed node, which is moved along with the replayed inputs. Its timers never fire
+// by themselves, as the timeouts are replayed from the log.
+#[derive(Debug)]
+struct ReplayClock {
+    start: Instant,
+    now: Mutex<Instant>,
+}
+
+impl ReplayClock {
+    fn set_elapsed(&self, elapsed: Duration) {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner()) = self.start + elapsed;
+    }
+}
+
+impl Default for ReplayClock {
+    fn default() -> Self {
+        let start = Instant::now();
+        Self {
+            start,
+            now: Mutex::new(start),
+        }
+    }
+}
+
+impl Clock for ReplayClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|err| err.into_inner())
+    }
+
+    fn sleep(&self, _: Duration) -> BoxFuture<'static, ()> {
+        future::pending().boxed()
+    }
+}
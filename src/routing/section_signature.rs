@@ -0,0 +1,201 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    agreement::{payload_digest, AggregatorError, SignatureAggregator, Signed, SignedShare},
+    ed25519::Digest256,
+    error::{Error, Result},
+    messages::{tagged, USER_SIGNATURE_TAG},
+};
+use bytes::Bytes;
+use secured_linked_list::SecuredLinkedList;
+use std::{collections::HashMap, time::Instant};
+use tokio::sync::oneshot;
+
+/// Verifies that `signed` is the section signature over `data` returned by
+/// `Routing::request_section_signature`. Check `signed.public_key` is a key of the section too,
+/// e.g. with the proof chain returned along with it.
+pub fn verify_section_signature(data: &[u8], signed: &Signed) -> bool {
+    signed.verify(&signable_bytes(data))
+}
+
+// Our section signs the data of its members prefixed with a tag, so the signature can't pass for
+// one over any of our own messages.
+pub(crate) fn signable_bytes(data: &[u8]) -> Vec<u8> {
+    tagged(USER_SIGNATURE_TAG, data)
+}
+
+// Signature of our section, with the chain proving the signing key belongs to our section.
+pub(crate) type SectionSigned = (Signed, SecuredLinkedList);
+
+// Our requests for section signatures that haven't been fulfilled yet.
+#[derive(Default)]
+pub(crate) struct SectionSignatureRequests {
+    aggregator: SignatureAggregator,
+    pending: HashMap<Digest256, PendingRequest>,
+}
+
+struct PendingRequest {
+    data: Bytes,
+    // Callers waiting for the signature.
+    waiters: Vec<oneshot::Sender<SectionSigned>>,
+}
+
+impl SectionSignatureRequests {
+    // Registers a request for the section signature over `data`. Returns the receiver of the
+    // signature and its digest to ask the elders with. Repeated requests for the same data share
+    // the signature shares received so far.
+    pub fn insert(&mut self, data: Bytes) -> (Digest256, oneshot::Receiver<SectionSigned>) {
+        let digest = payload_digest(&data);
+        let (tx, rx) = oneshot::channel();
+
+        let request = self
+            .pending
+            .entry(digest)
+            .or_insert_with(|| PendingRequest {
+                data,
+                waiters: vec![],
+            });
+        request.waiters.retain(|waiter| !waiter.is_closed());
+        request.waiters.push(tx);
+
+        (digest, rx)
+    }
+
+    // Forgets the request for `data` unless somebody is still waiting for it.
+    pub fn remove_abandoned(&mut self, data: &[u8]) {
+        let digest = payload_digest(data);
+        if let Some(request) = self.pending.get_mut(&digest) {
+            request.waiters.retain(|waiter| !waiter.is_closed());
+            if request.waiters.is_empty() {
+                let _ = self.pending.remove(&digest);
+            }
+        }
+    }
+
+    // Adds the signature share of an elder. Once there are enough shares, the signature is passed
+    // to the callers waiting for it. `proof_chain` must have been checked to start at a key we
+    // trust.
+    pub fn handle_response(
+        &mut self,
+        digest: &Digest256,
+        signed_share: SignedShare,
+        proof_chain: SecuredLinkedList,
+        now: Instant,
+    ) -> Result<()> {
+        let request = if let Some(request) = self.pending.get(digest) {
+            request
+        } else {
+            trace!("Ignoring signature share of a request we don't wait for");
+            return Ok(());
+        };
+
+        if !proof_chain.has_key(&signed_share.public_key_set.public_key()) {
+            error!("Signature share with a key not in its proof chain");
+            return Err(Error::InvalidSignatureShare);
+        }
+
+        let signed = match self
+            .aggregator
+            .add(&signable_bytes(&request.data), signed_share, now)
+        {
+            Ok(signed) => signed,
+            Err(AggregatorError::NotEnoughShares) => return Ok(()),
+            Err(error) => {
+                error!("Failed to add signature share: {}", error);
+                return Err(Error::InvalidSignatureShare);
+            }
+        };
+
+        if let Some(request) = self.pending.remove(digest) {
+            for waiter in request.waiters {
+                let _ = waiter.send((signed.clone(), proof_chain.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Result;
+    use assert_matches::assert_matches;
+
+    #[test]
+    fn aggregate_responses() -> Result<()> {
+        let sk_set = bls::SecretKeySet::random(2, &mut rand::thread_rng());
+        let proof_chain = SecuredLinkedList::new(sk_set.public_keys().public_key());
+        let data = Bytes::from_static(b"receipt");
+        let now = Instant::now();
+
+        let mut requests = SectionSignatureRequests::default();
+        let (digest, mut first_rx) = requests.insert(data.clone());
+        let (_, second_rx) = requests.insert(data.clone());
+        drop(second_rx);
+
+        for index in 0..=sk_set.threshold() {
+            let signed_share = SignedShare::new(
+                sk_set.public_keys(),
+                index,
+                &sk_set.secret_key_share(index),
+                &signable_bytes(&data),
+            );
+            assert_matches!(first_rx.try_recv(), Err(_));
+            requests.handle_response(&digest, signed_share, proof_chain.clone(), now)?;
+        }
+
+        let (signed, chain) = first_rx.try_recv()?;
+        assert!(chain.has_key(&signed.public_key));
+        assert!(verify_section_signature(&data, &signed));
+        assert!(requests.pending.is_empty());
+
+        // Not a signature over the untagged data.
+        assert!(!signed.verify(&data));
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_untrusted_share() {
+        let sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let other_sk_set = bls::SecretKeySet::random(1, &mut rand::thread_rng());
+        let data = Bytes::from_static(b"receipt");
+
+        let mut requests = SectionSignatureRequests::default();
+        let (digest, _rx) = requests.insert(data.clone());
+
+        let signed_share = SignedShare::new(
+            other_sk_set.public_keys(),
+            0,
+            &other_sk_set.secret_key_share(0),
+            &signable_bytes(&data),
+        );
+        let proof_chain = SecuredLinkedList::new(sk_set.public_keys().public_key());
+
+        assert_matches!(
+            requests.handle_response(&digest, signed_share, proof_chain, Instant::now()),
+            Err(Error::InvalidSignatureShare)
+        );
+    }
+
+    #[test]
+    fn remove_abandoned() {
+        let data = Bytes::from_static(b"receipt");
+        let mut requests = SectionSignatureRequests::default();
+
+        let (_, rx) = requests.insert(data.clone());
+        requests.remove_abandoned(&data);
+        assert_eq!(requests.pending.len(), 1);
+
+        drop(rx);
+        requests.remove_abandoned(&data);
+        assert!(requests.pending.is_empty());
+    }
+}
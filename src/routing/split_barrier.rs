@@ -8,7 +8,7 @@
 
 use std::mem;
 
-use crate::agreement::Signed;
+use crate::agreement::{DkgPrecedence, Signed};
 use sn_messaging::{node::Proven, SectionAuthorityProvider};
 use xor_name::Prefix;
 
@@ -16,47 +16,134 @@ type Entry = (Proven<SectionAuthorityProvider>, Signed);
 
 // Helper structure to make sure we process a split by updating info about both our section and the
 // sibling section at the same time.
-pub(crate) struct SplitBarrier(Vec<Entry>);
+pub(crate) struct SplitBarrier(Vec<(DkgPrecedence, Entry)>);
 
 impl SplitBarrier {
     pub fn new() -> Self {
         Self(Vec::new())
     }
 
-    // Pass an aggreed-on proposal for `OurElders` through this function. If there is no split, it
-    // returns it unchanged. If there is a split and we've seen the aggreement for only one
-    // subsection so far, it caches it and returns nothing. Otherwise it returns both proposals.
+    // Returns the sections waiting for the agreement on their sibling.
+    pub fn pending(&self) -> impl Iterator<Item = &SectionAuthorityProvider> {
+        self.0
+            .iter()
+            .map(|(_, (section_auth, _))| &section_auth.value)
+    }
+
+    // Pass an aggreed-on proposal for `OurElders` through this function, with the precedence of
+    // the DKG session that generated it. If there is no split, it returns it unchanged. If there
+    // is a split and we've seen the aggreement for only one subsection so far, it caches it and
+    // returns nothing. Otherwise it returns both proposals.
     //
-    // Note: in case of a fork, it can return more than two proposals. In that case one of the
-    // proposals will be for one subsection and all the others for the other subsection.
+    // Note: in case of a fork, only the proposal with the greatest precedence is kept for each
+    // subsection, so it never returns more than two proposals.
     pub fn process(
         &mut self,
         our_prefix: &Prefix,
         section_auth: Proven<SectionAuthorityProvider>,
         key_signed: Signed,
+        precedence: DkgPrecedence,
     ) -> Vec<Entry> {
         if !section_auth.value.prefix.is_extension_of(our_prefix) {
             // Not a split, no need to cache.
             return vec![(section_auth, key_signed)];
         }
 
-        // Split detected. Find all cached siblings.
-        let (mut give, keep) =
+        // Split detected. Drop the update if a competing one for the same subsection takes
+        // precedence over it, otherwise it replaces them.
+        let prefix = section_auth.value.prefix;
+        if self
+            .0
+            .iter()
+            .any(|(cached_precedence, (cached_section_auth, _))| {
+                cached_section_auth.value.prefix == prefix && *cached_precedence > precedence
+            })
+        {
+            return vec![];
+        }
+        self.0
+            .retain(|(_, (cached_section_auth, _))| cached_section_auth.value.prefix != prefix);
+
+        // Find all cached siblings.
+        let (give, keep): (Vec<_>, _) =
             mem::take(&mut self.0)
                 .into_iter()
-                .partition(|(cached_section_auth, _)| {
-                    cached_section_auth.value.prefix == section_auth.value.prefix.sibling()
+                .partition(|(_, (cached_section_auth, _))| {
+                    cached_section_auth.value.prefix == prefix.sibling()
                 });
         self.0 = keep;
 
-        if give.is_empty() {
+        if let Some((_, sibling)) = give
+            .into_iter()
+            .max_by_key(|(cached_precedence, _)| *cached_precedence)
+        {
+            // Sibling found. We can proceed with the update.
+            vec![sibling, (section_auth, key_signed)]
+        } else {
             // No sibling found. Cache this update until we see the sibling update.
-            self.0.push((section_auth, key_signed));
+            self.0.push((precedence, (section_auth, key_signed)));
             vec![]
-        } else {
-            // Sibling found. We can proceed with the update.
-            give.push((section_auth, key_signed));
-            give
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agreement::test_utils::proven,
+        section::{test_utils::gen_section_authority_provider, SectionAuthorityProviderUtils},
+    };
+    use anyhow::Result;
+
+    #[test]
+    fn keep_greatest_precedence_per_subsection() -> Result<()> {
+        let parent_sk = bls::SecretKey::random();
+        let our_prefix = Prefix::default();
+        let prefix0 = our_prefix.pushed(false);
+
+        let gen_entry = |prefix| -> Result<(DkgPrecedence, Entry)> {
+            let (section_auth, _, sk_set) = gen_section_authority_provider(prefix, 3);
+            let precedence = DkgPrecedence::new(&section_auth.elder_candidates(), 1);
+            let key_signed = proven(&parent_sk, section_auth.section_key())?.signed;
+            Ok((
+                precedence,
+                (proven(sk_set.secret_key(), section_auth)?, key_signed),
+            ))
+        };
+
+        let mut competing = vec![gen_entry(prefix0)?, gen_entry(prefix0)?];
+        competing.sort_by_key(|(precedence, _)| *precedence);
+        let (greatest, (greatest_section_auth, greatest_key_signed)) =
+            competing.pop().expect("no entry");
+        let (lowest, (lowest_section_auth, lowest_key_signed)) = competing.pop().expect("no entry");
+        let (sibling, (sibling_section_auth, sibling_key_signed)) = gen_entry(prefix0.sibling())?;
+
+        let mut barrier = SplitBarrier::new();
+        assert!(barrier
+            .process(
+                &our_prefix,
+                greatest_section_auth.clone(),
+                greatest_key_signed,
+                greatest
+            )
+            .is_empty());
+        // Superseded by the cached one.
+        assert!(barrier
+            .process(&our_prefix, lowest_section_auth, lowest_key_signed, lowest)
+            .is_empty());
+        assert_eq!(barrier.pending().count(), 1);
+
+        let updates = barrier.process(
+            &our_prefix,
+            sibling_section_auth,
+            sibling_key_signed,
+            sibling,
+        );
+        assert_eq!(updates.len(), 2);
+        assert_eq!(updates[0].0, greatest_section_auth);
+        assert_eq!(barrier.pending().count(), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,438 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use super::bootstrap_cache::BootstrapCache;
+use crate::{
+    agreement::Signed,
+    error::{Error, Result},
+    node::Node,
+    section::SectionKeyShare,
+    NetworkParams,
+};
+use bls::serde_impl::SerdeSecret;
+use ed25519_dalek::Keypair;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sn_messaging::node::{Network, Section};
+use std::{
+    fmt::Debug,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+use zeroize::Zeroize;
+
+const STATE_KEY_FILE_NAME: &str = "state_key";
+const STATE_KEY_TMP_FILE_NAME: &str = "state_key.tmp";
+const STATE_FILE_NAME: &str = "node_state";
+const STATE_TMP_FILE_NAME: &str = "node_state.tmp";
+const BOOTSTRAP_CACHE_FILE_NAME: &str = "bootstrap_cache";
+const BOOTSTRAP_CACHE_TMP_FILE_NAME: &str = "bootstrap_cache.tmp";
+
+/// The durable part of the state of a node, which is enough to resume it after a restart without
+/// having to rejoin the network.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct NodeState {
+    // Serialized ed25519 keypair. Stored as bytes because `Keypair` is not `Clone`.
+    keypair: Vec<u8>,
+    pub addr: SocketAddr,
+    pub section: Section,
+    pub network: Network,
+    key_shares: Vec<PersistedKeyShare>,
+    pub joins_allowed: bool,
+    pub network_params: NetworkParams,
+    // Signature of the genesis key over `network_params`.
+    pub network_params_proof: Option<Signed>,
+}
+
+impl NodeState {
+    pub fn new<'a>(
+        node: &Node,
+        section: Section,
+        network: Network,
+        key_shares: impl IntoIterator<Item = &'a SectionKeyShare>,
+        joins_allowed: bool,
+        network_params: NetworkParams,
+        network_params_proof: Option<Signed>,
+    ) -> Self {
+        Self {
+            keypair: node.keypair.to_bytes().to_vec(),
+            addr: node.addr,
+            section,
+            network,
+            key_shares: key_shares
+                .into_iter()
+                .map(PersistedKeyShare::from)
+                .collect(),
+            joins_allowed,
+            network_params,
+            network_params_proof,
+        }
+    }
+
+    pub fn keypair(&self) -> Result<Keypair> {
+        Keypair::from_bytes(&self.keypair).map_err(|_| Error::InvalidPersistedState)
+    }
+
+    // Returns the key shares, oldest first.
+    pub fn key_shares(self) -> Vec<SectionKeyShare> {
+        self.key_shares.into_iter().map(Into::into).collect()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct PersistedKeyShare {
+    public_key_set: bls::PublicKeySet,
+    index: usize,
+    secret_key_share: SerdeSecret<bls::SecretKeyShare>,
+}
+
+impl From<&SectionKeyShare> for PersistedKeyShare {
+    fn from(share: &SectionKeyShare) -> Self {
+        Self {
+            public_key_set: share.public_key_set.clone(),
+            index: share.index,
+            secret_key_share: SerdeSecret(share.secret_key_share.clone()),
+        }
+    }
+}
+
+impl From<PersistedKeyShare> for SectionKeyShare {
+    fn from(share: PersistedKeyShare) -> Self {
+        Self {
+            public_key_set: share.public_key_set,
+            index: share.index,
+            secret_key_share: share.secret_key_share.0,
+        }
+    }
+}
+
+/// Provides the key the persisted node state is encrypted under.
+///
+/// The node state contains the secret keys of the node: its ed25519 keypair and its shares of the
+/// section keys. Whoever can read both the state and its key can act as the node, and, together
+/// with enough other elders, as its section. The key is only as safe as the place it is kept in,
+/// so implement this to keep it apart from the state directory, e.g. in an OS keystore, a KMS or
+/// derived from a passphrase the operator supplies on start.
+pub trait StateKeyProvider: Debug + Send + Sync {
+    /// Returns the key of the state stored in `dir`. The first time a directory is used there is
+    /// no state yet, so a new key is to be created and kept for the next calls.
+    fn state_key(&self, dir: &Path) -> io::Result<bls::SecretKey>;
+}
+
+/// Keeps the key of the persisted node state in a file of the state directory, readable by its
+/// owner only. Used by default.
+///
+/// This only protects the state from leaking on its own, e.g. in a backup of just the state
+/// file. Anyone who can read the state directory, such as another process of the same user, can
+/// decrypt the state. Use a `StateKeyProvider` that keeps the key elsewhere to protect against
+/// that.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LocalStateKey;
+
+impl StateKeyProvider for LocalStateKey {
+    fn state_key(&self, dir: &Path) -> io::Result<bls::SecretKey> {
+        load_or_create_local_key(dir)
+    }
+}
+
+/// Stores snapshots of `NodeState` and the `BootstrapCache` in a local directory.
+///
+/// The `NodeState` contains the secret keys of the node, so it is stored encrypted under the key
+/// provided by a `StateKeyProvider`.
+#[derive(Debug)]
+pub(crate) struct StateStore {
+    dir: PathBuf,
+    state_key: bls::SecretKey,
+}
+
+impl StateStore {
+    pub fn new(dir: impl Into<PathBuf>, key_provider: &dyn StateKeyProvider) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)?;
+        let state_key = key_provider.state_key(&dir)?;
+        Ok(Self { dir, state_key })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn load(&self) -> Result<NodeState> {
+        let stored = fs::read(self.dir.join(STATE_FILE_NAME))?;
+        let ciphertext = bincode::deserialize::<bls::Ciphertext>(&stored)
+            .ok()
+            .filter(|ciphertext| ciphertext.verify())
+            .ok_or_else(|| {
+                error!(
+                    "The persisted {} is not a valid ciphertext",
+                    STATE_FILE_NAME
+                );
+                Error::InvalidPersistedState
+            })?;
+
+        let mut bytes = self.state_key.decrypt(&ciphertext).ok_or_else(|| {
+            error!("Failed to decrypt the persisted {}", STATE_FILE_NAME);
+            Error::InvalidPersistedState
+        })?;
+
+        let state = deserialize_state(&bytes);
+        bytes.zeroize();
+        state
+    }
+
+    pub fn store(&self, state: &NodeState) -> Result<()> {
+        let mut bytes = bincode::serialize(state).map_err(|_| Error::InvalidPersistedState)?;
+        let ciphertext = self.state_key.public_key().encrypt(&bytes);
+        bytes.zeroize();
+
+        self.write(&ciphertext, STATE_FILE_NAME, STATE_TMP_FILE_NAME)
+    }
+
+    pub fn load_bootstrap_cache(&self) -> Result<BootstrapCache> {
+        self.read(BOOTSTRAP_CACHE_FILE_NAME)
+    }
+
+    pub fn store_bootstrap_cache(&self, cache: &BootstrapCache) -> Result<()> {
+        self.write(
+            cache,
+            BOOTSTRAP_CACHE_FILE_NAME,
+            BOOTSTRAP_CACHE_TMP_FILE_NAME,
+        )
+    }
+
+    fn read<T: DeserializeOwned>(&self, file_name: &str) -> Result<T> {
+        let bytes = fs::read(self.dir.join(file_name))?;
+        bincode::deserialize(&bytes).map_err(|err| {
+            error!("Failed to deserialize the persisted {}: {}", file_name, err);
+            Error::InvalidPersistedState
+        })
+    }
+
+    // Write to a temporary file first and then move it in place, so a crash in the middle of
+    // writing never leaves a corrupted snapshot behind.
+    fn write<T: Serialize>(&self, value: &T, file_name: &str, tmp_file_name: &str) -> Result<()> {
+        let bytes = bincode::serialize(value).map_err(|_| Error::InvalidPersistedState)?;
+        let tmp_path = self.dir.join(tmp_file_name);
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(tmp_path, self.dir.join(file_name))?;
+        Ok(())
+    }
+}
+
+fn deserialize_state(bytes: &[u8]) -> Result<NodeState> {
+    bincode::deserialize(bytes).map_err(|err| {
+        error!(
+            "Failed to deserialize the persisted {}: {}",
+            STATE_FILE_NAME, err
+        );
+        Error::InvalidPersistedState
+    })
+}
+
+fn load_or_create_local_key(dir: &Path) -> io::Result<bls::SecretKey> {
+    let path = dir.join(STATE_KEY_FILE_NAME);
+    if path.exists() {
+        let mut bytes = fs::read(&path)?;
+        let key = bincode::deserialize::<SerdeSecret<bls::SecretKey>>(&bytes).map_err(|err| {
+            error!("Failed to deserialize the {}: {}", STATE_KEY_FILE_NAME, err);
+            io::Error::new(io::ErrorKind::InvalidData, err)
+        });
+        bytes.zeroize();
+        return Ok(key?.0);
+    }
+
+    let key = bls::SecretKey::random();
+    let mut bytes = bincode::serialize(&SerdeSecret(key.clone()))
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    // Write to a temporary file first and then move it in place, so a crash in the middle of
+    // writing never leaves a truncated key behind, which would make the state unreadable.
+    let tmp_path = dir.join(STATE_KEY_TMP_FILE_NAME);
+    let mut options = OpenOptions::new();
+    let _ = options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let _ = options.mode(0o600);
+    }
+    let result = options.open(&tmp_path).and_then(|mut file| {
+        file.write_all(&bytes)?;
+        file.sync_all()
+    });
+    bytes.zeroize();
+    result?;
+    fs::rename(tmp_path, path)?;
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        network::NetworkUtils,
+        section::{test_utils::gen_node, SectionUtils},
+    };
+    use anyhow::Result;
+    use std::iter;
+
+    #[test]
+    fn store_and_load() -> Result<()> {
+        let (node, section, key_share) = gen_state()?;
+
+        let store = StateStore::new(temp_dir(), &LocalStateKey)?;
+        store.store(&NodeState::new(
+            &node,
+            section.clone(),
+            Network::new(),
+            iter::once(&key_share),
+            false,
+            NetworkParams::default(),
+            None,
+        ))?;
+
+        let state = store.load()?;
+        assert_eq!(state.keypair()?.public, node.keypair.public);
+        assert_eq!(state.addr, node.addr);
+        assert_eq!(state.section.chain(), section.chain());
+        assert!(!state.joins_allowed);
+
+        let key_shares = state.key_shares();
+        assert_eq!(key_shares.len(), 1);
+        assert_eq!(key_shares[0].index, key_share.index);
+        assert_eq!(
+            key_shares[0].secret_key_share.public_key_share(),
+            key_share.secret_key_share.public_key_share()
+        );
+
+        fs::remove_dir_all(store.dir())?;
+        Ok(())
+    }
+
+    #[test]
+    fn state_encrypted_under_local_key() -> Result<()> {
+        let (node, section, key_share) = gen_state()?;
+        let secret_bytes = bincode::serialize(&SerdeSecret(key_share.secret_key_share.clone()))?;
+
+        let dir = temp_dir();
+        StateStore::new(&dir, &LocalStateKey)?.store(&NodeState::new(
+            &node,
+            section,
+            Network::new(),
+            iter::once(&key_share),
+            false,
+            NetworkParams::default(),
+            None,
+        ))?;
+
+        // The secret key share doesn't appear in the stored file.
+        let stored = fs::read(dir.join(STATE_FILE_NAME))?;
+        assert!(!stored
+            .windows(secret_bytes.len())
+            .any(|window| window == &secret_bytes[..]));
+
+        // The same local key is used after a restart...
+        assert!(StateStore::new(&dir, &LocalStateKey)?.load().is_ok());
+
+        // ...but the state can't be loaded without it.
+        fs::remove_file(dir.join(STATE_KEY_FILE_NAME))?;
+        assert!(matches!(
+            StateStore::new(&dir, &LocalStateKey)?.load(),
+            Err(Error::InvalidPersistedState)
+        ));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn state_key_from_provider() -> Result<()> {
+        #[derive(Debug)]
+        struct FixedKey(bls::SecretKey);
+
+        impl StateKeyProvider for FixedKey {
+            fn state_key(&self, _: &Path) -> io::Result<bls::SecretKey> {
+                Ok(self.0.clone())
+            }
+        }
+
+        let (node, section, key_share) = gen_state()?;
+        let key = FixedKey(bls::SecretKey::random());
+
+        let dir = temp_dir();
+        StateStore::new(&dir, &key)?.store(&NodeState::new(
+            &node,
+            section,
+            Network::new(),
+            iter::once(&key_share),
+            false,
+            NetworkParams::default(),
+            None,
+        ))?;
+
+        // The key is not kept in the state directory.
+        assert!(!dir.join(STATE_KEY_FILE_NAME).exists());
+
+        assert!(StateStore::new(&dir, &key)?.load().is_ok());
+        assert!(matches!(
+            StateStore::new(&dir, &LocalStateKey)?.load(),
+            Err(Error::InvalidPersistedState)
+        ));
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn reject_plaintext_state() -> Result<()> {
+        let (node, section, key_share) = gen_state()?;
+
+        let dir = temp_dir();
+        fs::create_dir_all(&dir)?;
+        let plaintext = bincode::serialize(&NodeState::new(
+            &node,
+            section,
+            Network::new(),
+            iter::once(&key_share),
+            false,
+            NetworkParams::default(),
+            None,
+        ))?;
+        fs::write(dir.join(STATE_FILE_NAME), &plaintext)?;
+
+        assert!(matches!(
+            StateStore::new(&dir, &LocalStateKey)?.load(),
+            Err(Error::InvalidPersistedState)
+        ));
+        // The file is left as it is.
+        assert_eq!(fs::read(dir.join(STATE_FILE_NAME))?, plaintext);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn load_missing_state() -> Result<()> {
+        let store = StateStore::new(temp_dir(), &LocalStateKey)?;
+        assert!(matches!(store.load(), Err(Error::StateStore(_))));
+
+        fs::remove_dir_all(store.dir())?;
+        Ok(())
+    }
+
+    fn gen_state() -> Result<(Node, Section, SectionKeyShare)> {
+        let node = gen_node();
+        let (section, key_share) = Section::first_node(node.peer(), &mut rand::thread_rng())?;
+        Ok((node, section, key_share))
+    }
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("sn_routing-{:x}", rand::random::<u64>()))
+    }
+}
@@ -6,26 +6,37 @@
 // KIND, either express or implied. Please review the Licences for the specific language governing
 // permissions and limitations relating to use of the SAFE Network Software.
 
-use super::{Comm, Command, Core, Dispatcher};
+mod replay;
+
+use super::{
+    decryption::{
+        ciphertext_digest, open_sealed, seal_for_section, DecryptionPolicy, DecryptionRequest,
+    },
+    internal_msg::InternalMsg,
+    message_command, section_signature, BindConfig, Command, Core, Dispatcher,
+    QuicTransportBuilder,
+};
 use crate::{
     agreement::{
+        payload_digest,
         test_utils::{prove, proven},
-        ProposalUtils,
+        verify_signed, DkgPrecedence, ProposalUtils, ProvenUtils, SignedShare,
     },
+    clock::SharedRng,
     ed25519,
     event::Event,
-    messages::{PlainMessageUtils, RoutingMsgUtils, SrcAuthorityUtils, VerifyStatus},
+    messages::{PlainMessageUtils, RoutingMsgUtils, SignableView, SrcAuthorityUtils, VerifyStatus},
     network::NetworkUtils,
     node::Node,
     peer::PeerUtils,
     relocation::{self, RelocatePayloadUtils, SignedRelocateDetailsUtils},
-    routing::core::{RESOURCE_PROOF_DATA_SIZE, RESOURCE_PROOF_DIFFICULTY},
+    routing::core::RESOURCE_PROOF_DATA_SIZE,
     section::{
         test_utils::*, ElderCandidatesUtils, MemberInfoUtils, SectionAuthorityProviderUtils,
         SectionKeyShare, SectionPeersUtils, SectionUtils, FIRST_SECTION_MIN_AGE, MIN_ADULT_AGE,
         MIN_AGE,
     },
-    supermajority, ELDER_SIZE,
+    supermajority, Error, NetworkParams, ELDER_SIZE,
 };
 use anyhow::Result;
 use assert_matches::assert_matches;
@@ -48,6 +59,7 @@ use std::{
     iter,
     net::Ipv4Addr,
     ops::Deref,
+    sync::Arc,
 };
 use tokio::{
     sync::mpsc,
@@ -60,12 +72,21 @@ static TEST_EVENT_CHANNEL_SIZE: usize = 20;
 #[tokio::test]
 async fn receive_matching_get_section_request_as_elder() -> Result<()> {
     let node = create_node(MIN_ADULT_AGE);
-    let state = Core::first_node(node, mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0)?;
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let new_node_comm = create_comm().await?;
     let new_node = Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
+        ed25519::gen_keypair(
+            &mut rand::thread_rng(),
+            &Prefix::default().range_inclusive(),
+            MIN_ADULT_AGE,
+        ),
         new_node_comm.our_connection_info(),
     );
     let new_node_name = new_node.name();
@@ -102,6 +123,41 @@ async fn receive_matching_get_section_request_as_elder() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn count_duplicate_message_received_once() -> Result<()> {
+    let node = create_node(MIN_ADULT_AGE);
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+
+    let sender = create_node(MIN_ADULT_AGE);
+    let bytes = MessageType::SectionInfo {
+        msg: SectionInfoMsg::GetSectionQuery(PublicKey::from(sender.keypair.public)),
+        dest_info: DestInfo {
+            dest: sender.name(),
+            dest_section_pk: bls::SecretKey::random().public_key(),
+        },
+    }
+    .serialize()?;
+
+    assert!(message_command(&dispatcher, bytes.clone(), sender.addr)
+        .await
+        .is_some());
+    // The same message again is filtered out and not counted.
+    assert!(message_command(&dispatcher, bytes, sender.addr)
+        .await
+        .is_none());
+
+    let metrics = dispatcher.comm.metrics().snapshot();
+    assert_eq!(metrics.messages_received.values().sum::<u64>(), 1);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn receive_mismatching_get_section_request_as_adult() -> Result<()> {
     let good_prefix = Prefix::default().pushed(false);
@@ -115,6 +171,7 @@ async fn receive_mismatching_get_section_request_as_adult() -> Result<()> {
         node,
         section,
         None,
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -174,12 +231,21 @@ async fn receive_mismatching_get_section_request_as_adult() -> Result<()> {
 async fn receive_join_request_without_resource_proof_response() -> Result<()> {
     let node = create_node(FIRST_SECTION_MIN_AGE);
     let node_name = node.name();
-    let state = Core::first_node(node, mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0)?;
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let new_node_comm = create_comm().await?;
     let new_node = Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), FIRST_SECTION_MIN_AGE),
+        ed25519::gen_keypair(
+            &mut rand::thread_rng(),
+            &Prefix::default().range_inclusive(),
+            FIRST_SECTION_MIN_AGE,
+        ),
         new_node_comm.our_connection_info(),
     );
     let section_key = *dispatcher.core.read().await.section().chain().last_key();
@@ -222,6 +288,30 @@ async fn receive_join_request_without_resource_proof_response() -> Result<()> {
         JoinResponse::ResourceChallenge { .. }
     );
 
+    // Our network parameters are sent along, proven by the genesis key for the new node to verify.
+    let genesis_key = *dispatcher.core.read().await.section().genesis_key();
+    assert_matches!(
+        commands.next(),
+        Some(Command::SendMessage {
+            recipients,
+            message: MessageType::Routing {
+                msg: RoutingMsg { variant: Variant::UserMessage(content), .. },
+                ..
+            },
+            ..
+        }) => {
+            assert_eq!(recipients, [(new_node.name(), new_node.addr)]);
+            assert_matches!(
+                InternalMsg::from_content(&content),
+                Ok(InternalMsg::NetworkParams(params)) => {
+                    assert_eq!(params.value, NetworkParams::default());
+                    assert_eq!(params.signed.public_key, genesis_key);
+                    assert!(params.self_verify());
+                }
+            );
+        }
+    );
+
     Ok(())
 }
 
@@ -229,11 +319,20 @@ async fn receive_join_request_without_resource_proof_response() -> Result<()> {
 async fn receive_join_request_with_resource_proof_response() -> Result<()> {
     let node = create_node(FIRST_SECTION_MIN_AGE);
     let node_name = node.name();
-    let state = Core::first_node(node, mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0)?;
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let new_node = Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), FIRST_SECTION_MIN_AGE),
+        ed25519::gen_keypair(
+            &mut rand::thread_rng(),
+            &Prefix::default().range_inclusive(),
+            FIRST_SECTION_MIN_AGE,
+        ),
         gen_addr(),
     );
     let section_key = *dispatcher.core.read().await.section().chain().last_key();
@@ -242,7 +341,10 @@ async fn receive_join_request_with_resource_proof_response() -> Result<()> {
     let serialized = bincode::serialize(&(new_node.name(), nonce))?;
     let nonce_signature = ed25519::sign(&serialized, &dispatcher.core.read().await.node().keypair);
 
-    let rp = ResourceProof::new(RESOURCE_PROOF_DATA_SIZE, RESOURCE_PROOF_DIFFICULTY);
+    let rp = ResourceProof::new(
+        RESOURCE_PROOF_DATA_SIZE,
+        NetworkParams::default().resource_proof_difficulty,
+    );
     let data = rp.create_proof_data(&nonce);
     let mut prover = rp.create_prover(data.clone());
     let solution = prover.solve();
@@ -313,15 +415,23 @@ async fn receive_join_request_from_relocated_node() -> Result<()> {
         node,
         section,
         Some(section_key_share),
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
-    let relocated_node_old_keypair =
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE);
+    let relocated_node_old_keypair = ed25519::gen_keypair(
+        &mut rand::thread_rng(),
+        &Prefix::default().range_inclusive(),
+        MIN_ADULT_AGE,
+    );
     let relocated_node_old_name = ed25519::name(&relocated_node_old_keypair.public);
     let relocated_node = Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_AGE + 2),
+        ed25519::gen_keypair(
+            &mut rand::thread_rng(),
+            &Prefix::default().range_inclusive(),
+            MIN_AGE + 2,
+        ),
         gen_addr(),
     );
 
@@ -411,6 +521,7 @@ async fn aggregate_proposals() -> Result<()> {
         nodes[0].clone(),
         section.clone(),
         Some(section_key_share),
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -485,6 +596,282 @@ async fn aggregate_proposals() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn custom_agreement() -> Result<()> {
+    let (section_auth, nodes) = create_section_auth();
+    let sk_set = SecretKeySet::random();
+    let pk_set = sk_set.public_keys();
+    let (section, section_key_share) = create_section(&sk_set, &section_auth)?;
+    let state = Core::new(
+        nodes[0].clone(),
+        section.clone(),
+        Some(section_key_share),
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    );
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+    let payload = Bytes::from_static(b"chunk holder is faulty");
+    let dest_info = DestInfo {
+        dest: nodes[0].name(),
+        dest_section_pk: *section.chain().last_key(),
+    };
+
+    // Our own proposal is handled by us and sent to the other elders.
+    let mut proposal = None;
+    for command in dispatcher
+        .handle_command(Command::ProposeCustom(payload.clone()))
+        .await?
+    {
+        if let Command::HandleMessage { message, .. } = command {
+            if let Variant::Propose { content, .. } = &message.variant {
+                proposal = Some(content.clone());
+            }
+            assert!(dispatcher
+                .handle_command(Command::HandleMessage {
+                    message,
+                    sender: Some(nodes[0].addr),
+                    dest_info: dest_info.clone(),
+                })
+                .await?
+                .is_empty());
+        }
+    }
+    let proposal = proposal.expect("custom proposal not handled by us");
+
+    let mut commands = vec![];
+    for index in 1..=THRESHOLD {
+        let signed_share =
+            proposal.prove(pk_set.clone(), index, &sk_set.secret_key_share(index))?;
+        let message = RoutingMsg::single_src(
+            &nodes[index],
+            DstLocation::DirectAndUnrouted,
+            Variant::Propose {
+                content: proposal.clone(),
+                signed_share,
+            },
+            section_auth.section_key(),
+        )?;
+        commands = dispatcher
+            .handle_command(Command::HandleMessage {
+                message,
+                sender: Some(nodes[index].addr),
+                dest_info: dest_info.clone(),
+            })
+            .await?;
+    }
+
+    let agreement = commands
+        .into_iter()
+        .find(|command| matches!(command, Command::HandleAgreement { .. }))
+        .expect("custom proposal not aggregated");
+    let commands = dispatcher.handle_command(agreement).await?;
+
+    assert_matches!(
+        &commands[..],
+        [Command::SendEvent(Event::CustomAgreement { payload: agreed, signed })] => {
+            assert_eq!(agreed, &payload);
+            assert_eq!(signed.public_key, pk_set.public_key());
+            assert!(crate::verify_custom_agreement(agreed, signed));
+            assert!(!crate::verify_custom_agreement(b"something else", signed));
+
+            // The signature doesn't pass for one over a user message with the same content.
+            let user_message = SignableView {
+                dst: &DstLocation::DirectAndUnrouted,
+                variant: &Variant::UserMessage(agreed.to_vec()),
+            };
+            assert!(!verify_signed(signed, &user_message));
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn section_signature() -> Result<()> {
+    let (section_auth, nodes) = create_section_auth();
+    let sk_set = SecretKeySet::random();
+    let pk_set = sk_set.public_keys();
+    let (section, section_key_share) = create_section(&sk_set, &section_auth)?;
+    let state = Core::new(
+        nodes[0].clone(),
+        section.clone(),
+        Some(section_key_share),
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    );
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+    let data = Bytes::from_static(b"receipt");
+
+    let (commands, mut rx) = dispatcher
+        .core
+        .write()
+        .await
+        .request_section_signature(data.clone())?;
+
+    // The request goes to each of the other elders and to us, as we are an elder too. Our
+    // response is handled by us as well.
+    let mut queue = commands;
+    let mut sent_to_others = 0;
+    while let Some(command) = queue.pop() {
+        match command {
+            Command::SendMessage {
+                recipients,
+                message: MessageType::Routing { msg, .. },
+                ..
+            } => {
+                assert_matches!(&recipients[..], [(name, _)] => {
+                    assert_eq!(msg.dst, DstLocation::Node(*name));
+                });
+                sent_to_others += 1;
+            }
+            command @ Command::HandleMessage { .. } => {
+                queue.extend(dispatcher.handle_command(command).await?)
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+    assert_eq!(sent_to_others, ELDER_SIZE - 1);
+
+    let dest_info = DestInfo {
+        dest: nodes[0].name(),
+        dest_section_pk: *section.chain().last_key(),
+    };
+    let bytes = section_signature::signable_bytes(&data);
+    for index in 1..=THRESHOLD {
+        assert_matches!(rx.try_recv(), Err(_));
+
+        let response = InternalMsg::SignatureResponse {
+            digest: payload_digest(&data),
+            signed_share: SignedShare {
+                public_key_set: pk_set.clone(),
+                index,
+                signature_share: sk_set.secret_key_share(index).sign(&bytes),
+            },
+            proof_chain: section.chain().clone(),
+        };
+        let message = RoutingMsg::single_src(
+            &nodes[index],
+            DstLocation::Node(nodes[0].name()),
+            response.to_variant()?,
+            section_auth.section_key(),
+        )?;
+        let _ = dispatcher
+            .handle_command(Command::HandleMessage {
+                message,
+                sender: Some(nodes[index].addr),
+                dest_info: dest_info.clone(),
+            })
+            .await?;
+    }
+
+    let (signed, proof_chain) = rx.try_recv()?;
+    assert_eq!(signed.public_key, pk_set.public_key());
+    assert!(proof_chain.has_key(&signed.public_key));
+    assert!(crate::verify_section_signature(&data, &signed));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn threshold_decryption() -> Result<()> {
+    let (section_auth, nodes) = create_section_auth();
+    let sk_set = SecretKeySet::random();
+    let pk_set = sk_set.public_keys();
+    let (section, section_key_share) = create_section(&sk_set, &section_auth)?;
+    let mut state = Core::new(
+        nodes[0].clone(),
+        section.clone(),
+        Some(section_key_share),
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    );
+    state.set_decryption_policy(Some(Arc::new(AllowContext(b"auction"))));
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+    let ciphertext = seal_for_section(&pk_set.public_key(), b"auction", b"sealed bid")?;
+
+    // Our policy doesn't allow this context, so we don't respond to our own request.
+    let (commands, _) = dispatcher.core.write().await.request_decryption(
+        ciphertext.clone(),
+        pk_set.public_key(),
+        Bytes::from_static(b"escrow"),
+    )?;
+    for command in commands {
+        if let command @ Command::HandleMessage { .. } = command {
+            assert!(dispatcher.handle_command(command).await?.is_empty());
+        }
+    }
+
+    // Keys not in our chain are rejected right away.
+    assert_matches!(
+        dispatcher.core.write().await.request_decryption(
+            ciphertext.clone(),
+            bls::SecretKey::random().public_key(),
+            Bytes::from_static(b"auction"),
+        ),
+        Err(Error::InvalidSectionChain(_))
+    );
+
+    let (commands, mut rx) = dispatcher.core.write().await.request_decryption(
+        ciphertext.clone(),
+        pk_set.public_key(),
+        Bytes::from_static(b"auction"),
+    )?;
+
+    // As an elder, we respond to our own request too.
+    let mut queue = commands;
+    while let Some(command) = queue.pop() {
+        if let command @ Command::HandleMessage { .. } = command {
+            queue.extend(dispatcher.handle_command(command).await?)
+        }
+    }
+
+    let dest_info = DestInfo {
+        dest: nodes[0].name(),
+        dest_section_pk: *section.chain().last_key(),
+    };
+    for index in 1..=THRESHOLD {
+        assert_matches!(rx.try_recv(), Err(_));
+
+        let decryption_share = sk_set
+            .secret_key_share(index)
+            .decrypt_share(&ciphertext)
+            .expect("invalid ciphertext");
+        let response = InternalMsg::DecryptionResponse {
+            digest: ciphertext_digest(&ciphertext)?,
+            public_key_set: pk_set.clone(),
+            index,
+            decryption_share,
+        };
+        let message = RoutingMsg::single_src(
+            &nodes[index],
+            DstLocation::Node(nodes[0].name()),
+            response.to_variant()?,
+            section_auth.section_key(),
+        )?;
+        let _ = dispatcher
+            .handle_command(Command::HandleMessage {
+                message,
+                sender: Some(nodes[index].addr),
+                dest_info: dest_info.clone(),
+            })
+            .await?;
+    }
+
+    let plaintext = rx.try_recv()?;
+    assert_eq!(open_sealed(&plaintext, b"auction")?, &b"sealed bid"[..]);
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct AllowContext(&'static [u8]);
+
+impl DecryptionPolicy for AllowContext {
+    fn authorise(&self, request: &DecryptionRequest) -> bool {
+        request.context == self.0
+    }
+}
+
 #[tokio::test]
 async fn handle_agreement_on_online() -> Result<()> {
     let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
@@ -495,7 +882,13 @@ async fn handle_agreement_on_online() -> Result<()> {
     let sk_set = SecretKeySet::random();
     let (section, section_key_share) = create_section(&sk_set, &section_auth)?;
     let node = nodes.remove(0);
-    let state = Core::new(node, section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let new_peer = create_peer(MIN_AGE);
@@ -550,6 +943,7 @@ async fn handle_agreement_on_online_of_elder_candidate() -> Result<()> {
         node,
         section,
         Some(section_key_share),
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -699,7 +1093,13 @@ async fn handle_agreement_on_online_of_rejoined_node(phase: NetworkPhase, age: u
     // Make a Node
     let (event_tx, _event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
     let node = nodes.remove(0);
-    let state = Core::new(node, section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     // Simulate peer with the same name is rejoin and verify resulted behaviours.
@@ -755,7 +1155,13 @@ async fn handle_agreement_on_offline_of_non_elder() -> Result<()> {
 
     let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
     let node = nodes.remove(0);
-    let state = Core::new(node, section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let member_info = MemberInfo {
@@ -765,7 +1171,7 @@ async fn handle_agreement_on_offline_of_non_elder() -> Result<()> {
     let proposal = Proposal::Offline(member_info);
     let signed = prove(sk_set.secret_key(), &proposal.as_signable())?;
 
-    let _ = dispatcher
+    let commands = dispatcher
         .handle_command(Command::HandleAgreement { proposal, signed })
         .await?;
 
@@ -774,6 +1180,133 @@ async fn handle_agreement_on_offline_of_non_elder() -> Result<()> {
         assert_eq!(age, MIN_AGE);
     });
 
+    // Verify the departed peer is notified about the agreement.
+    assert!(commands.iter().any(|command| matches!(
+        command,
+        Command::SendMessage {
+            recipients,
+            message: MessageType::Routing { msg, .. },
+            ..
+        } if recipients == &[(*existing_peer.name(), *existing_peer.addr())]
+            && matches!(msg.variant, Variant::Sync { .. })
+    )));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn receive_leave_request_as_elder() -> Result<()> {
+    let (section_auth, mut nodes) = create_section_auth();
+    let sk_set = SecretKeySet::random();
+
+    let (mut section, section_key_share) = create_section(&sk_set, &section_auth)?;
+
+    let leaving_node = create_node(MIN_AGE);
+    let member_info = MemberInfo::joined(leaving_node.peer());
+    let member_info = proven(sk_set.secret_key(), member_info)?;
+    let _ = section.update_member(member_info);
+
+    let node = nodes.remove(0);
+    let node_name = node.name();
+    let section_key = *section.chain().last_key();
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    );
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+
+    // The leaving node announces it is leaving.
+    let message = RoutingMsg::single_src(
+        &leaving_node,
+        DstLocation::Node(node_name),
+        InternalMsg::Leaving {
+            last_known_key: section_key,
+        }
+        .to_variant()?,
+        section_key,
+    )?;
+
+    let commands = dispatcher
+        .handle_command(Command::HandleMessage {
+            message,
+            sender: Some(leaving_node.addr),
+            dest_info: DestInfo {
+                dest: node_name,
+                dest_section_pk: section_key,
+            },
+        })
+        .await?;
+
+    // Verify we proposed the node offline instead of testing its connectivity.
+    let mut offline_proposal_sent = false;
+
+    for command in commands {
+        match command {
+            Command::TestConnectivity(_) => panic!("unexpected connectivity test"),
+            Command::SendMessage {
+                message: MessageType::Routing { msg, .. },
+                ..
+            } => {
+                if let Variant::Propose {
+                    content: Proposal::Offline(member_info),
+                    ..
+                } = msg.variant
+                {
+                    assert_eq!(member_info.peer.name(), &leaving_node.name());
+                    assert_matches!(member_info.state, PeerState::Left);
+                    offline_proposal_sent = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    assert!(offline_proposal_sent);
+
+    // Once our section agrees, only the leaving node is told.
+    let member_info = dispatcher
+        .core
+        .read()
+        .await
+        .section()
+        .members()
+        .get(&leaving_node.name())
+        .expect("member not found")
+        .leave()?;
+    let proposal = Proposal::Offline(member_info);
+    let signed = prove(sk_set.secret_key(), &proposal.as_signable())?;
+    let commands = dispatcher
+        .handle_command(Command::HandleAgreement { proposal, signed })
+        .await?;
+
+    let left_recipients: Vec<_> = commands
+        .iter()
+        .filter_map(|command| match command {
+            Command::SendMessage {
+                recipients,
+                message: MessageType::Routing { msg, .. },
+                ..
+            } => match &msg.variant {
+                Variant::UserMessage(content) => match InternalMsg::from_content(content) {
+                    Ok(InternalMsg::Left { member_info, .. }) => {
+                        assert_eq!(member_info.value.peer.name(), &leaving_node.name());
+                        Some(recipients.clone())
+                    }
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        left_recipients,
+        vec![vec![(leaving_node.name(), leaving_node.addr)]]
+    );
+
     Ok(())
 }
 
@@ -802,7 +1335,13 @@ async fn handle_agreement_on_offline_of_elder() -> Result<()> {
     let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
     let node = nodes.remove(0);
     let node_name = node.name();
-    let state = Core::new(node, section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     // Handle agreement on the Offline proposal
@@ -916,6 +1455,7 @@ async fn handle_untrusted_message(source: UntrustedMessageSource) -> Result<()>
         node,
         section.clone(),
         None,
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -1008,10 +1548,7 @@ async fn handle_bounced_untrusted_message() -> Result<()> {
 
     // Create the original message whose bounce we want to test. Attach a signed that starts
     // at `pk1`.
-    let other_node = Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), MIN_ADULT_AGE),
-        gen_addr(),
-    );
+    let other_node = create_node(MIN_ADULT_AGE);
 
     let original_message_content = b"unknown message".to_vec();
     let original_message = PlainMessage {
@@ -1038,6 +1575,7 @@ async fn handle_bounced_untrusted_message() -> Result<()> {
         node,
         section.clone(),
         Some(section_key_share),
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -1115,7 +1653,13 @@ async fn handle_sync() -> Result<()> {
     let section_key_share = create_section_key_share(&sk1_set, 0);
     let node = nodes.remove(0);
     let node_name = node.name();
-    let state = Core::new(node, old_section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        old_section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     // Create new `Section` as a successor to the previous one.
@@ -1206,7 +1750,7 @@ async fn handle_untrusted_sync() -> Result<()> {
     let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
     let node = create_node(MIN_ADULT_AGE);
     let node_name = node.name();
-    let state = Core::new(node, old_section, None, event_tx);
+    let state = Core::new(node, old_section, None, NetworkParams::default(), event_tx);
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let sender = create_node(MIN_ADULT_AGE);
@@ -1291,6 +1835,7 @@ async fn handle_bounced_untrusted_sync() -> Result<()> {
         node.clone(),
         section_full.clone(),
         Some(section_key_share),
+        NetworkParams::default(),
         event_tx,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -1386,6 +1931,7 @@ async fn relocation(relocated_peer_role: RelocatedPeerRole) -> Result<()> {
         node,
         section,
         Some(section_key_share),
+        NetworkParams::default(),
         mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
     );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
@@ -1466,7 +2012,12 @@ enum MessageDst {
 async fn message_to_self(dst: MessageDst) -> Result<()> {
     let node = create_node(MIN_ADULT_AGE);
     let peer = node.peer();
-    let state = Core::first_node(node, mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0)?;
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
     let dispatcher = Dispatcher::new(state, create_comm().await?);
     let section_name = XorName::random();
 
@@ -1503,6 +2054,153 @@ async fn message_to_self(dst: MessageDst) -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn debug_snapshot_of_first_node() -> Result<()> {
+    let node = create_node(MIN_ADULT_AGE);
+    let name = node.name();
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
+
+    let snapshot = state.debug_snapshot();
+    assert_eq!(snapshot.name, name);
+    assert!(snapshot.is_elder);
+    assert_eq!(snapshot.prefix, Prefix::default());
+    assert_eq!(snapshot.key_shares, vec![snapshot.section_key]);
+    assert!(snapshot.pending_key_shares.is_empty());
+    assert_eq!(snapshot.members.len(), 1);
+    assert_eq!(snapshot.members[0].name, name);
+    assert_eq!(snapshot.members[0].state, PeerState::Joined);
+    assert!(snapshot.relocate_state.is_none());
+
+    let json = serde_json::to_value(&snapshot)?;
+    assert_eq!(json["is_elder"], serde_json::Value::Bool(true));
+
+    Ok(())
+}
+
+#[test]
+fn health_of_first_node() -> Result<()> {
+    let node = create_node(MIN_ADULT_AGE);
+    let name = node.name();
+    let mut state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
+
+    let health = state.health();
+    assert_eq!(health.elders, 1);
+    assert_eq!(health.reachable_elders, 1);
+    assert!(health.since_last_agreement.is_none());
+    assert!(health.is_ready());
+
+    // An elder signing with a key we don't know means we are lagging behind.
+    state.record_elder_key(name, bls::SecretKey::random().public_key());
+    let health = state.health();
+    assert!(health.behind_elders);
+    assert!(!health.is_ready());
+
+    // Keys of non-elders are ignored.
+    state.record_elder_key(name, *state.section_chain().last_key());
+    state.record_elder_key(XorName::random(), bls::SecretKey::random().public_key());
+    assert!(state.health().is_ready());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn health_of_adult_behind_elders() -> Result<()> {
+    let (section_auth, elders, sk_set) =
+        gen_section_authority_provider(Prefix::default(), ELDER_SIZE);
+    let (section, _) = create_section(&sk_set, &section_auth)?;
+
+    let node = create_node(MIN_ADULT_AGE);
+    let node_name = node.name();
+    let mut state = Core::new(
+        node,
+        section,
+        None,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    );
+    assert!(!state.health().behind_elders);
+
+    // An elder addresses us with a section key we don't know yet, so it's ahead of us.
+    let new_section_key = bls::SecretKey::random().public_key();
+    let message = RoutingMsg::single_src(
+        &elders[0],
+        DstLocation::Node(node_name),
+        Variant::UserMessage(b"hello".to_vec()),
+        new_section_key,
+    )?;
+    let _ = state
+        .handle_message(
+            Some(elders[0].addr),
+            message,
+            DestInfo {
+                dest: node_name,
+                dest_section_pk: new_section_key,
+            },
+        )
+        .await;
+
+    assert!(state.health().behind_elders);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn drop_message_of_another_protocol_version() -> Result<()> {
+    let (section_auth, elders, sk_set) =
+        gen_section_authority_provider(Prefix::default(), ELDER_SIZE);
+    let (section, _) = create_section(&sk_set, &section_auth)?;
+    let section_key = *section.chain().last_key();
+
+    let node = create_node(MIN_ADULT_AGE);
+    let node_name = node.name();
+    let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
+    let mut state = Core::new(node, section, None, NetworkParams::default(), event_tx);
+
+    let dest_info = DestInfo {
+        dest: node_name,
+        dest_section_pk: section_key,
+    };
+
+    // Reserved content of a protocol version we don't speak is not a user message.
+    for content in &[&b"sn_routing/v0/internal-msg"[..], b"sn_routing/v1/unknown"] {
+        let message = RoutingMsg::single_src(
+            &elders[0],
+            DstLocation::Node(node_name),
+            Variant::UserMessage(content.to_vec()),
+            section_key,
+        )?;
+        let _ = state
+            .handle_message(Some(elders[0].addr), message, dest_info.clone())
+            .await?;
+    }
+
+    let message = RoutingMsg::single_src(
+        &elders[0],
+        DstLocation::Node(node_name),
+        Variant::UserMessage(b"hello".to_vec()),
+        section_key,
+    )?;
+    let _ = state
+        .handle_message(Some(elders[0].addr), message, dest_info)
+        .await?;
+
+    assert_matches!(event_rx.recv().await, Some(Event::MessageReceived { content, .. }) => {
+        assert_eq!(content, Bytes::from_static(b"hello"));
+    });
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn handle_elders_update() -> Result<()> {
     // Start with section that has `ELDER_SIZE` elders with age 6, 1 non-elder with age 5 and one
@@ -1557,7 +2255,13 @@ async fn handle_elders_update() -> Result<()> {
     };
 
     let (event_tx, mut event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
-    let state = Core::new(node, section0.clone(), Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section0.clone(),
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let commands = dispatcher
@@ -1614,6 +2318,108 @@ async fn handle_elders_update() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn handle_competing_elders_updates_winner_first() -> Result<()> {
+    handle_competing_elders_updates(true).await
+}
+
+#[tokio::test]
+async fn handle_competing_elders_updates_loser_first() -> Result<()> {
+    handle_competing_elders_updates(false).await
+}
+
+// Two outcomes of the same generation get agreed. The first one agreed decides the generation,
+// whichever of them takes precedence, and the other one is refused without forking the chain.
+async fn handle_competing_elders_updates(winner_first: bool) -> Result<()> {
+    let node = create_node(MIN_AGE + 2);
+    let other_elder_peers: Vec<_> = iter::repeat_with(|| create_peer(MIN_AGE + 2))
+        .take(ELDER_SIZE - 1)
+        .collect();
+
+    let sk_set0 = SecretKeySet::random();
+    let section_auth0 = SectionAuthorityProvider::new(
+        iter::once(node.peer()).chain(other_elder_peers.clone()),
+        Prefix::default(),
+        sk_set0.public_keys(),
+    );
+    let (section0, section_key_share) = create_section(&sk_set0, &section_auth0)?;
+
+    // Two DKG sessions of the same generation completed, each with a different new candidate.
+    let mut outcomes: Vec<_> = iter::repeat_with(|| {
+        let sk_set = SecretKeySet::random();
+        let section_auth = SectionAuthorityProvider::new(
+            iter::once(node.peer())
+                .chain(other_elder_peers.iter().copied().skip(1))
+                .chain(iter::once(create_peer(MIN_AGE + 3))),
+            Prefix::default(),
+            sk_set.public_keys(),
+        );
+        let precedence = DkgPrecedence::new(&section_auth.elder_candidates(), 1);
+        (precedence, sk_set, section_auth)
+    })
+    .take(2)
+    .collect();
+    outcomes.sort_by_key(|(precedence, ..)| *precedence);
+
+    let create_our_elders_command = |sk, section_auth| -> Result<_> {
+        let proposal = Proposal::OurElders(proven(sk, section_auth)?);
+        let signed = prove(sk_set0.secret_key(), &proposal.as_signable())?;
+        Ok(Command::HandleAgreement { proposal, signed })
+    };
+
+    let (event_tx, _) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
+    let state = Core::new(
+        node,
+        section0,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
+    let dispatcher = Dispatcher::new(state, create_comm().await?);
+
+    let (_, winner_sk_set, winner_section_auth) = outcomes.pop().expect("no outcome");
+    let (_, loser_sk_set, loser_section_auth) = outcomes.pop().expect("no outcome");
+    let ((first_sk_set, first_section_auth), (second_sk_set, second_section_auth)) = if winner_first
+    {
+        (
+            (winner_sk_set, winner_section_auth),
+            (loser_sk_set, loser_section_auth),
+        )
+    } else {
+        (
+            (loser_sk_set, loser_section_auth),
+            (winner_sk_set, winner_section_auth),
+        )
+    };
+
+    let command = create_our_elders_command(first_sk_set.secret_key(), first_section_auth)?;
+    let _ = dispatcher.handle_command(command).await?;
+
+    let first_key = first_sk_set.secret_key().public_key();
+    assert_eq!(
+        dispatcher.core.read().await.section().chain().last_key(),
+        &first_key
+    );
+
+    // The generation is decided, so the other outcome is refused.
+    let command = create_our_elders_command(second_sk_set.secret_key(), second_section_auth)?;
+    let commands = dispatcher.handle_command(command).await?;
+    assert!(commands.is_empty());
+
+    let state = dispatcher.core.read().await;
+    assert_eq!(state.section().chain().last_key(), &first_key);
+    assert_eq!(
+        state.section().authority_provider().section_key(),
+        first_key
+    );
+    assert!(!state
+        .section()
+        .chain()
+        .has_key(&second_sk_set.secret_key().public_key()));
+
+    Ok(())
+}
+
 // Test that demoted node still sends `Sync` messages on split.
 #[tokio::test]
 async fn handle_demote_during_split() -> Result<()> {
@@ -1652,7 +2458,13 @@ async fn handle_demote_during_split() -> Result<()> {
     }
 
     let (event_tx, _) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
-    let state = Core::new(node, section, Some(section_key_share), event_tx);
+    let state = Core::new(
+        node,
+        section,
+        Some(section_key_share),
+        NetworkParams::default(),
+        event_tx,
+    );
     let dispatcher = Dispatcher::new(state, create_comm().await?);
 
     let sk_set_v1_p0 = SecretKeySet::random();
@@ -1751,7 +2563,11 @@ fn create_peer_in_prefix(prefix: &Prefix, age: u8) -> Peer {
 
 fn create_node(age: u8) -> Node {
     Node::new(
-        ed25519::gen_keypair(&Prefix::default().range_inclusive(), age),
+        ed25519::gen_keypair(
+            &mut rand::thread_rng(),
+            &Prefix::default().range_inclusive(),
+            age,
+        ),
         gen_addr(),
     )
 }
@@ -1759,7 +2575,8 @@ fn create_node(age: u8) -> Node {
 async fn create_comm() -> Result<Comm> {
     let (tx, _rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
     Ok(Comm::new(
-        qp2p::Config {
+        &QuicTransportBuilder::default(),
+        BindConfig {
             local_ip: Some(Ipv4Addr::LOCALHOST.into()),
             ..Default::default()
         },
@@ -0,0 +1,103 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Records the traffic of a node and replays it with `replay_traffic_log`.
+//!
+//! To reproduce a bug seen in production, put the log of the node and its state key next to these
+//! tests and write one that replays it and asserts on the output.
+
+use super::{
+    super::{
+        comm::{Comm, ConnectionEvent},
+        handle_connection_events, replay_traffic_log, BindConfig, Core, Dispatcher, LocalStateKey,
+        MemoryNetwork, StateKeyProvider,
+    },
+    create_node, TEST_EVENT_CHANNEL_SIZE,
+};
+use crate::{clock::SharedRng, NetworkParams, MIN_ADULT_AGE};
+use anyhow::Result;
+use assert_matches::assert_matches;
+use sn_data_types::PublicKey;
+use sn_messaging::{
+    section_info::{GetSectionResponse, SectionInfoMsg},
+    DestInfo, MessageType,
+};
+use std::{
+    env, fs,
+    net::{Ipv4Addr, SocketAddr},
+    sync::Arc,
+};
+use tokio::sync::mpsc;
+use xor_name::XorName;
+
+#[tokio::test]
+async fn record_and_replay() -> Result<()> {
+    let dir = env::temp_dir().join(format!("sn_routing-{:x}", rand::random::<u64>()));
+    fs::create_dir_all(&dir)?;
+    let path = dir.join("traffic.log");
+    let state_key = LocalStateKey.state_key(&dir)?;
+    let network = MemoryNetwork::new(0);
+
+    let node = create_node(MIN_ADULT_AGE);
+    let state = Core::first_node(
+        node,
+        NetworkParams::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+        &mut SharedRng::from_entropy(),
+    )?;
+    let comm = Comm::new(
+        &network,
+        BindConfig::default(),
+        mpsc::channel(TEST_EVENT_CHANNEL_SIZE).0,
+    )
+    .await?;
+    let dispatcher =
+        Arc::new(Dispatcher::new(state, comm).with_traffic_log(&path, &state_key.public_key())?);
+
+    // A peer queries our section and then disconnects.
+    let peer = SocketAddr::from((Ipv4Addr::LOCALHOST, 4321));
+    let query = MessageType::SectionInfo {
+        msg: SectionInfoMsg::GetSectionQuery(PublicKey::Bls(bls::SecretKey::random().public_key())),
+        dest_info: DestInfo {
+            dest: XorName::random(),
+            dest_section_pk: bls::SecretKey::random().public_key(),
+        },
+    };
+
+    let (connection_event_tx, connection_event_rx) = mpsc::channel(TEST_EVENT_CHANNEL_SIZE);
+    connection_event_tx
+        .send(ConnectionEvent::Received((peer, query.serialize()?)))
+        .await?;
+    connection_event_tx
+        .send(ConnectionEvent::Disconnected(peer))
+        .await?;
+    drop(connection_event_tx);
+    handle_connection_events(dispatcher.clone(), connection_event_rx).await;
+    dispatcher.flush_traffic_log().await;
+
+    let replay = replay_traffic_log(&path, &LocalStateKey).await;
+    fs::remove_dir_all(&dir)?;
+
+    let replay = replay?;
+    assert_matches!(&replay.sent[..], [sent] => {
+        assert_eq!(
+            sent.recipients.iter().map(|(_, addr)| *addr).collect::<Vec<_>>(),
+            vec![peer]
+        );
+        assert_matches!(
+            sent.message,
+            MessageType::SectionInfo {
+                msg: SectionInfoMsg::GetSectionResponse(GetSectionResponse::Success(_)),
+                ..
+            }
+        );
+    });
+    assert!(replay.events.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,290 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+//! Log of everything that drives a node from the outside, so its run can be replayed offline.
+//!
+//! The log starts with a `TrafficHeader` frame, holding the seed of the RNG of the node and its
+//! encrypted `NodeState` at the start of the recording, followed by a frame per input. Every
+//! frame is a big-endian `u32` length followed by that many bytes of bincode.
+
+use super::{log_writer::LogWriter, state_store::NodeState};
+use crate::{
+    clock::Clock,
+    error::{Error, Result},
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sn_messaging::{node::RoutingMsg, DestInfo};
+use std::{
+    convert::TryFrom,
+    fs::{File, OpenOptions},
+    io::{self, BufReader, Read},
+    net::SocketAddr,
+    path::Path,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use zeroize::Zeroize;
+
+// An input of the node.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum TrafficInput {
+    // Serialized `WireMsg` received from `sender`.
+    Message {
+        sender: SocketAddr,
+        bytes: Vec<u8>,
+    },
+    // Message received while bootstrapping, handled once the node started.
+    BacklogMessage {
+        sender: SocketAddr,
+        message: RoutingMsg,
+        dest_info: DestInfo,
+    },
+    // The connection to the peer was lost.
+    ConnectionLost(SocketAddr),
+    // A timer was scheduled. Only needed to map the tokens of the timers on replay, as those
+    // depend on the order the timers of the whole process were scheduled in.
+    TimerScheduled(u64),
+    // The timer with the given token fired.
+    Timeout(u64),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct TrafficEntry {
+    // Time since the recording started.
+    pub elapsed: Duration,
+    pub input: TrafficInput,
+}
+
+// First frame of the log.
+#[derive(Serialize, Deserialize)]
+struct TrafficHeader {
+    // Seed of the RNG of the node from the start of the recording on.
+    rng_seed: u64,
+    // Serialized `NodeState` at the start of the recording. Encrypted under the state key (see
+    // `StateKeyProvider`), as it holds the secret keys of the node.
+    state: bls::Ciphertext,
+}
+
+// The contents of a traffic log.
+pub(crate) struct TrafficLog {
+    pub rng_seed: u64,
+    pub state: NodeState,
+    pub entries: Vec<TrafficEntry>,
+}
+
+// Appends the inputs of a node to the traffic log.
+pub(crate) struct TrafficRecorder {
+    writer: LogWriter,
+    clock: Arc<dyn Clock>,
+    started_at: Instant,
+}
+
+impl TrafficRecorder {
+    // Creates the log at `path`, replacing any previous one, and starts it with `state` encrypted
+    // under `state_key` and with the seed the RNG of the node was reseeded with.
+    pub fn create(
+        path: &Path,
+        state: &NodeState,
+        state_key: &bls::PublicKey,
+        rng_seed: u64,
+        clock: Arc<dyn Clock>,
+    ) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(path)
+            .map_err(Error::TrafficLog)?;
+        let writer = LogWriter::new(path.to_path_buf(), file).map_err(Error::TrafficLog)?;
+
+        let mut state = bincode::serialize(state).map_err(|_| Error::InvalidTrafficLog)?;
+        let header = TrafficHeader {
+            rng_seed,
+            state: state_key.encrypt(&state),
+        };
+        state.zeroize();
+        writer.write(frame(&header)?);
+
+        let started_at = clock.now();
+
+        Ok(Self {
+            writer,
+            clock,
+            started_at,
+        })
+    }
+
+    pub fn record(&self, input: TrafficInput) {
+        let entry = TrafficEntry {
+            elapsed: self.clock.now().saturating_duration_since(self.started_at),
+            input,
+        };
+
+        match frame(&entry) {
+            Ok(frame) => self.writer.write(frame),
+            Err(error) => error!("Failed to serialize {:?}: {}", entry, error),
+        }
+    }
+
+    // Completes once everything recorded so far is written.
+    #[cfg(test)]
+    pub async fn flush(&self) {
+        self.writer.flush().await
+    }
+}
+
+// Returns the directory of the traffic log at `path`, whose state key is looked up for.
+pub(crate) fn traffic_log_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(dir) if dir != Path::new("") => dir,
+        _ => Path::new("."),
+    }
+}
+
+// Reads the traffic log at `path`, decrypting the state it starts with using `state_key`. A frame
+// cut short at the end, e.g. by a crash, is ignored.
+pub(crate) fn read_traffic_log(path: &Path, state_key: &bls::SecretKey) -> Result<TrafficLog> {
+    let mut reader = BufReader::new(File::open(path).map_err(Error::TrafficLog)?);
+
+    let header: TrafficHeader = read_frame(&mut reader)?.ok_or(Error::InvalidTrafficLog)?;
+    let mut state = state_key.decrypt(&header.state).ok_or_else(|| {
+        error!("Failed to decrypt the state the traffic log starts with");
+        Error::InvalidTrafficLog
+    })?;
+    let state_result = bincode::deserialize(&state).map_err(|_| Error::InvalidTrafficLog);
+    state.zeroize();
+
+    let mut entries = Vec::new();
+    while let Some(entry) = read_frame(&mut reader)? {
+        entries.push(entry);
+    }
+
+    Ok(TrafficLog {
+        rng_seed: header.rng_seed,
+        state: state_result?,
+        entries,
+    })
+}
+
+fn frame<T: Serialize>(value: &T) -> Result<Vec<u8>> {
+    let payload = bincode::serialize(value).map_err(|_| Error::InvalidTrafficLog)?;
+    let len = u32::try_from(payload.len()).map_err(|_| Error::InvalidTrafficLog)?;
+
+    let mut frame = Vec::with_capacity(4 + payload.len());
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(&payload);
+    Ok(frame)
+}
+
+// Returns `None` at the end of the log or if the last frame is incomplete.
+fn read_frame<T: DeserializeOwned>(reader: &mut impl Read) -> Result<Option<T>> {
+    let mut len = [0; 4];
+    if !read_exact_or_eof(reader, &mut len)? {
+        return Ok(None);
+    }
+
+    let mut payload = vec![0; u32::from_be_bytes(len) as usize];
+    if !read_exact_or_eof(reader, &mut payload)? {
+        warn!("Ignoring incomplete frame at the end of the traffic log");
+        return Ok(None);
+    }
+
+    bincode::deserialize(&payload)
+        .map(Some)
+        .map_err(|_| Error::InvalidTrafficLog)
+}
+
+fn read_exact_or_eof(reader: &mut impl Read, buf: &mut [u8]) -> Result<bool> {
+    match reader.read_exact(buf) {
+        Ok(()) => Ok(true),
+        Err(error) if error.kind() == io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(Error::TrafficLog(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        clock::TokioClock,
+        network::NetworkUtils,
+        section::{
+            test_utils::{gen_addr, gen_node},
+            SectionUtils,
+        },
+        NetworkParams,
+    };
+    use anyhow::Result;
+    use assert_matches::assert_matches;
+    use bls::serde_impl::SerdeSecret;
+    use sn_messaging::node::{Network, Section};
+    use std::{env, fs, io::Write, iter};
+
+    #[tokio::test]
+    async fn record_and_read() -> Result<()> {
+        let node = gen_node();
+        let (section, key_share) = Section::first_node(node.peer(), &mut rand::thread_rng())?;
+        let state = NodeState::new(
+            &node,
+            section,
+            Network::new(),
+            iter::once(&key_share),
+            true,
+            NetworkParams::default(),
+            None,
+        );
+        let state_key = bls::SecretKey::random();
+
+        let path = env::temp_dir().join(format!("sn_routing-{:x}.log", rand::random::<u64>()));
+        let recorder = TrafficRecorder::create(
+            &path,
+            &state,
+            &state_key.public_key(),
+            42,
+            Arc::new(TokioClock),
+        )?;
+
+        let peer = gen_addr();
+        recorder.record(TrafficInput::Message {
+            sender: peer,
+            bytes: vec![1, 2, 3],
+        });
+        recorder.record(TrafficInput::TimerScheduled(7));
+        recorder.record(TrafficInput::ConnectionLost(peer));
+        recorder.flush().await;
+
+        // The secret key share doesn't appear in the log.
+        let secret_bytes = bincode::serialize(&SerdeSecret(key_share.secret_key_share))?;
+        assert!(!fs::read(&path)?
+            .windows(secret_bytes.len())
+            .any(|window| window == &secret_bytes[..]));
+
+        // An incomplete frame at the end is ignored.
+        let mut file = OpenOptions::new().append(true).open(&path)?;
+        file.write_all(&[0, 0, 1, 0, 42])?;
+
+        let log = read_traffic_log(&path, &state_key);
+        let other_key_log = read_traffic_log(&path, &bls::SecretKey::random());
+        fs::remove_file(&path)?;
+
+        assert_matches!(other_key_log, Err(Error::InvalidTrafficLog));
+
+        let log = log?;
+        assert_eq!(log.rng_seed, 42);
+        assert_eq!(log.state.keypair()?.public, node.keypair.public);
+        assert_eq!(log.entries.len(), 3);
+        assert_matches!(
+            &log.entries[0].input,
+            TrafficInput::Message { sender, bytes } if *sender == peer && bytes == &[1, 2, 3]
+        );
+        assert_matches!(log.entries[1].input, TrafficInput::TimerScheduled(7));
+        assert_matches!(log.entries[2].input, TrafficInput::ConnectionLost(addr) if addr == peer);
+
+        Ok(())
+    }
+}
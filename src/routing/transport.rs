@@ -0,0 +1,328 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+use crate::{
+    error::{Error, Result, TransportError},
+    TransportConfig,
+};
+use bytes::Bytes;
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream, StreamExt},
+    FutureExt,
+};
+use hex_fmt::HexFmt;
+use qp2p::{DisconnectionEvents, Endpoint, IncomingMessages, QuicP2p};
+use std::{
+    fmt::{self, Debug, Formatter},
+    net::{IpAddr, SocketAddr},
+};
+
+/// Event reported by a `Transport`.
+pub enum TransportEvent {
+    /// A message was received from the peer at `src`.
+    Received {
+        /// Address of the sender.
+        src: SocketAddr,
+        /// The message.
+        msg: Bytes,
+    },
+    /// The connection to the peer at the given address was lost.
+    Disconnected(SocketAddr),
+}
+
+impl Debug for TransportEvent {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Received { src, msg } => {
+                write!(f, "Received(src: {}, msg: {})", src, HexFmt(msg))
+            }
+            Self::Disconnected(addr) => write!(f, "Disconnected({})", addr),
+        }
+    }
+}
+
+/// Stream of the events of a `Transport`. Ends when the transport is closed.
+pub type TransportEvents = BoxStream<'static, TransportEvent>;
+
+/// Connection-oriented transport the node uses to exchange messages with its peers.
+///
+/// The default transport is QUIC (using `qp2p`). Implement this, together with
+/// `TransportBuilder`, to run nodes over something else, e.g. an in-memory network in tests.
+pub trait Transport: Send + Sync {
+    /// Returns the address the peers can reach us at.
+    fn local_addr(&self) -> SocketAddr;
+
+    /// Establishes a connection to `peer`, unless there is one already.
+    fn connect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, Result<(), TransportError>>;
+
+    /// Sends `msg` to `peer` over an existing connection. Fails if there is no such connection.
+    fn send<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+        msg: Bytes,
+    ) -> BoxFuture<'a, Result<(), TransportError>>;
+
+    /// Checks whether `peer` accepts connections from others than us, without affecting our
+    /// own connection to it.
+    fn is_reachable<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+    ) -> BoxFuture<'a, Result<(), TransportError>>;
+
+    /// Closes the connection to `peer`, if any.
+    fn disconnect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, ()>;
+
+    /// Closes all connections and stops accepting new ones.
+    fn close(&self);
+}
+
+/// Where a `Transport` listens for the connections of its peers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BindConfig {
+    /// IP address to listen on. `None` lets the transport choose.
+    pub local_ip: Option<IpAddr>,
+    /// Port to listen on. `None` lets the transport choose a free one.
+    pub local_port: Option<u16>,
+}
+
+impl From<&TransportConfig> for BindConfig {
+    fn from(config: &TransportConfig) -> Self {
+        Self {
+            local_ip: config.local_ip,
+            local_port: config.local_port,
+        }
+    }
+}
+
+/// Bound `Transport`, with the stream of its incoming messages and disconnections.
+pub type BoundTransport = (Box<dyn Transport>, TransportEvents);
+
+/// Creates the `Transport` of a node.
+pub trait TransportBuilder: Debug + Send + Sync {
+    /// Creates a transport listening as specified by `config`.
+    fn bind(&self, config: BindConfig) -> BoxFuture<'static, Result<BoundTransport>>;
+
+    /// Creates a transport listening as specified by `config` and connects it to the first of
+    /// `contacts` that accepts the connection. Returns the address of that contact too.
+    ///
+    /// Override this if the transport learns more from the contacts while connecting, e.g. the
+    /// address the peers can reach us at from behind a NAT.
+    fn bootstrap(
+        &self,
+        config: BindConfig,
+        contacts: Vec<SocketAddr>,
+    ) -> BoxFuture<'static, Result<(BoundTransport, SocketAddr)>> {
+        let bind = self.bind(config);
+        async move {
+            let (transport, events) = bind.await?;
+            let contact = connect_any(transport.as_ref(), &contacts).await?;
+            Ok(((transport, events), contact))
+        }
+        .boxed()
+    }
+}
+
+/// Builds the default QUIC transport, using `qp2p`.
+#[derive(Clone, Debug, Default)]
+pub struct QuicTransportBuilder {
+    config: TransportConfig,
+}
+
+impl QuicTransportBuilder {
+    /// Creates a builder of QUIC transports with the given settings. The local address and the
+    /// bootstrap contacts in `config` are replaced by the ones the transports are built with.
+    pub fn new(config: TransportConfig) -> Self {
+        Self { config }
+    }
+
+    fn config(&self, bind: BindConfig, contacts: Vec<SocketAddr>) -> TransportConfig {
+        TransportConfig {
+            local_ip: bind.local_ip.or(self.config.local_ip),
+            local_port: bind.local_port.or(self.config.local_port),
+            hard_coded_contacts: contacts.into_iter().collect(),
+            ..self.config.clone()
+        }
+    }
+}
+
+impl TransportBuilder for QuicTransportBuilder {
+    fn bind(&self, config: BindConfig) -> BoxFuture<'static, Result<BoundTransport>> {
+        let config = self.config(config, vec![]);
+        async move {
+            let quic_p2p = QuicP2p::with_config(Some(config), &[], true)
+                .map_err(|err| Error::InvalidConfig { err })?;
+
+            // Don't bootstrap, just create an endpoint to listen to
+            // the incoming messages from other nodes.
+            // This also returns the a channel where we can listen for
+            // disconnection events.
+            let (endpoint, _incoming_connections, incoming_messages, disconnections) = quic_p2p
+                .new_endpoint()
+                .await
+                .map_err(|err| Error::CannotConnectEndpoint { err })?;
+
+            Ok(QuicTransport::bound(
+                quic_p2p,
+                endpoint,
+                incoming_messages,
+                disconnections,
+            ))
+        }
+        .boxed()
+    }
+
+    fn bootstrap(
+        &self,
+        config: BindConfig,
+        contacts: Vec<SocketAddr>,
+    ) -> BoxFuture<'static, Result<(BoundTransport, SocketAddr)>> {
+        let config = self.config(config, contacts);
+        async move {
+            let quic_p2p = QuicP2p::with_config(Some(config), &[], true)
+                .map_err(|err| Error::InvalidConfig { err })?;
+
+            // Bootstrapping also asks the contacts for our external address, so the endpoint
+            // reports the address the peers can reach us at.
+            let (endpoint, _incoming_connections, incoming_messages, disconnections, contact) =
+                quic_p2p
+                    .bootstrap()
+                    .await
+                    .map_err(|err| Error::CannotConnectEndpoint { err })?;
+
+            Ok((
+                QuicTransport::bound(quic_p2p, endpoint, incoming_messages, disconnections),
+                contact,
+            ))
+        }
+        .boxed()
+    }
+}
+
+struct QuicTransport {
+    _quic_p2p: QuicP2p,
+    endpoint: Endpoint,
+}
+
+impl QuicTransport {
+    fn bound(
+        quic_p2p: QuicP2p,
+        endpoint: Endpoint,
+        incoming_messages: IncomingMessages,
+        disconnections: DisconnectionEvents,
+    ) -> BoundTransport {
+        let incoming_messages = stream::unfold(incoming_messages, |mut incoming_messages| async {
+            let (src, msg) = incoming_messages.next().await?;
+            Some((TransportEvent::Received { src, msg }, incoming_messages))
+        });
+        let disconnections = stream::unfold(disconnections, |mut disconnections| async {
+            let addr = disconnections.next().await?;
+            Some((TransportEvent::Disconnected(addr), disconnections))
+        });
+        let events = stream::select(incoming_messages, disconnections).boxed();
+
+        let transport = Self {
+            _quic_p2p: quic_p2p,
+            endpoint,
+        };
+        (Box::new(transport), events)
+    }
+}
+
+impl Transport for QuicTransport {
+    fn local_addr(&self) -> SocketAddr {
+        self.endpoint.socket_addr()
+    }
+
+    fn connect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, Result<(), TransportError>> {
+        self.endpoint
+            .connect_to(peer)
+            .map(|result| result.map_err(convert_error))
+            .boxed()
+    }
+
+    fn send<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+        msg: Bytes,
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        self.endpoint
+            .send_message(msg, peer)
+            .map(|result| result.map_err(convert_error))
+            .boxed()
+    }
+
+    fn is_reachable<'a>(
+        &'a self,
+        peer: &'a SocketAddr,
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        async move {
+            // Use a fresh endpoint so the check is not satisfied by an existing connection.
+            let qp2p_config = qp2p::Config {
+                local_ip: Some(self.endpoint.local_addr().ip()),
+                local_port: Some(0),
+                forward_port: false,
+                ..Default::default()
+            };
+
+            let qp2p =
+                QuicP2p::with_config(Some(qp2p_config), &[], false).map_err(convert_error)?;
+            let (connectivity_endpoint, _, _, _) =
+                qp2p.new_endpoint().await.map_err(convert_error)?;
+
+            connectivity_endpoint
+                .is_reachable(peer)
+                .await
+                .map_err(convert_error)
+        }
+        .boxed()
+    }
+
+    fn disconnect<'a>(&'a self, peer: &'a SocketAddr) -> BoxFuture<'a, ()> {
+        async move {
+            if let Err(err) = self.endpoint.disconnect_from(peer).await {
+                debug!("Failed to disconnect from {}: {}", peer, err);
+            }
+        }
+        .boxed()
+    }
+
+    fn close(&self) {
+        self.endpoint.close()
+    }
+}
+
+impl Drop for QuicTransport {
+    fn drop(&mut self) {
+        self.endpoint.close()
+    }
+}
+
+fn convert_error(err: qp2p::Error) -> TransportError {
+    match err {
+        qp2p::Error::Connection(qp2p::ConnectionError::LocallyClosed) => TransportError::Closed,
+        err => TransportError::Other(Box::new(err)),
+    }
+}
+
+// Connects to the first of `contacts` that accepts our connection. Returns its address.
+async fn connect_any(transport: &dyn Transport, contacts: &[SocketAddr]) -> Result<SocketAddr> {
+    let mut attempts: stream::FuturesUnordered<_> = contacts
+        .iter()
+        .map(|addr| transport.connect(addr).map(move |result| (result, *addr)))
+        .collect();
+
+    while let Some((result, addr)) = attempts.next().await {
+        match result {
+            Ok(()) => return Ok(addr),
+            Err(err) => debug!("Failed to connect to bootstrap contact {}: {}", addr, err),
+        }
+    }
+
+    Err(Error::Transport(TransportError::Unreachable))
+}
@@ -27,8 +27,9 @@ use crate::{
     agreement::ProvenUtils,
     error::{Error, Result},
     peer::PeerUtils,
-    ELDER_SIZE, RECOMMENDED_SECTION_SIZE,
+    NetworkParams,
 };
+use rand::RngCore;
 use secured_linked_list::{error::Error as SecuredLinkedListError, SecuredLinkedList};
 use serde::Serialize;
 use sn_messaging::{
@@ -52,7 +53,7 @@ pub trait SectionUtils {
         Self: Sized;
 
     /// Creates `Section` for the first node in the network
-    fn first_node(peer: Peer) -> Result<(Section, SectionKeyShare)>;
+    fn first_node<R: RngCore>(peer: Peer, rng: &mut R) -> Result<(Section, SectionKeyShare)>;
 
     fn genesis_key(&self) -> &bls::PublicKey;
 
@@ -85,9 +86,14 @@ pub trait SectionUtils {
 
     fn is_elder(&self, name: &XorName) -> bool;
 
-    /// Generate a new section info(s) based on the current set of members.
-    /// Returns a set of candidate SectionAuthorityProviders.
-    fn promote_and_demote_elders(&self, our_name: &XorName) -> Vec<ElderCandidates>;
+    /// Generate a new section info(s) based on the current set of members, except the `excluded`
+    /// ones. Returns a set of candidate SectionAuthorityProviders.
+    fn promote_and_demote_elders(
+        &self,
+        network_params: &NetworkParams,
+        our_name: &XorName,
+        excluded: &BTreeSet<XorName>,
+    ) -> Vec<ElderCandidates>;
 
     // Prefix of our section.
     fn prefix(&self) -> &Prefix;
@@ -108,11 +114,16 @@ pub trait SectionUtils {
     // Tries to split our section.
     // If we have enough mature nodes for both subsections, returns the SectionAuthorityProviders
     // of the two subsections. Otherwise returns `None`.
-    fn try_split(&self, our_name: &XorName) -> Option<(ElderCandidates, ElderCandidates)>;
+    fn try_split(
+        &self,
+        network_params: &NetworkParams,
+        our_name: &XorName,
+        excluded: &BTreeSet<XorName>,
+    ) -> Option<(ElderCandidates, ElderCandidates)>;
 
     // Returns the candidates for elders out of all the nodes in the section, even out of the
     // relocating nodes if there would not be enough instead.
-    fn elder_candidates(&self, elder_size: usize) -> Vec<Peer>;
+    fn elder_candidates(&self, elder_size: usize, excluded: &BTreeSet<XorName>) -> Vec<Peer>;
 }
 
 impl SectionUtils for Section {
@@ -140,8 +151,8 @@ impl SectionUtils for Section {
     }
 
     /// Creates `Section` for the first node in the network
-    fn first_node(peer: Peer) -> Result<(Section, SectionKeyShare)> {
-        let secret_key_set = bls::SecretKeySet::random(0, &mut rand::thread_rng());
+    fn first_node<R: RngCore>(peer: Peer, rng: &mut R) -> Result<(Section, SectionKeyShare)> {
+        let secret_key_set = bls::SecretKeySet::random(0, rng);
         let public_key_set = secret_key_set.public_keys();
         let secret_key_share = secret_key_set.secret_key_share(0);
 
@@ -296,14 +307,21 @@ impl SectionUtils for Section {
         self.authority_provider().contains_elder(name)
     }
 
-    /// Generate a new section info(s) based on the current set of members.
-    /// Returns a set of candidate SectionAuthorityProviders.
-    fn promote_and_demote_elders(&self, our_name: &XorName) -> Vec<ElderCandidates> {
-        if let Some((our_elder_candidates, other_elder_candidates)) = self.try_split(our_name) {
+    /// Generate a new section info(s) based on the current set of members, except the `excluded`
+    /// ones. Returns a set of candidate SectionAuthorityProviders.
+    fn promote_and_demote_elders(
+        &self,
+        network_params: &NetworkParams,
+        our_name: &XorName,
+        excluded: &BTreeSet<XorName>,
+    ) -> Vec<ElderCandidates> {
+        if let Some((our_elder_candidates, other_elder_candidates)) =
+            self.try_split(network_params, our_name, excluded)
+        {
             return vec![our_elder_candidates, other_elder_candidates];
         }
 
-        let expected_peers = self.elder_candidates(ELDER_SIZE);
+        let expected_peers = self.elder_candidates(network_params.elder_size, excluded);
         let expected_names: BTreeSet<_> = expected_peers.iter().map(Peer::name).cloned().collect();
         let current_names: BTreeSet<_> = self.authority_provider().names();
 
@@ -370,7 +388,12 @@ impl SectionUtils for Section {
     // Tries to split our section.
     // If we have enough mature nodes for both subsections, returns the SectionAuthorityProviders
     // of the two subsections. Otherwise returns `None`.
-    fn try_split(&self, our_name: &XorName) -> Option<(ElderCandidates, ElderCandidates)> {
+    fn try_split(
+        &self,
+        network_params: &NetworkParams,
+        our_name: &XorName,
+        excluded: &BTreeSet<XorName>,
+    ) -> Option<(ElderCandidates, ElderCandidates)> {
         let next_bit_index = if let Ok(index) = self.prefix().bit_count().try_into() {
             index
         } else {
@@ -393,7 +416,8 @@ impl SectionUtils for Section {
             });
 
         // If none of the two new sections would contain enough entries, return `None`.
-        if our_new_size < RECOMMENDED_SECTION_SIZE || sibling_new_size < RECOMMENDED_SECTION_SIZE {
+        let min_size = network_params.recommended_section_size;
+        if our_new_size < min_size || sibling_new_size < min_size {
             return None;
         }
 
@@ -402,13 +426,15 @@ impl SectionUtils for Section {
 
         let our_elders = self.members.elder_candidates_matching_prefix(
             &our_prefix,
-            ELDER_SIZE,
+            network_params.elder_size,
             self.authority_provider(),
+            excluded,
         );
         let other_elders = self.members.elder_candidates_matching_prefix(
             &other_prefix,
-            ELDER_SIZE,
+            network_params.elder_size,
             self.authority_provider(),
+            excluded,
         );
 
         let our_elder_candidates = ElderCandidates::new(our_elders, our_prefix);
@@ -419,9 +445,9 @@ impl SectionUtils for Section {
 
     // Returns the candidates for elders out of all the nodes in the section, even out of the
     // relocating nodes if there would not be enough instead.
-    fn elder_candidates(&self, elder_size: usize) -> Vec<Peer> {
+    fn elder_candidates(&self, elder_size: usize, excluded: &BTreeSet<XorName>) -> Vec<Peer> {
         self.members
-            .elder_candidates(elder_size, self.authority_provider())
+            .elder_candidates(elder_size, self.authority_provider(), excluded)
     }
 }
 
@@ -438,7 +464,7 @@ fn create_first_section_authority_provider(
     Ok(Proven::new(section_auth, signed))
 }
 
-fn create_first_signed<T: Serialize>(
+pub(crate) fn create_first_signed<T: Serialize>(
     pk_set: &bls::PublicKeySet,
     sk_share: &bls::SecretKeyShare,
     payload: &T,
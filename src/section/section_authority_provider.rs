@@ -228,6 +228,18 @@ pub(crate) mod test_utils {
         ([192, 0, 2, 0], port).into()
     }
 
+    // Generate an adult node of the first section, at a unique address.
+    pub(crate) fn gen_node() -> Node {
+        Node::new(
+            ed25519::gen_keypair(
+                &mut rand::thread_rng(),
+                &Prefix::default().range_inclusive(),
+                MIN_ADULT_AGE,
+            ),
+            gen_addr(),
+        )
+    }
+
     // Create `count` Nodes sorted by their names.
     // The `age_diff` flag is used to trigger nodes being generated with different age pattern.
     // The test of `handle_agreement_on_online_of_elder_candidate` requires most nodes to be with
@@ -241,7 +253,7 @@ pub(crate) mod test_utils {
                     MIN_ADULT_AGE
                 };
                 Node::new(
-                    ed25519::gen_keypair(&prefix.range_inclusive(), age),
+                    ed25519::gen_keypair(&mut rand::thread_rng(), &prefix.range_inclusive(), age),
                     gen_addr(),
                 )
             })
@@ -8,7 +8,7 @@
 
 use crate::error::{Error, Result};
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::VecDeque,
     fmt::{self, Debug, Formatter},
 };
 
@@ -36,26 +36,29 @@ impl Debug for SectionKeyShare {
 /// Struct that holds the current section keys and helps with new key generation.
 #[derive(Debug)]
 pub struct SectionKeysProvider {
-    /// A cache for current and previous section BLS keys.
+    /// A cache for current and previous section BLS keys. The new keys, generated by DKG, are
+    /// kept by the `DkgVoter` until our section agrees on them.
     cache: MiniKeyCache,
-    /// The new keys to use when section update completes.
-    // TODO: evict outdated keys.
-    // TODO: alternatively, store the pending keys in DkgVoter instead. That way the outdated ones
-    //       would get dropped when the DKG session itself gets dropped which we already have
-    //       implemented.
-    pending: HashMap<bls::PublicKey, SectionKeyShare>,
 }
 
 impl SectionKeysProvider {
     pub fn new(cache_size: u8, current: Option<SectionKeyShare>) -> Self {
         let mut provider = Self {
-            pending: HashMap::new(),
             cache: MiniKeyCache::with_capacity(cache_size as usize),
         };
         if let Some(share) = current {
             let public_key = share.public_key_set.public_key();
-            provider.insert_dkg_outcome(share);
-            provider.finalise_dkg(&public_key);
+            let _ = provider.cache.add(&public_key, share);
+        }
+        provider
+    }
+
+    // Creates the provider from previously finalised key shares, oldest first.
+    pub fn restore(cache_size: u8, shares: Vec<SectionKeyShare>) -> Self {
+        let mut provider = Self::new(cache_size, None);
+        for share in shares {
+            let public_key = share.public_key_set.public_key();
+            let _ = provider.cache.add(&public_key, share);
         }
         provider
     }
@@ -64,6 +67,11 @@ impl SectionKeysProvider {
         self.cache.get_most_recent()
     }
 
+    // Returns all the finalised key shares, oldest first.
+    pub fn key_shares(&self) -> impl Iterator<Item = &SectionKeyShare> {
+        self.cache.iter()
+    }
+
     pub fn sign_with(
         &self,
         data: &[u8],
@@ -72,22 +80,26 @@ impl SectionKeysProvider {
         self.cache.sign_with(data, public_key)
     }
 
+    pub fn decrypt_share_with(
+        &self,
+        ciphertext: &bls::Ciphertext,
+        public_key: &bls::PublicKey,
+    ) -> Result<bls::DecryptionShare> {
+        self.cache.decrypt_share_with(ciphertext, public_key)
+    }
+
     pub fn has_key_share(&self) -> bool {
         self.cache.has_key_share()
     }
 
-    pub fn insert_dkg_outcome(&mut self, share: SectionKeyShare) {
+    // Adds the key share of a new section key once our section agreed on it, evicting the oldest
+    // one if the cache is full.
+    pub fn insert(&mut self, share: SectionKeyShare) {
         let public_key = share.public_key_set.public_key();
-        let _ = self.pending.insert(public_key, share);
-    }
-
-    pub fn finalise_dkg(&mut self, public_key: &bls::PublicKey) {
-        if let Some(share) = self.pending.remove(public_key) {
-            if let Some(evicted) = self.cache.add(public_key, share) {
-                trace!("evicted old key from cache: {:?}", evicted);
-            }
-            trace!("finalised DKG: {:?}", public_key);
+        if let Some(evicted) = self.cache.add(&public_key, share) {
+            trace!("evicted old key from cache: {:?}", evicted);
         }
+        trace!("finalised DKG: {:?}", public_key);
     }
 }
 
@@ -110,6 +122,11 @@ impl MiniKeyCache {
         !self.list.is_empty()
     }
 
+    /// Returns all the cached key shares, oldest first.
+    pub fn iter(&self) -> impl Iterator<Item = &SectionKeyShare> {
+        self.list.iter().map(|(_, share)| share)
+    }
+
     /// Returns the most recently added key.
     pub fn get_most_recent(&self) -> Result<&SectionKeyShare> {
         if let Some((_, share)) = self.list.back() {
@@ -133,8 +150,27 @@ impl MiniKeyCache {
         Err(Error::MissingSecretKeyShare)
     }
 
+    /// Uses the secret key from cache, corresponding to
+    /// the provided public key, to produce a decryption share.
+    pub fn decrypt_share_with(
+        &self,
+        ciphertext: &bls::Ciphertext,
+        public_key: &bls::PublicKey,
+    ) -> Result<bls::DecryptionShare> {
+        for (cached_public, section_key_share) in &self.list {
+            if public_key == cached_public {
+                return section_key_share
+                    .secret_key_share
+                    .decrypt_share(ciphertext)
+                    .ok_or(Error::InvalidCiphertext);
+            }
+        }
+        Err(Error::MissingSecretKeyShare)
+    }
+
     /// Adds a new key to the cache, and removes + returns the oldest
-    /// key if cache size is exceeded.
+    /// key if cache size is exceeded. The share of the removed key is dropped, which zeroes its
+    /// secret.
     pub fn add(
         &mut self,
         public_key: &bls::PublicKey,
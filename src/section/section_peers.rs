@@ -15,7 +15,10 @@ use sn_messaging::{
 };
 use std::{
     cmp::Ordering,
-    collections::btree_map::{self, Entry},
+    collections::{
+        btree_map::{self, Entry},
+        BTreeSet,
+    },
     mem,
 };
 use xor_name::{Prefix, XorName};
@@ -37,19 +40,23 @@ pub trait SectionPeersUtils {
     /// Get proven info for the member with the given name.
     fn get_proven(&self, name: &XorName) -> Option<&Proven<MemberInfo>>;
 
-    /// Returns the candidates for elders out of all the nodes in this section.
+    /// Returns the candidates for elders out of all the nodes in this section, except the
+    /// `excluded` ones.
     fn elder_candidates(
         &self,
         elder_size: usize,
         current_elders: &SectionAuthorityProvider,
+        excluded: &BTreeSet<XorName>,
     ) -> Vec<Peer>;
 
-    /// Returns the candidates for elders out of all nodes matching the prefix.
+    /// Returns the candidates for elders out of all nodes matching the prefix, except the
+    /// `excluded` ones.
     fn elder_candidates_matching_prefix(
         &self,
         prefix: &Prefix,
         elder_size: usize,
         current_elders: &SectionAuthorityProvider,
+        excluded: &BTreeSet<XorName>,
     ) -> Vec<Peer>;
 
     /// Returns whether the given peer is a joined member of our section.
@@ -103,10 +110,12 @@ impl SectionPeersUtils for SectionPeers {
         &self,
         elder_size: usize,
         current_elders: &SectionAuthorityProvider,
+        excluded: &BTreeSet<XorName>,
     ) -> Vec<Peer> {
         elder_candidates(
             elder_size,
             current_elders,
+            excluded,
             self.members
                 .values()
                 .filter(|info| is_active(&info.value, current_elders))
@@ -114,16 +123,19 @@ impl SectionPeersUtils for SectionPeers {
         )
     }
 
-    /// Returns the candidates for elders out of all nodes matching the prefix.
+    /// Returns the candidates for elders out of all nodes matching the prefix, except the
+    /// `excluded` ones.
     fn elder_candidates_matching_prefix(
         &self,
         prefix: &Prefix,
         elder_size: usize,
         current_elders: &SectionAuthorityProvider,
+        excluded: &BTreeSet<XorName>,
     ) -> Vec<Peer> {
         elder_candidates(
             elder_size,
             current_elders,
+            excluded,
             self.members.values().filter(|info| {
                 info.value.state == PeerState::Joined
                     && prefix.matches(info.value.peer.name())
@@ -194,6 +206,7 @@ impl Iterator for IntoIter {
 fn elder_candidates<'a, I>(
     elder_size: usize,
     current_elders: &SectionAuthorityProvider,
+    excluded: &BTreeSet<XorName>,
     members: I,
 ) -> Vec<Peer>
 where
@@ -201,6 +214,7 @@ where
 {
     members
         .into_iter()
+        .filter(|info| !excluded.contains(info.value.peer.name()))
         .sorted_by(|lhs, rhs| cmp_elder_candidates(lhs, rhs, current_elders))
         .map(|info| info.value.peer)
         .take(elder_size)
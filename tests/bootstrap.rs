@@ -11,7 +11,7 @@ mod utils;
 use anyhow::{Error, Result};
 use ed25519_dalek::Keypair;
 use futures::future;
-use sn_routing::{Config, Event, NodeElderChange, ELDER_SIZE};
+use sn_routing::{Config, Event, NetworkParams, NodeElderChange, ELDER_SIZE};
 use std::collections::HashSet;
 use tokio::time;
 use utils::*;
@@ -138,3 +138,52 @@ async fn test_startup_elders() -> Result<()> {
 
     Ok(())
 }
+
+// Test that nodes joining with the default network parameters adopt the ones of the network.
+#[tokio::test]
+async fn test_joining_node_adopts_network_params() -> Result<()> {
+    let network_params = NetworkParams {
+        elder_size: 3,
+        recommended_section_size: 6,
+        resource_proof_difficulty: 1,
+        key_cache_size: 3,
+        ..Default::default()
+    };
+    let (genesis_node, mut event_stream) = create_node(Config {
+        first: true,
+        network_params,
+        ..Default::default()
+    })
+    .await?;
+    assert_next_event!(event_stream, Event::EldersChanged { .. });
+
+    // Fill the section up to three elders.
+    let genesis_contact = genesis_node.our_connection_info();
+    let mut nodes = vec![];
+    for _ in 0..2 {
+        let (node, _) = create_node(config_with_contact(genesis_contact)).await?;
+        let node_name = node.name().await;
+        assert_event!(event_stream, Event::MemberJoined { name, .. } if name == node_name);
+        nodes.push(node);
+    }
+
+    if genesis_node.our_elders().await.len() < 3 {
+        assert_event!(
+            event_stream,
+            Event::EldersChanged { elders, .. }
+                if elders.remaining.len() + elders.added.len() == 3
+        );
+    }
+
+    let (node, _) = create_node(config_with_contact(genesis_contact)).await?;
+    let node_name = node.name().await;
+    assert_event!(event_stream, Event::MemberJoined { name, .. } if name == node_name);
+    nodes.push(node);
+
+    assert_eq!(genesis_node.our_elders().await.len(), 3);
+    for node in &nodes {
+        assert_eq!(node.network_params().await, network_params);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+// Copyright 2021 MaidSafe.net limited.
+//
+// This SAFE Network Software is licensed to you under The General Public License (GPL), version 3.
+// Unless required by applicable law or agreed to in writing, the SAFE Network Software distributed
+// under the GPL Licence is distributed on an "AS IS" BASIS, WITHOUT WARRANTIES OR CONDITIONS OF ANY
+// KIND, either express or implied. Please review the Licences for the specific language governing
+// permissions and limitations relating to use of the SAFE Network Software.
+
+mod utils;
+
+use self::utils::*;
+use anyhow::Result;
+use sn_routing::{
+    Clock, Config, Event, LinkConfig, MemoryNetwork, NodeElderChange, SharedRng, TokioClock,
+    ELDER_SIZE,
+};
+use std::{collections::BTreeSet, iter, net::SocketAddr, sync::Arc, time::Duration};
+
+// Seed of the simulated network faults and of the keys of the nodes. Change it to explore other
+// scenarios; a failing one is replayed by running it again with the same seed.
+const SEED: u64 = 7;
+
+// Network with the faults all the simulations run over, measuring its delays with `clock`.
+fn faulty_network(clock: Arc<dyn Clock>) -> MemoryNetwork {
+    let network = MemoryNetwork::new(SEED);
+    network.set_clock(clock);
+    network.set_default_link(LinkConfig {
+        latency: Duration::from_millis(10),
+        jitter: Duration::from_millis(20),
+        duplication: 0.1,
+        ..Default::default()
+    });
+    network
+}
+
+// Config of a node of the simulation, with its own RNG forked from `rng` and `contact` as its
+// bootstrap contact, if any.
+fn simulated_config(
+    network: &MemoryNetwork,
+    clock: &Arc<dyn Clock>,
+    rng: &SharedRng,
+    contact: Option<SocketAddr>,
+) -> Config {
+    let mut config = Config {
+        first: contact.is_none(),
+        transport: Some(Arc::new(network.clone())),
+        clock: clock.clone(),
+        rng: rng.fork(),
+        ..Default::default()
+    };
+    config.transport_config.hard_coded_contacts = contact.into_iter().collect();
+    config
+}
+
+#[tokio::test]
+async fn test_bootstrapping_over_faulty_links() -> Result<()> {
+    let clock: Arc<dyn Clock> = Arc::new(TokioClock);
+    let rng = SharedRng::seeded(SEED);
+    let network = faulty_network(clock.clone());
+
+    let (genesis_node, mut genesis_events) =
+        create_node(simulated_config(&network, &clock, &rng, None)).await?;
+    assert_next_event!(genesis_events, Event::EldersChanged { .. });
+
+    let contact = Some(genesis_node.our_connection_info());
+    let (node, mut events) = create_node(simulated_config(&network, &clock, &rng, contact)).await?;
+    let node_name = node.name().await;
+    assert_event!(genesis_events, Event::MemberJoined { name, .. } if name == node_name);
+    assert_event!(
+        events,
+        Event::EldersChanged {
+            self_status_change: NodeElderChange::Promoted,
+            ..
+        }
+    );
+
+    let elder_size = 2;
+    verify_invariants_for_node(&genesis_node, elder_size).await?;
+    verify_invariants_for_node(&node, elder_size).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_partitioned_node_cannot_join() -> Result<()> {
+    let clock: Arc<dyn Clock> = Arc::new(TokioClock);
+    let rng = SharedRng::seeded(SEED);
+    let network = MemoryNetwork::new(SEED);
+    network.set_clock(clock.clone());
+
+    let (genesis_node, mut genesis_events) =
+        create_node(simulated_config(&network, &clock, &rng, None)).await?;
+    assert_next_event!(genesis_events, Event::EldersChanged { .. });
+
+    network.partition(iter::once(genesis_node.our_connection_info()));
+
+    let contact = Some(genesis_node.our_connection_info());
+    assert!(
+        create_node(simulated_config(&network, &clock, &rng, contact))
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_elder_leaving_over_faulty_links() -> Result<()> {
+    let clock: Arc<dyn Clock> = Arc::new(TokioClock);
+    let rng = SharedRng::seeded(SEED);
+    let network = faulty_network(clock.clone());
+
+    let (genesis_node, mut genesis_events) =
+        create_node(simulated_config(&network, &clock, &rng, None)).await?;
+    assert_next_event!(genesis_events, Event::EldersChanged { .. });
+
+    // Join one node more than there are elders, so there is an adult to take over from the
+    // leaving elder.
+    let contact = Some(genesis_node.our_connection_info());
+    let mut nodes = vec![];
+    for _ in 0..ELDER_SIZE {
+        let (node, events) = create_node(simulated_config(&network, &clock, &rng, contact)).await?;
+        let node_name = node.name().await;
+        assert_event!(genesis_events, Event::MemberJoined { name, .. } if name == node_name);
+        nodes.push((node, events));
+    }
+
+    // Every change of the elders takes a DKG.
+    if genesis_node.our_elders().await.len() < ELDER_SIZE {
+        assert_event!(
+            genesis_events,
+            Event::EldersChanged { elders, .. }
+                if elders.remaining.len() + elders.added.len() == ELDER_SIZE
+        );
+    }
+
+    let mut elder_index = None;
+    for (index, (node, _)) in nodes.iter().enumerate() {
+        if node.is_elder().await {
+            elder_index = Some(index);
+            break;
+        }
+    }
+    let (leaving_node, _) = nodes.remove(elder_index.expect("no elder besides the genesis node"));
+    let leaving_name = leaving_node.name().await;
+    leaving_node.leave().await?;
+
+    // The section agrees on the node leaving and runs another DKG to replace it.
+    assert_event!(genesis_events, Event::MemberLeft { name, .. } if name == leaving_name);
+    assert_event!(
+        genesis_events,
+        Event::EldersChanged { elders, .. } if elders.removed.contains(&leaving_name)
+    );
+
+    let elders: BTreeSet<_> = genesis_node
+        .our_elders()
+        .await
+        .into_iter()
+        .map(|peer| *peer.name())
+        .collect();
+    assert_eq!(elders.len(), ELDER_SIZE);
+    assert!(!elders.contains(&leaving_name));
+
+    verify_invariants_for_node(&genesis_node, ELDER_SIZE).await?;
+    for (node, _) in &nodes {
+        verify_invariants_for_node(node, ELDER_SIZE).await?;
+    }
+
+    Ok(())
+}